@@ -51,15 +51,15 @@ app.on('window-all-closed', () => {
 // Function to run precompute
 async function runPrecompute(filePath) {
     return new Promise((resolve, reject) => {
-        // Path to Rust binary precontract_cli
-        // Binary is compiled in src/wasm/target/release/precontract_cli
-        const cliPath = path.join(__dirname, '..', 'src', 'wasm', 'target', 'release', 'precontract_cli');
+        // Path to Rust binary sox
+        // Binary is compiled in src/wasm/target/release/sox
+        const cliPath = path.join(__dirname, '..', 'src', 'wasm', 'target', 'release', 'sox');
         
         // On Windows, add .exe
         const command = process.platform === 'win32' ? cliPath + '.exe' : cliPath;
         
-        // Arguments: binary takes file as first argument (no --input)
-        const args = [filePath];
+        // Arguments: `precontract` subcommand followed by the file (no --input)
+        const args = ['precontract', filePath];
         
         const child = spawn(command, args, {
             cwd: path.join(__dirname, '..'),
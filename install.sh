@@ -144,13 +144,13 @@ if [ "$RUST_AVAILABLE" = true ]; then
     echo -e "${GREEN}6. Compiling Rust/WASM binary...${NC}"
     if [ -d "src/wasm" ]; then
         cd src/wasm
-        BINARY_PATH="target/release/precontract_cli"
+        BINARY_PATH="target/release/sox"
         if [ -f "$BINARY_PATH" ]; then
             echo -e "   ✅ Rust binary already exists: $BINARY_PATH"
             echo -e "   ${YELLOW}   (Skipping compilation. Delete the binary to force recompilation)${NC}"
         else
-            echo -e "   Compiling precontract_cli (this may take a few minutes)..."
-            if cargo build --release --bin precontract_cli; then
+            echo -e "   Compiling sox (this may take a few minutes)..."
+            if cargo build --release --bin sox; then
                 if [ -f "$BINARY_PATH" ]; then
                     echo -e "   ✅ Rust binary compiled successfully"
                 else
@@ -24,13 +24,13 @@ try {
 
 const execFileAsync = promisify(execFile);
 
-const PRECONTRACT_CLI_PATH = path.join(
+const SOX_CLI_PATH = path.join(
     process.cwd(),
     "src",
     "wasm",
     "target",
     "release",
-    "precontract_cli"
+    "sox"
 );
 
 async function parseMultipartRequest(
@@ -172,13 +172,13 @@ export async function PUT(req: Request) {
                     algorithm_suite: parsed.fields.algorithm_suite,
                 };
 
-                const { stdout } = await execFileAsync(PRECONTRACT_CLI_PATH, [tempFilePath]);
+                const { stdout } = await execFileAsync(SOX_CLI_PATH, ["precontract", tempFilePath]);
                 preOut = JSON.parse(stdout.toString());
             } catch (error: any) {
                 if (tempFilePath && fs.existsSync(tempFilePath)) {
                     fs.unlinkSync(tempFilePath);
                 }
-                console.error("Error executing precontract_cli:", error);
+                console.error("Error executing sox precontract:", error);
                 return NextResponse.json(
                     { error: `Error calculating precontract: ${error.message || error.toString()}` },
                     { status: 500 }
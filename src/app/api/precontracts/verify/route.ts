@@ -8,13 +8,13 @@ import { promisify } from "node:util";
 
 const execFileAsync = promisify(execFile);
 
-const CHECK_PRECONTRACT_CLI_PATH = path.join(
+const SOX_CLI_PATH = path.join(
     process.cwd(),
     "src",
     "wasm",
     "target",
     "release",
-    "check_precontract_cli"
+    "sox"
 );
 
 export async function POST(req: Request) {
@@ -67,7 +67,8 @@ export async function POST(req: Request) {
             );
         }
 
-        const { stdout } = await execFileAsync(CHECK_PRECONTRACT_CLI_PATH, [
+        const { stdout } = await execFileAsync(SOX_CLI_PATH, [
+            "check",
             cipherPath,
             item_description,
             commitment,
@@ -79,7 +80,7 @@ export async function POST(req: Request) {
             parsed = JSON.parse(stdout.toString());
         } catch (e: any) {
             console.error(
-                "Erreur de parsing JSON depuis check_precontract_cli:",
+                "Erreur de parsing JSON depuis sox check:",
                 e,
                 stdout.toString()
             );
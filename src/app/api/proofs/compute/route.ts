@@ -7,18 +7,30 @@ import { UPLOADS_PATH } from "../../files/[id]/route";
 
 const execFileAsync = promisify(execFile);
 
-const COMPUTE_PROOFS_CLI_PATH = path.join(
+const SOX_CLI_PATH = path.join(
     process.cwd(),
     "src",
     "wasm",
     "target",
     "release",
-    "compute_proofs_cli"
+    "sox"
 );
 
+// DisputeSOX states in which the vendor has to send proofs
+const SUPPORTED_STATES = [2, 3, 4];
+
 export async function POST(req: Request) {
     try {
-        const { state, contractId, num_blocks, num_gates } = await req.json();
+        const {
+            state,
+            contractId,
+            num_blocks,
+            num_gates,
+            evaluated_circuit_hex,
+            circuit_hex,
+            challenge,
+            version,
+        } = await req.json();
 
         if (!state || contractId === undefined || !num_blocks || !num_gates) {
             return NextResponse.json(
@@ -27,16 +39,13 @@ export async function POST(req: Request) {
             );
         }
 
-
-        if (state !== 4) {
+        if (!SUPPORTED_STATES.includes(state)) {
             return NextResponse.json(
-                { error: `State ${state} not supported. Only state 4 (WaitVendorDataRight) is supported for now.` },
+                { error: `State ${state} not supported. Expected 2 (WaitVendorData), 3 (WaitVendorDataLeft) or 4 (WaitVendorDataRight).` },
                 { status: 400 }
             );
         }
 
-        const { evaluated_circuit_hex } = await req.json();
-        
         if (!evaluated_circuit_hex) {
             return NextResponse.json(
                 { error: "Field 'evaluated_circuit_hex' is required" },
@@ -44,30 +53,63 @@ export async function POST(req: Request) {
             );
         }
 
+        const needsCircuit = state !== 4;
+        if (needsCircuit && (!circuit_hex || challenge === undefined)) {
+            return NextResponse.json(
+                { error: `Fields 'circuit_hex' and 'challenge' are required in state ${state}` },
+                { status: 400 }
+            );
+        }
+
+        const cipherPath = path.join(UPLOADS_PATH, `file_${contractId}.enc`);
+        if (needsCircuit && !fs.existsSync(cipherPath)) {
+            return NextResponse.json(
+                { error: `Ciphertext for contract ${contractId} not found` },
+                { status: 404 }
+            );
+        }
+
         const tempDir = path.join(process.cwd(), "tmp");
         if (!fs.existsSync(tempDir)) {
             fs.mkdirSync(tempDir, { recursive: true });
         }
-        
+
         const tempEvaluatedCircuitPath = path.join(tempDir, `evaluated_circuit_${contractId}.bin`);
-        const evaluated_circuit_bytes = Buffer.from(evaluated_circuit_hex, "hex");
-        fs.writeFileSync(tempEvaluatedCircuitPath, evaluated_circuit_bytes);
+        fs.writeFileSync(tempEvaluatedCircuitPath, Buffer.from(evaluated_circuit_hex, "hex"));
+        const tempFiles = [tempEvaluatedCircuitPath];
 
-        const { stdout } = await execFileAsync(COMPUTE_PROOFS_CLI_PATH, [
+        const args = [
+            "compute-proofs",
             state.toString(),
             tempEvaluatedCircuitPath,
             num_blocks.toString(),
             num_gates.toString(),
-        ]);
+        ];
+        if (needsCircuit) {
+            const tempCircuitPath = path.join(tempDir, `circuit_${contractId}.bin`);
+            fs.writeFileSync(tempCircuitPath, Buffer.from(circuit_hex, "hex"));
+            tempFiles.push(tempCircuitPath);
+            args.push(tempCircuitPath, cipherPath, challenge.toString());
+        }
+        if (version === 1) {
+            args.push("--v1");
+        }
 
-        fs.unlinkSync(tempEvaluatedCircuitPath);
+        let stdout: string | Buffer;
+        try {
+            ({ stdout } = await execFileAsync(SOX_CLI_PATH, args));
+        } finally {
+            for (const file of tempFiles) {
+                fs.rmSync(file, { force: true });
+            }
+        }
 
         let parsed: any;
         try {
             parsed = JSON.parse(stdout.toString());
         } catch (e: any) {
             console.error(
-                "JSON parsing error from compute_proofs_cli:",
+                "JSON parsing error from sox compute-proofs:",
                 e,
                 stdout.toString()
             );
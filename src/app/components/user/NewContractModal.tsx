@@ -65,7 +65,7 @@ export default function NewContractModal({
                 return null;
             }
 
-            // Ouvre UNE SEULE fois la fenêtre native et lance precontract_cli
+            // Ouvre UNE SEULE fois la fenêtre native et lance sox precontract
             const preOut = await anyWindow.electronAPI.precompute();
 
             if (preOut.cancelled) {
@@ -117,8 +117,9 @@ library DisputeSOXHelpers {
             if (sonIdx < 0) {
                 uint32 ctIdx = uint32(uint64(-sonIdx));
                 require(ctIdx >= 1 && ctIdx <= numBlocks, "CT index out of bounds");
-                // Convert to 0-indexed to match Rust's proof2 generation (Merkle trees use 0-indexed arrays)
-                sInL[iterInL] = ctIdx - 1;
+                // hCt is accumulated over [IV, block1, block2, ...], so block ctIdx (1-indexed)
+                // is at index ctIdx in the root, as in DisputeSOXAccount._extractInAndNotInL_V2
+                sInL[iterInL] = ctIdx;
                 vInL[iterInL] = _valuesKeccak[valueIdx];
                 ++iterInL;
             } else {
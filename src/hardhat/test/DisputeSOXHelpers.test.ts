@@ -0,0 +1,98 @@
+import { expect } from "chai";
+import hre from "hardhat";
+import "@nomicfoundation/hardhat-chai-matchers";
+import { readFile } from "node:fs/promises";
+import { join } from "node:path";
+import {
+    bytes_to_hex,
+    compute_precontract_values_v2,
+    compute_proofs_v2,
+    evaluate_circuit_v2_wasm,
+    initSync,
+} from "../../app/lib/crypto_lib/crypto_lib";
+
+const { ethers } = hre;
+
+describe("DisputeSOXHelpers", function () {
+    // Decodes the sons of a V2 gate (6-byte big-endian signed integers after the opcode)
+    function decodeSonsV2(gate: Uint8Array, arity: number): number[] {
+        const limit = 1n << 48n;
+        const sons: number[] = [];
+        for (let i = 0; i < arity; i++) {
+            let v = 0n;
+            for (const byte of gate.slice(1 + i * 6, 7 + i * 6)) {
+                v = (v << 8n) | BigInt(byte);
+            }
+            sons.push(Number(v >= limit / 2n ? v - limit : v));
+        }
+        return sons;
+    }
+
+    it("extractInAndNotInL_V2 opens ciphertext blocks after the IV in hCt", async function () {
+        const modulePath = join(__dirname, "../../app/lib/crypto_lib/crypto_lib_bg.wasm");
+        initSync({ module: await readFile(modulePath) });
+
+        const file = new Uint8Array(1024);
+        for (let i = 0; i < file.length; i++) {
+            file[i] = (i * 7) % 256;
+        }
+        const key = new Uint8Array(16).map((_, i) => (i * 17) % 256);
+        const precontract = compute_precontract_values_v2(file, key);
+        const evaluated = evaluate_circuit_v2_wasm(
+            precontract.circuit_bytes,
+            precontract.ct,
+            bytes_to_hex(key)
+        ).to_bytes();
+
+        const DisputeSOXHelpersFactory = await ethers.getContractFactory("DisputeSOXHelpers");
+        const disputeHelpers = await DisputeSOXHelpersFactory.deploy();
+        await disputeHelpers.waitForDeployment();
+
+        const TestAccumulatorVerifierFactory = await ethers.getContractFactory(
+            "TestAccumulatorVerifier"
+        );
+        const accumulatorVerifier = await TestAccumulatorVerifierFactory.deploy();
+        await accumulatorVerifier.waitForDeployment();
+
+        // the first gate after the first one reading ciphertext blocks
+        let proofs: any;
+        let sons: number[] = [];
+        for (let gateNum = 2; gateNum <= precontract.num_gates; gateNum++) {
+            proofs = compute_proofs_v2(
+                precontract.circuit_bytes,
+                evaluated,
+                precontract.ct,
+                gateNum
+            );
+            sons = decodeSonsV2(new Uint8Array(proofs.gate_bytes), proofs.values.length);
+            if (sons.some((son) => son < 0)) {
+                break;
+            }
+        }
+        expect(sons.some((son) => son < 0)).to.equal(true);
+
+        const valuesKeccak = proofs.values.map((v: Uint8Array) =>
+            ethers.keccak256(new Uint8Array(v))
+        );
+        const [sInL, vInL] = await disputeHelpers.extractInAndNotInL_V2(
+            new Uint8Array(proofs.gate_bytes),
+            valuesKeccak,
+            precontract.num_blocks
+        );
+
+        // DisputeSOXAccount._extractInAndNotInL_V2 proves block -son at index -son
+        const ctSons = sons.filter((son) => son < 0);
+        expect(sInL.map(Number)).to.deep.equal(ctSons.map((son) => -son));
+
+        // and the proof2 of compute_proofs_v2 opens hCt at those indices
+        const proof2 = proofs.proof2.map((level: Uint8Array[]) =>
+            level.map((v: Uint8Array) => ethers.hexlify(new Uint8Array(v)))
+        );
+        const hCt = ethers.hexlify(new Uint8Array(precontract.h_ct));
+        expect(await accumulatorVerifier.verify(hCt, [...sInL], [...vInL], proof2)).to.equal(true);
+
+        // the 0-indexed blocks, which skip the IV, are not opened by proof2
+        const unshifted = sInL.map((idx: bigint) => Number(idx) - 1);
+        expect(await accumulatorVerifier.verify(hCt, unshifted, [...vInL], proof2)).to.equal(false);
+    });
+});
@@ -1,9 +1,13 @@
-use crate::utils::die;
-use crate::{split_ct_blocks, CompiledCircuit};
-use crate::circuits_v2::{CompiledCircuitV2, acc_circuit_v2};
+use crate::circuits::CompiledCircuit;
+use crate::circuits_v2::{acc_circuit_v2, CompiledCircuitV2};
+use crate::error::{SoxError, SoxResult};
+use crate::utils::split_ct_blocks;
 use sha3::{Digest, Keccak256};
 use js_sys::{Array, Uint8Array};
 use rayon::prelude::*;
+use rmp_serde::{encode::write, from_read};
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
 use wasm_bindgen::prelude::wasm_bindgen;
 
 /// Converts a JavaScript Uint8Array to a Rust Vec<u8>
@@ -17,6 +21,140 @@ pub fn uint8_array_to_vec_u8(array: &Uint8Array) -> Vec<u8> {
     (0..array.length()).map(|i| array.get_index(i)).collect()
 }
 
+// Prefixes of the hashed data of the leaves and of the nodes in domain-separated mode
+const LEAF_TAG: u8 = 0x00;
+const NODE_TAG: u8 = 0x01;
+
+/// Hash function of the accumulator. The deployed verifiers use `Keccak256Hasher`, which is the
+/// default of every function that is not generic over the hash; the other implementations build
+/// trees of the same shape, e.g. to benchmark cheaper off-chain modes or to target chains with a
+/// SHA-256 precompile.
+pub trait AccumulatorHash {
+    /// Hashes the concatenation of `parts`
+    fn hash_parts(parts: &[&[u8]]) -> [u8; 32];
+
+    /// Hashes `data`
+    fn hash(data: &[u8]) -> [u8; 32] {
+        Self::hash_parts(&[data])
+    }
+
+    /// Hashes `data` right-padded with zeros (or truncated) to 64 bytes
+    fn hash_block64(data: &[u8]) -> [u8; 32] {
+        let mut block = [0u8; 64];
+        let len = usize::min(data.len(), 64);
+        block[..len].copy_from_slice(&data[..len]);
+        Self::hash(&block)
+    }
+}
+
+/// Keccak-256, the hash of the EVM
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Keccak256Hasher;
+
+impl AccumulatorHash for Keccak256Hasher {
+    fn hash_parts(parts: &[&[u8]]) -> [u8; 32] {
+        let mut hasher = Keccak256::new();
+        parts.iter().for_each(|part| hasher.update(part));
+        hasher.finalize().into()
+    }
+}
+
+/// SHA-256
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Sha256Hasher;
+
+impl AccumulatorHash for Sha256Hasher {
+    fn hash_parts(parts: &[&[u8]]) -> [u8; 32] {
+        let mut hasher = sha2::Sha256::new();
+        parts.iter().for_each(|part| hasher.update(part));
+        hasher.finalize().into()
+    }
+}
+
+/// BLAKE3 with a 32-byte output
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Blake3Hasher;
+
+impl AccumulatorHash for Blake3Hasher {
+    fn hash_parts(parts: &[&[u8]]) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        parts.iter().for_each(|part| {
+            hasher.update(part);
+        });
+        hasher.finalize().into()
+    }
+}
+
+/// Hashing scheme of the accumulator. The shape of the tree is the same in every mode (the last
+/// node of a layer with an odd number of nodes is promoted as-is), only the leaf and node hashes
+/// change.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AccumulatorMode {
+    /// Scheme of the deployed verifiers: a leaf is the Keccak of the value right-padded with zeros
+    /// or truncated to 64 bytes, a node the Keccak of the concatenation of its children
+    #[default]
+    Legacy = 0,
+
+    /// A leaf is Keccak(0x00 || value) over the whole value, a node Keccak(0x01 || left || right),
+    /// so that values longer than 64 bytes are not truncated and a leaf never collides with a node
+    DomainSeparated = 1,
+}
+
+impl AccumulatorMode {
+    /// Hashes a value into a leaf of the accumulator.
+    ///
+    /// # Arguments
+    /// * `value` - Value to hash
+    ///
+    /// # Returns
+    /// The 32-byte leaf hash
+    pub fn leaf_hash(self, value: &[u8]) -> [u8; 32] {
+        self.leaf_hash_with::<Keccak256Hasher>(value)
+    }
+
+    /// Hashes a value into a leaf of the accumulator with a given hash function.
+    ///
+    /// # Arguments
+    /// * `value` - Value to hash
+    ///
+    /// # Returns
+    /// The 32-byte leaf hash
+    pub fn leaf_hash_with<H: AccumulatorHash>(self, value: &[u8]) -> [u8; 32] {
+        match self {
+            AccumulatorMode::Legacy => H::hash_block64(value),
+            AccumulatorMode::DomainSeparated => H::hash_parts(&[&[LEAF_TAG], value]),
+        }
+    }
+
+    /// Hashes two nodes of the tree into their parent.
+    ///
+    /// # Arguments
+    /// * `left` - Left child
+    /// * `right` - Right child
+    ///
+    /// # Returns
+    /// The 32-byte parent node
+    pub fn node_hash(self, left: &[u8], right: &[u8]) -> [u8; 32] {
+        self.node_hash_with::<Keccak256Hasher>(left, right)
+    }
+
+    /// Hashes two nodes of the tree into their parent with a given hash function.
+    ///
+    /// # Arguments
+    /// * `left` - Left child
+    /// * `right` - Right child
+    ///
+    /// # Returns
+    /// The 32-byte parent node
+    pub fn node_hash_with<H: AccumulatorHash>(self, left: &[u8], right: &[u8]) -> [u8; 32] {
+        match self {
+            AccumulatorMode::Legacy => H::hash_parts(&[left, right]),
+            AccumulatorMode::DomainSeparated => H::hash_parts(&[&[NODE_TAG], left, right]),
+        }
+    }
+}
+
 /// Computes the accumulator value. It is the root of the Merkle tree built with `values`.
 ///
 /// # Arguments
@@ -25,58 +163,68 @@ pub fn uint8_array_to_vec_u8(array: &Uint8Array) -> Vec<u8> {
 /// # Returns
 /// A 32-byte vector containing the accumulated hash
 pub fn acc(values: &[Vec<u8>]) -> Vec<u8> {
-    if values.len() == 0 {
-        return vec![];
-    }
-    if values.len() == 1 {
-        return hash(&values[0]);
-    }
-
-    let hashes: Vec<Vec<u8>> = values.iter().map(hash).collect();
+    acc_with_mode(values, AccumulatorMode::Legacy)
+}
 
-    compute_merkle_root(hashes)
+/// Computes the accumulator value in a given hashing mode (see `acc`).
+///
+/// # Arguments
+/// * `values` - Vector of byte vectors to accumulate
+/// * `mode` - Hashing scheme of the leaves and nodes
+///
+/// # Returns
+/// A 32-byte vector containing the accumulated hash, empty if there are no values
+pub fn acc_with_mode(values: &[Vec<u8>], mode: AccumulatorMode) -> Vec<u8> {
+    acc_with_hash::<Keccak256Hasher>(values, mode)
 }
 
-/// Computes the accumulator value for a circuit
+/// Computes the accumulator value with a given hash function and hashing mode (see `acc`).
 ///
 /// # Arguments
-/// * `circuit` - The compiled circuit to accumulate
+/// * `values` - Vector of byte vectors to accumulate
+/// * `mode` - Hashing scheme of the leaves and nodes
 ///
 /// # Returns
-/// A 32-byte vector containing the accumulated hash of the circuit's components
+/// A 32-byte vector containing the accumulated hash, empty if there are no values
+pub fn acc_with_hash<H: AccumulatorHash>(values: &[Vec<u8>], mode: AccumulatorMode) -> Vec<u8> {
+    if values.is_empty() {
+        return vec![];
+    }
+
+    let hashes: Vec<Vec<u8>> = values
+        .iter()
+        .map(|v| mode.leaf_hash_with::<H>(v).to_vec())
+        .collect();
+
+    compute_merkle_root::<H>(hashes, mode)
+}
+
 /// Trait implemented by any compiled circuit that can expose its gate encodings
 /// for accumulation. V1 uses ABI encoding; V2 uses the 64-byte gate encoding.
 pub trait AccumulableCircuit {
-    fn encoded_gates(&self) -> Vec<Vec<u8>>;
-    
+    fn encoded_gates(&self) -> SoxResult<Vec<Vec<u8>>>;
+
     /// Optimized accumulation that can avoid storing all encoded gates.
     /// Default implementation uses encoded_gates(), but can be overridden for better performance.
-    fn acc_direct(&self) -> Option<Vec<u8>> {
+    fn acc_direct(&self) -> Option<SoxResult<Vec<u8>>> {
         None // Default: use encoded_gates path
     }
 }
 
 impl AccumulableCircuit for CompiledCircuit {
-    fn encoded_gates(&self) -> Vec<Vec<u8>> {
-        self.to_abi_encoded()
+    fn encoded_gates(&self) -> SoxResult<Vec<Vec<u8>>> {
+        Ok(self.to_abi_encoded())
     }
 }
 
 impl AccumulableCircuit for CompiledCircuitV2 {
-    fn encoded_gates(&self) -> Vec<Vec<u8>> {
+    fn encoded_gates(&self) -> SoxResult<Vec<Vec<u8>>> {
         // Fallback: encode all gates (used by V1 path)
-        self.gates
-            .iter()
-            .map(|g| {
-                let mut buf = [0u8; 64];
-                g.encode_into(&mut buf);
-                buf.to_vec()
-            })
-            .collect()
+        self.gates.iter().map(|g| g.encode()).collect()
     }
-    
+
     /// Optimized accumulation for V2: encode and hash in parallel without storing all gates
-    fn acc_direct(&self) -> Option<Vec<u8>> {
+    fn acc_direct(&self) -> Option<SoxResult<Vec<u8>>> {
         Some(acc_circuit_v2(&self.gates))
     }
 }
@@ -84,21 +232,45 @@ impl AccumulableCircuit for CompiledCircuitV2 {
 /// Computes the accumulator value for a circuit (v1 or v2).
 /// Selects an optimized 64-byte Merkle accumulator when all gates are 64B.
 /// For V2 circuits, uses acc_direct() to avoid storing all encoded gates.
-pub fn acc_circuit<C: AccumulableCircuit>(circuit: &C) -> Vec<u8> {
+///
+/// # Arguments
+/// * `circuit` - The compiled circuit to accumulate
+///
+/// # Returns
+/// A 32-byte vector containing the accumulated hash of the circuit's components
+pub fn acc_circuit<C: AccumulableCircuit>(circuit: &C) -> SoxResult<Vec<u8>> {
     // Try optimized direct path first (for V2)
     if let Some(result) = circuit.acc_direct() {
         return result;
     }
-    
+
     // Fallback to encoded_gates path (for V1 or when acc_direct not implemented)
-    let circuit_bytes_array = circuit.encoded_gates();
+    let circuit_bytes_array = circuit.encoded_gates()?;
     let use_fixed64 = circuit_bytes_array.iter().all(|g| g.len() == 64);
 
     if use_fixed64 {
         // Use optimized parallel accumulator for 64-byte gates
-        acc_fixed64(&circuit_bytes_array)
+        Ok(acc_fixed64(&circuit_bytes_array))
     } else {
-        acc(&circuit_bytes_array)
+        Ok(acc(&circuit_bytes_array))
+    }
+}
+
+/// Computes the accumulator value for a circuit in a given hashing mode (see `acc_circuit`).
+///
+/// # Arguments
+/// * `circuit` - The compiled circuit to accumulate
+/// * `mode` - Hashing scheme of the leaves and nodes
+///
+/// # Returns
+/// A 32-byte vector containing the accumulated hash of the encoded gates
+pub fn acc_circuit_with_mode<C: AccumulableCircuit>(
+    circuit: &C,
+    mode: AccumulatorMode,
+) -> SoxResult<Vec<u8>> {
+    match mode {
+        AccumulatorMode::Legacy => acc_circuit(circuit),
+        _ => Ok(acc_with_mode(&circuit.encoded_gates()?, mode)),
     }
 }
 
@@ -111,14 +283,14 @@ pub fn acc_circuit<C: AccumulableCircuit>(circuit: &C) -> Vec<u8> {
 /// # Returns
 /// A 32-byte vector containing the accumulated hash of the ciphertext blocks
 /// Optimized to use fixed64 accumulator for better performance
-pub fn acc_ct(ct: &[u8], block_size: usize) -> Vec<u8> {
-    let blocks = split_ct_blocks(ct, block_size);
-    
+pub fn acc_ct(ct: &[u8], block_size: usize) -> SoxResult<Vec<u8>> {
+    let blocks = split_ct_blocks(ct, block_size)?;
+
     // Use acc_fixed64 for better performance (all blocks are 64B)
     if block_size == 64 {
-        acc_fixed64(&blocks)
+        Ok(acc_fixed64(&blocks))
     } else {
-        acc(&blocks)
+        Ok(acc(&blocks))
     }
 }
 
@@ -130,24 +302,69 @@ pub fn acc_ct(ct: &[u8], block_size: usize) -> Vec<u8> {
 /// * `indices` - Indices of values to include in the proof
 ///
 /// # Returns
-/// A vector of proof components
-pub fn prove(values: &[Vec<u8>], indices: &[u32]) -> Vec<Vec<Vec<u8>>> {
+/// A vector of proof components, or `SoxError::Index` if there are more indices than values or
+/// if one of the indices is out of bounds
+pub fn prove(values: &[Vec<u8>], indices: &[u32]) -> SoxResult<Vec<Vec<Vec<u8>>>> {
+    prove_with_mode(values, indices, AccumulatorMode::Legacy)
+}
+
+/// Generates a proof for a subset of values in a given hashing mode (see `prove`).
+///
+/// # Arguments
+/// * `values` - Complete sequence of values
+/// * `indices` - Indices of values to include in the proof
+/// * `mode` - Hashing scheme of the leaves and nodes
+///
+/// # Returns
+/// A vector of proof components, or the errors of `prove`
+pub fn prove_with_mode(
+    values: &[Vec<u8>],
+    indices: &[u32],
+    mode: AccumulatorMode,
+) -> SoxResult<Vec<Vec<Vec<u8>>>> {
+    prove_with_hash::<Keccak256Hasher>(values, indices, mode)
+}
+
+/// Generates a proof for a subset of values with a given hash function and hashing mode (see
+/// `prove`).
+///
+/// # Arguments
+/// * `values` - Complete sequence of values
+/// * `indices` - Indices of values to include in the proof
+/// * `mode` - Hashing scheme of the leaves and nodes
+///
+/// # Returns
+/// A vector of proof components, or the errors of `prove`
+pub fn prove_with_hash<H: AccumulatorHash>(
+    values: &[Vec<u8>],
+    indices: &[u32],
+    mode: AccumulatorMode,
+) -> SoxResult<Vec<Vec<Vec<u8>>>> {
     if values.len() < indices.len() {
-        die(&format!(
-            "Number of indices ({}) is greater than number of values ({})",
+        return Err(SoxError::Index(format!(
+            "number of indices ({}) is greater than number of values ({})",
             indices.len(),
             values.len()
-        ));
+        )));
     }
-    if indices.len() == 0 || values.len() == 0 {
-        return vec![];
+    if let Some(&idx) = indices.iter().find(|&&i| i as usize >= values.len()) {
+        return Err(SoxError::Index(format!(
+            "index {idx} is out of bounds ({} values)",
+            values.len()
+        )));
+    }
+    if indices.is_empty() || values.is_empty() {
+        return Ok(vec![]);
     }
     let mut a = indices.to_vec();
     a.sort();
 
     let mut proof: Vec<Vec<Vec<u8>>> = vec![];
 
-    let mut curr_layer: Vec<Vec<u8>> = values.iter().map(hash).collect();
+    let mut curr_layer: Vec<Vec<u8>> = values
+        .iter()
+        .map(|v| mode.leaf_hash_with::<H>(v).to_vec())
+        .collect();
 
     while curr_layer.len() > 1 {
         let mut b: Vec<(u32, u32)> = vec![];
@@ -180,11 +397,11 @@ pub fn prove(values: &[Vec<u8>], indices: &[u32]) -> Vec<Vec<Vec<u8>>> {
                 .collect(),
         );
 
-        curr_layer = compute_next_layer(curr_layer);
+        curr_layer = compute_next_layer::<H>(curr_layer, mode);
         a = b.iter().map(|p| p.0 >> 1).collect();
     }
 
-    proof
+    Ok(proof)
 }
 
 /// Generates an extension proof for a sequence of values
@@ -193,181 +410,1663 @@ pub fn prove(values: &[Vec<u8>], indices: &[u32]) -> Vec<Vec<Vec<u8>>> {
 /// * `values` - Sequence of values to generate the proof for
 ///
 /// # Returns
-/// A vector of proof components demonstrating correct extension
-pub fn prove_ext(values: &[Vec<u8>]) -> Vec<Vec<Vec<u8>>> {
-    prove(values, &vec![(values.len() - 1) as u32])
+/// A vector of proof components demonstrating correct extension, or `SoxError::Index` if
+/// `values` is empty
+pub fn prove_ext(values: &[Vec<u8>]) -> SoxResult<Vec<Vec<Vec<u8>>>> {
+    prove_ext_with_mode(values, AccumulatorMode::Legacy)
 }
 
-/// Converts a proof to a JavaScript array
+/// Generates an extension proof in a given hashing mode (see `prove_ext`).
 ///
 /// # Arguments
-/// * `proof` - Vector of proof components
+/// * `values` - Sequence of values to generate the proof for
+/// * `mode` - Hashing scheme of the leaves and nodes
 ///
 /// # Returns
-/// A JavaScript Array containing the proof components as Uint8Arrays
-pub fn proof_to_js_array(proof: Vec<Vec<Vec<u8>>>) -> Array {
-    Array::from_iter(
-        proof
-            .iter()
-            .map(|l| Array::from_iter(l.iter().map(|v| Uint8Array::from(v.as_slice())))),
-    )
+/// A vector of proof components, or `SoxError::Index` if `values` is empty
+pub fn prove_ext_with_mode(
+    values: &[Vec<u8>],
+    mode: AccumulatorMode,
+) -> SoxResult<Vec<Vec<Vec<u8>>>> {
+    prove_ext_with_hash::<Keccak256Hasher>(values, mode)
 }
 
-/// JavaScript wrapper of the accumulator function
+/// Generates an extension proof with a given hash function and hashing mode (see `prove_ext`).
 ///
 /// # Arguments
-/// * `values` - Array of Uint8Arrays to accumulate
+/// * `values` - Sequence of values to generate the proof for
+/// * `mode` - Hashing scheme of the leaves and nodes
 ///
 /// # Returns
-/// Accumulated value as bytes
-#[wasm_bindgen]
-pub fn acc_js(values: Vec<Uint8Array>) -> Vec<u8> {
-    let values_vec: Vec<Vec<u8>> = values.iter().map(uint8_array_to_vec_u8).collect();
-    acc(&values_vec)
+/// A vector of proof components, or `SoxError::Index` if `values` is empty
+pub fn prove_ext_with_hash<H: AccumulatorHash>(
+    values: &[Vec<u8>],
+    mode: AccumulatorMode,
+) -> SoxResult<Vec<Vec<Vec<u8>>>> {
+    if values.is_empty() {
+        return Err(SoxError::Index("cannot prove the extension of an empty sequence".into()));
+    }
+    prove_with_hash::<H>(values, &[(values.len() - 1) as u32], mode)
 }
 
-/// JavaScript wrapper of the prove function
+/// Hashes a value into a leaf of the accumulator. These are the `valuesKeccak` expected by the
+/// verifiers.
 ///
 /// # Arguments
-/// * `values` - Array of Uint8Arrays containing all values in the tree
-/// * `indices` - Array of indices for values to include in proof
+/// * `value` - Value to hash, right-padded with zeros (or truncated) to 64 bytes
 ///
 /// # Returns
-/// Array of arrays of Uint8Arrays containing the proof layers
-#[wasm_bindgen]
-pub fn prove_js(values: Vec<Uint8Array>, indices: Array) -> Array {
-    let values_vec: Vec<Vec<u8>> = values.iter().map(uint8_array_to_vec_u8).collect();
-    let indices_u32 = indices
-        .iter()
-        .map(|i| i.as_f64().unwrap() as u32)
-        .collect::<Vec<u32>>();
-    let proof = prove(&values_vec, &indices_u32);
-    proof_to_js_array(proof)
+/// The 32-byte leaf hash
+pub fn leaf_hash(value: &[u8]) -> [u8; 32] {
+    hash_block64(value)
 }
 
-/// JavaScript wrapper of the prove_ext function
+/// Sorts the indices in increasing order, moving the values along. Port of `sortAligned` from
+/// `AccumulatorSOX.sol`; the sort is stable like its insertion sort.
 ///
 /// # Arguments
-/// * `values` - Array of Uint8Arrays containing the sequence of values
+/// * `indices` - Indices of the values
+/// * `values` - Values, aligned with `indices`
 ///
 /// # Returns
-/// Array of Uint8Arrays containing the extension proof components
-#[wasm_bindgen]
-pub fn prove_ext_js(values: Vec<Uint8Array>) -> Array {
-    let values_vec: Vec<Vec<u8>> = values.iter().map(uint8_array_to_vec_u8).collect();
-    let proof = prove_ext(&values_vec);
-    proof_to_js_array(proof)
+/// The sorted indices and the values in the same order, or `SoxError::InvalidInput` if the two
+/// slices have different lengths
+pub fn sort_aligned<T: Clone>(indices: &[u32], values: &[T]) -> SoxResult<(Vec<u32>, Vec<T>)> {
+    if indices.len() != values.len() {
+        return Err(SoxError::InvalidInput(format!(
+            "mismatched input lengths: {} indices for {} values",
+            indices.len(),
+            values.len()
+        )));
+    }
+    let mut pairs: Vec<(u32, T)> = indices
+        .iter()
+        .copied()
+        .zip(values.iter().cloned())
+        .collect();
+    pairs.sort_by_key(|pair| pair.0);
+
+    Ok(pairs.into_iter().unzip())
 }
 
-// Computes the root of a Merkle tree given the leaf hashes
-fn compute_merkle_root(hashes: Vec<Vec<u8>>) -> Vec<u8> {
-    let mut curr_layer = hashes;
+// Converts a list of 32-byte values coming from outside, `None` if one of them has another length
+// (the contract would reject it when decoding its arguments)
+fn to_bytes32<T: AsRef<[u8]>>(values: &[T]) -> Option<Vec<[u8; 32]>> {
+    values.iter().map(|v| v.as_ref().try_into().ok()).collect()
+}
 
-    while curr_layer.len() > 1 {
-        curr_layer = compute_next_layer(curr_layer)
-    }
+/// Verifies a multi-value proof of the accumulator. Port of `verify` from `AccumulatorSOX.sol`:
+/// the indices are sorted with `sort_aligned`, each proof layer is consumed from its last element,
+/// and the cases where the contract reverts return `false`.
+///
+/// # Arguments
+/// * `root` - Expected accumulator value
+/// * `indices` - Indices of the proven values, in any order
+/// * `values_keccak` - Leaf hashes of the proven values (see `leaf_hash`), aligned with `indices`
+/// * `proof` - Proof layers, as returned by `prove`
+///
+/// # Returns
+/// `true` if the proof reconstructs `root`, `false` otherwise. An empty set of indices is always
+/// valid.
+pub fn verify<V: AsRef<[u8]>, P: AsRef<[u8]>>(
+    root: &[u8],
+    indices: &[u32],
+    values_keccak: &[V],
+    proof: &[Vec<P>],
+) -> bool {
+    verify_with_mode(root, indices, values_keccak, proof, AccumulatorMode::Legacy)
+}
 
-    curr_layer.remove(0)
+/// Verifies a multi-value proof in a given hashing mode (see `verify`).
+///
+/// # Arguments
+/// * `root` - Expected accumulator value
+/// * `indices` - Indices of the proven values, in any order
+/// * `values_keccak` - Leaf hashes of the proven values (see `AccumulatorMode::leaf_hash`)
+/// * `proof` - Proof layers, as returned by `prove_with_mode`
+/// * `mode` - Hashing scheme of the nodes
+///
+/// # Returns
+/// `true` if the proof reconstructs `root`, `false` otherwise
+pub fn verify_with_mode<V: AsRef<[u8]>, P: AsRef<[u8]>>(
+    root: &[u8],
+    indices: &[u32],
+    values_keccak: &[V],
+    proof: &[Vec<P>],
+    mode: AccumulatorMode,
+) -> bool {
+    verify_with_hash::<Keccak256Hasher, V, P>(root, indices, values_keccak, proof, mode)
 }
 
-// Computes the layer above in a Merkle tree. If the layer has odd number of nodes, the last one is
-// copied as-is.
-// FIXME could introduce issues when using it as proofs. E.g [1,2,3,4] and [1,2,h(3)||h(4)] lead to
-// the same root !!
-fn compute_next_layer(curr_layer: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
-    (0..curr_layer.len())
-        .step_by(2)
-        .collect::<Vec<_>>()
-        .par_iter()
-        .map(|&i| {
-            if i < curr_layer.len() - 1 {
-                concat_and_hash(&curr_layer[i], &curr_layer[i + 1])
+/// Verifies a multi-value proof with a given hash function and hashing mode (see `verify`).
+///
+/// # Arguments
+/// * `root` - Expected accumulator value
+/// * `indices` - Indices of the proven values, in any order
+/// * `values_keccak` - Leaf hashes of the proven values (see `AccumulatorMode::leaf_hash_with`)
+/// * `proof` - Proof layers, as returned by `prove_with_hash`
+/// * `mode` - Hashing scheme of the nodes
+///
+/// # Returns
+/// `true` if the proof reconstructs `root`, `false` otherwise
+pub fn verify_with_hash<H: AccumulatorHash, V: AsRef<[u8]>, P: AsRef<[u8]>>(
+    root: &[u8],
+    indices: &[u32],
+    values_keccak: &[V],
+    proof: &[Vec<P>],
+    mode: AccumulatorMode,
+) -> bool {
+    if indices.len() != values_keccak.len() {
+        return false;
+    }
+    if indices.is_empty() {
+        return true;
+    }
+    let (Some(values), Some(proof)) = (
+        to_bytes32(values_keccak),
+        proof
+            .iter()
+            .map(|l| to_bytes32(l))
+            .collect::<Option<Vec<_>>>(),
+    ) else {
+        return false;
+    };
+    let Ok((mut indices, mut values)) = sort_aligned(indices, &values) else {
+        return false;
+    };
+
+    for layer in &proof {
+        let b: Vec<(u32, u32)> = indices
+            .iter()
+            .map(|&idx| {
+                let neighbor = get_neighbor_idx(&idx);
+                (u32::min(idx, neighbor), u32::max(idx, neighbor))
+            })
+            .collect();
+        let pruned_len = 1 + b.windows(2).filter(|w| w[0].0 != w[1].0).count();
+
+        let mut next_indices = Vec::with_capacity(pruned_len);
+        let mut next_values = Vec::with_capacity(pruned_len);
+        let mut remaining = layer.len();
+        let mut i = 0;
+        while i < b.len() {
+            if i + 1 < b.len() && b[i].0 == b[i + 1].0 {
+                // both children are known, hash them together and skip the sibling
+                next_values.push(mode.node_hash_with::<H>(&values[i], &values[i + 1]));
+                i += 1;
+            } else if remaining > 0 {
+                let sibling = &layer[remaining - 1];
+                if indices[i] % 2 == 1 {
+                    next_values.push(mode.node_hash_with::<H>(sibling, &values[i]));
+                } else {
+                    next_values.push(mode.node_hash_with::<H>(&values[i], sibling));
+                }
+                remaining -= 1;
             } else {
-                curr_layer[i].clone()
+                // lonely node, promoted as-is to the next layer
+                next_values.push(values[i]);
             }
-        })
-        .collect()
-}
+            next_indices.push(indices[i] >> 1);
+            i += 1;
 
-// Returns the index of the neighbor node
-fn get_neighbor_idx(index: &u32) -> u32 {
-    if index % 2 == 0 {
-        index + 1
-    } else {
-        index - 1
+            // out-of-bounds write in the contract, e.g. with repeated indices
+            if next_values.len() > pruned_len {
+                return false;
+            }
+        }
+
+        indices = next_indices;
+        values = next_values;
     }
+
+    values.len() == 1 && values[0] == root
 }
 
-// Concatenates two 32-byte vectors and hashes the result. Panics if one of the vectors is not 32
-// bytes long
-fn concat_and_hash(left: &Vec<u8>, right: &Vec<u8>) -> Vec<u8> {
-    assert_eq!(left.len(), 32);
-    assert_eq!(right.len(), 32);
+/// Verifies that the proof of the last value of a sequence also proves the accumulator of the
+/// sequence without that value. Port of `verifyPrevious` from `AccumulatorSOX.sol`.
+///
+/// # Arguments
+/// * `prev_root` - Accumulator of the sequence without its last value
+/// * `proof` - Extension proof, as returned by `prove_ext`
+///
+/// # Returns
+/// `true` if the proof rebuilds `prev_root`. An empty proof is only valid for a zero `prev_root`.
+pub fn verify_previous<P: AsRef<[u8]>>(prev_root: &[u8], proof: &[Vec<P>]) -> bool {
+    verify_previous_with_mode(prev_root, proof, AccumulatorMode::Legacy)
+}
 
-    let mut hasher = Keccak256::new();
-    hasher.update(left);
-    hasher.update(right);
-    hasher.finalize().to_vec()
+/// Verifies an extension proof against the previous accumulator in a given hashing mode (see
+/// `verify_previous`).
+///
+/// # Arguments
+/// * `prev_root` - Accumulator of the sequence without its last value
+/// * `proof` - Extension proof, as returned by `prove_ext_with_mode`
+/// * `mode` - Hashing scheme of the nodes
+///
+/// # Returns
+/// `true` if the proof rebuilds `prev_root`
+pub fn verify_previous_with_mode<P: AsRef<[u8]>>(
+    prev_root: &[u8],
+    proof: &[Vec<P>],
+    mode: AccumulatorMode,
+) -> bool {
+    verify_previous_with_hash::<Keccak256Hasher, P>(prev_root, proof, mode)
 }
 
-// Computes SHA256' (single compression without padding) of a 64-byte block.
-// Values shorter than 64 bytes are right-padded with zeros; longer values are truncated.
-fn hash(data: &Vec<u8>) -> Vec<u8> {
-    hash_block64(data).to_vec()
+/// Verifies an extension proof against the previous accumulator with a given hash function and
+/// hashing mode (see `verify_previous`).
+///
+/// # Arguments
+/// * `prev_root` - Accumulator of the sequence without its last value
+/// * `proof` - Extension proof, as returned by `prove_ext_with_hash`
+/// * `mode` - Hashing scheme of the nodes
+///
+/// # Returns
+/// `true` if the proof rebuilds `prev_root`
+pub fn verify_previous_with_hash<H: AccumulatorHash, P: AsRef<[u8]>>(
+    prev_root: &[u8],
+    proof: &[Vec<P>],
+    mode: AccumulatorMode,
+) -> bool {
+    let Some(proof) = proof
+        .iter()
+        .map(|l| to_bytes32(l))
+        .collect::<Option<Vec<_>>>()
+    else {
+        return false;
+    };
+    let mut nodes = proof.iter().flat_map(|layer| layer.iter().rev());
+    let Some(&first) = nodes.next() else {
+        return prev_root == [0u8; 32];
+    };
+
+    let computed_root = nodes.fold(first, |root, node| mode.node_hash_with::<H>(node, &root));
+    computed_root == prev_root
 }
 
-// Optimized hashing for fixed 64-byte semantics, returning array to reduce reallocations.
-// Accepts both Vec and slice for flexibility
-pub(crate) fn hash_block64(data: &[u8]) -> [u8; 32] {
-    let mut block = [0u8; 64];
-    if data.len() >= 64 {
-        block.copy_from_slice(&data[..64]);
-    } else {
-        block[..data.len()].copy_from_slice(data);
-    }
-    let mut hasher = Keccak256::new();
-    hasher.update(&block);
-    hasher.finalize().into()
+/// Verifies an extension proof. Port of `verifyExt` from `AccumulatorSOX.sol`.
+///
+/// # Arguments
+/// * `i` - Index of the added value
+/// * `prev_root` - Accumulator before the value was added
+/// * `curr_root` - Accumulator after the value was added
+/// * `added_val_keccak` - Leaf hash of the added value (see `leaf_hash`)
+/// * `proof` - Extension proof, as returned by `prove_ext`
+///
+/// # Returns
+/// `true` if the proof is valid. For the first gate (`i` equal to 0 or 1 with a zero
+/// `prev_root`), only `curr_root` is checked, with the value at index 0.
+pub fn verify_ext<P: AsRef<[u8]>>(
+    i: u32,
+    prev_root: &[u8],
+    curr_root: &[u8],
+    added_val_keccak: &[u8],
+    proof: &[Vec<P>],
+) -> bool {
+    verify_ext_with_mode(
+        i,
+        prev_root,
+        curr_root,
+        added_val_keccak,
+        proof,
+        AccumulatorMode::Legacy,
+    )
 }
 
-/// Optimized accumulator when every value is treated as a 64-byte block (padded/tronqué).
-/// Returns the Merkle root as 32 bytes.
-/// Uses parallel processing for maximum performance.
-pub fn acc_fixed64(values: &[Vec<u8>]) -> Vec<u8> {
-    if values.is_empty() {
-        return vec![];
-    }
-    if values.len() == 1 {
-        return hash_block64(&values[0]).to_vec();
+/// Verifies an extension proof in a given hashing mode (see `verify_ext`).
+///
+/// # Arguments
+/// * `i` - Index of the added value
+/// * `prev_root` - Accumulator before the value was added
+/// * `curr_root` - Accumulator after the value was added
+/// * `added_val_keccak` - Leaf hash of the added value (see `AccumulatorMode::leaf_hash`)
+/// * `proof` - Extension proof, as returned by `prove_ext_with_mode`
+/// * `mode` - Hashing scheme of the nodes
+///
+/// # Returns
+/// `true` if the proof is valid
+pub fn verify_ext_with_mode<P: AsRef<[u8]>>(
+    i: u32,
+    prev_root: &[u8],
+    curr_root: &[u8],
+    added_val_keccak: &[u8],
+    proof: &[Vec<P>],
+    mode: AccumulatorMode,
+) -> bool {
+    verify_ext_with_hash::<Keccak256Hasher, P>(
+        i,
+        prev_root,
+        curr_root,
+        added_val_keccak,
+        proof,
+        mode,
+    )
+}
+
+/// Verifies an extension proof with a given hash function and hashing mode (see `verify_ext`).
+///
+/// # Arguments
+/// * `i` - Index of the added value
+/// * `prev_root` - Accumulator before the value was added
+/// * `curr_root` - Accumulator after the value was added
+/// * `added_val_keccak` - Leaf hash of the added value (see `AccumulatorMode::leaf_hash_with`)
+/// * `proof` - Extension proof, as returned by `prove_ext_with_hash`
+/// * `mode` - Hashing scheme of the nodes
+///
+/// # Returns
+/// `true` if the proof is valid
+pub fn verify_ext_with_hash<H: AccumulatorHash, P: AsRef<[u8]>>(
+    i: u32,
+    prev_root: &[u8],
+    curr_root: &[u8],
+    added_val_keccak: &[u8],
+    proof: &[Vec<P>],
+    mode: AccumulatorMode,
+) -> bool {
+    if (i == 0 || i == 1) && prev_root == [0u8; 32] {
+        return verify_with_hash::<H, _, P>(curr_root, &[0], &[added_val_keccak], proof, mode);
     }
 
-    // Parallel hash of all leaves
-    let mut layer: Vec<[u8; 32]> = values.par_iter().map(|v| hash_block64(v)).collect();
+    verify_with_hash::<H, _, P>(curr_root, &[i], &[added_val_keccak], proof, mode)
+        && verify_previous_with_hash::<H, P>(prev_root, proof, mode)
+}
 
-    // Parallel computation of each layer
-    // CRITICAL: Use indexed parallel iteration to preserve order deterministically
-    while layer.len() > 1 {
-        let layer_ref = &layer; // Create reference for closure
-        let indices: Vec<usize> = (0..layer_ref.len()).step_by(2).collect();
-        let next: Vec<[u8; 32]> = indices
-            .into_par_iter()
-            .map(|i| {
-                if i + 1 < layer_ref.len() {
+/// Generates a consistency proof showing that `acc(&values[..old_len])` is the accumulator of a
+/// prefix of `values`, in the style of the consistency proofs of Certificate Transparency
+/// (RFC 6962). The tree of `acc` has the same shape as the CT tree, so the proofs are the same
+/// lists of nodes.
+///
+/// # Arguments
+/// * `values` - Sequence of values
+/// * `old_len` - Length of the prefix, at least 1
+///
+/// # Returns
+/// The nodes of the proof, or `SoxError::Index` if `old_len` is not between 1 and the number of
+/// values
+pub fn prove_consistency(values: &[Vec<u8>], old_len: usize) -> SoxResult<Vec<Vec<u8>>> {
+    prove_consistency_with_mode(values, old_len, AccumulatorMode::Legacy)
+}
+
+/// Generates a consistency proof in a given hashing mode (see `prove_consistency`).
+///
+/// # Arguments
+/// * `values` - Sequence of values
+/// * `old_len` - Length of the prefix, at least 1
+/// * `mode` - Hashing scheme of the leaves and nodes
+///
+/// # Returns
+/// The nodes of the proof, or `SoxError::Index` if `old_len` is not between 1 and the number of
+/// values
+pub fn prove_consistency_with_mode(
+    values: &[Vec<u8>],
+    old_len: usize,
+    mode: AccumulatorMode,
+) -> SoxResult<Vec<Vec<u8>>> {
+    prove_consistency_with_hash::<Keccak256Hasher>(values, old_len, mode)
+}
+
+/// Generates a consistency proof with a given hash function and hashing mode (see
+/// `prove_consistency`).
+///
+/// # Arguments
+/// * `values` - Sequence of values
+/// * `old_len` - Length of the prefix, at least 1
+/// * `mode` - Hashing scheme of the leaves and nodes
+///
+/// # Returns
+/// The nodes of the proof, or `SoxError::Index` if `old_len` is not between 1 and the number of
+/// values
+pub fn prove_consistency_with_hash<H: AccumulatorHash>(
+    values: &[Vec<u8>],
+    old_len: usize,
+    mode: AccumulatorMode,
+) -> SoxResult<Vec<Vec<u8>>> {
+    if values.is_empty() {
+        return Err(SoxError::Index(
+            "cannot prove the consistency of an empty sequence".into(),
+        ));
+    }
+    let tree = MerkleTree::<H>::with_hash(values, mode)?;
+    let proof = tree.prove_consistency(old_len, values.len())?;
+    Ok(proof.iter().map(|node| node.to_vec()).collect())
+}
+
+/// Verifies a consistency proof: checks that `old_root` is the accumulator of the first `old_len`
+/// values of a sequence of `new_len` values accumulated in `new_root`. This is the verification
+/// algorithm of RFC 9162 (section 2.1.4.2).
+///
+/// # Arguments
+/// * `old_len` - Length of the prefix
+/// * `new_len` - Length of the sequence
+/// * `old_root` - Accumulator of the prefix
+/// * `new_root` - Accumulator of the sequence
+/// * `proof` - Nodes of the proof, as returned by `prove_consistency`
+///
+/// # Returns
+/// `true` if the proof is valid. Equal lengths require equal roots and an empty proof, and an
+/// empty prefix is never valid.
+pub fn verify_consistency<P: AsRef<[u8]>>(
+    old_len: usize,
+    new_len: usize,
+    old_root: &[u8],
+    new_root: &[u8],
+    proof: &[P],
+) -> bool {
+    verify_consistency_with_mode(
+        old_len,
+        new_len,
+        old_root,
+        new_root,
+        proof,
+        AccumulatorMode::Legacy,
+    )
+}
+
+/// Verifies a consistency proof in a given hashing mode (see `verify_consistency`).
+///
+/// # Arguments
+/// * `old_len` - Length of the prefix
+/// * `new_len` - Length of the sequence
+/// * `old_root` - Accumulator of the prefix
+/// * `new_root` - Accumulator of the sequence
+/// * `proof` - Nodes of the proof, as returned by `prove_consistency_with_mode`
+/// * `mode` - Hashing scheme of the nodes
+///
+/// # Returns
+/// `true` if the proof is valid
+pub fn verify_consistency_with_mode<P: AsRef<[u8]>>(
+    old_len: usize,
+    new_len: usize,
+    old_root: &[u8],
+    new_root: &[u8],
+    proof: &[P],
+    mode: AccumulatorMode,
+) -> bool {
+    verify_consistency_with_hash::<Keccak256Hasher, P>(
+        old_len, new_len, old_root, new_root, proof, mode,
+    )
+}
+
+/// Verifies a consistency proof with a given hash function and hashing mode (see
+/// `verify_consistency`).
+///
+/// # Arguments
+/// * `old_len` - Length of the prefix
+/// * `new_len` - Length of the sequence
+/// * `old_root` - Accumulator of the prefix
+/// * `new_root` - Accumulator of the sequence
+/// * `proof` - Nodes of the proof, as returned by `prove_consistency_with_hash`
+/// * `mode` - Hashing scheme of the nodes
+///
+/// # Returns
+/// `true` if the proof is valid
+pub fn verify_consistency_with_hash<H: AccumulatorHash, P: AsRef<[u8]>>(
+    old_len: usize,
+    new_len: usize,
+    old_root: &[u8],
+    new_root: &[u8],
+    proof: &[P],
+    mode: AccumulatorMode,
+) -> bool {
+    let Some(proof) = to_bytes32(proof) else {
+        return false;
+    };
+    let Ok(old_root) = <[u8; 32]>::try_from(old_root) else {
+        return false;
+    };
+    if old_len == 0 || old_len > new_len {
+        return false;
+    }
+    if old_len == new_len {
+        return proof.is_empty() && old_root == new_root;
+    }
+
+    // When the prefix is a complete subtree, its root is the first node of the path and is not
+    // part of the proof
+    let mut path = proof.into_iter();
+    let first = if old_len.is_power_of_two() {
+        old_root
+    } else {
+        match path.next() {
+            Some(node) => node,
+            None => return false,
+        }
+    };
+
+    let mut old_idx = old_len - 1;
+    let mut new_idx = new_len - 1;
+    while old_idx & 1 == 1 {
+        old_idx >>= 1;
+        new_idx >>= 1;
+    }
+
+    let (mut old_acc, mut new_acc) = (first, first);
+    for node in path {
+        if new_idx == 0 {
+            return false;
+        }
+        if old_idx & 1 == 1 || old_idx == new_idx {
+            old_acc = mode.node_hash_with::<H>(&node, &old_acc);
+            new_acc = mode.node_hash_with::<H>(&node, &new_acc);
+            while old_idx & 1 == 0 && old_idx != 0 {
+                old_idx >>= 1;
+                new_idx >>= 1;
+            }
+        } else {
+            new_acc = mode.node_hash_with::<H>(&new_acc, &node);
+        }
+        old_idx >>= 1;
+        new_idx >>= 1;
+    }
+
+    new_idx == 0 && old_acc == old_root && new_acc == new_root
+}
+
+// Converts a JavaScript array of numbers into indices
+fn js_array_to_indices(indices: &Array) -> SoxResult<Vec<u32>> {
+    indices
+        .iter()
+        .map(|i| {
+            i.as_f64()
+                .map(|f| f as u32)
+                .ok_or_else(|| SoxError::InvalidInput("indices must be numbers".into()))
+        })
+        .collect()
+}
+
+// Converts a JavaScript array of layers (arrays of Uint8Arrays) into a proof, the inverse of
+// `proof_to_js_array`
+pub(crate) fn js_array_to_proof(proof: &Array) -> Vec<Vec<Vec<u8>>> {
+    proof
+        .iter()
+        .map(|layer| {
+            Array::from(&layer)
+                .iter()
+                .map(|node| Uint8Array::new(&node).to_vec())
+                .collect()
+        })
+        .collect()
+}
+
+/// Converts a proof to a JavaScript array
+///
+/// # Arguments
+/// * `proof` - Proof layers, each layer being a list of hashes
+///
+/// # Returns
+/// A JavaScript Array containing the proof components as Uint8Arrays
+pub fn proof_to_js_array<T: AsRef<[u8]>>(proof: &[Vec<T>]) -> Array {
+    Array::from_iter(
+        proof
+            .iter()
+            .map(|l| Array::from_iter(l.iter().map(|v| Uint8Array::from(v.as_ref())))),
+    )
+}
+
+/// JavaScript wrapper of the accumulator function
+///
+/// # Arguments
+/// * `values` - Array of Uint8Arrays to accumulate
+///
+/// # Returns
+/// Accumulated value as bytes
+#[wasm_bindgen]
+pub fn acc_js(values: Vec<Uint8Array>) -> Vec<u8> {
+    let values_vec: Vec<Vec<u8>> = values.iter().map(uint8_array_to_vec_u8).collect();
+    acc(&values_vec)
+}
+
+/// JavaScript wrapper of the prove function
+///
+/// # Arguments
+/// * `values` - Array of Uint8Arrays containing all values in the tree
+/// * `indices` - Array of indices for values to include in proof
+///
+/// # Returns
+/// Array of arrays of Uint8Arrays containing the proof layers
+#[wasm_bindgen]
+pub fn prove_js(values: Vec<Uint8Array>, indices: Array) -> SoxResult<Array> {
+    let values_vec: Vec<Vec<u8>> = values.iter().map(uint8_array_to_vec_u8).collect();
+    let indices_u32 = js_array_to_indices(&indices)?;
+    let proof = prove(&values_vec, &indices_u32)?;
+    Ok(proof_to_js_array(&proof))
+}
+
+/// JavaScript wrapper of the prove_ext function
+///
+/// # Arguments
+/// * `values` - Array of Uint8Arrays containing the sequence of values
+///
+/// # Returns
+/// Array of Uint8Arrays containing the extension proof components
+#[wasm_bindgen]
+pub fn prove_ext_js(values: Vec<Uint8Array>) -> SoxResult<Array> {
+    let values_vec: Vec<Vec<u8>> = values.iter().map(uint8_array_to_vec_u8).collect();
+    let proof = prove_ext(&values_vec)?;
+    Ok(proof_to_js_array(&proof))
+}
+
+/// JavaScript wrapper of the leaf_hash function
+///
+/// # Arguments
+/// * `value` - Value to hash
+///
+/// # Returns
+/// The 32-byte leaf hash of the value
+#[wasm_bindgen]
+pub fn leaf_hash_js(value: &[u8]) -> Vec<u8> {
+    leaf_hash(value).to_vec()
+}
+
+/// JavaScript wrapper of the verify function
+///
+/// # Arguments
+/// * `root` - Expected accumulator value
+/// * `indices` - Array of indices of the proven values
+/// * `values_keccak` - Array of Uint8Arrays containing the leaf hashes of the proven values
+/// * `proof` - Array of arrays of Uint8Arrays containing the proof layers
+///
+/// # Returns
+/// true if the proof is valid, false otherwise
+#[wasm_bindgen]
+pub fn verify_js(
+    root: &[u8],
+    indices: Array,
+    values_keccak: Vec<Uint8Array>,
+    proof: Array,
+) -> SoxResult<bool> {
+    let values_vec: Vec<Vec<u8>> = values_keccak.iter().map(uint8_array_to_vec_u8).collect();
+    let indices_u32 = js_array_to_indices(&indices)?;
+    Ok(verify(
+        root,
+        &indices_u32,
+        &values_vec,
+        &js_array_to_proof(&proof),
+    ))
+}
+
+/// JavaScript wrapper of the verify_previous function
+///
+/// # Arguments
+/// * `prev_root` - Accumulator of the sequence without its last value
+/// * `proof` - Array of arrays of Uint8Arrays containing the extension proof
+///
+/// # Returns
+/// true if the proof is valid, false otherwise
+#[wasm_bindgen]
+pub fn verify_previous_js(prev_root: &[u8], proof: Array) -> bool {
+    verify_previous(prev_root, &js_array_to_proof(&proof))
+}
+
+/// JavaScript wrapper of the verify_ext function
+///
+/// # Arguments
+/// * `i` - Index of the added value
+/// * `prev_root` - Accumulator before the value was added
+/// * `curr_root` - Accumulator after the value was added
+/// * `added_val_keccak` - Leaf hash of the added value
+/// * `proof` - Array of arrays of Uint8Arrays containing the extension proof
+///
+/// # Returns
+/// true if the proof is valid, false otherwise
+#[wasm_bindgen]
+pub fn verify_ext_js(
+    i: u32,
+    prev_root: &[u8],
+    curr_root: &[u8],
+    added_val_keccak: &[u8],
+    proof: Array,
+) -> bool {
+    verify_ext(
+        i,
+        prev_root,
+        curr_root,
+        added_val_keccak,
+        &js_array_to_proof(&proof),
+    )
+}
+
+/// JavaScript wrapper of the acc_with_mode function
+///
+/// # Arguments
+/// * `values` - Array of Uint8Arrays to accumulate
+/// * `mode` - Hashing scheme of the leaves and nodes
+///
+/// # Returns
+/// Accumulated value as bytes
+#[wasm_bindgen]
+pub fn acc_with_mode_js(values: Vec<Uint8Array>, mode: AccumulatorMode) -> Vec<u8> {
+    let values_vec: Vec<Vec<u8>> = values.iter().map(uint8_array_to_vec_u8).collect();
+    acc_with_mode(&values_vec, mode)
+}
+
+/// JavaScript wrapper of the prove_with_mode function
+///
+/// # Arguments
+/// * `values` - Array of Uint8Arrays containing all values in the tree
+/// * `indices` - Array of indices for values to include in proof
+/// * `mode` - Hashing scheme of the leaves and nodes
+///
+/// # Returns
+/// Array of arrays of Uint8Arrays containing the proof layers
+#[wasm_bindgen]
+pub fn prove_with_mode_js(
+    values: Vec<Uint8Array>,
+    indices: Array,
+    mode: AccumulatorMode,
+) -> SoxResult<Array> {
+    let values_vec: Vec<Vec<u8>> = values.iter().map(uint8_array_to_vec_u8).collect();
+    let indices_u32 = js_array_to_indices(&indices)?;
+    let proof = prove_with_mode(&values_vec, &indices_u32, mode)?;
+    Ok(proof_to_js_array(&proof))
+}
+
+/// JavaScript wrapper of the prove_ext_with_mode function
+///
+/// # Arguments
+/// * `values` - Array of Uint8Arrays containing the sequence of values
+/// * `mode` - Hashing scheme of the leaves and nodes
+///
+/// # Returns
+/// Array of arrays of Uint8Arrays containing the extension proof layers
+#[wasm_bindgen]
+pub fn prove_ext_with_mode_js(values: Vec<Uint8Array>, mode: AccumulatorMode) -> SoxResult<Array> {
+    let values_vec: Vec<Vec<u8>> = values.iter().map(uint8_array_to_vec_u8).collect();
+    let proof = prove_ext_with_mode(&values_vec, mode)?;
+    Ok(proof_to_js_array(&proof))
+}
+
+/// JavaScript wrapper of `AccumulatorMode::leaf_hash`
+///
+/// # Arguments
+/// * `value` - Value to hash
+/// * `mode` - Hashing scheme of the leaves
+///
+/// # Returns
+/// The 32-byte leaf hash of the value
+#[wasm_bindgen]
+pub fn leaf_hash_with_mode_js(value: &[u8], mode: AccumulatorMode) -> Vec<u8> {
+    mode.leaf_hash(value).to_vec()
+}
+
+/// JavaScript wrapper of the verify_with_mode function
+///
+/// # Arguments
+/// * `root` - Expected accumulator value
+/// * `indices` - Array of indices of the proven values
+/// * `values_keccak` - Array of Uint8Arrays containing the leaf hashes of the proven values
+/// * `proof` - Array of arrays of Uint8Arrays containing the proof layers
+/// * `mode` - Hashing scheme of the nodes
+///
+/// # Returns
+/// true if the proof is valid, false otherwise
+#[wasm_bindgen]
+pub fn verify_with_mode_js(
+    root: &[u8],
+    indices: Array,
+    values_keccak: Vec<Uint8Array>,
+    proof: Array,
+    mode: AccumulatorMode,
+) -> SoxResult<bool> {
+    let values_vec: Vec<Vec<u8>> = values_keccak.iter().map(uint8_array_to_vec_u8).collect();
+    let indices_u32 = js_array_to_indices(&indices)?;
+    Ok(verify_with_mode(
+        root,
+        &indices_u32,
+        &values_vec,
+        &js_array_to_proof(&proof),
+        mode,
+    ))
+}
+
+/// JavaScript wrapper of the verify_ext_with_mode function
+///
+/// # Arguments
+/// * `i` - Index of the added value
+/// * `prev_root` - Accumulator before the value was added
+/// * `curr_root` - Accumulator after the value was added
+/// * `added_val_keccak` - Leaf hash of the added value
+/// * `proof` - Array of arrays of Uint8Arrays containing the extension proof
+/// * `mode` - Hashing scheme of the nodes
+///
+/// # Returns
+/// true if the proof is valid, false otherwise
+#[wasm_bindgen]
+pub fn verify_ext_with_mode_js(
+    i: u32,
+    prev_root: &[u8],
+    curr_root: &[u8],
+    added_val_keccak: &[u8],
+    proof: Array,
+    mode: AccumulatorMode,
+) -> bool {
+    verify_ext_with_mode(
+        i,
+        prev_root,
+        curr_root,
+        added_val_keccak,
+        &js_array_to_proof(&proof),
+        mode,
+    )
+}
+
+/// JavaScript wrapper of the prove_consistency function
+///
+/// # Arguments
+/// * `values` - Array of Uint8Arrays containing the sequence of values
+/// * `old_len` - Length of the prefix, at least 1
+///
+/// # Returns
+/// Array of Uint8Arrays containing the nodes of the proof
+#[wasm_bindgen]
+pub fn prove_consistency_js(values: Vec<Uint8Array>, old_len: usize) -> SoxResult<Array> {
+    let values_vec: Vec<Vec<u8>> = values.iter().map(uint8_array_to_vec_u8).collect();
+    let proof = prove_consistency(&values_vec, old_len)?;
+    Ok(Array::from_iter(
+        proof.iter().map(|n| Uint8Array::from(&n[..])),
+    ))
+}
+
+/// JavaScript wrapper of the verify_consistency function
+///
+/// # Arguments
+/// * `old_len` - Length of the prefix
+/// * `new_len` - Length of the sequence
+/// * `old_root` - Accumulator of the prefix
+/// * `new_root` - Accumulator of the sequence
+/// * `proof` - Array of Uint8Arrays containing the nodes of the proof
+///
+/// # Returns
+/// true if the proof is valid, false otherwise
+#[wasm_bindgen]
+pub fn verify_consistency_js(
+    old_len: usize,
+    new_len: usize,
+    old_root: &[u8],
+    new_root: &[u8],
+    proof: Vec<Uint8Array>,
+) -> bool {
+    let proof_vec: Vec<Vec<u8>> = proof.iter().map(uint8_array_to_vec_u8).collect();
+    verify_consistency(old_len, new_len, old_root, new_root, &proof_vec)
+}
+
+/// JavaScript wrapper of the verify_consistency_with_mode function
+///
+/// # Arguments
+/// * `old_len` - Length of the prefix
+/// * `new_len` - Length of the sequence
+/// * `old_root` - Accumulator of the prefix
+/// * `new_root` - Accumulator of the sequence
+/// * `proof` - Array of Uint8Arrays containing the nodes of the proof
+/// * `mode` - Hashing scheme of the nodes
+///
+/// # Returns
+/// true if the proof is valid, false otherwise
+#[wasm_bindgen]
+pub fn verify_consistency_with_mode_js(
+    old_len: usize,
+    new_len: usize,
+    old_root: &[u8],
+    new_root: &[u8],
+    proof: Vec<Uint8Array>,
+    mode: AccumulatorMode,
+) -> bool {
+    let proof_vec: Vec<Vec<u8>> = proof.iter().map(uint8_array_to_vec_u8).collect();
+    verify_consistency_with_mode(old_len, new_len, old_root, new_root, &proof_vec, mode)
+}
+
+// Computes the root of a Merkle tree given the leaf hashes
+fn compute_merkle_root<H: AccumulatorHash>(hashes: Vec<Vec<u8>>, mode: AccumulatorMode) -> Vec<u8> {
+    let mut curr_layer = hashes;
+
+    while curr_layer.len() > 1 {
+        curr_layer = compute_next_layer::<H>(curr_layer, mode)
+    }
+
+    curr_layer.remove(0)
+}
+
+// Computes the layer above in a Merkle tree. If the layer has odd number of nodes, the last one is
+// copied as-is.
+// FIXME could introduce issues when using it as proofs. E.g [1,2,3,4] and [1,2,h(3)||h(4)] lead to
+// the same root !!
+fn compute_next_layer<H: AccumulatorHash>(
+    curr_layer: Vec<Vec<u8>>,
+    mode: AccumulatorMode,
+) -> Vec<Vec<u8>> {
+    (0..curr_layer.len())
+        .step_by(2)
+        .collect::<Vec<_>>()
+        .par_iter()
+        .map(|&i| {
+            if i < curr_layer.len() - 1 {
+                concat_and_hash::<H>(&curr_layer[i], &curr_layer[i + 1], mode)
+            } else {
+                curr_layer[i].clone()
+            }
+        })
+        .collect()
+}
+
+// Returns the index of the neighbor node
+fn get_neighbor_idx(index: &u32) -> u32 {
+    index ^ 1
+}
+
+// Concatenates two 32-byte vectors and hashes the result. Panics if one of the vectors is not 32
+// bytes long
+fn concat_and_hash<H: AccumulatorHash>(
+    left: &[u8],
+    right: &[u8],
+    mode: AccumulatorMode,
+) -> Vec<u8> {
+    assert_eq!(left.len(), 32);
+    assert_eq!(right.len(), 32);
+
+    mode.node_hash_with::<H>(left, right).to_vec()
+}
+
+// Optimized hashing for fixed 64-byte semantics, returning array to reduce reallocations.
+// Accepts both Vec and slice for flexibility
+pub(crate) fn hash_block64(data: &[u8]) -> [u8; 32] {
+    Keccak256Hasher::hash_block64(data)
+}
+
+/// Optimized accumulator when every value is treated as a 64-byte block (padded/tronqué).
+/// Returns the Merkle root as 32 bytes.
+/// Uses parallel processing for maximum performance.
+pub fn acc_fixed64(values: &[Vec<u8>]) -> Vec<u8> {
+    acc_fixed64_with_hash::<Keccak256Hasher>(values)
+}
+
+/// `acc_fixed64` with a given hash function.
+///
+/// # Arguments
+/// * `values` - Values to accumulate, right-padded with zeros (or truncated) to 64 bytes
+///
+/// # Returns
+/// The 32-byte Merkle root, empty if there are no values
+pub fn acc_fixed64_with_hash<H: AccumulatorHash>(values: &[Vec<u8>]) -> Vec<u8> {
+    if values.is_empty() {
+        return vec![];
+    }
+    if values.len() == 1 {
+        return H::hash_block64(&values[0]).to_vec();
+    }
+
+    // Parallel hash of all leaves
+    let mut layer: Vec<[u8; 32]> = values.par_iter().map(|v| H::hash_block64(v)).collect();
+
+    // Parallel computation of each layer
+    // CRITICAL: Use indexed parallel iteration to preserve order deterministically
+    while layer.len() > 1 {
+        let layer_ref = &layer; // Create reference for closure
+        let indices: Vec<usize> = (0..layer_ref.len()).step_by(2).collect();
+        let next: Vec<[u8; 32]> = indices
+            .into_par_iter()
+            .map(|i| {
+                if i + 1 < layer_ref.len() {
                     // Pair exists: hash pair[i] and pair[i+1]
-                    let mut hasher = Keccak256::new();
-                    hasher.update(&layer_ref[i]);
-                    hasher.update(&layer_ref[i + 1]);
-                    hasher.finalize().into()
+                    H::hash_parts(&[&layer_ref[i], &layer_ref[i + 1]])
+                } else {
+                    // Odd element: copy as-is
+                    layer_ref[i]
+                }
+            })
+            .collect();
+        layer = next;
+    }
+
+    layer[0].to_vec()
+}
+
+/// Incremental equivalent of `acc_fixed64`. Leaves are pushed one at a time and only the roots of
+/// the complete subtrees seen so far are kept (O(log n) memory), so that large sequences can be
+/// accumulated without holding every value. The root is bit-identical to `acc_fixed64`, and thus
+/// to `acc`; pushing the IV followed by the 64-byte blocks gives `acc_ct(ct, 64)`. With another
+/// hash function `H`, the root is the one of `acc_fixed64_with_hash::<H>`.
+#[derive(Clone, Debug)]
+pub struct AccumulatorBuilder<H = Keccak256Hasher> {
+    // Roots of the complete subtrees with their height, from the largest to the smallest
+    frontier: Vec<([u8; 32], u32)>,
+    len: u64,
+    hash: PhantomData<H>,
+}
+
+impl<H> Default for AccumulatorBuilder<H> {
+    fn default() -> Self {
+        Self {
+            frontier: vec![],
+            len: 0,
+            hash: PhantomData,
+        }
+    }
+}
+
+impl AccumulatorBuilder {
+    /// Creates an empty builder
+    pub fn new() -> AccumulatorBuilder {
+        Self::default()
+    }
+}
+
+impl<H: AccumulatorHash> AccumulatorBuilder<H> {
+    /// Adds a value to the sequence.
+    ///
+    /// # Arguments
+    /// * `value` - Value to accumulate, right-padded with zeros (or truncated) to 64 bytes
+    pub fn push(&mut self, value: &[u8]) {
+        self.push_leaf(H::hash_block64(value));
+    }
+
+    /// Returns the number of values pushed so far
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns whether no value has been pushed yet
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Computes the accumulator of the values pushed so far. The builder can keep being used
+    /// afterwards.
+    ///
+    /// # Returns
+    /// The 32-byte root, or an empty vector if no value has been pushed, like `acc_fixed64`
+    pub fn root(&self) -> Vec<u8> {
+        // `acc_fixed64` copies the last node of an odd layer as-is, so the incomplete subtrees
+        // are merged from right to left
+        let mut nodes = self.frontier.iter().rev().map(|(hash, _)| *hash);
+        match nodes.next() {
+            None => vec![],
+            Some(last) => nodes
+                .fold(last, |right, left| H::hash_parts(&[&left, &right]))
+                .to_vec(),
+        }
+    }
+
+    /// Adds a value whose leaf hash (`H::hash_block64`, see `leaf_hash` for Keccak) has already
+    /// been computed, e.g. in parallel.
+    ///
+    /// # Arguments
+    /// * `leaf` - Leaf hash of the value
+    pub fn push_leaf(&mut self, leaf: [u8; 32]) {
+        // equal-height subtrees are merged like the carries of a binary counter
+        let mut node = (leaf, 0);
+        while let Some(&(left, height)) = self.frontier.last() {
+            if height != node.1 {
+                break;
+            }
+            self.frontier.pop();
+            node = (H::hash_parts(&[&left, &node.0]), height + 1);
+        }
+        self.frontier.push(node);
+        self.len += 1;
+    }
+}
+
+/// JavaScript handle of a Keccak-256 `AccumulatorBuilder`
+#[wasm_bindgen(js_name = AccumulatorBuilder)]
+#[derive(Clone, Debug, Default)]
+pub struct AccumulatorBuilderJs(AccumulatorBuilder);
+
+#[wasm_bindgen(js_class = AccumulatorBuilder)]
+impl AccumulatorBuilderJs {
+    /// Creates an empty builder
+    pub fn new() -> AccumulatorBuilderJs {
+        Self::default()
+    }
+
+    /// JavaScript wrapper of `push`
+    ///
+    /// # Arguments
+    /// * `value` - Value to accumulate, right-padded with zeros (or truncated) to 64 bytes
+    pub fn push(&mut self, value: &[u8]) {
+        self.0.push(value);
+    }
+
+    /// Returns the number of values pushed so far
+    pub fn len(&self) -> u64 {
+        self.0.len()
+    }
+
+    /// Returns whether no value has been pushed yet
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// JavaScript wrapper of `root`
+    pub fn root(&self) -> Vec<u8> {
+        self.0.root()
+    }
+}
+
+/// Read access to the layers of a Merkle tree built like `acc`, from the leaf hashes to the root.
+/// The roots of the prefixes of the sequence and the proofs are computed from the stored nodes
+/// only; they are identical to those of `acc`, `prove` and `prove_ext` applied on the same values.
+pub trait MerkleLayers {
+    /// Hash function of the nodes
+    type Hash: AccumulatorHash;
+
+    /// Returns the number of layers, the root layer included
+    fn num_layers(&self) -> usize;
+
+    /// Returns the nodes of a layer, layer 0 being the leaf hashes
+    fn layer(&self, level: usize) -> &[[u8; 32]];
+
+    /// Returns the hashing scheme of the nodes
+    fn mode(&self) -> AccumulatorMode {
+        AccumulatorMode::Legacy
+    }
+
+    /// Returns the root of the tree, equal to `acc(values)`
+    fn root(&self) -> [u8; 32] {
+        self.layer(self.num_layers() - 1)[0]
+    }
+
+    /// Returns the accumulator of the first `prefix_len` values, i.e. `acc(&values[..prefix_len])`,
+    /// in O(log n).
+    ///
+    /// # Arguments
+    /// * `prefix_len` - Length of the prefix, at least 1
+    ///
+    /// # Returns
+    /// The root of the prefix, or `SoxError::Index` if `prefix_len` is not between 1 and the
+    /// number of values
+    fn prefix_root(&self, prefix_len: usize) -> SoxResult<[u8; 32]> {
+        check_prefix_len(self, prefix_len, 1)?;
+        let edges = prefix_edges(self, prefix_len);
+        Ok(edges[edges.len() - 1])
+    }
+
+    /// Generates a proof for a subset of the values, like `prove(values, indices)`.
+    ///
+    /// # Arguments
+    /// * `indices` - Indices of the values to include in the proof
+    ///
+    /// # Returns
+    /// The proof layers, or `SoxError::Index` if there are more indices than values or if one of
+    /// the indices is out of bounds
+    fn prove(&self, indices: &[u32]) -> SoxResult<Vec<Vec<[u8; 32]>>> {
+        self.prove_prefix(self.layer(0).len(), indices)
+    }
+
+    /// Generates a proof for a subset of the first `prefix_len` values, like
+    /// `prove(&values[..prefix_len], indices)`.
+    ///
+    /// # Arguments
+    /// * `prefix_len` - Length of the proven prefix of the sequence
+    /// * `indices` - Indices of the values to include in the proof, all below `prefix_len`
+    ///
+    /// # Returns
+    /// The proof layers, or `SoxError::Index` if `prefix_len` is greater than the number of
+    /// values, or for the same errors as `prove`
+    fn prove_prefix(&self, prefix_len: usize, indices: &[u32]) -> SoxResult<Vec<Vec<[u8; 32]>>> {
+        check_prefix_len(self, prefix_len, 0)?;
+        if prefix_len < indices.len() {
+            return Err(SoxError::Index(format!(
+                "number of indices ({}) is greater than number of values ({prefix_len})",
+                indices.len()
+            )));
+        }
+        if let Some(&idx) = indices.iter().find(|&&i| i as usize >= prefix_len) {
+            return Err(SoxError::Index(format!(
+                "index {idx} is out of bounds ({prefix_len} values)"
+            )));
+        }
+        if indices.is_empty() || prefix_len == 0 {
+            return Ok(vec![]);
+        }
+
+        // Same walk as `prove`, on the layers of the tree of the prefix: every node is a node of
+        // the full tree except the last one of each layer, which is recomputed
+        let edges = prefix_edges(self, prefix_len);
+        let mut a = indices.to_vec();
+        a.sort();
+        let mut proof = vec![];
+        let mut width = prefix_len;
+        for (level, edge) in edges.iter().enumerate() {
+            if width <= 1 {
+                break;
+            }
+            let layer = self.layer(level);
+            let node = |i: u32| {
+                if i as usize == width - 1 {
+                    *edge
                 } else {
-                    // Odd element: copy as-is
-                    layer_ref[i]
+                    layer[i as usize]
                 }
-            })
-            .collect();
-        layer = next;
+            };
+
+            let mut b: Vec<(u32, u32)> = vec![];
+            let mut diff: Vec<u32> = vec![];
+            let mut i = 0;
+            while i < a.len() {
+                let idx = a[i];
+                let neighbor = get_neighbor_idx(&idx);
+                b.push((u32::min(idx, neighbor), u32::max(idx, neighbor)));
+                if i < a.len() - 1 && neighbor == a[i + 1] {
+                    i += 1;
+                }
+                if !a.contains(&neighbor) && (neighbor as usize) < width {
+                    diff.push(neighbor);
+                }
+                i += 1;
+            }
+
+            proof.push(diff.iter().rev().map(|&i| node(i)).collect());
+            a = b.iter().map(|p| p.0 >> 1).collect();
+            width = width.div_ceil(2);
+        }
+
+        Ok(proof)
     }
 
-    layer[0].to_vec()
+    /// Generates an extension proof for the first `prefix_len` values, like
+    /// `prove_ext(&values[..prefix_len])`.
+    ///
+    /// # Arguments
+    /// * `prefix_len` - Length of the extended prefix, at least 1
+    ///
+    /// # Returns
+    /// The proof layers, or `SoxError::Index` if `prefix_len` is not between 1 and the number of
+    /// values
+    fn prove_ext(&self, prefix_len: usize) -> SoxResult<Vec<Vec<[u8; 32]>>> {
+        check_prefix_len(self, prefix_len, 1)?;
+        self.prove_prefix(prefix_len, &[(prefix_len - 1) as u32])
+    }
+
+    /// Generates a consistency proof between the prefixes of length `old_len` and `new_len`,
+    /// like `prove_consistency(&values[..new_len], old_len)`.
+    ///
+    /// # Arguments
+    /// * `old_len` - Length of the shorter prefix, at least 1
+    /// * `new_len` - Length of the longer prefix, at most the number of values
+    ///
+    /// # Returns
+    /// The nodes of the proof, or `SoxError::Index` unless `1 <= old_len <= new_len` and
+    /// `new_len` is at most the number of values
+    fn prove_consistency(&self, old_len: usize, new_len: usize) -> SoxResult<Vec<[u8; 32]>> {
+        consistency_proof(self, old_len, new_len)
+    }
+}
+
+// Last node of each layer of the tree of the first `prefix_len` values (at least 1), from the
+// leaves to the root of the prefix
+fn prefix_edges<T: MerkleLayers + ?Sized>(tree: &T, prefix_len: usize) -> Vec<[u8; 32]> {
+    let mut width = prefix_len;
+    let mut edge = tree.layer(0)[prefix_len - 1];
+    let mut edges = vec![edge];
+    for level in 0..tree.num_layers() {
+        if width == 1 {
+            break;
+        }
+        if width.is_multiple_of(2) {
+            edge = tree
+                .mode()
+                .node_hash_with::<T::Hash>(&tree.layer(level)[width - 2], &edge);
+        }
+        edges.push(edge);
+        width = width.div_ceil(2);
+    }
+    edges
+}
+
+// Fails unless `min <= prefix_len <= num_leaves`
+fn check_prefix_len<T: MerkleLayers + ?Sized>(
+    tree: &T,
+    prefix_len: usize,
+    min: usize,
+) -> SoxResult<()> {
+    let num_leaves = tree.layer(0).len();
+    if prefix_len < min || prefix_len > num_leaves {
+        return Err(SoxError::Index(format!(
+            "prefix length {prefix_len} out of bounds ({min} to {num_leaves} values)"
+        )));
+    }
+    Ok(())
+}
+
+// Consistency proof between the prefixes of length `old_len` and `new_len` of a tree, see
+// `MerkleLayers::prove_consistency`
+fn consistency_proof<T: MerkleLayers + ?Sized>(
+    tree: &T,
+    old_len: usize,
+    new_len: usize,
+) -> SoxResult<Vec<[u8; 32]>> {
+    check_prefix_len(tree, new_len, 1)?;
+    if old_len == 0 || old_len > new_len {
+        return Err(SoxError::Index(format!(
+            "prefix length {old_len} out of bounds (1 to {new_len} values)"
+        )));
+    }
+    let mut proof = vec![];
+    if old_len < new_len {
+        consistency_subproof(tree, old_len, 0, new_len, true, &mut proof);
+    }
+    Ok(proof)
+}
+
+// SUBPROOF of RFC 6962 (section 2.1.2) for the first `old_len` values of the range [start, end):
+// appends the nodes proving that the tree of the old values is a prefix of the tree of the range.
+// `complete` is set while the old values form the left edge of the whole tree, in which case their
+// root is known to the verifier.
+fn consistency_subproof<T: MerkleLayers + ?Sized>(
+    tree: &T,
+    old_len: usize,
+    start: usize,
+    end: usize,
+    complete: bool,
+    proof: &mut Vec<[u8; 32]>,
+) {
+    let size = end - start;
+    if old_len == size {
+        if !complete {
+            proof.push(range_root(tree, start, end));
+        }
+        return;
+    }
+
+    // Largest power of two strictly below `size`, the size of the left subtree
+    let split = 1 << (usize::BITS - 1 - (size - 1).leading_zeros());
+    if old_len <= split {
+        consistency_subproof(tree, old_len, start, start + split, complete, proof);
+        proof.push(range_root(tree, start + split, end));
+    } else {
+        consistency_subproof(tree, old_len - split, start + split, end, false, proof);
+        proof.push(range_root(tree, start, start + split));
+    }
+}
+
+// Root of the values of [start, end), `start` being a multiple of the smallest power of two
+// greater than or equal to the size of the range. The node is stored in the tree when the range is
+// a complete subtree or the tail of the sequence; otherwise it is recomputed from its two subtrees.
+fn range_root<T: MerkleLayers + ?Sized>(tree: &T, start: usize, end: usize) -> [u8; 32] {
+    let width = (end - start).next_power_of_two();
+    let level = width.trailing_zeros() as usize;
+    if end - start == width || end == tree.layer(0).len() {
+        return tree.layer(level)[start >> level];
+    }
+    let left = range_root(tree, start, start + width / 2);
+    let right = range_root(tree, start + width / 2, end);
+    tree.mode().node_hash_with::<T::Hash>(&left, &right)
+}
+
+// Hashes the nodes of a layer two by two into the layer above, which has `layer.len().div_ceil(2)`
+// nodes. The last node of a layer with an odd number of nodes is promoted as-is, like in `acc`.
+pub(crate) fn hash_layer<H: AccumulatorHash>(
+    layer: &[[u8; 32]],
+    next: &mut [[u8; 32]],
+    mode: AccumulatorMode,
+) {
+    next.par_iter_mut()
+        .zip(layer.par_chunks(2))
+        .for_each(|(node, pair)| {
+            *node = match pair {
+                [left, right] => mode.node_hash_with::<H>(left, right),
+                [single] => *single,
+                _ => unreachable!("chunks of 2 nodes"),
+            }
+        });
+}
+
+/// Merkle tree of a sequence of values, with all its layers kept in memory, serving roots, prefix
+/// roots and proofs through `MerkleLayers` without rehashing the values. The tree can be
+/// serialized, e.g. to keep the vendor's tree of gate outputs between dispute rounds. The nodes
+/// are hashed with `H`, Keccak-256 by default like the deployed verifiers.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleTree<H = Keccak256Hasher> {
+    // Layers from the leaf hashes to the root
+    layers: Vec<Vec<[u8; 32]>>,
+    #[serde(default)]
+    mode: AccumulatorMode,
+    #[serde(skip)]
+    hash: PhantomData<H>,
+}
+
+impl MerkleTree {
+    /// Builds the tree of a sequence of values.
+    ///
+    /// # Arguments
+    /// * `values` - Values to accumulate, right-padded with zeros (or truncated) to 64 bytes
+    ///
+    /// # Returns
+    /// The tree, or `SoxError::InvalidInput` if `values` is empty
+    pub fn new(values: &[Vec<u8>]) -> SoxResult<Self> {
+        Self::with_mode(values, AccumulatorMode::Legacy)
+    }
+
+    /// Builds the tree of a sequence of values in a given hashing mode.
+    ///
+    /// # Arguments
+    /// * `values` - Values to accumulate
+    /// * `mode` - Hashing scheme of the leaves and nodes
+    ///
+    /// # Returns
+    /// The tree, or `SoxError::InvalidInput` if `values` is empty
+    pub fn with_mode(values: &[Vec<u8>], mode: AccumulatorMode) -> SoxResult<Self> {
+        Self::with_hash(values, mode)
+    }
+}
+
+impl<H: AccumulatorHash> MerkleTree<H> {
+    /// Builds the tree of a sequence of values with a given hash function and hashing mode.
+    ///
+    /// # Arguments
+    /// * `values` - Values to accumulate
+    /// * `mode` - Hashing scheme of the leaves and nodes
+    ///
+    /// # Returns
+    /// The tree, or `SoxError::InvalidInput` if `values` is empty
+    pub fn with_hash(values: &[Vec<u8>], mode: AccumulatorMode) -> SoxResult<Self> {
+        if values.is_empty() {
+            return Err(SoxError::InvalidInput(
+                "cannot build the Merkle tree of an empty sequence".into(),
+            ));
+        }
+        Ok(Self {
+            layers: build_layers::<H>(values, mode),
+            mode,
+            hash: PhantomData,
+        })
+    }
+
+    /// Returns the number of values in the tree
+    pub fn num_leaves(&self) -> usize {
+        self.layers[0].len()
+    }
+
+    /// Serializes the tree into bytes. The hash function is not serialized: the tree must be
+    /// deserialized as a `MerkleTree<H>` with the same `H`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write(&mut buf, self).expect("serializing into a Vec cannot fail");
+        buf
+    }
+
+    /// Deserializes a tree from bytes.
+    ///
+    /// # Arguments
+    /// * `bytes` - Bytes returned by `to_bytes`
+    ///
+    /// # Returns
+    /// The tree, or `SoxError::Decode` if the bytes are malformed or the layers do not have the
+    /// shape of a Merkle tree
+    pub fn from_bytes(bytes: &[u8]) -> SoxResult<Self> {
+        let tree: Self = from_read(bytes)?;
+        let well_formed = tree.layers.first().is_some_and(|leaves| !leaves.is_empty())
+            && tree.layers[tree.layers.len() - 1].len() == 1
+            && tree
+                .layers
+                .windows(2)
+                .all(|w| w[0].len() > 1 && w[1].len() == w[0].len().div_ceil(2));
+        if !well_formed {
+            return Err(SoxError::Decode("malformed Merkle tree layers".into()));
+        }
+        Ok(tree)
+    }
+}
+
+// Layers of the tree of a non-empty sequence of values, from the leaf hashes to the root
+fn build_layers<H: AccumulatorHash>(
+    values: &[Vec<u8>],
+    mode: AccumulatorMode,
+) -> Vec<Vec<[u8; 32]>> {
+    let mut layers = vec![values
+        .par_iter()
+        .map(|v| mode.leaf_hash_with::<H>(v))
+        .collect::<Vec<_>>()];
+    while layers[layers.len() - 1].len() > 1 {
+        let layer = &layers[layers.len() - 1];
+        let mut next = vec![[0u8; 32]; layer.len().div_ceil(2)];
+        hash_layer::<H>(layer, &mut next, mode);
+        layers.push(next);
+    }
+    layers
+}
+
+impl<H: AccumulatorHash> MerkleLayers for MerkleTree<H> {
+    type Hash = H;
+
+    fn num_layers(&self) -> usize {
+        self.layers.len()
+    }
+
+    fn layer(&self, level: usize) -> &[[u8; 32]] {
+        &self.layers[level]
+    }
+
+    fn mode(&self) -> AccumulatorMode {
+        self.mode
+    }
+}
+
+/// JavaScript handle of a Keccak-256 `MerkleTree`, the hash of the deployed verifiers
+#[wasm_bindgen(js_name = MerkleTree)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleTreeJs(MerkleTree);
+
+#[wasm_bindgen(js_class = MerkleTree)]
+impl MerkleTreeJs {
+    /// Builds the tree of a sequence of values (see `acc_js`).
+    ///
+    /// # Arguments
+    /// * `values` - Array of Uint8Arrays to accumulate
+    ///
+    /// # Returns
+    /// The tree, or `SoxError::InvalidInput` if `values` is empty
+    pub fn from_values(values: Vec<Uint8Array>) -> SoxResult<MerkleTreeJs> {
+        let values_vec: Vec<Vec<u8>> = values.iter().map(uint8_array_to_vec_u8).collect();
+        Ok(Self(MerkleTree::new(&values_vec)?))
+    }
+
+    /// Builds the tree of a sequence of values in a given hashing mode (see `acc_with_mode_js`).
+    ///
+    /// # Arguments
+    /// * `values` - Array of Uint8Arrays to accumulate
+    /// * `mode` - Hashing scheme of the leaves and nodes
+    ///
+    /// # Returns
+    /// The tree, or `SoxError::InvalidInput` if `values` is empty
+    pub fn from_values_with_mode(
+        values: Vec<Uint8Array>,
+        mode: AccumulatorMode,
+    ) -> SoxResult<MerkleTreeJs> {
+        let values_vec: Vec<Vec<u8>> = values.iter().map(uint8_array_to_vec_u8).collect();
+        Ok(Self(MerkleTree::with_mode(&values_vec, mode)?))
+    }
+
+    /// Returns the number of values in the tree
+    pub fn num_leaves(&self) -> usize {
+        self.0.num_leaves()
+    }
+
+    /// JavaScript wrapper of `root`
+    pub fn root_js(&self) -> Vec<u8> {
+        self.0.root().to_vec()
+    }
+
+    /// JavaScript wrapper of `prefix_root`
+    ///
+    /// # Arguments
+    /// * `prefix_len` - Length of the prefix, at least 1
+    ///
+    /// # Returns
+    /// The 32-byte root of the prefix
+    pub fn prefix_root_js(&self, prefix_len: usize) -> SoxResult<Vec<u8>> {
+        Ok(self.0.prefix_root(prefix_len)?.to_vec())
+    }
+
+    /// JavaScript wrapper of `prove`
+    ///
+    /// # Arguments
+    /// * `indices` - Array of indices for values to include in proof
+    ///
+    /// # Returns
+    /// Array of arrays of Uint8Arrays containing the proof layers
+    pub fn prove_js(&self, indices: Array) -> SoxResult<Array> {
+        let proof = self.0.prove(&js_array_to_indices(&indices)?)?;
+        Ok(proof_to_js_array(&proof))
+    }
+
+    /// JavaScript wrapper of `prove_prefix`
+    ///
+    /// # Arguments
+    /// * `prefix_len` - Length of the proven prefix of the sequence
+    /// * `indices` - Array of indices for values to include in proof
+    ///
+    /// # Returns
+    /// Array of arrays of Uint8Arrays containing the proof layers
+    pub fn prove_prefix_js(&self, prefix_len: usize, indices: Array) -> SoxResult<Array> {
+        let proof = self
+            .0
+            .prove_prefix(prefix_len, &js_array_to_indices(&indices)?)?;
+        Ok(proof_to_js_array(&proof))
+    }
+
+    /// JavaScript wrapper of `prove_ext`
+    ///
+    /// # Arguments
+    /// * `prefix_len` - Length of the extended prefix, at least 1
+    ///
+    /// # Returns
+    /// Array of arrays of Uint8Arrays containing the extension proof layers
+    pub fn prove_ext_js(&self, prefix_len: usize) -> SoxResult<Array> {
+        Ok(proof_to_js_array(&self.0.prove_ext(prefix_len)?))
+    }
+
+    /// JavaScript wrapper of `prove_consistency`
+    ///
+    /// # Arguments
+    /// * `old_len` - Length of the shorter prefix, at least 1
+    /// * `new_len` - Length of the longer prefix, at most the number of values
+    ///
+    /// # Returns
+    /// Array of Uint8Arrays containing the nodes of the proof
+    pub fn prove_consistency_js(&self, old_len: usize, new_len: usize) -> SoxResult<Array> {
+        let proof = self.0.prove_consistency(old_len, new_len)?;
+        Ok(Array::from_iter(
+            proof.iter().map(|n| Uint8Array::from(&n[..])),
+        ))
+    }
+
+    /// Serializes the tree into bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_bytes()
+    }
+
+    /// Deserializes a tree from bytes.
+    ///
+    /// # Arguments
+    /// * `bytes` - Bytes returned by `to_bytes`
+    ///
+    /// # Returns
+    /// The tree, or `SoxError::Decode` if the bytes are malformed or the layers do not have the
+    /// shape of a Merkle tree
+    pub fn from_bytes(bytes: &[u8]) -> SoxResult<MerkleTreeJs> {
+        Ok(Self(MerkleTree::from_bytes(bytes)?))
+    }
 }
 
 // =================================================================================================
@@ -375,9 +2074,95 @@ pub fn acc_fixed64(values: &[Vec<u8>]) -> Vec<u8> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::circuits_v2::{acc_circuit_v2_with_hash, compile_circuit_v2};
     use rand::prelude::SliceRandom;
     use rand::Rng;
 
+    // Legacy leaf hash of a value
+    fn hash(data: &[u8]) -> Vec<u8> {
+        hash_block64(data).to_vec()
+    }
+
+    #[test]
+    fn test_builder_matches_acc_fixed64() {
+        let mut rng = rand::rng();
+        for n in 0..70usize {
+            let values: Vec<Vec<u8>> = (0..n)
+                .map(|_| {
+                    let mut v = vec![0u8; rng.random_range(0..80)];
+                    rng.fill(&mut v[..]);
+                    v
+                })
+                .collect();
+            let mut builder = AccumulatorBuilder::new();
+            for (i, v) in values.iter().enumerate() {
+                builder.push(v);
+                assert_eq!(builder.len(), i as u64 + 1);
+            }
+            assert_eq!(builder.root(), acc_fixed64(&values), "n = {n}");
+            assert_eq!(builder.root(), acc(&values), "n = {n}");
+        }
+
+        let ct: Vec<u8> = (0..16 + 1000).map(|i| i as u8).collect();
+        let mut builder = AccumulatorBuilder::new();
+        for block in split_ct_blocks(&ct, 64).unwrap() {
+            builder.push_leaf(leaf_hash(&block));
+        }
+        assert_eq!(builder.root(), acc_ct(&ct, 64).unwrap());
+    }
+
+    #[test]
+    fn test_merkle_tree_matches_prove() {
+        let as_vecs = |proof: Vec<Vec<[u8; 32]>>| -> Vec<Vec<Vec<u8>>> {
+            proof
+                .iter()
+                .map(|layer| layer.iter().map(|node| node.to_vec()).collect())
+                .collect()
+        };
+        let mut rng = rand::rng();
+        for n in 1..70u32 {
+            let values = random_values(n);
+            let tree = MerkleTree::new(&values).unwrap();
+            assert_eq!(tree.root().to_vec(), acc(&values), "n = {n}");
+
+            for len in 1..=n as usize {
+                let prefix = &values[..len];
+                assert_eq!(
+                    tree.prefix_root(len).unwrap().to_vec(),
+                    acc(prefix),
+                    "n = {n}, len = {len}"
+                );
+                assert_eq!(
+                    as_vecs(tree.prove_ext(len).unwrap()),
+                    prove_ext(prefix).unwrap()
+                );
+
+                let mut indices: Vec<u32> = (0..len as u32).collect();
+                indices.shuffle(&mut rng);
+                indices.truncate(rng.random_range(1..=len));
+                assert_eq!(
+                    as_vecs(tree.prove_prefix(len, &indices).unwrap()),
+                    prove(prefix, &indices).unwrap(),
+                    "n = {n}, len = {len}, indices = {indices:?}"
+                );
+            }
+            assert!(tree.prefix_root(0).is_err());
+            assert!(tree.prove(&[n]).is_err());
+            assert!(tree.prove_prefix(n as usize + 1, &[0]).is_err());
+
+            let bytes = tree.to_bytes();
+            assert_eq!(MerkleTree::from_bytes(&bytes).unwrap(), tree);
+        }
+        assert!(MerkleTree::new(&[]).is_err());
+
+        let malformed: MerkleTree = MerkleTree {
+            layers: vec![vec![[0u8; 32]; 3], vec![[0u8; 32]]],
+            mode: AccumulatorMode::Legacy,
+            hash: PhantomData,
+        };
+        assert!(MerkleTree::<Keccak256Hasher>::from_bytes(&malformed.to_bytes()).is_err());
+    }
+
     #[test]
     pub fn test_acc_simple_root() {
         //          root
@@ -386,7 +2171,11 @@ mod tests {
         //          |   |
         //       0xdead 0xbeef
         let values = vec![vec![0xde, 0xad], vec![0xbe, 0xef]];
-        let expected_root = concat_and_hash(&hash(&values[0]), &hash(&values[1]));
+        let expected_root = concat_and_hash::<Keccak256Hasher>(
+            &hash(&values[0]),
+            &hash(&values[1]),
+            AccumulatorMode::Legacy,
+        );
 
         let root = acc(&values);
         assert_eq!(expected_root, root);
@@ -398,7 +2187,7 @@ mod tests {
         let indices = vec![0];
         let expected_proof = vec![vec![hash(&values[1])]];
 
-        let proof = prove(&values, &indices);
+        let proof = prove(&values, &indices).unwrap();
         assert_eq!(expected_proof, proof);
     }
 
@@ -419,13 +2208,13 @@ mod tests {
             indices.sort(); // ensure indices are increasing
 
             // Get the values at the indices of the vector `indices`
-            let proof_values: Vec<Vec<u8>> = indices
+            let proof_values: Vec<[u8; 32]> = indices
                 .iter()
-                .map(|&idx| values[idx as usize].clone())
+                .map(|&idx| leaf_hash(&values[idx as usize]))
                 .collect();
 
             // Call `prove(&proof_values, &indices)` and store in `proof`
-            let proof = prove(&values, &indices);
+            let proof = prove(&values, &indices).unwrap();
 
             // Call `verify(&h, &indices, &proof_values, &proof)` and assert that it should be true
             assert!(
@@ -443,153 +2232,459 @@ mod tests {
 
             let prev_h = acc(&values[..(i - 1) as usize]);
             let curr_h = acc(&values);
-            let proof = prove_ext(&values);
+            let proof = prove_ext(&values).unwrap();
 
             assert!(
-                verify_ext(i - 1, &prev_h, &curr_h, values.last().unwrap(), &proof),
+                verify_ext(
+                    i - 1,
+                    &prev_h,
+                    &curr_h,
+                    &leaf_hash(values.last().unwrap()),
+                    &proof
+                ),
                 "Verification failed for i = {}",
                 i
             );
         }
     }
 
-    fn random_values(num_bytes: u32) -> Vec<Vec<u8>> {
+    #[test]
+    fn test_verify_random_round_trip() {
         let mut rng = rand::rng();
+        for _ in 0..300 {
+            let n = rng.random_range(1..300u32);
+            let values: Vec<Vec<u8>> = (0..n)
+                .map(|_| {
+                    (0..rng.random_range(1..=64))
+                        .map(|_| rng.random())
+                        .collect()
+                })
+                .collect();
+            let root = acc(&values);
 
-        (0..num_bytes)
-            .map(|_| (0..1).map(|_| rng.random_range(0..=255)).collect())
-            .collect()
-    }
+            // indices are given unsorted, as the contract receives them
+            let mut indices: Vec<u32> = (0..n).collect();
+            indices.shuffle(&mut rng);
+            indices.truncate(rng.random_range(1..=n as usize));
+            let mut leaves: Vec<[u8; 32]> = indices
+                .iter()
+                .map(|&idx| leaf_hash(&values[idx as usize]))
+                .collect();
+            let proof = prove(&values, &indices).unwrap();
+            assert!(verify(&root, &indices, &leaves, &proof), "n = {n}");
 
-    /// Verifies an extension proof. Not useful at the moment apart from testing.
-    ///
-    /// # Arguments
-    /// * `i` - Position in the sequence
-    /// * `prev_h` - Previous accumulator value
-    /// * `curr_h` - Current accumulator value
-    /// * `value` - Value being added
-    /// * `proof` - Extension proof components
-    ///
-    /// # Returns
-    /// true if the proof is valid, false otherwise
-    fn verify_ext(
-        i: u32,
-        prev_root: &Vec<u8>,
-        curr_root: &Vec<u8>,
-        added_val: &Vec<u8>,
-        proof: &Vec<Vec<Vec<u8>>>,
-    ) -> bool {
-        verify(curr_root, &vec![i], &vec![added_val.clone()], proof)
-            && verify_previous(prev_root, proof)
-    }
+            let wrong_root = AccumulatorMode::Legacy.node_hash(&root, &[0u8; 32]);
+            assert!(!verify(&wrong_root, &indices, &leaves, &proof));
+            assert!(!verify(&root, &indices, &leaves[1..], &proof));
 
-    /// Verifies a Merkle proof for multiple values in a tree. Inspired by
-    /// https://arxiv.org/pdf/2002.07648
-    ///
-    /// # Arguments
-    /// * `root` - Expected Merkle root hash
-    /// * `indices` - Vector of indices for the values being proven
-    /// * `values` - Slice of values being proven
-    /// * `proof` - Vector of proof layers, where each layer contains the sibling hashes needed for
-    ///             verification
-    ///
-    /// # Returns
-    /// `true` if:
-    /// - The number of indices matches the number of values
-    /// - The proof successfully reconstructs the Merkle root
-    /// - All sibling relationships are valid
-    /// `false` otherwise
-    ///
-    fn verify(
-        root: &Vec<u8>,
-        indices: &Vec<u32>,
-        values: &[Vec<u8>],
-        proof: &Vec<Vec<Vec<u8>>>,
-    ) -> bool {
-        if indices.len() != values.len() {
-            return false;
+            let tampered = rng.random_range(0..leaves.len());
+            leaves[tampered][0] ^= 1;
+            assert!(!verify(&root, &indices, &leaves, &proof), "n = {n}");
         }
 
-        let mut proof_copy = proof.clone();
-        let mut current_indices = indices.clone();
-        let mut layer: Vec<Vec<u8>> = values.iter().map(hash).collect();
+        let no_indices: [[u8; 32]; 0] = [];
+        assert!(verify(
+            &[0u8; 32],
+            &[],
+            &no_indices,
+            &Vec::<Vec<[u8; 32]>>::new()
+        ));
+
+        // indices at the end of the u32 range are rejected without overflowing
+        let values = random_values(4);
+        let proof = prove(&values, &[0]).unwrap();
+        let leaf = leaf_hash(&values[0]);
+        for idx in [u32::MAX - 1, u32::MAX] {
+            assert!(!verify(&acc(&values), &[idx], &[leaf], &proof));
+        }
+    }
 
-        let mut paired: Vec<(u32, Vec<u8>)> =
-            current_indices.into_iter().zip(layer.into_iter()).collect();
-        paired.sort_by_key(|pair| pair.0);
-        (current_indices, layer) = paired.into_iter().unzip();
+    #[test]
+    fn test_verify_ext_random_round_trip() {
+        let mut rng = rand::rng();
+        for _ in 0..300 {
+            let n = rng.random_range(2..300usize);
+            let values = random_values(n as u32);
+            let prev_root = acc(&values[..n - 1]);
+            let curr_root = acc(&values);
+            let added = leaf_hash(&values[n - 1]);
+            let proof = prove_ext(&values).unwrap();
+
+            assert!(verify_previous(&prev_root, &proof), "n = {n}");
+            assert!(verify_ext(
+                (n - 1) as u32,
+                &prev_root,
+                &curr_root,
+                &added,
+                &proof
+            ));
+            assert!(!verify_previous(&curr_root, &proof));
+            assert!(!verify_ext(
+                (n - 1) as u32,
+                &prev_root,
+                &curr_root,
+                &[0u8; 32],
+                &proof
+            ));
+        }
 
-        for proof_layer in &mut proof_copy {
-            let mut b: Vec<(u32, u32)> = vec![];
+        // first gate: no previous accumulator and an empty proof
+        let values = random_values(1);
+        let proof = prove_ext(&values).unwrap();
+        let leaf = leaf_hash(&values[0]);
+        assert!(verify_previous(&[0u8; 32], &proof));
+        assert!(verify_ext(0, &[0u8; 32], &acc(&values), &leaf, &proof));
+        assert!(verify_ext(1, &[0u8; 32], &acc(&values), &leaf, &proof));
+        assert!(!verify_ext(1, &[0u8; 32], &[0u8; 32], &leaf, &proof));
+    }
 
-            for i in &current_indices {
-                let neighbor = get_neighbor_idx(i);
+    #[test]
+    fn test_consistency_proofs() {
+        for mode in [AccumulatorMode::Legacy, AccumulatorMode::DomainSeparated] {
+            let values = random_values(70);
+            let tree = MerkleTree::with_mode(&values, mode).unwrap();
+            for new_len in 1..=values.len() {
+                let new_root = acc_with_mode(&values[..new_len], mode);
+                for old_len in 1..=new_len {
+                    let old_root = acc_with_mode(&values[..old_len], mode);
+                    let proof = tree.prove_consistency(old_len, new_len).unwrap();
+                    assert_eq!(
+                        proof,
+                        prove_consistency_with_mode(&values[..new_len], old_len, mode)
+                            .unwrap()
+                            .iter()
+                            .map(|n| <[u8; 32]>::try_from(&n[..]).unwrap())
+                            .collect::<Vec<_>>()
+                    );
+                    assert!(
+                        verify_consistency_with_mode(
+                            old_len, new_len, &old_root, &new_root, &proof, mode
+                        ),
+                        "{old_len} -> {new_len}"
+                    );
+                    if old_len == new_len {
+                        continue;
+                    }
 
-                if neighbor < *i {
-                    b.push((neighbor, i.clone()))
-                } else {
-                    b.push((i.clone(), neighbor))
+                    // wrong roots and nodes are rejected
+                    assert!(!verify_consistency_with_mode(
+                        old_len, new_len, &new_root, &new_root, &proof, mode
+                    ));
+                    assert!(!verify_consistency_with_mode(
+                        old_len, new_len, &old_root, &old_root, &proof, mode
+                    ));
+                    for i in 0..proof.len() {
+                        let mut tampered = proof.clone();
+                        tampered[i][0] ^= 1;
+                        assert!(!verify_consistency_with_mode(
+                            old_len, new_len, &old_root, &new_root, &tampered, mode
+                        ));
+                    }
+                    let mut longer = proof.clone();
+                    longer.push([0u8; 32]);
+                    assert!(!verify_consistency_with_mode(
+                        old_len, new_len, &old_root, &new_root, &longer, mode
+                    ));
                 }
             }
+        }
 
-            let mut next_indices: Vec<u32> = vec![];
-            let mut next_layer: Vec<Vec<u8>> = vec![];
+        // a sequence is not consistent with a different prefix
+        let values = random_values(10);
+        let mut other = values.clone();
+        other[2] = vec![other[2].first().copied().unwrap_or(0).wrapping_add(1)];
+        let proof = prove_consistency(&values, 5).unwrap();
+        assert!(verify_consistency(
+            5,
+            10,
+            &acc(&values[..5]),
+            &acc(&values),
+            &proof
+        ));
+        assert!(!verify_consistency(
+            5,
+            10,
+            &acc(&other[..5]),
+            &acc(&values),
+            &proof
+        ));
+        assert!(!verify_consistency::<[u8; 32]>(
+            0,
+            10,
+            &[0u8; 32],
+            &acc(&values),
+            &[]
+        ));
+        assert!(prove_consistency(&values, 0).is_err());
+        assert!(prove_consistency(&values, 11).is_err());
+        assert!(MerkleTree::new(&values)
+            .unwrap()
+            .prove_consistency(3, 11)
+            .is_err());
+    }
 
-            let mut i = 0;
-            while i < b.len() {
-                // use a while loop because we cannot manually increment in for loops
-                if i < b.len() - 1 && b[i].0 == b[i + 1].0 {
-                    // duplicate found
-                    // this means that b[i][0] and b[i][1] are elements of
-                    // nextIndices. Furthermore, b[i] is computed based on
-                    // nextIndices[i] and since we skip the duplicates,
-                    // it can only be that b[i][0] == nextIndices[i]
-                    // => the corresponding values are valuesKeccak[i]
-                    // and valuesKeccak[i+1]
-                    next_layer.push(concat_and_hash(&layer[i], &layer[i + 1]));
+    #[test]
+    fn test_accumulator_hashes() {
+        let abc = [
+            Keccak256Hasher::hash(b"abc"),
+            Sha256Hasher::hash(b"abc"),
+            Blake3Hasher::hash(b"abc"),
+        ];
+        let expected = [
+            "4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45",
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85",
+        ];
+        for (hash, expected) in abc.iter().zip(expected) {
+            assert_eq!(hex::encode(hash), expected);
+        }
 
-                    i += 1;
-                } else if proof_layer.len() > 0 {
-                    let last_layer_val = proof_layer.pop().unwrap();
+        let values = random_values(37);
+        assert_eq!(
+            acc_with_hash::<Keccak256Hasher>(&values, AccumulatorMode::Legacy),
+            acc(&values)
+        );
+        let circuit = compile_circuit_v2(&[7u8; 16 + 200], &[1u8; 32]).unwrap();
+        let gates = circuit.encoded_gates().unwrap();
+        assert_eq!(
+            acc_circuit_v2_with_hash::<Keccak256Hasher>(&circuit.gates).unwrap(),
+            acc_circuit_v2(&circuit.gates).unwrap()
+        );
+        assert_eq!(
+            acc_circuit_v2_with_hash::<Blake3Hasher>(&circuit.gates).unwrap(),
+            acc_fixed64_with_hash::<Blake3Hasher>(&gates)
+        );
 
-                    if current_indices[i] % 2 == 1 {
-                        next_layer.push(concat_and_hash(&last_layer_val, &layer[i]));
-                    } else {
-                        next_layer.push(concat_and_hash(&layer[i], &last_layer_val));
-                    }
-                } else {
-                    // proofLayer is empty, move the element that must be combined to the next layer
-                    next_layer.push(layer[i].clone());
+        fn round_trip<H: AccumulatorHash>(values: &[Vec<u8>]) -> Vec<u8> {
+            let root = acc_with_hash::<H>(values, AccumulatorMode::Legacy);
+            assert_eq!(root, acc_fixed64_with_hash::<H>(values));
+            for mode in [AccumulatorMode::Legacy, AccumulatorMode::DomainSeparated] {
+                let root = acc_with_hash::<H>(values, mode);
+                let indices = [0, 5, 36];
+                let leaves: Vec<_> = indices
+                    .iter()
+                    .map(|&i| mode.leaf_hash_with::<H>(&values[i as usize]))
+                    .collect();
+                let proof = prove_with_hash::<H>(values, &indices, mode).unwrap();
+                assert!(verify_with_hash::<H, _, _>(
+                    &root, &indices, &leaves, &proof, mode
+                ));
+
+                let n = values.len();
+                let prev_root = acc_with_hash::<H>(&values[..n - 1], mode);
+                let added = mode.leaf_hash_with::<H>(&values[n - 1]);
+                let proof = prove_ext_with_hash::<H>(values, mode).unwrap();
+                assert!(verify_previous_with_hash::<H, _>(&prev_root, &proof, mode));
+                assert!(!verify_previous_with_hash::<H, _>(&root, &proof, mode));
+                assert!(verify_ext_with_hash::<H, _>(
+                    (n - 1) as u32,
+                    &prev_root,
+                    &root,
+                    &added,
+                    &proof,
+                    mode
+                ));
+
+                for old_len in [1, 8, 20, n - 1] {
+                    let old_root = acc_with_hash::<H>(&values[..old_len], mode);
+                    let proof = prove_consistency_with_hash::<H>(values, old_len, mode).unwrap();
+                    assert!(verify_consistency_with_hash::<H, _>(
+                        old_len, n, &old_root, &root, &proof, mode
+                    ));
+                    assert!(!verify_consistency_with_hash::<H, _>(
+                        old_len, n, &root, &root, &proof, mode
+                    ));
                 }
 
-                next_indices.push(current_indices[i] >> 1);
-                i += 1;
+                // the same roots and proofs from the layers of a tree hashed with `H`
+                let tree = MerkleTree::<H>::with_hash(values, mode).unwrap();
+                assert_eq!(tree.root().to_vec(), root);
+                let proof = tree.prove(&indices).unwrap();
+                assert!(verify_with_hash::<H, _, _>(
+                    &root, &indices, &leaves, &proof, mode
+                ));
+                let proof = tree.prove_ext(n).unwrap();
+                assert!(verify_ext_with_hash::<H, _>(
+                    (n - 1) as u32,
+                    &tree.prefix_root(n - 1).unwrap(),
+                    &root,
+                    &added,
+                    &proof,
+                    mode
+                ));
+                let proof = tree.prove_consistency(8, n - 1).unwrap();
+                assert!(verify_consistency_with_hash::<H, _>(
+                    8,
+                    n - 1,
+                    &tree.prefix_root(8).unwrap(),
+                    &prev_root,
+                    &proof,
+                    mode
+                ));
+                let bytes = tree.to_bytes();
+                assert_eq!(
+                    MerkleTree::<H>::from_bytes(&bytes).unwrap().to_bytes(),
+                    bytes
+                );
             }
 
-            layer = next_layer.clone();
-            current_indices = next_indices.clone();
+            let mut builder = AccumulatorBuilder::<H>::default();
+            values.iter().for_each(|value| builder.push(value));
+            assert_eq!(builder.root(), root);
+
+            let path = std::env::temp_dir().join(format!(
+                "sox-layers-{}-{}",
+                std::any::type_name::<H>().rsplit("::").next().unwrap(),
+                std::process::id()
+            ));
+            let layers = crate::mmap_store::MmapLayers::<H>::create(&path, values.len(), |i| {
+                Ok(H::hash_block64(&values[i]))
+            })
+            .unwrap();
+            assert_eq!(layers.root().to_vec(), root);
+            let proof = layers.prove_consistency(20, values.len()).unwrap();
+            assert!(verify_consistency_with_hash::<H, _>(
+                20,
+                values.len(),
+                &layers.prefix_root(20).unwrap(),
+                &root,
+                &proof,
+                AccumulatorMode::Legacy
+            ));
+            std::fs::remove_file(&path).unwrap();
+            root
         }
+        let roots = [
+            round_trip::<Keccak256Hasher>(&values),
+            round_trip::<Sha256Hasher>(&values),
+            round_trip::<Blake3Hasher>(&values),
+        ];
+        assert_ne!(roots[0], roots[1]);
+        assert_ne!(roots[1], roots[2]);
+        assert_ne!(roots[0], roots[2]);
+
+        // the Keccak verifiers reject the proofs built with another hash
+        let mode = AccumulatorMode::Legacy;
+        let n = values.len();
+        let prev_root = acc_with_hash::<Sha256Hasher>(&values[..n - 1], mode);
+        let proof = prove_ext_with_hash::<Sha256Hasher>(&values, mode).unwrap();
+        assert!(!verify_previous_with_mode(&prev_root, &proof, mode));
+        let old_root = acc_with_hash::<Blake3Hasher>(&values[..8], mode);
+        let proof = prove_consistency_with_hash::<Blake3Hasher>(&values, 8, mode).unwrap();
+        assert!(!verify_consistency_with_mode(
+            8, n, &old_root, &roots[2], &proof, mode
+        ));
+    }
 
-        layer[0].eq(root)
+    #[test]
+    fn test_sort_aligned_is_stable() {
+        let (indices, values) = sort_aligned(&[3, 1, 3, 0], &['a', 'b', 'c', 'd']).unwrap();
+        assert_eq!(indices, vec![0, 1, 3, 3]);
+        assert_eq!(values, vec!['d', 'b', 'a', 'c']);
+        assert!(sort_aligned(&[1], &['a', 'b']).is_err());
     }
 
-    // Verifies the previous root of the accumulator. Used only for verify_ext.
-    fn verify_previous(prev_root: &Vec<u8>, proof: &Vec<Vec<Vec<u8>>>) -> bool {
-        let mut proof_copy = proof.clone();
-        let mut first_found = false;
-        let mut computed_root: Vec<u8> = vec![];
+    #[test]
+    fn test_domain_separated_vectors() {
+        let mode = AccumulatorMode::DomainSeparated;
+        let to_hex = |bytes: &[u8]| format!("0x{}", hex::encode(bytes));
+        let proof_to_hex = |proof: &[Vec<Vec<u8>>]| -> Vec<Vec<String>> {
+            proof
+                .iter()
+                .map(|layer| layer.iter().map(|node| to_hex(node)).collect())
+                .collect()
+        };
+
+        let mut cases = vec![];
+        for lengths in [
+            vec![0],
+            vec![1, 2],
+            vec![1, 64, 65],
+            vec![0, 32, 64, 100, 200],
+            vec![64; 8],
+        ] {
+            let values: Vec<Vec<u8>> = lengths
+                .iter()
+                .enumerate()
+                .map(|(i, &len)| (0..len).map(|j| (i * 31 + j) as u8).collect())
+                .collect();
+            let n = values.len() as u32;
+            let leaves: Vec<[u8; 32]> = values.iter().map(|v| mode.leaf_hash(v)).collect();
+            let root = acc_with_mode(&values, mode);
+            assert_eq!(
+                MerkleTree::with_mode(&values, mode)
+                    .unwrap()
+                    .root()
+                    .to_vec(),
+                root
+            );
+            if lengths.iter().any(|&len| len != 64) {
+                assert_ne!(root, acc(&values));
+            }
 
-        for i in 0..proof_copy.len() {
-            while proof_copy[i].len() > 0 {
-                if !first_found {
-                    computed_root = proof_copy[i].pop().unwrap();
-                    first_found = true;
-                } else {
-                    computed_root = concat_and_hash(&proof_copy[i].pop().unwrap(), &computed_root);
-                }
+            let mut proofs = vec![];
+            let mut index_sets = vec![vec![0], vec![n - 1], (0..n).collect()];
+            index_sets.dedup();
+            for indices in index_sets {
+                let proof = prove_with_mode(&values, &indices, mode).unwrap();
+                let proven: Vec<[u8; 32]> = indices.iter().map(|&i| leaves[i as usize]).collect();
+                assert!(verify_with_mode(&root, &indices, &proven, &proof, mode));
+                assert_eq!(verify(&root, &indices, &proven, &proof), n == 1);
+                proofs.push(serde_json::json!({
+                    "indices": indices,
+                    "proof": proof_to_hex(&proof),
+                }));
             }
+
+            let ext_proof = prove_ext_with_mode(&values, mode).unwrap();
+            let prev_root = match n {
+                1 => vec![0u8; 32],
+                _ => acc_with_mode(&values[..n as usize - 1], mode),
+            };
+            assert!(verify_ext_with_mode(
+                n - 1,
+                &prev_root,
+                &root,
+                &leaves[n as usize - 1],
+                &ext_proof,
+                mode
+            ));
+
+            cases.push(serde_json::json!({
+                "values": values.iter().map(|v| to_hex(v)).collect::<Vec<_>>(),
+                "leaves": leaves.iter().map(|l| to_hex(l)).collect::<Vec<_>>(),
+                "root": to_hex(&root),
+                "proofs": proofs,
+                "prevRoot": to_hex(&prev_root),
+                "extProof": proof_to_hex(&ext_proof),
+            }));
+        }
+        let vectors = serde_json::json!({
+            "mode": "DomainSeparated",
+            "leaf": "keccak256(0x00 || value)",
+            "node": "keccak256(0x01 || left || right)",
+            "cases": cases,
+        });
+
+        // SOX_UPDATE_VECTORS=1 cargo test regenerates the file after a deliberate change
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/test_vectors/accumulator_domain_separated.json"
+        );
+        if std::env::var_os("SOX_UPDATE_VECTORS").is_some() {
+            let json = serde_json::to_string_pretty(&vectors).unwrap();
+            std::fs::write(path, json + "\n").unwrap();
         }
+        let expected: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(vectors, expected);
+    }
+
+    fn random_values(num_bytes: u32) -> Vec<Vec<u8>> {
+        let mut rng = rand::rng();
 
-        computed_root.eq(prev_root)
+        (0..num_bytes)
+            .map(|_| (0..1).map(|_| rng.random_range(0..=255)).collect())
+            .collect()
     }
 }
@@ -1,5 +1,5 @@
 use crate::accumulator::uint8_array_to_vec_u8;
-use crate::utils::die;
+use crate::error::{SoxError, SoxResult};
 use aes::cipher::{KeyIvInit, StreamCipher};
 use js_sys::Uint8Array;
 use wasm_bindgen::prelude::wasm_bindgen;
@@ -24,25 +24,35 @@ type Aes128Ctr128BE = ctr::Ctr128BE<aes::Aes128>;
 ///
 /// # Returns
 /// Encrypted/decrypted bytes
-pub fn encrypt_block(data: &Vec<&Vec<u8>>) -> Vec<u8> {
+pub fn encrypt_block(data: &[&Vec<u8>]) -> SoxResult<Vec<u8>> {
     if data.len() < 3 {
-        die("AES encryption/decryption requires a key, blocks and counter starting value")
+        return Err(SoxError::InvalidInput(
+            "AES encryption/decryption requires a key, blocks and counter starting value".into(),
+        ));
     }
 
     if data[0].len() != 16 {
-        die("AES encryption/decryption requires a key of exactly 16 bytes")
+        return Err(SoxError::KeyLength {
+            expected: 16,
+            got: data[0].len(),
+        });
     }
 
     if data[1].len() > 64 {
-        die("AES encryption/decryption requires blocks of at most 64 bytes");
+        return Err(SoxError::InvalidInput(
+            "AES encryption/decryption requires blocks of at most 64 bytes".into(),
+        ));
     }
 
-    if data[1].len() == 0 {
-        return vec![];
+    if data[1].is_empty() {
+        return Ok(vec![]);
     }
 
     if data[2].len() != 16 {
-        die("AES encryption/decryption requires a counter starting value of exactly 16 bytes");
+        return Err(SoxError::InvalidInput(
+            "AES encryption/decryption requires a counter starting value of exactly 16 bytes"
+                .into(),
+        ));
     }
 
     let key = &data[0][..16];
@@ -62,7 +72,7 @@ pub fn encrypt_block(data: &Vec<&Vec<u8>>) -> Vec<u8> {
 ///
 /// # Returns  
 /// Decrypted bytes
-pub fn decrypt_block(data: &Vec<&Vec<u8>>) -> Vec<u8> {
+pub fn decrypt_block(data: &[&Vec<u8>]) -> SoxResult<Vec<u8>> {
     encrypt_block(data)
 }
 
@@ -77,7 +87,7 @@ pub fn decrypt_block(data: &Vec<&Vec<u8>>) -> Vec<u8> {
 /// # Returns
 /// Encrypted bytes
 #[wasm_bindgen]
-pub fn encrypt_block_js(data: Vec<Uint8Array>) -> Vec<u8> {
+pub fn encrypt_block_js(data: Vec<Uint8Array>) -> SoxResult<Vec<u8>> {
     let values_vec: Vec<Vec<u8>> = data.iter().map(uint8_array_to_vec_u8).collect();
     let refs: Vec<&Vec<u8>> = values_vec.iter().collect();
     encrypt_block(&refs)
@@ -94,7 +104,7 @@ pub fn encrypt_block_js(data: Vec<Uint8Array>) -> Vec<u8> {
 /// # Returns
 /// Decrypted bytes
 #[wasm_bindgen]
-pub fn decrypt_block_js(data: Vec<Uint8Array>) -> Vec<u8> {
+pub fn decrypt_block_js(data: Vec<Uint8Array>) -> SoxResult<Vec<u8>> {
     let values_vec: Vec<Vec<u8>> = data.iter().map(uint8_array_to_vec_u8).collect();
     let refs: Vec<&Vec<u8>> = values_vec.iter().collect();
     decrypt_block(&refs)
@@ -109,17 +119,31 @@ pub fn decrypt_block_js(data: Vec<Uint8Array>) -> Vec<u8> {
 ///
 /// # Returns
 /// Encrypted/decrypted data
-fn internal_encrypt(key: &[u8], block: &[u8], ctr: &[u8]) -> Vec<u8> {
-    let mut res = vec![0u8; block.len()];
-    res.clone_from_slice(block);
-
-    let mut cipher = match Aes128Ctr128BE::new_from_slices(key, ctr) {
-        Ok(c) => c,
-        Err(_) => die("Key should be 16 bytes"),
-    };
+fn internal_encrypt(key: &[u8], block: &[u8], ctr: &[u8]) -> SoxResult<Vec<u8>> {
+    let mut res = block.to_vec();
+
+    let mut cipher =
+        Aes128Ctr128BE::new_from_slices(key, ctr).map_err(|_| SoxError::KeyLength {
+            expected: 16,
+            got: key.len(),
+        })?;
     cipher.apply_keystream(&mut res);
 
-    res
+    Ok(res)
+}
+
+// Keystream of a 64-byte block for AES-128 in CTR mode, without allocating
+pub(crate) fn keystream64(key: &[u8], ctr: &[u8]) -> SoxResult<[u8; 64]> {
+    let mut res = [0u8; 64];
+
+    let mut cipher =
+        Aes128Ctr128BE::new_from_slices(key, ctr).map_err(|_| SoxError::KeyLength {
+            expected: 16,
+            got: key.len(),
+        })?;
+    cipher.apply_keystream(&mut res);
+
+    Ok(res)
 }
 
 // =================================================================================================
@@ -133,23 +157,42 @@ mod tests {
     fn test_aes_ctr_blocks() {
         let mut rng = rand::rng();
         for i in 1..(1 << 12) {
-            let mut data = vec![0u8; i];
+            // blocks are at most 64 bytes long
+            let mut data = vec![0u8; 1 + i % 64];
             rng.fill_bytes(&mut data);
             let data_orig = data.clone();
 
             let mut key = vec![0u8; 16];
             rng.fill_bytes(&mut key);
 
-            let mut ctr = vec![0u8; 64];
+            let mut ctr = vec![0u8; 16];
             rng.fill_bytes(&mut ctr);
 
             // encrypt
-            let ct = encrypt_block(&vec![&key, &data, &ctr]);
+            let ct = encrypt_block(&[&key, &data, &ctr]).unwrap();
 
             // decrypt
-            let pt = decrypt_block(&vec![&key, &ct, &ctr]);
+            let pt = decrypt_block(&[&key, &ct, &ctr]).unwrap();
 
             assert_eq!(pt, data_orig)
         }
     }
+
+    #[test]
+    fn test_aes_ctr_invalid_inputs() {
+        let key = vec![0u8; 15];
+        let ctr = vec![0u8; 16];
+        let data = vec![0u8; 64];
+        assert_eq!(
+            encrypt_block(&[&key, &data, &ctr]).unwrap_err().code(),
+            "SOX_KEY_LENGTH"
+        );
+
+        let key = vec![0u8; 16];
+        let too_long = vec![0u8; 65];
+        assert_eq!(
+            encrypt_block(&[&key, &too_long, &ctr]).unwrap_err().code(),
+            "SOX_INVALID_INPUT"
+        );
+    }
 }
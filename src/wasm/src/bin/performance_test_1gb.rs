@@ -1,13 +1,8 @@
 use anyhow::{bail, Context, Result};
-use crypto_lib::{
-    compute_precontract_values_v2,
-    evaluate_circuit_v2_wasm,
-    hpre_v2,
-};
-use hex::encode;
+use crypto_lib::native::{evaluate_circuit_v2, hpre_v2, PrecontractBuilder};
 use rand::RngCore;
 use serde::Serialize;
-use std::fs;
+use std::fs::{self, File};
 use std::path::PathBuf;
 use std::time::Instant;
 
@@ -52,7 +47,7 @@ struct MemoryStats {
 }
 
 fn main() -> Result<()> {
-    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
     if args.is_empty() {
         bail!("Usage: performance_test_1gb <input_file> [hex_key]");
     }
@@ -80,14 +75,6 @@ fn main() -> Result<()> {
         rnd.to_vec()
     };
 
-    // Load file fully
-    println!("📖 Loading file into memory...");
-    let load_start = Instant::now();
-    let mut file_bytes = fs::read(&input_path)
-        .with_context(|| format!("reading {:?}", input_path))?;
-    let load_time = load_start.elapsed();
-    println!("✅ File loaded: {:.2} ms\n", load_time.as_secs_f64() * 1000.0);
-
     let mut timings = Timings {
         encryption_ms: 0.0,
         circuit_compilation_ms: 0.0,
@@ -112,7 +99,12 @@ fn main() -> Result<()> {
     println!("   - Commitment computation");
     
     let precontract_start = Instant::now();
-    let precontract = compute_precontract_values_v2(&mut file_bytes, &key);
+    // The file is streamed from disk; only the ciphertext is kept since the evaluation needs it
+    let input = File::open(&input_path).with_context(|| format!("opening {:?}", input_path))?;
+    let mut builder =
+        PrecontractBuilder::new(&key, Vec::with_capacity(16 + file_size_bytes as usize))?;
+    builder.read_from(input)?;
+    let (precontract, ct) = builder.finalize()?;
     timings.precontract_total_ms = precontract_start.elapsed().as_secs_f64() * 1000.0;
     timings.precontract_total_s = timings.precontract_total_ms / 1000.0;
     
@@ -128,11 +120,7 @@ fn main() -> Result<()> {
     println!("🔍 Evaluating circuit (V2)...");
     let evaluation_start = Instant::now();
     
-    let evaluated = evaluate_circuit_v2_wasm(
-        &precontract.circuit_bytes,
-        &precontract.ct,
-        encode(&key),
-    );
+    let evaluated = evaluate_circuit_v2(&precontract.circuit_bytes, &ct, &key)?;
     let evaluated_bytes = evaluated.to_bytes();
     
     timings.evaluation_ms = evaluation_start.elapsed().as_secs_f64() * 1000.0;
@@ -149,7 +137,7 @@ fn main() -> Result<()> {
     let challenge = num_gates / 2; // Middle challenge
     let hpre_start = Instant::now();
     
-    let hpre_result = hpre_v2(&evaluated_bytes, num_blocks as usize, challenge as usize);
+    let hpre_result = hpre_v2(&evaluated_bytes, num_blocks as usize, challenge as usize)?;
     
     timings.hpre_single_ms = hpre_start.elapsed().as_secs_f64() * 1000.0;
     timings.hpre_all_rounds_ms = timings.hpre_single_ms * expected_rounds as f64;
@@ -185,7 +173,7 @@ fn main() -> Result<()> {
     
     // Memory stats (approximate)
     let memory = MemoryStats {
-        peak_rss_mb: (file_size_bytes * 3) as f64 / (1024.0 * 1024.0), // Rough estimate: file + ct + circuit
+        peak_rss_mb: (file_size_bytes * 2) as f64 / (1024.0 * 1024.0), // Rough estimate: ct + circuit
     };
     
     // ===== SUMMARY =====
@@ -0,0 +1,649 @@
+use anyhow::{bail, Context, Result};
+use crypto_lib::hex_to_bytes;
+use crypto_lib::native::{
+    self, AnyCircuit, CircuitVersion, FinalStep, FinalStepV2, PrecontractBuilder, Proof,
+    SoxCircuit, StreamedPrecontract,
+};
+use hex::encode;
+use rand::RngCore;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+const USAGE: &str = "Usage: sox <command> [--v1] [--out <file>] <args...>
+
+Commands:
+  precontract     <file> [key]
+  check           <ct> <description> <commitment> <opening_value>
+  decrypt-check   <ct> <key> <description>
+  evaluate        <circuit> <ct> <key>
+  hpre            <evaluated_circuit> <num_blocks> <challenge>
+  prove           <circuit> <evaluated_circuit> <ct> <challenge>
+  prove-left      <circuit> <evaluated_circuit> <ct> <challenge>
+  prove-right     <evaluated_circuit> <num_blocks> <num_gates>
+  compute-proofs  <state> <evaluated_circuit> <num_blocks> <num_gates>
+                  [circuit] [ct] [challenge]
+  make-argument   <ct> <description> <opening_value>
+  check-argument  <argument> <commitment> <description> <key>
+  disasm          <circuit> [gate]
+  dot             <circuit> [gate]
+
+Binary arguments can be given either as a path to a file or as a hex string. Every command prints
+its result as JSON on stdout.
+
+Commands taking a circuit or an evaluated circuit detect its generation: `evaluate`, `hpre`,
+`prove`, `prove-left`, `prove-right`, `compute-proofs`, `disasm` and `dot`. `precontract`, `check`,
+`make-argument` and `check-argument` use V2 circuits unless --v1 is given.
+
+--out writes the binary output of `evaluate`, `decrypt-check` and `make-argument` to a file
+instead of printing it as hex.
+
+`compute-proofs` picks the proofs expected by the dispute contract in the given state: 2
+(WaitVendorData) runs `prove`, 3 (WaitVendorDataLeft) runs `prove-left` and 4 (WaitVendorDataRight)
+runs `prove-right`. The circuit, ciphertext and challenge are only needed in states 2 and 3.
+
+`disasm` prints one line per gate of a circuit and `dot` exports its gate graph for Graphviz. Gates
+are numbered like in the dispute contract; the optional gate (e.g. the challenge a dispute
+converged on) is printed on its own by `disasm` and highlighted by `dot`. --out writes the
+disassembly or the graph to a file.";
+
+// States of the dispute contract (`DisputeSOX.State`) in which the vendor has to send proofs
+const STATE_WAIT_VENDOR_DATA: u32 = 2;
+const STATE_WAIT_VENDOR_DATA_LEFT: u32 = 3;
+const STATE_WAIT_VENDOR_DATA_RIGHT: u32 = 4;
+
+// Command line of a subcommand once the flags have been removed
+struct Args {
+    positional: Vec<String>,
+    v1: bool,
+    out: Option<PathBuf>,
+}
+
+impl Args {
+    fn parse(mut raw: impl Iterator<Item = String>) -> Result<Args> {
+        let mut args = Args {
+            positional: Vec::new(),
+            v1: false,
+            out: None,
+        };
+        while let Some(arg) = raw.next() {
+            match arg.as_str() {
+                "--v1" => args.v1 = true,
+                "--v2" => args.v1 = false,
+                "--out" => {
+                    let path = raw.next().context("--out expects a file path")?;
+                    args.out = Some(PathBuf::from(path));
+                }
+                _ => args.positional.push(arg),
+            }
+        }
+        Ok(args)
+    }
+
+    // Checks the number of positional arguments. `optional` arguments may be omitted.
+    fn expect(&self, command: &str, names: &[&str], optional: usize) -> Result<()> {
+        let n = self.positional.len();
+        if n > names.len() || n + optional < names.len() {
+            bail!(
+                "`{command}` expects the arguments: {}\n\n{USAGE}",
+                names.join(" ")
+            );
+        }
+        Ok(())
+    }
+
+    fn bytes(&self, idx: usize) -> Result<Vec<u8>> {
+        read_bytes(&self.positional[idx])
+    }
+
+    fn number<T: std::str::FromStr>(&self, idx: usize, name: &str) -> Result<T> {
+        self.positional[idx]
+            .parse()
+            .ok()
+            .with_context(|| format!("invalid {name}: {}", self.positional[idx]))
+    }
+
+    // Writes `bytes` to the --out file if there is one and returns (path, hex) for the JSON output
+    fn emit(&self, bytes: &[u8]) -> Result<(Option<String>, Option<String>)> {
+        match &self.out {
+            Some(path) => {
+                fs::write(path, bytes).with_context(|| format!("writing {:?}", path))?;
+                Ok((Some(path.to_string_lossy().into_owned()), None))
+            }
+            None => Ok((None, Some(encode(bytes)))),
+        }
+    }
+
+    // Same as `emit` for text outputs, which are printed as is when there is no --out file
+    fn emit_text(&self, text: &str) -> Result<(Option<String>, Option<String>)> {
+        match &self.out {
+            Some(path) => {
+                fs::write(path, text).with_context(|| format!("writing {:?}", path))?;
+                Ok((Some(path.to_string_lossy().into_owned()), None))
+            }
+            None => Ok((None, Some(text.to_string()))),
+        }
+    }
+}
+
+// Reads a binary argument: the content of the file if `arg` is a path, its hex decoding otherwise
+fn read_bytes(arg: &str) -> Result<Vec<u8>> {
+    let path = Path::new(arg);
+    if path.is_file() {
+        return fs::read(path).with_context(|| format!("reading {:?}", path));
+    }
+    hex_to_bytes(arg.to_string())
+        .with_context(|| format!("{arg:?} is neither an existing file nor a hex string"))
+}
+
+fn proof_to_hex(proof: &Proof) -> Vec<Vec<String>> {
+    proof
+        .iter()
+        .map(|layer| layer.iter().map(encode).collect())
+        .collect()
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+// ####################################
+// ###     JSON OUTPUTS             ###
+// ####################################
+
+#[derive(Serialize)]
+struct PrecontractOutput {
+    description_hex: String,
+    h_ct_hex: String,
+    h_circuit_hex: String,
+    commitment_c_hex: String,
+    commitment_o_hex: String,
+    num_blocks: u32,
+    num_gates: u32,
+    ciphertext_path: String,
+    circuit_path: String,
+    key_hex: String,
+}
+
+#[derive(Serialize)]
+struct CheckPrecontractOutput {
+    success: bool,
+    h_ct_hex: String,
+    h_circuit_hex: String,
+}
+
+#[derive(Serialize)]
+struct DecryptCheckOutput {
+    success: bool,
+    decrypted_path: Option<String>,
+    decrypted_hex: Option<String>,
+}
+
+#[derive(Serialize)]
+struct EvaluateOutput {
+    num_values: usize,
+    evaluated_circuit_path: Option<String>,
+    evaluated_circuit_hex: Option<String>,
+}
+
+#[derive(Serialize)]
+struct HpreOutput {
+    hpre_hex: String,
+}
+
+#[derive(Serialize)]
+struct FinalStepOutput {
+    /// Flattened gate (V1 only)
+    gate: Option<Vec<u32>>,
+    /// 64-byte encoded gate (V2 only)
+    gate_hex: Option<String>,
+    values: Vec<String>,
+    curr_acc_hex: String,
+    proof1: Vec<Vec<String>>,
+    proof2: Vec<Vec<String>>,
+    proof3: Vec<Vec<String>>,
+    proof_ext: Vec<Vec<String>>,
+}
+
+impl From<FinalStep> for FinalStepOutput {
+    fn from(step: FinalStep) -> Self {
+        FinalStepOutput {
+            gate: Some(step.gate),
+            gate_hex: None,
+            values: step.values.iter().map(encode).collect(),
+            curr_acc_hex: encode(&step.curr_acc),
+            proof1: proof_to_hex(&step.proof1),
+            proof2: proof_to_hex(&step.proof2),
+            proof3: proof_to_hex(&step.proof3),
+            proof_ext: proof_to_hex(&step.proof_ext),
+        }
+    }
+}
+
+impl From<FinalStepV2> for FinalStepOutput {
+    fn from(step: FinalStepV2) -> Self {
+        FinalStepOutput {
+            gate: None,
+            gate_hex: Some(encode(&step.gate_bytes)),
+            values: step.values.iter().map(encode).collect(),
+            curr_acc_hex: encode(&step.curr_acc),
+            proof1: proof_to_hex(&step.proof1),
+            proof2: proof_to_hex(&step.proof2),
+            proof3: proof_to_hex(&step.proof3),
+            proof_ext: proof_to_hex(&step.proof_ext),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ProofOutput {
+    proof: Vec<Vec<String>>, // Each layer is Vec<String> (hex-encoded bytes32)
+}
+
+#[derive(Serialize)]
+struct MakeArgumentOutput {
+    argument_path: Option<String>,
+    argument_hex: Option<String>,
+}
+
+#[derive(Serialize)]
+struct CheckArgumentOutput {
+    is_valid: bool,
+    supports_buyer: bool,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct DisasmOutput {
+    /// Line of the requested gate
+    gate: Option<String>,
+    disassembly_path: Option<String>,
+    lines: Option<Vec<String>>,
+}
+
+#[derive(Serialize)]
+struct DotOutput {
+    dot_path: Option<String>,
+    dot: Option<String>,
+}
+
+// ####################################
+// ###     COMMANDS                 ###
+// ####################################
+
+fn precontract(args: &Args) -> Result<()> {
+    args.expect("precontract", &["<file>", "[key]"], 1)?;
+    let input_path = PathBuf::from(&args.positional[0]);
+    let output_ct = input_path.with_extension("ct");
+    let output_circuit = input_path.with_extension("circuit");
+
+    // key: if provided use hex (must be 16 bytes), else random 16 bytes
+    let key: Vec<u8> = if args.positional.len() > 1 {
+        let key_bytes = args.bytes(1).context("failed to decode key")?;
+        if key_bytes.len() != 16 {
+            bail!("Key must be 16 bytes, got {}", key_bytes.len());
+        }
+        key_bytes
+    } else {
+        let mut rnd = [0u8; 16];
+        rand::rng().fill_bytes(&mut rnd);
+        rnd.to_vec()
+    };
+
+    let file_len = fs::metadata(&input_path)
+        .with_context(|| format!("reading metadata for {:?}", input_path))?
+        .len();
+
+    // Validate file is not empty (after encryption, we need at least 1 byte of data plus the 16-byte IV)
+    if file_len == 0 {
+        bail!("The file is empty. Please select a file containing at least 1 byte of data.");
+    }
+
+    let pre = if args.v1 {
+        let mut file_bytes =
+            fs::read(&input_path).with_context(|| format!("reading {:?}", input_path))?;
+        let pre = native::compute_precontract_values(&mut file_bytes, &key)?;
+        fs::write(&output_ct, &pre.ct)
+            .with_context(|| format!("writing ciphertext to {:?}", output_ct))?;
+        StreamedPrecontract {
+            circuit_bytes: pre.circuit_bytes,
+            description: pre.description,
+            h_ct: pre.h_ct,
+            h_circuit: pre.h_circuit,
+            commitment: pre.commitment,
+            num_blocks: pre.num_blocks,
+            num_gates: pre.num_gates,
+            ct_len: pre.ct.len() as u64,
+        }
+    } else {
+        // V2 precontracts are streamed so that the file and the ciphertext never sit in memory.
+        // The ciphertext is moved to its path once complete, so that an input already ending in
+        // .ct is not truncated before being read.
+        let input = File::open(&input_path).with_context(|| format!("opening {:?}", input_path))?;
+        let mut tmp_name = output_ct.as_os_str().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp_ct = PathBuf::from(tmp_name);
+        let pre = stream_precontract(input, &key, &tmp_ct).and_then(|pre| {
+            fs::rename(&tmp_ct, &output_ct)
+                .with_context(|| format!("writing ciphertext to {:?}", output_ct))?;
+            Ok(pre)
+        });
+        if pre.is_err() {
+            let _ = fs::remove_file(&tmp_ct);
+        }
+        pre?
+    };
+
+    fs::write(&output_circuit, &pre.circuit_bytes)
+        .with_context(|| format!("writing circuit to {:?}", output_circuit))?;
+
+    print_json(&PrecontractOutput {
+        description_hex: encode(pre.description),
+        h_ct_hex: encode(pre.h_ct),
+        h_circuit_hex: encode(pre.h_circuit),
+        commitment_c_hex: encode(pre.commitment.c),
+        commitment_o_hex: encode(pre.commitment.o),
+        num_blocks: pre.num_blocks,
+        num_gates: pre.num_gates,
+        ciphertext_path: output_ct.to_string_lossy().into_owned(),
+        circuit_path: output_circuit.to_string_lossy().into_owned(),
+        key_hex: encode(&key),
+    })
+}
+
+// Streams the V2 precontract of `input`, writing the ciphertext to `ct_path`
+fn stream_precontract(input: File, key: &[u8], ct_path: &Path) -> Result<StreamedPrecontract> {
+    let sink =
+        File::create(ct_path).with_context(|| format!("writing ciphertext to {:?}", ct_path))?;
+    let mut builder = PrecontractBuilder::new(key, BufWriter::new(sink))?;
+    builder.read_from(input)?;
+    Ok(builder.finalize()?.0)
+}
+
+fn check(args: &Args) -> Result<()> {
+    args.expect(
+        "check",
+        &["<ct>", "<description>", "<commitment>", "<opening_value>"],
+        0,
+    )?;
+    let (description, commitment) = (args.bytes(1)?, args.bytes(2)?);
+    let (opening_value, ct) = (args.bytes(3)?, args.bytes(0)?);
+    let res = if args.v1 {
+        native::check_precontract(&description, &commitment, &opening_value, &ct)?
+    } else {
+        native::check_precontract_v2(&description, &commitment, &opening_value, &ct)?
+    };
+
+    print_json(&CheckPrecontractOutput {
+        success: res.success,
+        h_ct_hex: encode(res.h_ct),
+        h_circuit_hex: encode(res.h_circuit),
+    })
+}
+
+fn decrypt_check(args: &Args) -> Result<()> {
+    args.expect("decrypt-check", &["<ct>", "<key>", "<description>"], 0)?;
+    let res = native::check_received_ct_key(&args.bytes(0)?, &args.bytes(1)?, &args.bytes(2)?)?;
+    let (decrypted_path, decrypted_hex) = args.emit(&res.decrypted_file)?;
+
+    print_json(&DecryptCheckOutput {
+        success: res.success,
+        decrypted_path,
+        decrypted_hex,
+    })
+}
+
+fn evaluate(args: &Args) -> Result<()> {
+    args.expect("evaluate", &["<circuit>", "<ct>", "<key>"], 0)?;
+    let (circuit, ct, key) = (args.bytes(0)?, args.bytes(1)?, args.bytes(2)?);
+
+    let evaluated = AnyCircuit::from_bytes(&circuit)?.evaluate(&ct, &key)?;
+    let (evaluated_bytes, num_values) = (evaluated.to_bytes(), evaluated.values().len());
+    let (evaluated_circuit_path, evaluated_circuit_hex) = args.emit(&evaluated_bytes)?;
+
+    print_json(&EvaluateOutput {
+        num_values,
+        evaluated_circuit_path,
+        evaluated_circuit_hex,
+    })
+}
+
+fn hpre(args: &Args) -> Result<()> {
+    args.expect(
+        "hpre",
+        &["<evaluated_circuit>", "<num_blocks>", "<challenge>"],
+        0,
+    )?;
+    let evaluated = args.bytes(0)?;
+    let num_blocks = args.number(1, "num_blocks")?;
+    let challenge = args.number(2, "challenge")?;
+
+    let hpre = match CircuitVersion::detect_evaluated(&evaluated)? {
+        CircuitVersion::V1 => native::hpre(&evaluated, num_blocks, challenge)?,
+        CircuitVersion::V2 => native::hpre_v2(&evaluated, num_blocks, challenge)?,
+    };
+
+    print_json(&HpreOutput {
+        hpre_hex: encode(hpre),
+    })
+}
+
+// Computes the components of step 8a (`left == false`) or step 8b (`left == true`). The
+// generation of the circuit is detected from its encoding and `challenge` is the index used by the
+// dispute contract.
+fn final_step(
+    left: bool,
+    circuit: &[u8],
+    evaluated: &[u8],
+    ct: &[u8],
+    challenge: u32,
+) -> Result<FinalStepOutput> {
+    Ok(match (CircuitVersion::detect(circuit)?, left) {
+        (CircuitVersion::V1, false) => {
+            native::compute_proofs(circuit, evaluated, ct, challenge)?.into()
+        }
+        (CircuitVersion::V1, true) => {
+            native::compute_proofs_left(circuit, evaluated, ct, challenge)?.into()
+        }
+        (CircuitVersion::V2, false) => {
+            native::compute_proofs_v2(circuit, evaluated, ct, challenge)?.into()
+        }
+        (CircuitVersion::V2, true) => {
+            native::compute_proofs_left_v2(circuit, evaluated, ct, challenge)?.into()
+        }
+    })
+}
+
+// Computes the proof of step 8c
+fn proof_right(evaluated: &[u8], num_blocks: u32, num_gates: u32) -> Result<ProofOutput> {
+    let proof = match CircuitVersion::detect_evaluated(evaluated)? {
+        CircuitVersion::V1 => native::compute_proof_right(evaluated, num_blocks, num_gates)?,
+        CircuitVersion::V2 => native::compute_proof_right_v2(evaluated, num_blocks, num_gates)?,
+    };
+
+    Ok(ProofOutput {
+        proof: proof_to_hex(&proof),
+    })
+}
+
+fn prove(args: &Args, left: bool) -> Result<()> {
+    let command = if left { "prove-left" } else { "prove" };
+    args.expect(
+        command,
+        &["<circuit>", "<evaluated_circuit>", "<ct>", "<challenge>"],
+        0,
+    )?;
+    let challenge = args.number(3, "challenge")?;
+
+    print_json(&final_step(
+        left,
+        &args.bytes(0)?,
+        &args.bytes(1)?,
+        &args.bytes(2)?,
+        challenge,
+    )?)
+}
+
+fn prove_right(args: &Args) -> Result<()> {
+    args.expect(
+        "prove-right",
+        &["<evaluated_circuit>", "<num_blocks>", "<num_gates>"],
+        0,
+    )?;
+    let num_blocks = args.number(1, "num_blocks")?;
+    let num_gates = args.number(2, "num_gates")?;
+
+    print_json(&proof_right(&args.bytes(0)?, num_blocks, num_gates)?)
+}
+
+fn compute_proofs(args: &Args) -> Result<()> {
+    args.expect(
+        "compute-proofs",
+        &[
+            "<state>",
+            "<evaluated_circuit>",
+            "<num_blocks>",
+            "<num_gates>",
+            "[circuit]",
+            "[ct]",
+            "[challenge]",
+        ],
+        3,
+    )?;
+    let state: u32 = args.number(0, "state")?;
+    let evaluated = args.bytes(1)?;
+    let num_blocks = args.number(2, "num_blocks")?;
+    let num_gates = args.number(3, "num_gates")?;
+
+    match state {
+        STATE_WAIT_VENDOR_DATA | STATE_WAIT_VENDOR_DATA_LEFT => {
+            if args.positional.len() < 7 {
+                bail!("state {state} requires the circuit, the ciphertext and the challenge");
+            }
+            let challenge = args.number(6, "challenge")?;
+            print_json(&final_step(
+                state == STATE_WAIT_VENDOR_DATA_LEFT,
+                &args.bytes(4)?,
+                &evaluated,
+                &args.bytes(5)?,
+                challenge,
+            )?)
+        }
+        STATE_WAIT_VENDOR_DATA_RIGHT => {
+            print_json(&proof_right(&evaluated, num_blocks, num_gates)?)
+        }
+        _ => bail!(
+            "no proofs to compute in state {state}, expected {STATE_WAIT_VENDOR_DATA} \
+             (WaitVendorData), {STATE_WAIT_VENDOR_DATA_LEFT} (WaitVendorDataLeft) or \
+             {STATE_WAIT_VENDOR_DATA_RIGHT} (WaitVendorDataRight)"
+        ),
+    }
+}
+
+fn make_argument(args: &Args) -> Result<()> {
+    args.expect(
+        "make-argument",
+        &["<ct>", "<description>", "<opening_value>"],
+        0,
+    )?;
+    let (ct, description, opening_value) = (args.bytes(0)?, args.bytes(1)?, args.bytes(2)?);
+    let argument = if args.v1 {
+        native::make_argument(ct, &description, opening_value)?
+    } else {
+        native::make_argument_v2(ct, &description, opening_value)?
+    };
+    let (argument_path, argument_hex) = args.emit(&argument)?;
+
+    print_json(&MakeArgumentOutput {
+        argument_path,
+        argument_hex,
+    })
+}
+
+fn check_argument(args: &Args) -> Result<()> {
+    args.expect(
+        "check-argument",
+        &["<argument>", "<commitment>", "<description>", "<key>"],
+        0,
+    )?;
+    let (argument, commitment) = (args.bytes(0)?, args.bytes(1)?);
+    let (description, key) = (args.bytes(2)?, args.bytes(3)?);
+    let res = if args.v1 {
+        native::check_argument(&argument, &commitment, &description, &key)?
+    } else {
+        native::check_argument_v2(&argument, &commitment, &description, &key)?
+    };
+
+    print_json(&CheckArgumentOutput {
+        is_valid: res.is_valid,
+        supports_buyer: res.supports_buyer,
+        error: res.error,
+    })
+}
+
+fn disasm(args: &Args) -> Result<()> {
+    args.expect("disasm", &["<circuit>", "[gate]"], 1)?;
+    let disassembly = native::disassemble(&AnyCircuit::from_bytes(&args.bytes(0)?)?);
+    let gate = if args.positional.len() > 1 {
+        let gate: u32 = args.number(1, "gate")?;
+        let prefix = format!("g{gate} = ");
+        let line = disassembly
+            .lines()
+            .find(|line| line.starts_with(&prefix))
+            .with_context(|| format!("no gate {gate} in the circuit"))?;
+        Some(line.to_string())
+    } else {
+        None
+    };
+    let (disassembly_path, text) = args.emit_text(&disassembly)?;
+
+    print_json(&DisasmOutput {
+        gate,
+        disassembly_path,
+        lines: text.map(|text| text.lines().map(String::from).collect()),
+    })
+}
+
+fn dot(args: &Args) -> Result<()> {
+    args.expect("dot", &["<circuit>", "[gate]"], 1)?;
+    let highlight = match args.positional.len() {
+        1 => None,
+        _ => Some(args.number(1, "gate")?),
+    };
+    let dot = native::circuit_to_dot(&AnyCircuit::from_bytes(&args.bytes(0)?)?, highlight);
+    let (dot_path, dot) = args.emit_text(&dot)?;
+
+    print_json(&DotOutput { dot_path, dot })
+}
+
+fn main() -> Result<()> {
+    // Flags can be given anywhere, the first positional argument is the command
+    let mut args = Args::parse(std::env::args().skip(1))?;
+    if args.positional.is_empty() {
+        bail!("{USAGE}");
+    }
+    let command = args.positional.remove(0);
+
+    match command.as_str() {
+        "precontract" => precontract(&args),
+        "check" => check(&args),
+        "decrypt-check" => decrypt_check(&args),
+        "evaluate" => evaluate(&args),
+        "hpre" => hpre(&args),
+        "prove" => prove(&args, false),
+        "prove-left" => prove(&args, true),
+        "prove-right" => prove_right(&args),
+        "compute-proofs" => compute_proofs(&args),
+        "make-argument" => make_argument(&args),
+        "check-argument" => check_argument(&args),
+        "disasm" => disasm(&args),
+        "dot" => dot(&args),
+        "help" | "--help" | "-h" => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        _ => bail!("unknown command `{command}`\n\n{USAGE}"),
+    }
+}
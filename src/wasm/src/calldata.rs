@@ -0,0 +1,251 @@
+//! ABI encoding of the calls to the `DisputeSOX` contract. Each function returns the complete
+//! calldata of an entry point (4-byte selector followed by the encoded arguments), as produced by
+//! `contract.interface.encodeFunctionData` on the TypeScript side.
+
+use crate::error::{SoxError, SoxResult};
+use crate::native::{FinalStepV2, Proof};
+use ethabi::{encode, short_signature, ParamType, Token};
+
+// Parameter type of a `bytes32[][]` proof
+fn proof_param() -> ParamType {
+    let layer = ParamType::Array(Box::new(ParamType::FixedBytes(32)));
+    ParamType::Array(Box::new(layer))
+}
+
+// Parameter types of `submitCommitment` (with `proof3`) or `submitCommitmentLeft` (without)
+fn submit_params(with_proof3: bool) -> Vec<ParamType> {
+    let mut params = vec![
+        ParamType::Bytes,
+        ParamType::Uint(32),
+        ParamType::Bytes,
+        ParamType::Array(Box::new(ParamType::Bytes)),
+        ParamType::FixedBytes(32),
+        proof_param(),
+        proof_param(),
+    ];
+    if with_proof3 {
+        params.push(proof_param());
+    }
+    params.push(proof_param());
+    params
+}
+
+// Encodes a proof as a `bytes32[][]` token
+fn proof_token(proof: &Proof) -> Token {
+    Token::Array(
+        proof
+            .iter()
+            .map(|layer| {
+                Token::Array(
+                    layer
+                        .iter()
+                        .map(|node| Token::FixedBytes(node.to_vec()))
+                        .collect(),
+                )
+            })
+            .collect(),
+    )
+}
+
+// Encodes a `bytes32` argument, which must be exactly 32 bytes long: ethabi would silently pad a
+// shorter value
+fn bytes32_token(name: &str, value: &[u8]) -> SoxResult<Token> {
+    if value.len() != 32 {
+        return Err(SoxError::InvalidInput(format!(
+            "{name} must be 32 bytes long, got {}",
+            value.len()
+        )));
+    }
+    Ok(Token::FixedBytes(value.to_vec()))
+}
+
+// Concatenates the selector of `name(params)` and the encoded arguments
+fn calldata(name: &str, params: &[ParamType], args: &[Token]) -> Vec<u8> {
+    let mut data = short_signature(name, params).to_vec();
+    data.extend(encode(args));
+    data
+}
+
+// Calldata of `submitCommitment` or `submitCommitmentLeft`
+fn submit_calldata(
+    name: &str,
+    opening_value: &[u8],
+    gate_num: u32,
+    step: &FinalStepV2,
+    with_proof3: bool,
+) -> SoxResult<Vec<u8>> {
+    let mut args = vec![
+        Token::Bytes(opening_value.to_vec()),
+        Token::Uint(gate_num.into()),
+        Token::Bytes(step.gate_bytes.clone()),
+        Token::Array(step.values.iter().cloned().map(Token::Bytes).collect()),
+        bytes32_token("curr_acc", &step.curr_acc)?,
+        proof_token(&step.proof1),
+        proof_token(&step.proof2),
+    ];
+    if with_proof3 {
+        args.push(proof_token(&step.proof3));
+    }
+    args.push(proof_token(&step.proof_ext));
+    Ok(calldata(name, &submit_params(with_proof3), &args))
+}
+
+/// Encodes a call to `submitCommitment` (step 8a).
+///
+/// # Arguments
+/// * `opening_value` - Opening value of the commitment
+/// * `gate_num` - Current challenge of the dispute
+/// * `step` - Components computed by `compute_proofs_v2`
+///
+/// # Returns
+/// The calldata, or `SoxError::InvalidInput` if the current accumulator is not 32 bytes long
+pub fn submit_commitment_calldata(
+    opening_value: &[u8],
+    gate_num: u32,
+    step: &FinalStepV2,
+) -> SoxResult<Vec<u8>> {
+    submit_calldata("submitCommitment", opening_value, gate_num, step, true)
+}
+
+/// Encodes a call to `submitCommitmentLeft` (step 8b). The `proof3` component of `step` is not
+/// part of the call.
+///
+/// # Arguments
+/// * `opening_value` - Opening value of the commitment
+/// * `gate_num` - Current challenge of the dispute
+/// * `step` - Components computed by `compute_proofs_left_v2`
+///
+/// # Returns
+/// The calldata, or `SoxError::InvalidInput` if the current accumulator is not 32 bytes long
+pub fn submit_commitment_left_calldata(
+    opening_value: &[u8],
+    gate_num: u32,
+    step: &FinalStepV2,
+) -> SoxResult<Vec<u8>> {
+    submit_calldata("submitCommitmentLeft", opening_value, gate_num, step, false)
+}
+
+/// Encodes a call to `submitCommitmentRight` (step 8c).
+///
+/// # Arguments
+/// * `proof` - Proof computed by `compute_proof_right_v2`
+///
+/// # Returns
+/// The calldata
+pub fn submit_commitment_right_calldata(proof: &Proof) -> Vec<u8> {
+    calldata(
+        "submitCommitmentRight",
+        &[proof_param()],
+        &[proof_token(proof)],
+    )
+}
+
+/// Encodes a call to `respondChallenge`.
+///
+/// # Arguments
+/// * `response` - The buyer's response, hpre(challenge)
+///
+/// # Returns
+/// The calldata, or `SoxError::InvalidInput` if the response is not 32 bytes long
+pub fn respond_challenge_calldata(response: &[u8]) -> SoxResult<Vec<u8>> {
+    Ok(calldata(
+        "respondChallenge",
+        &[ParamType::FixedBytes(32)],
+        &[bytes32_token("response", response)?],
+    ))
+}
+
+/// Encodes a call to `giveOpinion`.
+///
+/// # Arguments
+/// * `vendor_agrees` - Whether the vendor agrees with the buyer's latest response
+///
+/// # Returns
+/// The calldata
+pub fn give_opinion_calldata(vendor_agrees: bool) -> Vec<u8> {
+    calldata(
+        "giveOpinion",
+        &[ParamType::Bool],
+        &[Token::Bool(vendor_agrees)],
+    )
+}
+
+// =================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethabi::decode;
+    use sha3::{Digest, Keccak256};
+
+    // Splits calldata into its selector and arguments, checking the selector against the
+    // canonical signature of the function
+    fn split(data: &[u8], signature: &str) -> Vec<u8> {
+        assert_eq!(data[..4], Keccak256::digest(signature.as_bytes())[..4]);
+        data[4..].to_vec()
+    }
+
+    #[test]
+    fn test_calldata_round_trip() {
+        let step = FinalStepV2 {
+            gate_bytes: (0..64).collect(),
+            values: vec![vec![1; 64], vec![2; 3], vec![]],
+            curr_acc: vec![7; 32],
+            proof1: vec![vec![[1; 32], [2; 32]], vec![], vec![[3; 32]]],
+            proof2: vec![vec![[4; 32]]],
+            proof3: vec![vec![[5; 32]], vec![[6; 32]]],
+            proof_ext: vec![],
+        };
+        let opening = vec![9u8; 80];
+
+        let data = submit_commitment_calldata(&opening, 12, &step).unwrap();
+        let args = split(
+            &data,
+            "submitCommitment(bytes,uint32,bytes,bytes[],bytes32,bytes32[][],bytes32[][],bytes32[][],bytes32[][])",
+        );
+        let tokens = decode(&submit_params(true), &args).unwrap();
+        assert_eq!(tokens[0], Token::Bytes(opening.clone()));
+        assert_eq!(tokens[1], Token::Uint(12.into()));
+        assert_eq!(tokens[2], Token::Bytes(step.gate_bytes.clone()));
+        assert_eq!(
+            tokens[3],
+            Token::Array(step.values.iter().cloned().map(Token::Bytes).collect())
+        );
+        assert_eq!(tokens[4], Token::FixedBytes(step.curr_acc.clone()));
+        assert_eq!(tokens[5], proof_token(&step.proof1));
+        assert_eq!(tokens[7], proof_token(&step.proof3));
+        assert_eq!(tokens[8], proof_token(&step.proof_ext));
+
+        let data = submit_commitment_left_calldata(&opening, 1, &step).unwrap();
+        let args = split(
+            &data,
+            "submitCommitmentLeft(bytes,uint32,bytes,bytes[],bytes32,bytes32[][],bytes32[][],bytes32[][])",
+        );
+        let tokens = decode(&submit_params(false), &args).unwrap();
+        assert_eq!(tokens.len(), 8);
+        assert_eq!(tokens[6], proof_token(&step.proof2));
+        assert_eq!(tokens[7], proof_token(&step.proof_ext));
+
+        let data = submit_commitment_right_calldata(&step.proof1);
+        let args = split(&data, "submitCommitmentRight(bytes32[][])");
+        assert_eq!(
+            decode(&[proof_param()], &args).unwrap(),
+            vec![proof_token(&step.proof1)]
+        );
+
+        let data = respond_challenge_calldata(&[3; 32]).unwrap();
+        assert_eq!(split(&data, "respondChallenge(bytes32)"), vec![3; 32]);
+
+        let data = give_opinion_calldata(true);
+        let args = split(&data, "giveOpinion(bool)");
+        assert_eq!(args[..31], [0; 31]);
+        assert_eq!(args[31], 1);
+
+        assert!(respond_challenge_calldata(&[3; 31]).is_err());
+        let short_acc = FinalStepV2 {
+            curr_acc: vec![7; 20],
+            ..step
+        };
+        assert!(submit_commitment_calldata(&opening, 12, &short_acc).is_err());
+    }
+}
@@ -0,0 +1,305 @@
+//! Typed construction of V2 circuits. `CircuitBuilderV2` hands out `Wire`s for the inputs (the
+//! ciphertext blocks, dummy gates g_{-1}, g_{-2}, ...) and for the outputs of the gates it adds
+//! (g_1, g_2, ...), so that circuits can be composed without counting son indices by hand.
+
+use crate::circuits_v2::{
+    opcode_info, CompiledCircuitV2, GateV2, OPCODE_AES_CTR, OPCODE_COMP, OPCODE_CONST, OPCODE_SHA2,
+    OPCODE_XOR,
+};
+use crate::error::{SoxError, SoxResult};
+
+/// Value of a V2 circuit: an input block or the output of a gate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Wire(i64);
+
+impl Wire {
+    /// Returns the son index of the wire in the 64-byte gate format: -i for the i-th input,
+    /// i for the output of the i-th gate (both 1-indexed).
+    pub fn son(self) -> i64 {
+        self.0
+    }
+
+    /// Returns whether the wire is an input of the circuit
+    pub fn is_input(self) -> bool {
+        self.0 < 0
+    }
+}
+
+/// Builder of V2 circuits. Each gate is checked against the opcode table when it is added.
+#[derive(Clone, Debug, Default)]
+pub struct CircuitBuilderV2 {
+    num_inputs: usize,
+    gates: Vec<GateV2>,
+}
+
+impl CircuitBuilderV2 {
+    /// Creates a builder without inputs or gates
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of inputs declared so far
+    pub fn num_inputs(&self) -> usize {
+        self.num_inputs
+    }
+
+    /// Returns the number of gates added so far
+    pub fn num_gates(&self) -> usize {
+        self.gates.len()
+    }
+
+    /// Declares the next input of the circuit, i.e. the next ciphertext block.
+    ///
+    /// # Returns
+    /// The wire of the input
+    pub fn input(&mut self) -> Wire {
+        self.num_inputs += 1;
+        Wire(-(self.num_inputs as i64))
+    }
+
+    /// Declares the next `n` inputs of the circuit.
+    ///
+    /// # Arguments
+    /// * `n` - Number of inputs
+    ///
+    /// # Returns
+    /// The wires of the inputs, in order
+    pub fn inputs(&mut self, n: usize) -> Vec<Wire> {
+        (0..n).map(|_| self.input()).collect()
+    }
+
+    // Checks that a wire was handed out by this builder
+    fn check_wire(&self, wire: Wire) -> SoxResult<()> {
+        let valid = match wire.0 {
+            son if son < 0 => son.unsigned_abs() as usize <= self.num_inputs,
+            son => son >= 1 && son as usize <= self.gates.len(),
+        };
+        if !valid {
+            return Err(SoxError::Index(format!(
+                "wire {} does not belong to the circuit ({} inputs, {} gates)",
+                wire.0,
+                self.num_inputs,
+                self.gates.len()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Adds a gate, checking its arity and the length of its params against `OPCODES_V2`.
+    ///
+    /// # Arguments
+    /// * `opcode` - Opcode of the gate
+    /// * `sons` - Input wires of the gate
+    /// * `params` - Opcode-specific params
+    ///
+    /// # Returns
+    /// The output wire of the gate, `SoxError::CircuitShape` if the gate does not match the opcode
+    /// table or `SoxError::Index` if a son does not belong to the circuit
+    pub fn gate(&mut self, opcode: u8, sons: &[Wire], params: &[u8]) -> SoxResult<Wire> {
+        let info = opcode_info(opcode)?;
+        if !info.arities.contains(&sons.len()) {
+            return Err(SoxError::CircuitShape(format!(
+                "{} gate expects arity {:?}, got {}",
+                info.name,
+                info.arities,
+                sons.len()
+            )));
+        }
+        if params.len() != info.params_len {
+            return Err(SoxError::CircuitShape(format!(
+                "{} gate expects {} bytes of params, got {}",
+                info.name,
+                info.params_len,
+                params.len()
+            )));
+        }
+        for &son in sons {
+            self.check_wire(son)?;
+        }
+
+        self.gates.push(GateV2 {
+            opcode,
+            sons: sons.iter().map(|son| son.0).collect(),
+            params: params.to_vec(),
+        });
+        Ok(Wire(self.gates.len() as i64))
+    }
+
+    /// Adds an AES-CTR gate decrypting a block.
+    ///
+    /// # Arguments
+    /// * `block` - Encrypted block
+    /// * `counter` - Counter of the block
+    /// * `len_bits` - Number of bits of the block to keep, the others are zeroed
+    ///
+    /// # Returns
+    /// The decrypted block
+    pub fn aes_ctr(&mut self, block: Wire, counter: &[u8; 16], len_bits: u16) -> SoxResult<Wire> {
+        let mut params = [0u8; 18];
+        params[..16].copy_from_slice(counter);
+        params[16..].copy_from_slice(&len_bits.to_be_bytes());
+        self.gate(OPCODE_AES_CTR, &[block], &params)
+    }
+
+    /// Adds a SHA2 gate compressing a block with the SHA-256 IV.
+    ///
+    /// # Arguments
+    /// * `block` - 64-byte block
+    ///
+    /// # Returns
+    /// The hash state, in the first 32 bytes of the output
+    pub fn sha2(&mut self, block: Wire) -> SoxResult<Wire> {
+        self.gate(OPCODE_SHA2, &[block], &[])
+    }
+
+    /// Adds a SHA2 gate compressing a block with a previous hash state.
+    ///
+    /// # Arguments
+    /// * `prev` - Previous hash state, in its first 32 bytes
+    /// * `block` - 64-byte block
+    ///
+    /// # Returns
+    /// The hash state, in the first 32 bytes of the output
+    pub fn sha2_chain(&mut self, prev: Wire, block: Wire) -> SoxResult<Wire> {
+        self.gate(OPCODE_SHA2, &[prev, block], &[])
+    }
+
+    /// Adds a CONST gate outputting `value || 0^32`.
+    ///
+    /// # Arguments
+    /// * `value` - 32-byte constant
+    ///
+    /// # Returns
+    /// The wire of the constant
+    pub fn constant(&mut self, value: &[u8; 32]) -> SoxResult<Wire> {
+        self.gate(OPCODE_CONST, &[], value)
+    }
+
+    /// Adds a CONST gate outputting the first 32 bytes of `head` followed by `tail`.
+    ///
+    /// # Arguments
+    /// * `head` - Wire whose first 32 bytes are kept
+    /// * `tail` - 32-byte constant
+    ///
+    /// # Returns
+    /// The wire of the concatenation
+    pub fn constant_after(&mut self, head: Wire, tail: &[u8; 32]) -> SoxResult<Wire> {
+        self.gate(OPCODE_CONST, &[head], tail)
+    }
+
+    /// Adds the two CONST gates outputting a 64-byte constant.
+    ///
+    /// # Arguments
+    /// * `value` - 64-byte constant
+    ///
+    /// # Returns
+    /// The wire of the constant
+    pub fn constant64(&mut self, value: &[u8; 64]) -> SoxResult<Wire> {
+        let (head, tail) = value.split_at(32);
+        let head = self.constant(head.try_into().expect("split at 32"))?;
+        self.constant_after(head, tail.try_into().expect("split at 32"))
+    }
+
+    /// Adds a XOR gate.
+    ///
+    /// # Arguments
+    /// * `a`, `b` - Wires to xor, padded to 64 bytes
+    ///
+    /// # Returns
+    /// The wire of `a ^ b`
+    pub fn xor(&mut self, a: Wire, b: Wire) -> SoxResult<Wire> {
+        self.gate(OPCODE_XOR, &[a, b], &[])
+    }
+
+    /// Adds a COMP gate comparing the first 32 bytes of two wires.
+    ///
+    /// # Arguments
+    /// * `a`, `b` - Wires to compare
+    ///
+    /// # Returns
+    /// The wire of the result, whose first byte is 1 if they are equal and 0 otherwise
+    pub fn comp(&mut self, a: Wire, b: Wire) -> SoxResult<Wire> {
+        self.gate(OPCODE_COMP, &[a, b], &[])
+    }
+
+    /// Finishes the circuit. Its output is the output of the last gate.
+    ///
+    /// # Returns
+    /// The compiled circuit, or `SoxError::CircuitShape` if it has no gates
+    pub fn build(self) -> SoxResult<CompiledCircuitV2> {
+        if self.gates.is_empty() {
+            return Err(SoxError::CircuitShape(
+                "circuit must have at least one gate".into(),
+            ));
+        }
+        Ok(CompiledCircuitV2 {
+            version: 1,
+            gates: self.gates,
+            block_size: 64,
+            num_blocks: self.num_inputs as u32,
+        })
+    }
+}
+
+// =================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuits_v2::evaluate_circuit_v2;
+    use crate::sha256::sha256;
+
+    #[test]
+    fn test_builder_checks_gates() {
+        let mut builder = CircuitBuilderV2::new();
+        let block = builder.input();
+        assert_eq!(block.son(), -1);
+        assert!(block.is_input());
+
+        let hash = builder.sha2(block).unwrap();
+        assert_eq!(hash.son(), 1);
+        assert!(!hash.is_input());
+
+        // arity, params length, unknown opcode and foreign wires are rejected
+        assert!(builder.gate(OPCODE_XOR, &[hash], &[]).is_err());
+        assert!(builder.gate(OPCODE_CONST, &[], &[0; 31]).is_err());
+        assert!(builder.gate(0x42, &[], &[]).is_err());
+        assert!(builder.xor(hash, Wire(2)).is_err());
+        assert!(builder.xor(hash, Wire(-2)).is_err());
+        assert!(builder.xor(hash, Wire(0)).is_err());
+        assert_eq!(builder.num_gates(), 1);
+
+        assert!(CircuitBuilderV2::new().build().is_err());
+    }
+
+    #[test]
+    fn test_builder_hash_preimage_circuit() {
+        // checks that the single input block, padded by the circuit, hashes to a description
+        let data = b"custom sox predicate";
+        let mut padded = [0u8; 64];
+        padded[..data.len()].copy_from_slice(data);
+        padded[data.len()] = 0x80;
+        padded[56..].copy_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+        let description: [u8; 32] = sha256(data).try_into().unwrap();
+
+        let mut builder = CircuitBuilderV2::new();
+        let block = builder.input();
+        let mut mask = [0u8; 64];
+        mask[data.len()..].copy_from_slice(&padded[data.len()..]);
+        let mask = builder.constant64(&mask).unwrap();
+        let padded_block = builder.xor(block, mask).unwrap();
+        let hash = builder.sha2(padded_block).unwrap();
+        let expected = builder.constant(&description).unwrap();
+        builder.comp(hash, expected).unwrap();
+        let circuit = builder.build().unwrap();
+        assert_eq!(circuit.num_blocks, 1);
+        assert_eq!(circuit.gates.len(), 6);
+
+        let eval = |block: &[u8]| {
+            let values = evaluate_circuit_v2(&circuit.gates, &[block.to_vec()], &[0; 16]).unwrap();
+            values.last().unwrap()[0]
+        };
+        assert_eq!(eval(data), 1);
+        assert_eq!(eval(b"another predicate"), 0);
+    }
+}
@@ -1,4 +1,5 @@
-use crate::utils::die;
+use crate::error::{SoxError, SoxResult};
+use crate::labels::{CircuitLabels, GateRole};
 use crate::{aes_ctr, sha256, simple_operations};
 use ethabi::{encode, Token};
 use rmp_serde::encode::write;
@@ -7,7 +8,7 @@ use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::wasm_bindgen;
 
 /// Function type for instructions
-type Instruction = fn(data: &Vec<&Vec<u8>>) -> Vec<u8>;
+type Instruction = fn(data: &[&Vec<u8>]) -> SoxResult<Vec<u8>>;
 
 fn version_instructions(version: usize) -> Vec<Instruction> {
     match version {
@@ -114,7 +115,7 @@ impl CompiledCircuit {
     /// Returns a vector containing the serialized circuit data.
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut buf = Vec::new();
-        write(&mut buf, self).unwrap();
+        write(&mut buf, self).expect("serializing into a Vec cannot fail");
         buf
     }
 
@@ -124,9 +125,9 @@ impl CompiledCircuit {
     /// * `bytes` - The serialized circuit bytes
     ///
     /// # Returns
-    /// A new `CompiledCircuit` instance
-    pub fn from_bytes(bytes: &[u8]) -> CompiledCircuit {
-        from_read(bytes).unwrap()
+    /// A new `CompiledCircuit` instance, or `SoxError::Decode` if the bytes are malformed
+    pub fn from_bytes(bytes: &[u8]) -> SoxResult<CompiledCircuit> {
+        Ok(from_read(bytes)?)
     }
 }
 
@@ -139,16 +140,23 @@ impl CompiledCircuit {
     ///
     /// # Returns
     /// A new `CompiledCircuitWithConstants` instance with the bound constants
-    pub fn bind_constants(&self, constants: Vec<Vec<u8>>) -> CompiledCircuitWithConstants {
+    pub fn bind_constants(
+        &self,
+        constants: Vec<Vec<u8>>,
+    ) -> SoxResult<CompiledCircuitWithConstants> {
         if constants.len() != self.constants.len() {
-            die("The number of constants does not match the number of constants in the circuit");
+            return Err(SoxError::CircuitShape(format!(
+                "expected {} constants, got {}",
+                self.constants.len(),
+                constants.len()
+            )));
         }
-        CompiledCircuitWithConstants {
+        Ok(CompiledCircuitWithConstants {
             circuit: self.circuit.clone(),
             constants,
             version: self.version,
             block_size: self.block_size,
-        }
+        })
     }
 
     /// Binds only missing constants to the circuit. Replaces the `None` values with the ones
@@ -159,7 +167,10 @@ impl CompiledCircuit {
     ///
     /// # Returns
     /// A new `CompiledCircuitWithConstants` instance with all constants bound
-    pub fn bind_missing_constants(&self, constants: Vec<Vec<u8>>) -> CompiledCircuitWithConstants {
+    pub fn bind_missing_constants(
+        &self,
+        constants: Vec<Vec<u8>>,
+    ) -> SoxResult<CompiledCircuitWithConstants> {
         let mut all_constants = Vec::with_capacity(self.constants.len());
         let mut i = 0;
 
@@ -168,7 +179,7 @@ impl CompiledCircuit {
                 all_constants.push(val.to_owned());
             } else {
                 if i >= constants.len() {
-                    die("Not enough constants to bind");
+                    return Err(SoxError::CircuitShape("not enough constants to bind".into()));
                 }
                 all_constants.push(constants[i].to_owned());
                 i += 1;
@@ -185,13 +196,13 @@ impl CompiledCircuit {
         let mut res = vec![self.version.to_be_bytes().to_vec()];
         for g in &self.circuit {
             let mut buf = Vec::new();
-            write(&mut buf, g).unwrap();
+            write(&mut buf, g).expect("serializing into a Vec cannot fail");
             res.push(buf);
         }
 
         for c in &self.constants {
             let mut buf = Vec::new();
-            write(&mut buf, c).unwrap();
+            write(&mut buf, c).expect("serializing into a Vec cannot fail");
             res.push(buf);
         }
 
@@ -233,36 +244,33 @@ fn compile_basic_circuit_one_block(
     description: &[u8],
     block_size: u32,
 ) -> CompiledCircuit {
-    let mut circuit: Vec<Gate> = Vec::with_capacity(5);
-
-    // dummy gates
-    circuit.push(Gate::dummy());
-    circuit.push(Gate::dummy());
-
-    // AES decryption gate
-    circuit.push(Gate {
-        opcode: 2,
-        sons: vec![
-            array_idx_to_constant_idx(3), // key
-            1,                            // blocks to encrypt
-            0,                            // counter
-        ],
-    });
-
-    // SHA + pad gate
-    circuit.push(Gate {
-        opcode: 7,
-        sons: vec![
-            2,                            // block to hash
-            array_idx_to_constant_idx(2), // ciphertext size
-        ],
-    });
-
-    // comparison gate
-    circuit.push(Gate {
-        opcode: 5,
-        sons: vec![3, array_idx_to_constant_idx(1)],
-    });
+    let circuit: Vec<Gate> = vec![
+        // dummy gates
+        Gate::dummy(),
+        Gate::dummy(),
+        // AES decryption gate
+        Gate {
+            opcode: 2,
+            sons: vec![
+                array_idx_to_constant_idx(3), // key
+                1,                            // blocks to encrypt
+                0,                            // counter
+            ],
+        },
+        // SHA + pad gate
+        Gate {
+            opcode: 7,
+            sons: vec![
+                2,                            // block to hash
+                array_idx_to_constant_idx(2), // ciphertext size
+            ],
+        },
+        // comparison gate
+        Gate {
+            opcode: 5,
+            sons: vec![3, array_idx_to_constant_idx(1)],
+        },
+    ];
 
     CompiledCircuit {
         circuit,
@@ -286,21 +294,27 @@ fn compile_basic_circuit_one_block(
 /// * `description` - Description of the plaintext
 ///
 /// # Returns
-/// A `CompiledCircuit` configured for the given parameters
+/// A `CompiledCircuit` configured for the given parameters, or `SoxError::InvalidInput` if the
+/// ciphertext is shorter than 17 bytes
 #[wasm_bindgen]
-pub fn compile_basic_circuit(ct_size: u32, description: &[u8]) -> CompiledCircuit {
+pub fn compile_basic_circuit(ct_size: u32, description: &[u8]) -> SoxResult<CompiledCircuit> {
     let block_size = 64;
+    if ct_size < 17 {
+        return Err(SoxError::InvalidInput(
+            "the ciphertext's length should be at least 17 bytes (incl. IV)".into(),
+        ));
+    }
     let pt_size = ct_size - 16; // remove the size of the iv
     let ct_blocks_number = 1  // iv
-            + pt_size / block_size // number of blocks of the plaintext
-            + if pt_size % block_size == 0 { 0 } else { 1 }; // ceiling
-    if ct_blocks_number < 2 {
-        die("The ciphertext's length should be at least 17 bytes (incl. IV)");
-    }
+            + pt_size.div_ceil(block_size); // number of blocks of the plaintext
 
     if ct_blocks_number == 2 {
         // special case where pt has 1 block of data
-        return compile_basic_circuit_one_block(ct_size, description, block_size);
+        return Ok(compile_basic_circuit_one_block(
+            ct_size,
+            description,
+            block_size,
+        ));
     }
 
     // m dummy gates
@@ -386,7 +400,7 @@ pub fn compile_basic_circuit(ct_size: u32, description: &[u8]) -> CompiledCircui
         sons: vec![4 * ct_blocks_number - 5, array_idx_to_constant_idx(1)],
     });
 
-    CompiledCircuit {
+    Ok(CompiledCircuit {
         circuit: gates,
         constants: vec![
             Some(4u16.to_be_bytes().to_vec()),             // counter increment
@@ -397,7 +411,59 @@ pub fn compile_basic_circuit(ct_size: u32, description: &[u8]) -> CompiledCircui
         version: 0,
         block_size,
         num_blocks: ct_blocks_number,
+    })
+}
+
+/// Compiles a basic circuit like `compile_basic_circuit`, along with the labels of its gates (see
+/// `CircuitLabels`). The labels are not part of the circuit nor of its accumulator.
+///
+/// # Arguments
+/// * `ct_size` - Size of the ciphertext (including IV!)
+/// * `description` - Description of the plaintext
+///
+/// # Returns
+/// The compiled circuit and its labels, or `SoxError::InvalidInput` if the ciphertext is shorter
+/// than 17 bytes
+pub fn compile_basic_circuit_with_labels(
+    ct_size: u32,
+    description: &[u8],
+) -> SoxResult<(CompiledCircuit, CircuitLabels)> {
+    let circuit = compile_basic_circuit(ct_size, description)?;
+    let labels = basic_circuit_labels(circuit.num_blocks, ct_size - 16, circuit.block_size);
+    Ok((circuit, labels))
+}
+
+// labels the gates of a basic circuit of m blocks (IV included), following the layout of
+// compile_basic_circuit and compile_basic_circuit_one_block
+fn basic_circuit_labels(m: u32, pt_size: u32, block_size: u32) -> CircuitLabels {
+    let mut labels = CircuitLabels::new(pt_size as u64, block_size);
+
+    // dummy gates: the IV, then the ciphertext blocks
+    labels.push(0, GateRole::Input, None);
+    for i in 1..m {
+        labels.push(i, GateRole::Input, Some(i - 1));
+    }
+
+    if m == 2 {
+        labels.push(2, GateRole::Decrypt, Some(0));
+        labels.push(3, GateRole::HashStep, Some(0));
+        labels.push(4, GateRole::Compare, None);
+        return labels;
     }
+
+    // counters of the blocks 1..m-1, the one of block 0 is the IV
+    for j in 0..m - 2 {
+        labels.push(m + j, GateRole::CounterIncrement, Some(j + 1));
+    }
+    for k in 0..m - 1 {
+        labels.push(2 * m - 2 + k, GateRole::Decrypt, Some(k));
+    }
+    // the last compression gate also pads
+    for k in 0..m - 1 {
+        labels.push(3 * m - 3 + k, GateRole::HashStep, Some(k));
+    }
+    labels.push(4 * m - 4, GateRole::Compare, None);
+    labels
 }
 
 // ============================= EVALUATION =============================
@@ -431,23 +497,33 @@ pub struct CompiledCircuitWithConstants {
 /// Vector of references to the evaluated values for the gate's sons
 pub fn get_evaluated_sons<'a>(
     gate: &Gate,
-    evaluated_circuit: &'a Vec<Vec<u8>>,
-    constants: &'a Vec<Vec<u8>>,
-) -> Vec<&'a Vec<u8>> {
+    evaluated_circuit: &'a [Vec<u8>],
+    constants: &'a [Vec<u8>],
+) -> SoxResult<Vec<&'a Vec<u8>>> {
     let mut sons = Vec::with_capacity(gate.sons.len());
 
     for &s in &gate.sons {
         if !is_constant_idx(s) {
             if s >= evaluated_circuit.len() as u32 {
-                die("Gates should not have non constant sons after themselves in the circuit");
+                return Err(SoxError::CircuitShape(format!(
+                    "son {s} is not evaluated yet: gates should not have non constant sons after \
+                     themselves in the circuit"
+                )));
             }
             sons.push(&evaluated_circuit[s as usize]);
         } else if !constants.is_empty() {
-            sons.push(&constants[constant_idx_to_array_idx(s)]);
+            let idx = constant_idx_to_array_idx(s);
+            let constant = constants.get(idx).ok_or_else(|| {
+                SoxError::Index(format!(
+                    "constant {idx} out of bounds ({} constants)",
+                    constants.len()
+                ))
+            })?;
+            sons.push(constant);
         }
     }
 
-    sons
+    Ok(sons)
 }
 
 /// Evaluates a circuit with the given input and constants
@@ -461,28 +537,44 @@ pub fn get_evaluated_sons<'a>(
 pub fn evaluate_circuit_internal(
     input: &[Vec<u8>],
     compiled_circuit: CompiledCircuitWithConstants,
-) -> Vec<Vec<u8>> {
+) -> SoxResult<Vec<Vec<u8>>> {
     let instructions = version_instructions(compiled_circuit.version as usize);
 
     let mut evaluated_circuit: Vec<Vec<u8>> = Vec::with_capacity(compiled_circuit.circuit.len());
 
-    for i in 0..input.len() {
+    if input.len() > compiled_circuit.circuit.len() {
+        return Err(SoxError::CircuitShape(format!(
+            "the ciphertext is too large: {} blocks for a circuit of {} gates",
+            input.len(),
+            compiled_circuit.circuit.len()
+        )));
+    }
+
+    for (i, block) in input.iter().enumerate() {
         if !compiled_circuit.circuit[i].is_dummy() {
-            die(&format!("The ciphertext is too large, the number of blocks for the cipher text in this circuit should be {}", i));
+            return Err(SoxError::CircuitShape(format!(
+                "the ciphertext is too large, the number of blocks for the ciphertext in this \
+                 circuit should be {i}"
+            )));
         }
-        evaluated_circuit.push(input[i].clone());
+        evaluated_circuit.push(block.clone());
     }
 
     for gate in &compiled_circuit.circuit[input.len()..] {
         if gate.is_dummy() {
-            die("The ciphertext is too small");
+            return Err(SoxError::CircuitShape("the ciphertext is too small".into()));
         }
-        let sons = get_evaluated_sons(gate, &evaluated_circuit, &compiled_circuit.constants);
+        let sons = get_evaluated_sons(gate, &evaluated_circuit, &compiled_circuit.constants)?;
 
-        let op = instructions[gate.opcode as usize];
+        let op = instructions.get(gate.opcode as usize).ok_or_else(|| {
+            SoxError::CircuitShape(format!(
+                "invalid opcode {} for instruction set version {}",
+                gate.opcode, compiled_circuit.version
+            ))
+        })?;
 
-        evaluated_circuit.push(op(&sons));
+        evaluated_circuit.push(op(&sons)?);
     }
 
-    evaluated_circuit
+    Ok(evaluated_circuit)
 }
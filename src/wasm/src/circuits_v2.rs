@@ -1,11 +1,11 @@
+use crate::accumulator::{AccumulatorHash, Keccak256Hasher};
 use crate::aes_ctr;
-use crate::utils::die;
-use crate::accumulator::{acc, acc_fixed64, hash_block64};
-use crate::sha256::sha256_compress;
-use sha3::{Digest, Keccak256};
+use crate::circuit_builder::{CircuitBuilderV2, Wire};
+use crate::error::{SoxError, SoxResult};
+use crate::labels::{CircuitLabels, GateRole};
+use crate::sha256::sha256_compress64;
 use rmp_serde::{encode::write, from_read};
 use serde::{Deserialize, Serialize};
-use crate::sha256::sha256;
 
 /// Opcodes for the new 64-byte gate format.
 pub const OPCODE_AES_CTR: u8 = 0x01;
@@ -14,14 +14,119 @@ pub const OPCODE_CONST: u8 = 0x03;
 pub const OPCODE_XOR: u8 = 0x04;
 pub const OPCODE_COMP: u8 = 0x05;
 
+/// Arities and parameter length of an opcode of the 64-byte gate format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OpcodeInfo {
+    pub opcode: u8,
+    pub name: &'static str,
+    pub arities: &'static [usize], // accepted numbers of sons, in increasing order
+    pub params_len: usize,
+}
+
+/// Opcode table of V2 circuits, matching `_paramsLength` in `EvaluatorSOX_V2.sol` and the
+/// arities accepted by the evaluation functions.
+pub const OPCODES_V2: [OpcodeInfo; 5] = [
+    OpcodeInfo {
+        opcode: OPCODE_AES_CTR,
+        name: "AES_CTR",
+        arities: &[1],
+        params_len: 18, // counter (16B) + length in bits (2B)
+    },
+    OpcodeInfo {
+        opcode: OPCODE_SHA2,
+        name: "SHA2",
+        arities: &[1, 2],
+        params_len: 0,
+    },
+    OpcodeInfo {
+        opcode: OPCODE_CONST,
+        name: "CONST",
+        arities: &[0, 1],
+        params_len: 32,
+    },
+    OpcodeInfo {
+        opcode: OPCODE_XOR,
+        name: "XOR",
+        arities: &[2],
+        params_len: 0,
+    },
+    OpcodeInfo {
+        opcode: OPCODE_COMP,
+        name: "COMP",
+        arities: &[2],
+        params_len: 0,
+    },
+];
+
+/// Looks up an opcode in `OPCODES_V2`.
+/// Returns `SoxError::CircuitShape` for unknown opcodes.
+pub fn opcode_info(opcode: u8) -> SoxResult<&'static OpcodeInfo> {
+    OPCODES_V2
+        .iter()
+        .find(|info| info.opcode == opcode)
+        .ok_or_else(|| SoxError::CircuitShape(format!("invalid opcode {opcode} in GateV2")))
+}
+
+/// Value of a V2 gate or input. Values have at most 64 bytes: they are stored zero-padded in a
+/// fixed array along with their length, since SHA2 gates output 32 bytes and the last ciphertext
+/// block may be shorter than 64 bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Value64 {
+    bytes: [u8; 64],
+    len: u8,
+}
+
+impl Value64 {
+    const ZERO: Value64 = Value64 {
+        bytes: [0; 64],
+        len: 0,
+    };
+
+    // Value of the first len bytes of a 64-byte array, whose other bytes must be zero
+    fn new(bytes: [u8; 64], len: usize) -> Self {
+        Self {
+            bytes,
+            len: len as u8,
+        }
+    }
+
+    // Value of some bytes, at most 64
+    fn from_slice(v: &[u8]) -> SoxResult<Self> {
+        if v.len() > 64 {
+            return Err(SoxError::InvalidInput(format!(
+                "V2 values have at most 64 bytes, got {}",
+                v.len()
+            )));
+        }
+        let mut bytes = [0u8; 64];
+        bytes[..v.len()].copy_from_slice(v);
+        Ok(Self::new(bytes, v.len()))
+    }
+
+    // Value of a 32-byte SHA-256 state
+    fn from_state(state: [u8; 32]) -> Self {
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(&state);
+        Self::new(bytes, 32)
+    }
+
+    fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.bytes[..self.len()]
+    }
+}
+
 /// Function type for V2 instructions.
 /// Takes sons (input values), params (gate-specific parameters), and aes_key (for AES-CTR gates).
-type InstructionV2 = fn(sons: &[Vec<u8>], params: &[u8], aes_key: &[u8]) -> Vec<u8>;
+type InstructionV2 = fn(sons: &[&Value64], params: &[u8], aes_key: &[u8]) -> SoxResult<Value64>;
 
 /// Returns the instruction table for V2 circuits.
 /// This function provides a list of instruction functions indexed by opcode.
-fn version_instructions_v2() -> Vec<InstructionV2> {
-    vec![
+fn version_instructions_v2() -> [InstructionV2; 5] {
+    [
         instruction_aes_ctr,  // opcode 0x01
         instruction_sha2,     // opcode 0x02
         instruction_const,    // opcode 0x03
@@ -31,32 +136,32 @@ fn version_instructions_v2() -> Vec<InstructionV2> {
 }
 
 /// Instruction wrapper for AES-CTR opcode.
-fn instruction_aes_ctr(sons: &[Vec<u8>], params: &[u8], aes_key: &[u8]) -> Vec<u8> {
+fn instruction_aes_ctr(sons: &[&Value64], params: &[u8], aes_key: &[u8]) -> SoxResult<Value64> {
     eval_aes_ctr(sons, params, aes_key)
 }
 
 /// Instruction wrapper for SHA2 opcode.
-fn instruction_sha2(sons: &[Vec<u8>], _params: &[u8], _aes_key: &[u8]) -> Vec<u8> {
+fn instruction_sha2(sons: &[&Value64], _params: &[u8], _aes_key: &[u8]) -> SoxResult<Value64> {
     eval_sha2(sons)
 }
 
 /// Instruction wrapper for CONST opcode.
-fn instruction_const(sons: &[Vec<u8>], params: &[u8], _aes_key: &[u8]) -> Vec<u8> {
+fn instruction_const(sons: &[&Value64], params: &[u8], _aes_key: &[u8]) -> SoxResult<Value64> {
     eval_const(sons, params)
 }
 
 /// Instruction wrapper for XOR opcode.
-fn instruction_xor(sons: &[Vec<u8>], _params: &[u8], _aes_key: &[u8]) -> Vec<u8> {
+fn instruction_xor(sons: &[&Value64], _params: &[u8], _aes_key: &[u8]) -> SoxResult<Value64> {
     eval_xor(sons)
 }
 
 /// Instruction wrapper for COMP opcode.
-fn instruction_comp(sons: &[Vec<u8>], _params: &[u8], _aes_key: &[u8]) -> Vec<u8> {
+fn instruction_comp(sons: &[&Value64], _params: &[u8], _aes_key: &[u8]) -> SoxResult<Value64> {
     eval_comp(sons)
 }
 
 /// A gate encoded with the new 64-byte format.
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct GateV2 {
     pub opcode: u8,
     pub sons: Vec<i64>,  // signed, 6B each; negative => dummy
@@ -67,61 +172,108 @@ impl GateV2 {
     /// Encode the gate into the 64-byte layout:
     /// opcode (1B) | sons (arity * 6B) | params | zero padding up to 64B.
     /// Optimized to use stack-allocated buffer.
-    pub fn encode(&self) -> Vec<u8> {
+    pub fn encode(&self) -> SoxResult<Vec<u8>> {
         let mut out = [0u8; 64];
+        self.encode_into(&mut out)?;
+
+        Ok(out.to_vec())
+    }
+
+    /// Encode directly into a provided buffer (avoids allocation).
+    /// Returns `SoxError::CircuitShape` if the sons and params do not fit in 64 bytes.
+    pub fn encode_into(&self, out: &mut [u8; 64]) -> SoxResult<()> {
+        out.fill(0);
         out[0] = self.opcode;
 
         for (i, s) in self.sons.iter().enumerate() {
             let offset = 1 + i * 6;
             if offset + 6 > 64 {
-                die("Too many sons to fit in a 64-byte gate encoding");
+                return Err(SoxError::CircuitShape(
+                    "too many sons to fit in a 64-byte gate encoding".into(),
+                ));
             }
-            out[offset..offset + 6].copy_from_slice(&encode_i64_6(*s));
+            out[offset..offset + 6].copy_from_slice(&encode_i64_6(*s)?);
         }
 
         let params_start = 1 + self.sons.len() * 6;
         let params_end = params_start + self.params.len();
         if params_end > 64 {
-            die("Parameters do not fit in a 64-byte gate encoding");
+            return Err(SoxError::CircuitShape(
+                "parameters do not fit in a 64-byte gate encoding".into(),
+            ));
         }
         out[params_start..params_end].copy_from_slice(&self.params);
 
-        out.to_vec()
+        Ok(())
     }
-    
-    /// Encode directly into a provided buffer (avoids allocation).
-    /// Buffer must be at least 64 bytes.
-    pub fn encode_into(&self, out: &mut [u8; 64]) {
-        out.fill(0);
-        out[0] = self.opcode;
 
-        for (i, s) in self.sons.iter().enumerate() {
-            let offset = 1 + i * 6;
-            if offset + 6 > 64 {
-                die("Too many sons to fit in a 64-byte gate encoding");
-            }
-            out[offset..offset + 6].copy_from_slice(&encode_i64_6(*s));
+    /// Decode a 64-byte gate whose arity is known, like `decodeGate(gateBytes, expectedArity)` in
+    /// `EvaluatorSOX_V2.sol`. The contract takes the arity from the number of son values
+    /// submitted with the gate.
+    /// Returns `SoxError::CircuitShape` for unknown opcodes, out-of-bounds params or non-zero
+    /// padding.
+    pub(crate) fn decode_with_arity(bytes: &[u8], arity: usize) -> SoxResult<GateV2> {
+        if bytes.len() != 64 {
+            return Err(SoxError::CircuitShape(format!(
+                "gate must be exactly 64 bytes, got {}",
+                bytes.len()
+            )));
         }
-
-        let params_start = 1 + self.sons.len() * 6;
-        let params_end = params_start + self.params.len();
+        let opcode = bytes[0];
+        let params_len = opcode_info(opcode)?.params_len;
+        let params_start = 1 + arity * 6;
+        let params_end = params_start + params_len;
         if params_end > 64 {
-            die("Parameters do not fit in a 64-byte gate encoding");
+            return Err(SoxError::CircuitShape("params out of bounds".into()));
         }
-        out[params_start..params_end].copy_from_slice(&self.params);
+        if bytes[params_end..].iter().any(|&b| b != 0) {
+            return Err(SoxError::CircuitShape("non-zero padding".into()));
+        }
+
+        let sons = bytes[1..params_start]
+            .chunks(6)
+            .map(decode_i64_6)
+            .collect();
+        Ok(GateV2 {
+            opcode,
+            sons,
+            params: bytes[params_start..params_end].to_vec(),
+        })
     }
-}
 
-/// Helper to encode a gate without constructing GateV2 manually.
-pub fn encode_gate_v2(opcode: u8, sons: &[i64], params: &[u8]) -> Vec<u8> {
-    GateV2 {
-        opcode,
-        sons: sons.to_vec(),
-        params: params.to_vec(),
+    /// Decode a 64-byte gate, inverse of `encode`, like `decodeGate(gateBytes)` in
+    /// `EvaluatorSOX_V2.sol`. The arity is the smallest one of `OPCODES_V2` for which the padding
+    /// after the params is zero and no son is zero (son 0 does not exist), so a CONST gate with a
+    /// son whose constant ends with 6 zero bytes decodes as a CONST gate without sons, as on-chain.
+    /// Returns `SoxError::CircuitShape` for unknown opcodes or if no arity fits.
+    pub fn decode(bytes: &[u8; 64]) -> SoxResult<GateV2> {
+        let info = opcode_info(bytes[0])?;
+        let arity = info
+            .arities
+            .iter()
+            .copied()
+            .find(|&arity| {
+                let params_end = 1 + arity * 6 + info.params_len;
+                params_end <= 64
+                    && bytes[params_end..].iter().all(|&b| b == 0)
+                    && bytes[1..1 + arity * 6]
+                        .chunks(6)
+                        .all(|son| son.iter().any(|&b| b != 0))
+            })
+            .ok_or_else(|| {
+                SoxError::CircuitShape(format!("unable to infer the arity of a {} gate", info.name))
+            })?;
+        Self::decode_with_arity(bytes, arity)
     }
-    .encode()
 }
 
+// Below this number of gates, a level of the wavefront is evaluated on the current thread: the
+// SHA chain is a long series of single-gate levels. Browsers always use a single thread.
+#[cfg(not(target_arch = "wasm32"))]
+const PAR_LEVEL_MIN_GATES: usize = 64;
+#[cfg(target_arch = "wasm32")]
+const PAR_LEVEL_MIN_GATES: usize = usize::MAX;
+
 /// Evaluate a circuit composed of GateV2.
 ///
 /// According to the spec:
@@ -131,74 +283,238 @@ pub fn encode_gate_v2(opcode: u8, sons: &[i64], params: &[u8]) -> Vec<u8> {
 ///   - Negative (-m to -1): points to dummy gates (inputs)
 ///   - Positive (1 to i-1): points to previous real gates
 ///
+/// Gates are evaluated level by level (see `Wavefront`), the gates of a level in parallel on
+/// native builds, so that e.g. all AES-CTR gates are evaluated at once. The values are the same
+/// as with an evaluation in gate order, and so is the error of an invalid circuit: the one of its
+/// first invalid gate.
+///
 /// * `gates`   - ordered gates to evaluate after the inputs (g_1, g_2, ..., g_n)
-/// * `inputs`  - initial 64B values (e.g., ciphertext blocks) (ct1, ct2, ..., ctm)
+/// * `inputs`  - initial values of at most 64B (e.g., ciphertext blocks) (ct1, ct2, ..., ctm)
 /// * `aes_key` - AES-128 key used by AES-CTR gates (16B)
 pub fn evaluate_circuit_v2(
     gates: &[GateV2],
     inputs: &[Vec<u8>],
     aes_key: &[u8],
-) -> Vec<Vec<u8>> {
+) -> SoxResult<Vec<Vec<u8>>> {
+    use rayon::prelude::*;
+
     if aes_key.len() != 16 {
-        die("AES key must be 16 bytes");
+        return Err(SoxError::KeyLength {
+            expected: 16,
+            got: aes_key.len(),
+        });
     }
 
-    // Get the instruction table for V2
-    let instructions = version_instructions_v2();
-
+    // Value arena: ct1, ..., ctm, then the outputs of g_1, ..., g_n
     let m = inputs.len();
-    let mut values: Vec<Vec<u8>> = Vec::with_capacity(gates.len());
+    let mut values: Vec<Value64> = Vec::with_capacity(m + gates.len());
+    for input in inputs {
+        values.push(Value64::from_slice(input)?);
+    }
+    values.resize(m + gates.len(), Value64::ZERO);
+
+    let wavefront = Wavefront::new(gates, m);
+    let mut first_error: Option<(usize, SoxError)> = None;
+    for level in wavefront.levels() {
+        if level.len() < PAR_LEVEL_MIN_GATES {
+            for &gate_idx in level {
+                let out = eval_gate_in_arena(&gates[gate_idx], &values, m, aes_key);
+                store_value(&mut values, &mut first_error, m, gate_idx, out);
+            }
+        } else {
+            let outputs: Vec<SoxResult<Value64>> = level
+                .par_iter()
+                .map(|&gate_idx| eval_gate_in_arena(&gates[gate_idx], &values, m, aes_key))
+                .collect();
+            for (&gate_idx, out) in level.iter().zip(outputs) {
+                store_value(&mut values, &mut first_error, m, gate_idx, out);
+            }
+        }
+    }
 
-    for (gate_idx, gate) in gates.iter().enumerate() {
-        // Current gate is g_{gate_idx + 1} (1-indexed)
-        let current_gate_num = (gate_idx + 1) as i64;
-        
-        let sons: Vec<Vec<u8>> = gate
-            .sons
-            .iter()
-            .map(|&idx| {
-                if idx < 0 {
-                    // Negative index: points to dummy gate g_{idx}
-                    // g_{-1} = ct1 (input[0]), g_{-2} = ct2 (input[1]), etc.
-                    let input_idx = (-idx - 1) as usize;
-                    if input_idx >= m {
-                        die(&format!("Dummy gate index {} out of bounds (m={})", idx, m));
-                    }
-                    inputs
-                        .get(input_idx)
-                        .cloned()
-                        .unwrap_or_else(|| die("Negative son index out of bounds"))
-                } else {
-                    // Positive index: points to previous real gate (1-indexed)
-                    // g_1 = values[0], g_2 = values[1], etc.
-                    // So we need to convert: array_idx = (idx - 1)
-                    if idx == 0 {
-                        die("Gate index cannot be 0 (gates are 1-indexed)");
+    // the gate with an invalid son comes after all the evaluated ones
+    match (first_error, wavefront.son_error) {
+        (Some((_, err)), _) | (None, Some(err)) => Err(err),
+        (None, None) => Ok(values[m..].iter().map(|v| v.as_slice().to_vec()).collect()),
+    }
+}
+
+// Gates of a V2 circuit grouped by dependency level: gates whose sons are all inputs are at level
+// 0, the others are one level above the highest of their gate sons. The gates of a level only
+// depend on the previous levels.
+struct Wavefront {
+    // indices of the gates (0-based), level by level, in gate order within a level
+    order: Vec<usize>,
+
+    // start of each level in order, followed by order.len()
+    starts: Vec<usize>,
+
+    // error of the first gate with an invalid son, which is left out with all the next gates
+    son_error: Option<SoxError>,
+}
+
+impl Wavefront {
+    fn new(gates: &[GateV2], m: usize) -> Self {
+        let mut depths: Vec<usize> = Vec::with_capacity(gates.len());
+        let mut son_error = None;
+        'gates: for (gate_idx, gate) in gates.iter().enumerate() {
+            let mut depth = 0;
+            for &son in &gate.sons {
+                match son_gate(son, gate_idx, m) {
+                    Ok(Some(son_idx)) => depth = depth.max(depths[son_idx] + 1),
+                    Ok(None) => {}
+                    Err(err) => {
+                        son_error = Some(err);
+                        break 'gates;
                     }
-                    let array_idx = (idx - 1) as usize;
-                    values
-                        .get(array_idx)
-                        .cloned()
-                        .unwrap_or_else(|| die(&format!(
-                            "Son index {} out of bounds in gate {} (values.len()={})",
-                            idx, current_gate_num, values.len()
-                        )))
                 }
-            })
-            .collect();
+            }
+            depths.push(depth);
+        }
+
+        // stable counting sort of the gates by level
+        let num_levels = depths.iter().max().map_or(0, |&depth| depth + 1);
+        let mut starts = vec![0; num_levels + 1];
+        for &depth in &depths {
+            starts[depth + 1] += 1;
+        }
+        for level in 1..starts.len() {
+            starts[level] += starts[level - 1];
+        }
+        let mut next = starts.clone();
+        let mut order = vec![0; depths.len()];
+        for (gate_idx, &depth) in depths.iter().enumerate() {
+            order[next[depth]] = gate_idx;
+            next[depth] += 1;
+        }
+
+        Self {
+            order,
+            starts,
+            son_error,
+        }
+    }
+
+    // Gates of each level, from level 0
+    fn levels(&self) -> impl Iterator<Item = &[usize]> {
+        self.starts
+            .windows(2)
+            .map(|bounds| &self.order[bounds[0]..bounds[1]])
+    }
+}
+
+// Checks a son of g_{gate_idx + 1} and returns the index of the gate it points to, or None if it
+// points to an input
+fn son_gate(son: i64, gate_idx: usize, m: usize) -> SoxResult<Option<usize>> {
+    let current_gate_num = gate_idx + 1;
+    if son < 0 {
+        // g_{-1} = ct1 (input[0]), g_{-2} = ct2 (input[1]), etc.
+        if son.unsigned_abs() > m as u64 {
+            return Err(SoxError::Index(format!(
+                "dummy gate index {son} out of bounds in gate {current_gate_num} (m={m})"
+            )));
+        }
+        Ok(None)
+    } else if son == 0 {
+        Err(SoxError::CircuitShape(format!(
+            "son index 0 in gate {current_gate_num} (gates are 1-indexed)"
+        )))
+    } else if son as u64 > gate_idx as u64 {
+        Err(SoxError::Index(format!(
+            "son index {son} out of bounds in gate {current_gate_num} (values.len()={gate_idx})"
+        )))
+    } else {
+        Ok(Some(son as usize - 1))
+    }
+}
+
+// Index of a valid son in the value arena of evaluate_circuit_v2
+fn arena_slot(son: i64, m: usize) -> usize {
+    if son < 0 {
+        son.unsigned_abs() as usize - 1
+    } else {
+        m + son as usize - 1
+    }
+}
+
+// Evaluates a gate whose sons are in the value arena. Valid gates have at most 2 sons (see
+// OPCODES_V2), the sons of the others are only gathered to report the arity error of their opcode.
+fn eval_gate_in_arena(
+    gate: &GateV2,
+    values: &[Value64],
+    m: usize,
+    aes_key: &[u8],
+) -> SoxResult<Value64> {
+    let son = |idx: i64| &values[arena_slot(idx, m)];
+    match *gate.sons.as_slice() {
+        [] => eval_gate(gate, &[], aes_key),
+        [a] => eval_gate(gate, &[son(a)], aes_key),
+        [a, b] => eval_gate(gate, &[son(a), son(b)], aes_key),
+        ref sons => {
+            let sons: Vec<&Value64> = sons.iter().map(|&idx| son(idx)).collect();
+            eval_gate(gate, &sons, aes_key)
+        }
+    }
+}
 
-        // Use instruction table instead of match
-        let opcode_idx = gate.opcode as usize;
-        if opcode_idx == 0 || opcode_idx > instructions.len() {
-            die(&format!("Invalid opcode {} in GateV2 (must be 1-{})", gate.opcode, instructions.len()));
+// Stores the output of g_{gate_idx + 1} in the value arena, or its error if no gate before it has
+// failed so far
+fn store_value(
+    values: &mut [Value64],
+    first_error: &mut Option<(usize, SoxError)>,
+    m: usize,
+    gate_idx: usize,
+    out: SoxResult<Value64>,
+) {
+    match out {
+        Ok(value) => values[m + gate_idx] = value,
+        Err(err) => {
+            if first_error.as_ref().is_none_or(|&(idx, _)| gate_idx < idx) {
+                *first_error = Some((gate_idx, err));
+            }
         }
-        // Opcodes are 1-indexed (0x01, 0x02, etc.), so subtract 1 for array index
-        let instruction = instructions[opcode_idx - 1];
-        let out = instruction(&sons, &gate.params, aes_key);
-        values.push(out);
     }
+}
 
-    values
+// Evaluates a gate from the values of its sons with the instruction of its opcode
+fn eval_gate(gate: &GateV2, sons: &[&Value64], aes_key: &[u8]) -> SoxResult<Value64> {
+    let instructions = version_instructions_v2();
+    let opcode_idx = gate.opcode as usize;
+    if opcode_idx == 0 || opcode_idx > instructions.len() {
+        return Err(SoxError::CircuitShape(format!(
+            "invalid opcode {} in GateV2 (must be 1-{})",
+            gate.opcode,
+            instructions.len()
+        )));
+    }
+    // Opcodes are 1-indexed (0x01, 0x02, etc.), so subtract 1 for array index
+    instructions[opcode_idx - 1](sons, &gate.params, aes_key)
+}
+
+/// Evaluate a single gate from the values of its sons, like `evaluateGateFromSons` in
+/// `EvaluatorSOX_V2.sol`.
+///
+/// # Arguments
+/// * `gate`    - the gate to evaluate
+/// * `sons`    - values of the sons of the gate (at most 64B each), in the order of `gate.sons`
+/// * `aes_key` - AES-128 key used by AES-CTR gates (16B)
+pub(crate) fn evaluate_gate_v2(
+    gate: &GateV2,
+    sons: &[Vec<u8>],
+    aes_key: &[u8],
+) -> SoxResult<Vec<u8>> {
+    if aes_key.len() != 16 {
+        return Err(SoxError::KeyLength {
+            expected: 16,
+            got: aes_key.len(),
+        });
+    }
+    let sons = sons
+        .iter()
+        .map(|son| Value64::from_slice(son))
+        .collect::<SoxResult<Vec<_>>>()?;
+    let sons: Vec<&Value64> = sons.iter().collect();
+    Ok(eval_gate(gate, &sons, aes_key)?.as_slice().to_vec())
 }
 
 /// Compiled circuit V2 metadata.
@@ -213,30 +529,79 @@ pub struct CompiledCircuitV2 {
 impl CompiledCircuitV2 {
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut buf = Vec::new();
-        write(&mut buf, self).unwrap();
+        write(&mut buf, self).expect("serializing into a Vec cannot fail");
         buf
     }
 
-    pub fn from_bytes(bytes: &[u8]) -> CompiledCircuitV2 {
-        from_read(bytes).unwrap()
+    pub fn from_bytes(bytes: &[u8]) -> SoxResult<CompiledCircuitV2> {
+        Ok(from_read(bytes)?)
     }
 }
 
 /// Compiles a V2 circuit for decrypting a ciphertext and comparing its SHA256 hash
 /// against a known description. The ciphertext format is IV (16B) || data.
 /// The AES key is NOT embedded; it must be provided at evaluation time.
-pub fn compile_circuit_v2(ct: &[u8], description: &[u8]) -> CompiledCircuitV2 {
+pub fn compile_circuit_v2(ct: &[u8], description: &[u8]) -> SoxResult<CompiledCircuitV2> {
+    if ct.len() < 16 {
+        return Err(SoxError::InvalidInput(
+            "ciphertext must include a 16-byte IV".into(),
+        ));
+    }
+    compile_circuit_v2_from_len(&ct[..16], ct.len() - 16, description)
+}
+
+/// Compiles a V2 circuit like `compile_circuit_v2`, along with the labels of its gates (see
+/// `CircuitLabels`). The labels are not part of the circuit nor of its accumulator.
+///
+/// # Arguments
+/// * `ct` - Ciphertext bytes (IV included)
+/// * `description` - SHA-256 hash of the plaintext
+///
+/// # Returns
+/// The compiled circuit and its labels
+pub fn compile_circuit_v2_with_labels(
+    ct: &[u8],
+    description: &[u8],
+) -> SoxResult<(CompiledCircuitV2, CircuitLabels)> {
     if ct.len() < 16 {
-        die("Ciphertext must include a 16-byte IV");
+        return Err(SoxError::InvalidInput(
+            "ciphertext must include a 16-byte IV".into(),
+        ));
     }
-    let iv = &ct[..16];
-    let data = &ct[16..];
+    compile_circuit_v2_with_labels_from_len(&ct[..16], ct.len() - 16, description)
+}
 
+/// Compiles a V2 circuit from the IV and the length of the encrypted data only. The gates of the
+/// circuit do not depend on the ciphertext content, which lets the ciphertext be streamed to its
+/// destination without keeping it in memory (see `PrecontractBuilder`).
+///
+/// # Arguments
+/// * `iv` - The 16-byte IV prepended to the ciphertext
+/// * `pt_len` - Length of the encrypted data, without the IV
+/// * `description` - SHA-256 hash of the plaintext
+///
+/// # Returns
+/// The compiled circuit, or `SoxError::InvalidInput` if there is no data to encrypt
+pub(crate) fn compile_circuit_v2_from_len(
+    iv: &[u8],
+    pt_len: usize,
+    description: &[u8],
+) -> SoxResult<CompiledCircuitV2> {
+    compile_circuit_v2_with_labels_from_len(iv, pt_len, description).map(|(circuit, _)| circuit)
+}
+
+// Compiles a V2 circuit from the IV and the length of the encrypted data, labeling its gates
+fn compile_circuit_v2_with_labels_from_len(
+    iv: &[u8],
+    pt_len: usize,
+    description: &[u8],
+) -> SoxResult<(CompiledCircuitV2, CircuitLabels)> {
     let block_size = 64usize;
-    let pt_len = data.len();
-    let m = (pt_len + block_size - 1) / block_size;
+    let m = pt_len.div_ceil(block_size);
     if m == 0 {
-        die("Ciphertext must contain at least one block");
+        return Err(SoxError::InvalidInput(
+            "ciphertext must contain at least one block".into(),
+        ));
     }
 
     // Note: inputs are not stored, only used for gate construction
@@ -254,121 +619,62 @@ pub fn compile_circuit_v2(ct: &[u8], description: &[u8]) -> CompiledCircuitV2 {
         None
     };
 
-    // Estimate total gates: m (AES) + ~5 (padding) + m' (SHA) + 2 (CONST+COMP) = m + m' + 7
-    // m' = m or m+1 (depending on padding)
-    let estimated_gates = m + m + 1 + 7; // Upper bound
-    let mut gates: Vec<GateV2> = Vec::with_capacity(estimated_gates);
-    let mut block_outputs: Vec<usize> = Vec::with_capacity(m + pad_extra.is_some() as usize);
+    // Inputs are the ciphertext blocks ct_1, ..., ct_m (dummy gates g_{-1}, ..., g_{-m})
+    let mut builder = CircuitBuilderV2::new();
+    let inputs = builder.inputs(m);
+    let mut block_outputs: Vec<Wire> = Vec::with_capacity(m + pad_extra.is_some() as usize);
+    let mut labels = CircuitLabels::new(pt_len as u64, block_size as u32);
 
     // AES gates: g_1, g_2, ..., g_m
-    // Each gate g_i decrypts ciphertext block ct_i (dummy gate g_{-i-1})
+    // Each gate g_i decrypts ciphertext block ct_i (dummy gate g_{-i})
     // Note: Gate creation is fast, parallelization overhead not worth it
-    for i in 0..m {
-        let counter = increment_iv(iv, (i * (block_size / 16)) as u64);
+    for (i, &block) in inputs.iter().enumerate() {
+        let counter = increment_iv(iv, (i * (block_size / 16)) as u64)?;
         let remaining_bits = usize::min(512, (pt_len.saturating_sub(i * block_size)) * 8);
-        let mut params = Vec::with_capacity(18);
-        params.extend_from_slice(&counter);
-        params.extend_from_slice(&(remaining_bits as u16).to_be_bytes());
-
-        let mut sons = Vec::with_capacity(1);
-        sons.push(-(i as i64 + 1)); // negative => dummy gate g_{-(i+1)} = ct_{i+1}
-        gates.push(GateV2 {
-            opcode: OPCODE_AES_CTR,
-            sons,
-            params,
-        });
-        block_outputs.push(gates.len() - 1);
+        let decrypted = builder.aes_ctr(block, &counter, remaining_bits as u16)?;
+        labels.push(decrypted.son() as u32, GateRole::Decrypt, Some(i as u32));
+        block_outputs.push(decrypted);
     }
 
     // Padding on the last block following SHA256 standard:
     // 1. Preserve all original data (positions 0..rem-1)
     // 2. Add 0x80 at position rem (or in extra block if rem = 0)
-    // 3. Zeros are already present after normalization  
+    // 3. Zeros are already present after normalization
     // 4. Add length at positions 56..63 (if fits in first block, else in extra block)
     //
     // We use XOR masks to modify only necessary bytes, preserving all original data.
-    let last_gate_num = (*block_outputs.last().unwrap() + 1) as i64; // g_{last_idx+1}
-    
-    let current_gate_num = last_gate_num;
-    
+    let last_block = *block_outputs.last().expect("at least one block");
+
     // Case 1: rem = 0 (block is full, 64B exactly)
     // In this case, we need an extra block with 0x80 at position 0
     if rem == 0 {
         // The last block is full, so we create an extra padding block
         // This block will have 0x80 at position 0 and length at positions 56..63
-        let mut extra_padding = vec![0u8; 64];
+        let mut extra_padding = [0u8; 64];
         extra_padding[0] = 0x80;
         extra_padding[56..].copy_from_slice(&len_bits.to_be_bytes());
-        
-        // Create the extra padding block
-        let extra_const_gate_num = {
-            let g_idx = gates.len();
-            gates.push(GateV2 {
-                opcode: OPCODE_CONST,
-                sons: vec![],
-                params: extra_padding[..32].to_vec(),
-            });
-            (g_idx + 1) as i64
-        };
-        
-        let extra_full_gate_num = {
-            let g_idx = gates.len();
-            gates.push(GateV2 {
-                opcode: OPCODE_CONST,
-                sons: vec![extra_const_gate_num],
-                params: extra_padding[32..].to_vec(),
-            });
-            (g_idx + 1) as i64
-        };
-        
-        block_outputs.push((extra_full_gate_num - 1) as usize);
+        let extra = builder.constant64(&extra_padding)?;
+        label_constant64(&mut labels, extra, m);
+        block_outputs.push(extra);
     } else {
         // Case 2: rem > 0 (block has space for padding)
         // Create XOR mask with 0x80 at position rem and length at 56..63
-        let mut padding_mask = vec![0u8; 64];
-        
+        let mut padding_mask = [0u8; 64];
+
         // Add 0x80 at position rem (preserves all other bytes via XOR with 0)
         padding_mask[rem] = 0x80;
-        
+
         // Add length at positions 56..63 (if length fits in first block)
         if rem <= block_size - 9 {
             padding_mask[56..].copy_from_slice(&len_bits.to_be_bytes());
         }
-        
-        // Create full 64B mask using CONST arity 1
-        let mask_head_const_gate_num = {
-            let g_idx = gates.len();
-            gates.push(GateV2 {
-                opcode: OPCODE_CONST,
-                sons: vec![],
-                params: padding_mask[..32].to_vec(), // First 32B of mask
-            });
-            (g_idx + 1) as i64
-        };
-        
-        let mask_full_gate_num = {
-            let g_idx = gates.len();
-            gates.push(GateV2 {
-                opcode: OPCODE_CONST,
-                sons: vec![mask_head_const_gate_num],
-                params: padding_mask[32..].to_vec(), // Second 32B of mask
-            });
-            (g_idx + 1) as i64
-        };
-        
+
         // XOR with padding mask: preserves all original data, only modifies positions rem and 56..63
-        let padded_gate_num = {
-            let g_idx = gates.len();
-            gates.push(GateV2 {
-                opcode: OPCODE_XOR,
-                sons: vec![current_gate_num, mask_full_gate_num],
-                params: vec![],
-            });
-            (g_idx + 1) as i64
-        };
-        
-        // Store 0-indexed array position
-        *block_outputs.last_mut().unwrap() = (padded_gate_num - 1) as usize;
+        let mask = builder.constant64(&padding_mask)?;
+        label_constant64(&mut labels, mask, m - 1);
+        let padded = builder.xor(last_block, mask)?;
+        labels.push(padded.son() as u32, GateRole::Padding, Some(m as u32 - 1));
+        *block_outputs.last_mut().unwrap() = padded;
     }
 
     // Extra padding block if needed (only length bits in last 8 bytes of a 64B block).
@@ -377,99 +683,60 @@ pub fn compile_circuit_v2(ct: &[u8], description: &[u8]) -> CompiledCircuitV2 {
     if let Some(extra_tail) = pad_extra {
         // extra_tail is 32 bytes with length in the last 8 bytes (positions 24-31)
         // We need to create a 64B block: first 32B zeros, then 32B with length at positions 56-63
-        // But extra_tail has length at positions 24-31, so we need to shift it to 56-63
-        let mut extra_block = vec![0u8; 64];
-        extra_block[56..].copy_from_slice(&extra_tail[24..]); // Copy length from extra_tail[24..32] to extra_block[56..64]
-        
-        // Create the extra padding block using CONST arity 1
-        let extra_const_gate_num = {
-            let g_idx = gates.len();
-            gates.push(GateV2 {
-                opcode: OPCODE_CONST,
-                sons: vec![],
-                params: extra_block[..32].to_vec(), // First 32B (all zeros)
-            });
-            (g_idx + 1) as i64
-        };
-        
-        let extra_full_gate_num = {
-            let g_idx = gates.len();
-            gates.push(GateV2 {
-                opcode: OPCODE_CONST,
-                sons: vec![extra_const_gate_num],
-                params: extra_block[32..].to_vec(), // Second 32B (zeros + length)
-            });
-            (g_idx + 1) as i64
-        };
-        
-        block_outputs.push((extra_full_gate_num - 1) as usize);
+        let mut extra_block = [0u8; 64];
+        extra_block[56..].copy_from_slice(&extra_tail[24..]);
+        let extra = builder.constant64(&extra_block)?;
+        label_constant64(&mut labels, extra, m);
+        block_outputs.push(extra);
     }
 
-    // SHA chain: each SHA gate references previous gates as 1-indexed
-    let mut prev_hash_gate_num: Option<i64> = None;
-    for &blk_idx in block_outputs.iter() {
-        let blk_gate_num = (blk_idx + 1) as i64; // Convert 0-indexed to 1-indexed
-        let g_idx = gates.len();
-        if prev_hash_gate_num.is_none() {
-            // First SHA gate: SHA2(IV || block)
-            gates.push(GateV2 {
-                opcode: OPCODE_SHA2,
-                sons: vec![blk_gate_num], // Reference block gate as 1-indexed
-                params: vec![],
-            });
-        } else {
-            // Subsequent SHA gates: SHA2(prev_hash_32 || block_64)
-            gates.push(GateV2 {
-                opcode: OPCODE_SHA2,
-                sons: vec![prev_hash_gate_num.unwrap(), blk_gate_num], // Both 1-indexed
-                params: vec![],
-            });
-        }
-        prev_hash_gate_num = Some((g_idx + 1) as i64); // Store as 1-indexed
+    // SHA chain: SHA2(IV || block_1), then SHA2(prev_hash_32 || block_64)
+    let mut hash = builder.sha2(block_outputs[0])?;
+    labels.push(hash.son() as u32, GateRole::HashStep, Some(0));
+    for (i, &block) in block_outputs.iter().enumerate().skip(1) {
+        hash = builder.sha2_chain(hash, block)?;
+        labels.push(hash.son() as u32, GateRole::HashStep, Some(i as u32));
     }
 
-    let final_hash_gate_num = prev_hash_gate_num.expect("at least one sha gate");
-
     // Description constant and comparison
-    let desc_gate_num = {
-        let mut params = vec![0u8; 32];
-        let len = usize::min(32, description.len());
-        params[..len].copy_from_slice(&description[..len]);
-        let g_idx = gates.len();
-        gates.push(GateV2 {
-            opcode: OPCODE_CONST,
-            sons: vec![],
-            params,
-        });
-        (g_idx + 1) as i64 // 1-indexed
-    };
-
-    gates.push(GateV2 {
-        opcode: OPCODE_COMP,
-        sons: vec![final_hash_gate_num, desc_gate_num], // Both 1-indexed
-        params: vec![],
-    });
+    let mut desc = [0u8; 32];
+    let len = usize::min(32, description.len());
+    desc[..len].copy_from_slice(&description[..len]);
+    let desc = builder.constant(&desc)?;
+    labels.push(desc.son() as u32, GateRole::Description, None);
+    let comp = builder.comp(hash, desc)?;
+    labels.push(comp.son() as u32, GateRole::Compare, None);
+
+    Ok((builder.build()?, labels))
+}
 
-    CompiledCircuitV2 {
-        version: 1,
-        gates,
-        block_size: block_size as u32,
-        num_blocks: m as u32,
-    }
+// Labels the two CONST gates of a 64-byte padding constant of `block`
+fn label_constant64(labels: &mut CircuitLabels, constant: Wire, block: usize) {
+    let gate = constant.son() as u32;
+    labels.push_all(
+        gate - 1..gate + 1,
+        GateRole::PaddingMask,
+        Some(block as u32),
+    );
 }
 
 /// Accumulator for a V2 circuit (hashes encoded gates with keccak256).
 /// Optimized to encode and hash gates in parallel, avoiding intermediate storage.
-pub fn acc_circuit_v2(gates: &[GateV2]) -> Vec<u8> {
+pub fn acc_circuit_v2(gates: &[GateV2]) -> SoxResult<Vec<u8>> {
+    acc_circuit_v2_with_hash::<Keccak256Hasher>(gates)
+}
+
+/// Accumulator for a V2 circuit with a given hash function (see `acc_circuit_v2`).
+pub fn acc_circuit_v2_with_hash<H: AccumulatorHash>(gates: &[GateV2]) -> SoxResult<Vec<u8>> {
     use rayon::prelude::*;
-    
+
     if gates.is_empty() {
-        return vec![];
+        return Ok(vec![]);
     }
     if gates.len() == 1 {
         let mut enc = [0u8; 64];
-        gates[0].encode_into(&mut enc);
-        return hash_block64(&enc.to_vec()).to_vec();
+        gates[0].encode_into(&mut enc)?;
+        return Ok(H::hash_block64(&enc).to_vec());
     }
 
     // Parallel encode and hash: encode gates directly into stack buffer and hash
@@ -479,10 +746,10 @@ pub fn acc_circuit_v2(gates: &[GateV2]) -> Vec<u8> {
         .into_par_iter()
         .map(|i| {
             let mut enc = [0u8; 64];
-            gates[i].encode_into(&mut enc);
-            hash_block64(&enc)
+            gates[i].encode_into(&mut enc)?;
+            Ok(H::hash_block64(&enc))
         })
-        .collect();
+        .collect::<SoxResult<_>>()?;
 
     // Parallel computation of Merkle tree layers
     // CRITICAL: Use indexed parallel iteration to preserve order deterministically
@@ -495,10 +762,7 @@ pub fn acc_circuit_v2(gates: &[GateV2]) -> Vec<u8> {
             .map(|i| {
                 if i + 1 < layer_ref.len() {
                     // Pair exists: hash pair[i] and pair[i+1]
-                    let mut hasher = Keccak256::new();
-                    hasher.update(&layer_ref[i]);
-                    hasher.update(&layer_ref[i + 1]);
-                    hasher.finalize().into()
+                    H::hash_parts(&[&layer_ref[i], &layer_ref[i + 1]])
                 } else {
                     // Odd element: copy as-is
                     layer_ref[i]
@@ -508,32 +772,34 @@ pub fn acc_circuit_v2(gates: &[GateV2]) -> Vec<u8> {
         layer = next;
     }
 
-    layer[0].to_vec()
+    Ok(layer[0].to_vec())
 }
 
-
-fn increment_iv(iv: &[u8], inc: u64) -> [u8; 16] {
-    if iv.len() != 16 {
-        die("IV must be 16 bytes");
-    }
-    let mut ctr = u128::from_be_bytes(iv.try_into().unwrap());
-    ctr = ctr.wrapping_add(inc as u128);
-    ctr.to_be_bytes()
+fn increment_iv(iv: &[u8], inc: u64) -> SoxResult<[u8; 16]> {
+    let iv: [u8; 16] = iv
+        .try_into()
+        .map_err(|_| SoxError::InvalidInput(format!("IV must be 16 bytes, got {}", iv.len())))?;
+    let ctr = u128::from_be_bytes(iv).wrapping_add(inc as u128);
+    Ok(ctr.to_be_bytes())
 }
 
-fn eval_aes_ctr(sons: &[Vec<u8>], params: &[u8], key: &[u8]) -> Vec<u8> {
+fn eval_aes_ctr(sons: &[&Value64], params: &[u8], key: &[u8]) -> SoxResult<Value64> {
     if sons.len() != 1 {
-        die("AES-CTR gate expects arity 1");
+        return Err(SoxError::CircuitShape("AES-CTR gate expects arity 1".into()));
     }
     if params.len() < 18 {
-        die("AES-CTR gate expects 16B counter + 2B length");
+        return Err(SoxError::CircuitShape(
+            "AES-CTR gate expects 16B counter + 2B length".into(),
+        ));
     }
     let ctr = &params[..16];
     let len_bits = u16::from_be_bytes([params[16], params[17]]) as usize;
 
-    // xor64 will normalize internally, so we pass values directly
-    let keystream = aes_ctr::encrypt_block(&vec![&key.to_vec(), &vec![0u8; 64], &ctr.to_vec()]);
-    let mut out = xor64(&sons[0], &keystream);
+    // the block is zero-padded, so the keystream is kept past its end
+    let mut out = aes_ctr::keystream64(key, ctr)?;
+    for (o, b) in out.iter_mut().zip(&sons[0].bytes) {
+        *o ^= b;
+    }
 
     if len_bits < 512 {
         let full_bytes = len_bits / 8;
@@ -553,115 +819,106 @@ fn eval_aes_ctr(sons: &[Vec<u8>], params: &[u8], key: &[u8]) -> Vec<u8> {
         }
     }
 
-    out
+    Ok(Value64::new(out, 64))
 }
 
-fn eval_sha2(sons: &[Vec<u8>]) -> Vec<u8> {
+fn eval_sha2(sons: &[&Value64]) -> SoxResult<Value64> {
     match sons.len() {
         1 => {
             // SHA2 arity 1: compression SHA2 de IV et de l'entrée de 64B
-            // sha256_compress with 1 element uses default IV (SHA256 constants) and compresses the 64B block
-            sha256_compress(&vec![&normalize_64(sons[0].clone())])
+            // The default IV (SHA256 constants) is used to compress the zero-padded 64B block
+            Ok(Value64::from_state(sha256_compress64(None, &sons[0].bytes)))
         }
         2 => {
             // SHA2 arity 2: compression SHA2 de l'entrée 1 réduite sur 32B avec l'entrée 2 de 64B
             // According to spec: compress(truncate32(in1) || in2)
             // This means: use truncate32(in1) as previous hash (replaces IV) and in2 as the 64B block to compress
-            // sha256_compress with 2 elements: data[0] = prev_hash (32B), data[1] = current block (64B)
-            let in1_norm = normalize_64(sons[0].clone());
-            let in2_norm = normalize_64(sons[1].clone());
-            let prev_hash = in1_norm[..32].to_vec(); // truncate32(in1)
-            sha256_compress(&vec![&prev_hash, &in2_norm])
+            let prev_hash = sons[0].bytes.first_chunk(); // truncate32(in1)
+            Ok(Value64::from_state(sha256_compress64(
+                prev_hash,
+                &sons[1].bytes,
+            )))
         }
-        _ => die("SHA2 gate expects arity 1 or 2"),
+        _ => Err(SoxError::CircuitShape(
+            "SHA2 gate expects arity 1 or 2".into(),
+        )),
     }
 }
 
-fn eval_const(sons: &[Vec<u8>], params: &[u8]) -> Vec<u8> {
+fn eval_const(sons: &[&Value64], params: &[u8]) -> SoxResult<Value64> {
     if params.len() < 32 {
-        die("CONST gate expects 32B constant in params");
+        return Err(SoxError::CircuitShape(
+            "CONST gate expects 32B constant in params".into(),
+        ));
     }
+    let mut out = [0u8; 64];
     match sons.len() {
         0 => {
             // CONST arity 0: params (32B) || zeros (32B)
-            let mut out = vec![0u8; 64];
             out[..32].copy_from_slice(&params[..32]);
-            out
         }
         1 => {
             // CONST arity 1: sons[0][0..32] || params (32B)
-            let mut out = vec![0u8; 64];
-            out[..32].copy_from_slice(&normalize_64(sons[0].clone())[..32]);
+            out[..32].copy_from_slice(&sons[0].bytes[..32]);
             out[32..].copy_from_slice(&params[..32]);
-            out
         }
-        _ => die("CONST gate expects arity 0 or 1"),
+        _ => {
+            return Err(SoxError::CircuitShape(
+                "CONST gate expects arity 0 or 1".into(),
+            ))
+        }
     }
+    Ok(Value64::new(out, 64))
 }
 
-fn eval_xor(sons: &[Vec<u8>]) -> Vec<u8> {
+fn eval_xor(sons: &[&Value64]) -> SoxResult<Value64> {
     if sons.len() != 2 {
-        die("XOR gate expects arity 2");
+        return Err(SoxError::CircuitShape("XOR gate expects arity 2".into()));
     }
-    // xor64 will normalize internally, so we pass values directly
-    xor64(&sons[0], &sons[1])
+    // The output has the length of the longest input: in practice XOR inputs in V2 are always
+    // 64 bytes. Both are zero-padded, so the bytes of the longest one are kept past the other.
+    let mut out = sons[0].bytes;
+    for (o, b) in out.iter_mut().zip(&sons[1].bytes) {
+        *o ^= b;
+    }
+    Ok(Value64::new(out, sons[0].len().max(sons[1].len())))
 }
 
-fn eval_comp(sons: &[Vec<u8>]) -> Vec<u8> {
+fn eval_comp(sons: &[&Value64]) -> SoxResult<Value64> {
     if sons.len() != 2 {
-        die("COMP gate expects arity 2");
+        return Err(SoxError::CircuitShape("COMP gate expects arity 2".into()));
     }
     // Compare only the first 32 bytes without normalizing
     // This is safe because SHA2 outputs are 32 bytes and CONST outputs have 32 bytes of data
-    let min_len = usize::min(sons[0].len().min(32), sons[1].len().min(32));
-    let eq = if min_len < 32 {
+    let eq = if sons[0].len() < 32 || sons[1].len() < 32 {
         false // If either value has less than 32 bytes, they can't be equal
     } else {
-        sons[0][..32] == sons[1][..32]
+        sons[0].bytes[..32] == sons[1].bytes[..32]
     };
-    let mut out = vec![0u8; 64];
-    out[0] = if eq { 1 } else { 0 };
-    out
+    let mut out = [0u8; 64];
+    out[0] = u8::from(eq);
+    Ok(Value64::new(out, 64))
 }
 
-pub(crate) fn normalize_64(mut v: Vec<u8>) -> Vec<u8> {
-    if v.len() >= 64 {
-        v.truncate(64);
-        v
-    } else {
-        v.resize(64, 0);
-        v
+fn encode_i64_6(n: i64) -> SoxResult<[u8; 6]> {
+    if !(-0x8000_0000_0000..=0x7FFF_FFFF_FFFF).contains(&n) {
+        return Err(SoxError::CircuitShape(format!(
+            "son index {n} must fit in signed 48 bits"
+        )));
     }
+    let be = n.to_be_bytes();
+    Ok([be[2], be[3], be[4], be[5], be[6], be[7]])
 }
 
-fn xor64(a: &Vec<u8>, b: &Vec<u8>) -> Vec<u8> {
-    // Return the maximum size of both inputs, XOR only up to the minimum size
-    // This is more flexible like V1, but in practice XOR inputs in V2 are always 64 bytes
-    let max_len = a.len().max(b.len());
-    let min_len = a.len().min(b.len());
-    let mut out = vec![0u8; max_len];
-    
-    // XOR up to the minimum length
-    for i in 0..min_len {
-        out[i] = a[i] ^ b[i];
-    }
-    
-    // Copy remaining bytes from the longer input
-    if a.len() > b.len() {
-        out[min_len..].copy_from_slice(&a[min_len..]);
-    } else if b.len() > a.len() {
-        out[min_len..].copy_from_slice(&b[min_len..]);
-    }
-    
-    out
-}
-
-fn encode_i64_6(n: i64) -> [u8; 6] {
-    if n > 0x7FFF_FFFF_FFFF || n < -0x8000_0000_0000 {
-        die("Son index must fit in signed 48 bits");
-    }
-    let be = n.to_be_bytes();
-    [be[2], be[3], be[4], be[5], be[6], be[7]]
+// Inverse of `encode_i64_6`: sign-extends a big-endian 48-bit integer
+fn decode_i64_6(bytes: &[u8]) -> i64 {
+    let mut be = if bytes[0] & 0x80 != 0 {
+        [0xFFu8; 8]
+    } else {
+        [0u8; 8]
+    };
+    be[2..].copy_from_slice(bytes);
+    i64::from_be_bytes(be)
 }
 
 #[cfg(test)]
@@ -669,8 +926,17 @@ mod tests {
     use super::*;
     use crate::aes_ctr;
     use crate::sha256::sha256;
-    use crate::accumulator::hash_block64;
-    use hex;
+    use rand::Rng;
+
+    fn normalize_64(mut v: Vec<u8>) -> Vec<u8> {
+        if v.len() >= 64 {
+            v.truncate(64);
+            v
+        } else {
+            v.resize(64, 0);
+            v
+        }
+    }
 
     #[test]
     fn test_encode_gate_size() {
@@ -679,11 +945,85 @@ mod tests {
             sons: vec![1],
             params: vec![0xAB; 32],
         };
-        let enc = g.encode();
+        let enc = g.encode().unwrap();
         assert_eq!(enc.len(), 64);
         assert_eq!(enc[0], OPCODE_CONST);
     }
 
+    #[test]
+    fn test_decode_with_arity_round_trip() {
+        let ct = vec![7u8; 16 + 150];
+        let circuit = compile_circuit_v2(&ct, &[0u8; 32]).unwrap();
+        for gate in &circuit.gates {
+            let enc = gate.encode().unwrap();
+            let dec = GateV2::decode_with_arity(&enc, gate.sons.len()).unwrap();
+            assert_eq!(dec.opcode, gate.opcode);
+            assert_eq!(dec.sons, gate.sons);
+            assert_eq!(dec.params, gate.params);
+        }
+
+        // the arity must leave room for the params, and the padding must be zero
+        let aes = circuit.gates[0].encode().unwrap();
+        assert!(GateV2::decode_with_arity(&aes, 8).is_err());
+        let mut padded = aes.clone();
+        padded[63] = 1;
+        assert!(GateV2::decode_with_arity(&padded, 1).is_err());
+    }
+
+    #[test]
+    fn test_decode_round_trip() {
+        let ct = vec![7u8; 16 + 150];
+        let circuit = compile_circuit_v2(&ct, &[0u8; 32]).unwrap();
+        for gate in &circuit.gates {
+            let enc: [u8; 64] = gate.encode().unwrap().try_into().unwrap();
+            assert_eq!(&GateV2::decode(&enc).unwrap(), gate);
+        }
+
+        // random gates of every opcode and arity, with non-zero sons and a last param byte that
+        // cannot be mistaken for padding
+        let mut rng = rand::rng();
+        for _ in 0..1000 {
+            let info = OPCODES_V2[rng.random_range(0..OPCODES_V2.len())];
+            let arity = info.arities[rng.random_range(0..info.arities.len())];
+            let mut params: Vec<u8> = (0..info.params_len).map(|_| rng.random()).collect();
+            if let Some(last) = params.last_mut() {
+                *last |= 1;
+            }
+            let gate = GateV2 {
+                opcode: info.opcode,
+                sons: (0..arity)
+                    .map(|_| match rng.random_range(-1_000_000i64..1_000_000) {
+                        0 => 1,
+                        son => son,
+                    })
+                    .collect(),
+                params,
+            };
+            let enc: [u8; 64] = gate.encode().unwrap().try_into().unwrap();
+            assert_eq!(GateV2::decode(&enc).unwrap(), gate);
+        }
+
+        // unknown opcode, zero son, non-zero padding
+        let mut enc = [0u8; 64];
+        assert!(GateV2::decode(&enc).is_err());
+        enc[0] = OPCODE_XOR;
+        enc[1..7].copy_from_slice(&encode_i64_6(-1).unwrap());
+        assert!(GateV2::decode(&enc).is_err());
+        enc[7..13].copy_from_slice(&encode_i64_6(3).unwrap());
+        assert_eq!(GateV2::decode(&enc).unwrap().sons, vec![-1, 3]);
+        enc[63] = 1;
+        assert!(GateV2::decode(&enc).is_err());
+
+        // as on-chain, the smallest arity wins
+        let gate = GateV2 {
+            opcode: OPCODE_CONST,
+            sons: vec![5],
+            params: [vec![9u8; 26], vec![0u8; 6]].concat(),
+        };
+        let enc: [u8; 64] = gate.encode().unwrap().try_into().unwrap();
+        assert!(GateV2::decode(&enc).unwrap().sons.is_empty());
+    }
+
     #[test]
     fn test_eval_const_xor_comp() {
         // g_1: CONST (produces [1; 32] || [0; 32])
@@ -711,7 +1051,7 @@ mod tests {
             params: vec![],
         };
 
-        let values = evaluate_circuit_v2(&[g1, g2, g3, g4], &[], &[0u8; 16]);
+        let values = evaluate_circuit_v2(&[g1, g2, g3, g4], &[], &[0u8; 16]).unwrap();
 
         // values[0] = output of g_1
         assert_eq!(values[0][0], 1);
@@ -723,6 +1063,118 @@ mod tests {
         assert_eq!(values[3][0], 1);
     }
 
+    #[test]
+    fn test_evaluate_wavefront_matches_gate_order() {
+        // wide random circuit: most gates only depend on inputs of various lengths
+        let mut rng = rand::rng();
+        let m = 300;
+        let inputs: Vec<Vec<u8>> = (0..m)
+            .map(|_| {
+                (0..rng.random_range(0..=64))
+                    .map(|_| rng.random())
+                    .collect()
+            })
+            .collect();
+        let mut gates = vec![];
+        for i in 0..2000usize {
+            let son = |rng: &mut rand::rngs::ThreadRng| {
+                if i == 0 || rng.random_range(0..4) > 0 {
+                    -rng.random_range(1..=m as i64)
+                } else {
+                    rng.random_range(1..=i as i64)
+                }
+            };
+            let (opcode, sons, params) = match rng.random_range(0..6) {
+                0 => {
+                    let mut params = vec![0u8; 18];
+                    rng.fill(&mut params[..16]);
+                    let len_bits = rng.random_range(0..=512u16);
+                    params[16..].copy_from_slice(&len_bits.to_be_bytes());
+                    (OPCODE_AES_CTR, vec![son(&mut rng)], params)
+                }
+                1 => (OPCODE_SHA2, vec![son(&mut rng)], vec![]),
+                2 => (OPCODE_SHA2, vec![son(&mut rng), son(&mut rng)], vec![]),
+                3 => (OPCODE_CONST, vec![son(&mut rng)], vec![rng.random(); 32]),
+                4 => (OPCODE_XOR, vec![son(&mut rng), son(&mut rng)], vec![]),
+                _ => (OPCODE_COMP, vec![son(&mut rng), son(&mut rng)], vec![]),
+            };
+            gates.push(GateV2 {
+                opcode,
+                sons,
+                params,
+            });
+        }
+        let key: [u8; 16] = rng.random();
+
+        let mut expected: Vec<Vec<u8>> = vec![];
+        for gate in &gates {
+            let sons: Vec<Vec<u8>> = gate
+                .sons
+                .iter()
+                .map(|&son| match son {
+                    son if son < 0 => inputs[(-son - 1) as usize].clone(),
+                    son => expected[son as usize - 1].clone(),
+                })
+                .collect();
+            expected.push(evaluate_gate_v2(gate, &sons, &key).unwrap());
+        }
+        assert_eq!(
+            evaluate_circuit_v2(&gates, &inputs, &key).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_evaluate_reports_first_invalid_gate() {
+        let constant = GateV2 {
+            opcode: OPCODE_CONST,
+            sons: vec![],
+            params: vec![1; 32],
+        };
+        let mut gates = vec![
+            constant.clone(),
+            GateV2 {
+                opcode: OPCODE_SHA2,
+                sons: vec![1],
+                params: vec![],
+            },
+            // invalid, on a later level than g_4
+            GateV2 {
+                opcode: OPCODE_XOR,
+                sons: vec![2],
+                params: vec![],
+            },
+            GateV2 {
+                opcode: OPCODE_CONST,
+                sons: vec![],
+                params: vec![1; 31],
+            },
+        ];
+        let err = evaluate_circuit_v2(&gates, &[], &[0; 16]).unwrap_err();
+        assert!(
+            err.to_string().contains("XOR gate expects arity 2"),
+            "{err}"
+        );
+
+        gates[2] = constant;
+        let err = evaluate_circuit_v2(&gates, &[], &[0; 16]).unwrap_err();
+        assert!(err.to_string().contains("CONST gate expects 32B"), "{err}");
+
+        // forward reference after the invalid gates
+        gates.push(GateV2 {
+            opcode: OPCODE_SHA2,
+            sons: vec![6],
+            params: vec![],
+        });
+        let err = evaluate_circuit_v2(&gates, &[], &[0; 16]).unwrap_err();
+        assert!(err.to_string().contains("CONST gate expects 32B"), "{err}");
+        gates.remove(3);
+        let err = evaluate_circuit_v2(&gates, &[], &[0; 16]).unwrap_err();
+        assert!(err.to_string().contains("son index 6"), "{err}");
+
+        assert!(evaluate_circuit_v2(&gates[..1], &[vec![0; 65]], &[0; 16]).is_err());
+    }
+
     #[test]
     fn test_circuit_v2_end_to_end_single_block() {
         let key = vec![0u8; 16];
@@ -730,7 +1182,7 @@ mod tests {
         let pt = b"hello world";
 
         // Build ciphertext: ct = IV || (pt XOR keystream)
-        let keystream = aes_ctr::encrypt_block(&vec![&key, &vec![0u8; pt.len()], &iv]);
+        let keystream = aes_ctr::encrypt_block(&[&key, &vec![0u8; pt.len()], &iv]).unwrap();
         let ct_block: Vec<u8> = pt
             .iter()
             .zip(keystream.iter())
@@ -741,7 +1193,7 @@ mod tests {
 
         let description = sha256(pt);
 
-        let circuit = compile_circuit_v2(&ct, &description);
+        let circuit = compile_circuit_v2(&ct, &description).unwrap();
         let inputs = vec![ct[16..].to_vec()]; // ciphertext blocks without IV
         // Verify that gates reference previous gates correctly (1-indexed)
         for (idx, g) in circuit.gates.iter().enumerate() {
@@ -762,7 +1214,7 @@ mod tests {
                 // Negative indices are dummy gates (inputs), no validation needed here
             }
         }
-        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key);
+        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key).unwrap();
 
         // Expect XOR gate output (index 2) to match standard padded block.
         let padded_manual = {
@@ -780,7 +1232,7 @@ mod tests {
 
         let res = values.last().unwrap();
         let final_hash_gate_num = match circuit.gates.last().unwrap().sons[0] {
-            s if s > 0 => s as i64, // 1-indexed gate number
+            s if s > 0 => s, // 1-indexed gate number
             _ => unreachable!(),
         };
         // Convert 1-indexed to 0-indexed: g_1 -> values[0], g_2 -> values[1], etc.
@@ -806,8 +1258,8 @@ mod tests {
         while offset < pt.len() {
             let chunk = &pt[offset..usize::min(offset + 64, pt.len())];
             let block_idx = offset / 64;
-            let counter = increment_iv(&iv, (block_idx * 4) as u64).to_vec();
-            let keystream = aes_ctr::encrypt_block(&vec![&key, &vec![0u8; chunk.len()], &counter]);
+            let counter = increment_iv(&iv, (block_idx * 4) as u64).unwrap().to_vec();
+            let keystream = aes_ctr::encrypt_block(&[&key, &vec![0u8; chunk.len()], &counter]).unwrap();
             let ct_block: Vec<u8> = chunk
                 .iter()
                 .zip(keystream.iter())
@@ -818,7 +1270,7 @@ mod tests {
         }
 
         let description = sha256(&pt);
-        let circuit = compile_circuit_v2(&ct, &description);
+        let circuit = compile_circuit_v2(&ct, &description).unwrap();
         let inputs = {
             let mut v = Vec::new();
             let mut start = 16;
@@ -830,7 +1282,7 @@ mod tests {
             v
         };
 
-        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key);
+        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key).unwrap();
         // AES output block0 should match plaintext first 64 bytes.
         assert_eq!(&values[0][..64], &pt[..64], "block0 plaintext mismatch");
         // AES output block1 should match plaintext remaining bytes.
@@ -850,7 +1302,7 @@ mod tests {
         assert_eq!(values[4], padded_manual, "padded last block mismatch");
         let res = values.last().unwrap();
         let hash_gate_num = match circuit.gates.last().unwrap().sons[0] {
-            s if s > 0 => s as i64, // 1-indexed gate number
+            s if s > 0 => s, // 1-indexed gate number
             _ => unreachable!(),
         };
         // Convert 1-indexed to 0-indexed: g_1 -> values[0], g_2 -> values[1], etc.
@@ -871,10 +1323,10 @@ mod tests {
 
         let ct = build_ct(&pt, &key, &iv);
         let description = sha256(&pt);
-        let circuit = compile_circuit_v2(&ct, &description);
+        let circuit = compile_circuit_v2(&ct, &description).unwrap();
         let inputs = slice_ciphertext_blocks(&ct);
 
-        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key);
+        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key).unwrap();
         let res = values.last().unwrap();
         assert_eq!(res[0], 1, "final comparison should succeed with extra pad");
     }
@@ -887,10 +1339,10 @@ mod tests {
 
         let ct = build_ct(pt, &key, &iv);
         let bogus_desc = sha256(b"something else");
-        let circuit = compile_circuit_v2(&ct, &bogus_desc);
+        let circuit = compile_circuit_v2(&ct, &bogus_desc).unwrap();
         let inputs = slice_ciphertext_blocks(&ct);
 
-        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key);
+        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key).unwrap();
         let res = values.last().unwrap();
         assert_eq!(res[0], 0, "comparison should fail with wrong hash");
     }
@@ -904,9 +1356,9 @@ mod tests {
         while offset < pt.len() {
             let chunk = &pt[offset..usize::min(offset + 64, pt.len())];
             let block_idx = offset / 64;
-            let ctr = increment_iv(iv, (block_idx * 4) as u64).to_vec();
+            let ctr = increment_iv(iv, (block_idx * 4) as u64).unwrap().to_vec();
             let keystream =
-                aes_ctr::encrypt_block(&vec![&key.to_vec(), &vec![0u8; chunk.len()], &ctr]);
+                aes_ctr::encrypt_block(&[&key.to_vec(), &vec![0u8; chunk.len()], &ctr]).unwrap();
             let ct_block: Vec<u8> = chunk
                 .iter()
                 .zip(keystream.iter())
@@ -940,10 +1392,10 @@ mod tests {
 
         let ct = build_ct(&pt, &key, &iv);
         let description = sha256(&pt);
-        let circuit = compile_circuit_v2(&ct, &description);
+        let circuit = compile_circuit_v2(&ct, &description).unwrap();
         let inputs = slice_ciphertext_blocks(&ct);
 
-        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key);
+        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key).unwrap();
         let res = values.last().unwrap();
         assert_eq!(res[0], 1, "single byte should verify correctly");
     }
@@ -957,10 +1409,10 @@ mod tests {
 
         let ct = build_ct(&pt, &key, &iv);
         let description = sha256(&pt);
-        let circuit = compile_circuit_v2(&ct, &description);
+        let circuit = compile_circuit_v2(&ct, &description).unwrap();
         let inputs = slice_ciphertext_blocks(&ct);
 
-        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key);
+        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key).unwrap();
         let res = values.last().unwrap();
         assert_eq!(res[0], 1, "exactly 64 bytes should verify correctly");
     }
@@ -974,10 +1426,10 @@ mod tests {
 
         let ct = build_ct(&pt, &key, &iv);
         let description = sha256(&pt);
-        let circuit = compile_circuit_v2(&ct, &description);
+        let circuit = compile_circuit_v2(&ct, &description).unwrap();
         let inputs = slice_ciphertext_blocks(&ct);
 
-        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key);
+        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key).unwrap();
         let res = values.last().unwrap();
         assert_eq!(res[0], 1, "exactly 55 bytes should verify correctly");
     }
@@ -991,10 +1443,10 @@ mod tests {
 
         let ct = build_ct(&pt, &key, &iv);
         let description = sha256(&pt);
-        let circuit = compile_circuit_v2(&ct, &description);
+        let circuit = compile_circuit_v2(&ct, &description).unwrap();
         let inputs = slice_ciphertext_blocks(&ct);
 
-        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key);
+        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key).unwrap();
         let res = values.last().unwrap();
         assert_eq!(res[0], 1, "exactly 56 bytes should verify correctly");
     }
@@ -1008,10 +1460,10 @@ mod tests {
 
         let ct = build_ct(&pt, &key, &iv);
         let description = sha256(&pt);
-        let circuit = compile_circuit_v2(&ct, &description);
+        let circuit = compile_circuit_v2(&ct, &description).unwrap();
         let inputs = slice_ciphertext_blocks(&ct);
 
-        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key);
+        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key).unwrap();
         let res = values.last().unwrap();
         assert_eq!(res[0], 1, "three blocks should verify correctly");
     }
@@ -1025,10 +1477,10 @@ mod tests {
 
         let ct = build_ct(&pt, &key, &iv);
         let description = sha256(&pt);
-        let circuit = compile_circuit_v2(&ct, &description);
+        let circuit = compile_circuit_v2(&ct, &description).unwrap();
         let inputs = slice_ciphertext_blocks(&ct);
 
-        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key);
+        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key).unwrap();
         let res = values.last().unwrap();
         assert_eq!(res[0], 1, "large file (10 blocks) should verify correctly");
     }
@@ -1042,10 +1494,10 @@ mod tests {
 
         let ct = build_ct(&pt, &key, &iv);
         let description = sha256(&pt);
-        let circuit = compile_circuit_v2(&ct, &description);
+        let circuit = compile_circuit_v2(&ct, &description).unwrap();
         let inputs = slice_ciphertext_blocks(&ct);
 
-        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key);
+        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key).unwrap();
         let res = values.last().unwrap();
         assert_eq!(res[0], 1, "random data should verify correctly");
     }
@@ -1065,13 +1517,13 @@ mod tests {
         assert_ne!(ct1[16..], ct2[16..], "different keys should produce different ciphertexts");
 
         let description = sha256(pt);
-        let circuit1 = compile_circuit_v2(&ct1, &description);
-        let circuit2 = compile_circuit_v2(&ct2, &description);
+        let circuit1 = compile_circuit_v2(&ct1, &description).unwrap();
+        let circuit2 = compile_circuit_v2(&ct2, &description).unwrap();
         let inputs1 = slice_ciphertext_blocks(&ct1);
         let inputs2 = slice_ciphertext_blocks(&ct2);
 
-        let values1 = evaluate_circuit_v2(&circuit1.gates, &inputs1, &key1);
-        let values2 = evaluate_circuit_v2(&circuit2.gates, &inputs2, &key2);
+        let values1 = evaluate_circuit_v2(&circuit1.gates, &inputs1, &key1).unwrap();
+        let values2 = evaluate_circuit_v2(&circuit2.gates, &inputs2, &key2).unwrap();
         
         // Les deux doivent vérifier correctement avec leurs clés respectives
         assert_eq!(values1.last().unwrap()[0], 1, "key1 should verify correctly");
@@ -1087,7 +1539,7 @@ mod tests {
 
         let ct = build_ct(&pt, &key, &iv);
         let description = sha256(&pt);
-        let circuit = compile_circuit_v2(&ct, &description);
+        let circuit = compile_circuit_v2(&ct, &description).unwrap();
 
         for (idx, gate) in circuit.gates.iter().enumerate() {
             let current_gate_num = (idx + 1) as i64; // g_{idx+1}
@@ -1127,7 +1579,7 @@ mod tests {
 
         let ct = build_ct(&pt, &key, &iv);
         let description = sha256(&pt);
-        let circuit = compile_circuit_v2(&ct, &description);
+        let circuit = compile_circuit_v2(&ct, &description).unwrap();
 
         let mut opcodes_used = std::collections::HashSet::new();
         for gate in &circuit.gates {
@@ -1151,14 +1603,14 @@ mod tests {
 
         let ct = build_ct(&pt, &key, &iv);
         let description = sha256(&pt);
-        let circuit = compile_circuit_v2(&ct, &description);
+        let circuit = compile_circuit_v2(&ct, &description).unwrap();
         let inputs = slice_ciphertext_blocks(&ct);
 
-        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key);
+        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key).unwrap();
         
         // Trouver le gate de hash final
         let final_hash_gate_num = match circuit.gates.last().unwrap().sons[0] {
-            s if s > 0 => s as i64,
+            s if s > 0 => s,
             _ => unreachable!(),
         };
         let final_hash_idx = (final_hash_gate_num - 1) as usize;
@@ -1181,10 +1633,10 @@ mod tests {
 
         let ct = build_ct(pt, &key, &iv);
         let description = sha256(pt);
-        let circuit = compile_circuit_v2(&ct, &description);
+        let circuit = compile_circuit_v2(&ct, &description).unwrap();
         let inputs = slice_ciphertext_blocks(&ct);
 
-        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key);
+        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key).unwrap();
         
         // Le premier bloc décrypté doit contenir les données originales
         assert_eq!(
@@ -1203,12 +1655,12 @@ mod tests {
 
         let ct = build_ct(&pt, &key, &iv);
         let description = sha256(&pt);
-        let circuit = compile_circuit_v2(&ct, &description);
+        let circuit = compile_circuit_v2(&ct, &description).unwrap();
         let inputs = slice_ciphertext_blocks(&ct);
 
         // Vérifier plusieurs fois
         for _ in 0..5 {
-            let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key);
+            let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key).unwrap();
             let res = values.last().unwrap();
             assert_eq!(res[0], 1, "circuit should verify correctly on multiple evaluations");
         }
@@ -1233,7 +1685,7 @@ mod tests {
         ];
         
         // Construire le ciphertext: IV || (plaintext XOR keystream)
-        let keystream = aes_ctr::encrypt_block(&vec![&key, &vec![0u8; plaintext.len()], &iv]);
+        let keystream = aes_ctr::encrypt_block(&[&key, &vec![0u8; plaintext.len()], &iv]).unwrap();
         let ct_block: Vec<u8> = plaintext
             .iter()
             .zip(keystream.iter())
@@ -1252,7 +1704,7 @@ mod tests {
         println!("Description (hex): {}", hex::encode(&description));
         
         // Compiler le circuit
-        let circuit = compile_circuit_v2(&ciphertext, &description);
+        let circuit = compile_circuit_v2(&ciphertext, &description).unwrap();
         println!("Circuit compiled: {} gates", circuit.gates.len());
         println!("Number of blocks: {}", circuit.num_blocks);
         
@@ -1261,7 +1713,7 @@ mod tests {
         println!("Number of input blocks: {}", inputs.len());
         
         // Évaluer le circuit
-        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key);
+        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key).unwrap();
         
         // Vérifier que le plaintext décrypté est correct
         let decrypted_pt = &values[0][..plaintext.len()];
@@ -1274,7 +1726,7 @@ mod tests {
         
         // Vérifier le hash final
         let final_hash_gate_num = match circuit.gates.last().unwrap().sons[0] {
-            s if s > 0 => s as i64,
+            s if s > 0 => s,
             _ => unreachable!(),
         };
         let final_hash_idx = (final_hash_gate_num - 1) as usize;
@@ -1297,8 +1749,8 @@ mod tests {
         
         // Test avec une mauvaise description (doit échouer)
         let wrong_description = sha256(b"Wrong message");
-        let circuit_wrong = compile_circuit_v2(&ciphertext, &wrong_description);
-        let values_wrong = evaluate_circuit_v2(&circuit_wrong.gates, &inputs, &key);
+        let circuit_wrong = compile_circuit_v2(&ciphertext, &wrong_description).unwrap();
+        let values_wrong = evaluate_circuit_v2(&circuit_wrong.gates, &inputs, &key).unwrap();
         let final_result_wrong = values_wrong.last().unwrap();
         assert_eq!(
             final_result_wrong[0],
@@ -1309,7 +1761,7 @@ mod tests {
         
         // Test avec une mauvaise clé (doit échouer)
         let wrong_key: Vec<u8> = vec![0xFFu8; 16];
-        let values_wrong_key = evaluate_circuit_v2(&circuit.gates, &inputs, &wrong_key);
+        let values_wrong_key = evaluate_circuit_v2(&circuit.gates, &inputs, &wrong_key).unwrap();
         let final_result_wrong_key = values_wrong_key.last().unwrap();
         assert_eq!(
             final_result_wrong_key[0],
@@ -1370,7 +1822,7 @@ mod tests {
                  ciphertext.len(), ciphertext.len() - 16);
         
         // 4. Compilation du circuit
-        let circuit = compile_circuit_v2(&ciphertext, &expected_description);
+        let circuit = compile_circuit_v2(&ciphertext, &expected_description).unwrap();
         println!("🔧 Circuit compiled:");
         println!("   - Total gates: {}", circuit.gates.len());
         println!("   - Number of blocks: {}", circuit.num_blocks);
@@ -1406,7 +1858,7 @@ mod tests {
         
         // 6. Évaluation du circuit
         println!("\n⚙️  Evaluating circuit...");
-        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key);
+        let values = evaluate_circuit_v2(&circuit.gates, &inputs, &key).unwrap();
         println!("✅ Circuit evaluated: {} gate outputs", values.len());
         assert_eq!(values.len(), circuit.gates.len(), 
                    "Number of gate outputs should match number of gates");
@@ -1441,8 +1893,6 @@ mod tests {
         let mut aes_idx = 0;
         let mut sha_idx = 0;
         let mut const_idx = 0;
-        let mut xor_idx = 0;
-        let mut prev_hash: Option<Vec<u8>> = None; // Pour suivre le hash précédent dans la chaîne SHA
         
         for (gate_idx, gate) in circuit.gates.iter().enumerate() {
             let gate_num = gate_idx + 1; // 1-indexed
@@ -1493,14 +1943,14 @@ mod tests {
                             // Mais normalement les gates SHA2 référencent des gates AES, pas des inputs
                             normalize_64(inputs[(-gate.sons[0] - 1) as usize].clone())
                         };
-                        sha256_compress(&vec![&block_value])
+                        sha256_compress(&[&block_value]).unwrap()
                     } else {
                         // SHA2 suivant: SHA2(prev_hash_32 || block_64)
                         let prev_hash_idx = (gate.sons[0] - 1) as usize;
                         let block_idx = (gate.sons[1] - 1) as usize;
                         let prev_hash_val = normalize_64(values[prev_hash_idx].clone());
                         let block_val = normalize_64(values[block_idx].clone());
-                        sha256_compress(&vec![&prev_hash_val[..32].to_vec(), &block_val])
+                        sha256_compress(&[&prev_hash_val[..32].to_vec(), &block_val]).unwrap()
                     };
                     
                     // Comparer avec le résultat obtenu
@@ -1524,7 +1974,6 @@ mod tests {
                         println!("   ✓ g_{} (SHA2 {}): hash calculé manuellement = hash circuit", gate_num, sha_idx + 1);
                     }
                     
-                    prev_hash = Some(hash);
                     sha_idx += 1;
                 }
                 OPCODE_CONST => {
@@ -1560,7 +2009,6 @@ mod tests {
                         gate_num
                     );
                     println!("   ✓ g_{} (XOR): output de 64 bytes (padding)", gate_num);
-                    xor_idx += 1;
                 }
                 OPCODE_COMP => {
                     // Gate COMP final: doit retourner 1
@@ -1760,7 +2208,7 @@ mod tests {
         // 7.7. Test avec mauvaise clé (doit échouer)
         println!("\n🔍 Verification 7: Wrong key test");
         let wrong_key: Vec<u8> = vec![0xFFu8; 16];
-        let values_wrong_key = evaluate_circuit_v2(&circuit.gates, &inputs, &wrong_key);
+        let values_wrong_key = evaluate_circuit_v2(&circuit.gates, &inputs, &wrong_key).unwrap();
         let comp_result_wrong_key = values_wrong_key.last().unwrap();
         assert_eq!(
             comp_result_wrong_key[0],
@@ -1772,8 +2220,8 @@ mod tests {
         // 7.8. Test avec mauvaise description (doit échouer)
         println!("\n🔍 Verification 8: Wrong description test");
         let wrong_description = sha256(b"Wrong message");
-        let circuit_wrong = compile_circuit_v2(&ciphertext, &wrong_description);
-        let values_wrong_desc = evaluate_circuit_v2(&circuit_wrong.gates, &inputs, &key);
+        let circuit_wrong = compile_circuit_v2(&ciphertext, &wrong_description).unwrap();
+        let values_wrong_desc = evaluate_circuit_v2(&circuit_wrong.gates, &inputs, &key).unwrap();
         let comp_result_wrong_desc = values_wrong_desc.last().unwrap();
         assert_eq!(
             comp_result_wrong_desc[0],
@@ -1784,7 +2232,7 @@ mod tests {
         
         // 7.9. Vérifier la cohérence: ré-évaluer avec les mêmes inputs doit donner les mêmes résultats
         println!("\n🔍 Verification 9: Determinism");
-        let values2 = evaluate_circuit_v2(&circuit.gates, &inputs, &key);
+        let values2 = evaluate_circuit_v2(&circuit.gates, &inputs, &key).unwrap();
         assert_eq!(
             values.len(),
             values2.len(),
@@ -1818,7 +2266,7 @@ mod tests {
         use crate::accumulator::hash_block64;
         
         // Test pour comparer avec Solidity sha256GateV2
-        let gates = vec![
+        let gates = [
             // Gate 1: AES-CTR avec 1 son (g_{-1})
             GateV2 {
                 opcode: 0x01,
@@ -1850,14 +2298,14 @@ mod tests {
 
         println!("\n=== Test de hashage pour comparaison Solidity ===\n");
         for (i, gate) in gates.iter().enumerate() {
-            let encoded = gate.encode();
+            let encoded = gate.encode().unwrap();
             let hash = hash_block64(&encoded);
             
             println!("Gate {}:", i + 1);
             println!("  Opcode: 0x{:02x}", gate.opcode);
             println!("  Sons: {:?}", gate.sons);
             println!("  Encoded (64 bytes): {}", hex::encode(&encoded));
-            println!("  Hash (Rust): {}", hex::encode(&hash));
+            println!("  Hash (Rust): {}", hex::encode(hash));
             println!();
         }
     }
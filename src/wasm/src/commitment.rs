@@ -1,3 +1,4 @@
+use crate::error::{SoxError, SoxResult};
 use rand::RngCore;
 use sha3::{Digest, Keccak256};
 use wasm_bindgen::prelude::wasm_bindgen;
@@ -58,16 +59,52 @@ pub fn commit_hashes(h_circuit: &[u8], h_ct: &[u8]) -> Commitment {
 ///
 /// # Returns
 /// * `Ok(Vec<u8>)` - The original committed data if verification succeeds
-/// * `Err(&str)` - Error message if verification fails
-pub fn open_commitment_internal(
-    commitment: &Vec<u8>,
-    opening_value: &Vec<u8>,
-) -> Result<Vec<u8>, &'static str> {
+/// * `Err(SoxError::CommitmentMismatch)` - If the opening value does not match the commitment
+/// * `Err(SoxError::InvalidInput)` - If the opening value is shorter than the 16 random bytes
+pub fn open_commitment_internal(commitment: &[u8], opening_value: &[u8]) -> SoxResult<Vec<u8>> {
+    if opening_value.len() < 16 {
+        return Err(SoxError::InvalidInput(format!(
+            "opening value must be at least 16 bytes, got {}",
+            opening_value.len()
+        )));
+    }
+
     let mut hasher = Keccak256::new();
-    hasher.update(&opening_value);
-    if !commitment.eq(&hasher.finalize().to_vec()) {
-        return Err("The commitments do not match");
+    hasher.update(opening_value);
+    if commitment != hasher.finalize().as_slice() {
+        return Err(SoxError::CommitmentMismatch);
     }
 
     Ok(opening_value[..(opening_value.len() - 16)].to_vec())
 }
+
+// =================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commit_open() {
+        let data = vec![0xab; 64];
+        let commitment = commit(&data);
+        assert_eq!(open_commitment_internal(&commitment.c, &commitment.o), Ok(data));
+    }
+
+    #[test]
+    fn test_open_errors() {
+        let commitment = commit(&[1, 2, 3]);
+        let mut tampered = commitment.o.clone();
+        tampered[0] ^= 1;
+        assert_eq!(
+            open_commitment_internal(&commitment.c, &tampered),
+            Err(SoxError::CommitmentMismatch)
+        );
+        assert_eq!(
+            open_commitment_internal(&commitment.c, &[0u8; 4])
+                .unwrap_err()
+                .code(),
+            "SOX_INVALID_INPUT"
+        );
+    }
+}
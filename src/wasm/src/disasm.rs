@@ -0,0 +1,387 @@
+//! Human-readable views of compiled circuits: a textual disassembly with one line per gate
+//! (`g17 = AES_CTR(ct[3]; ctr=…, bits=512)`) and a Graphviz DOT export of the gate DAG.
+//!
+//! Gates are numbered like in the dispute contracts: from 1 for V2 circuits, by absolute index for
+//! V1 circuits (whose first `num_blocks` gates are the inputs). Inputs are written `ct[i]` (for V1
+//! circuits `ct[0]` is the IV) and V1 constants `c[i]`.
+
+use crate::circuits::{is_constant_idx, CompiledCircuit};
+use crate::circuits_v2::{opcode_info, CompiledCircuitV2, GateV2, OPCODE_AES_CTR, OPCODE_CONST};
+use crate::error::{SoxError, SoxResult};
+use crate::sox_circuit::{AnyCircuit, SoxCircuit};
+use hex::encode;
+use std::collections::HashSet;
+use std::fmt::Write;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+// A gate of either generation, reduced to what is displayed
+struct Node {
+    name: String,      // e.g. g3
+    op: String,        // e.g. AES_CTR
+    sons: Vec<String>, // names of the sons
+    params: String,    // rendered params, empty if there are none
+}
+
+impl Node {
+    fn line(&self) -> String {
+        let sep = if self.sons.is_empty() || self.params.is_empty() {
+            ""
+        } else {
+            "; "
+        };
+        format!(
+            "{} = {}({}{sep}{})",
+            self.name,
+            self.op,
+            self.sons.join(", "),
+            self.params
+        )
+    }
+}
+
+// Graphviz identifier of a gate, input or constant name (`ct[3]` -> `ct3`)
+fn dot_id(name: &str) -> String {
+    name.replace(['[', ']'], "")
+}
+
+// Name of a son of a V2 gate
+fn son_name_v2(son: i64) -> String {
+    if son < 0 {
+        format!("ct[{}]", son.unsigned_abs())
+    } else {
+        format!("g{son}")
+    }
+}
+
+// Node of the V2 gate g_{gate_num}
+fn node_v2(gate_num: u32, gate: &GateV2) -> Node {
+    let params = &gate.params;
+    let (op, params) = match opcode_info(gate.opcode) {
+        Ok(info) if info.opcode == OPCODE_AES_CTR && params.len() == 18 => (
+            info.name.to_string(),
+            format!(
+                "ctr={}, bits={}",
+                encode(&params[..16]),
+                u16::from_be_bytes([params[16], params[17]])
+            ),
+        ),
+        Ok(info) if info.opcode == OPCODE_CONST && params.len() == 32 => {
+            (info.name.to_string(), format!("value={}", encode(params)))
+        }
+        Ok(info) if params.is_empty() => (info.name.to_string(), String::new()),
+        Ok(info) => (info.name.to_string(), format!("params={}", encode(params))),
+        Err(_) => (
+            format!("OP_{:#04x}", gate.opcode),
+            format!("params={}", encode(params)),
+        ),
+    };
+    Node {
+        name: format!("g{gate_num}"),
+        op,
+        sons: gate.sons.iter().copied().map(son_name_v2).collect(),
+        params,
+    }
+}
+
+fn nodes_v2(circuit: &CompiledCircuitV2) -> Vec<Node> {
+    (1..)
+        .zip(&circuit.gates)
+        .map(|(gate_num, gate)| node_v2(gate_num, gate))
+        .collect()
+}
+
+// Mnemonics of instruction set 0 of V1 circuits (see `version_instructions`)
+const OPCODES_V1: [&str; 8] = [
+    "SHA256_COMPRESS",
+    "AES_ENCRYPT",
+    "AES_DECRYPT",
+    "ADD",
+    "MULT",
+    "EQUAL",
+    "CONCAT",
+    "SHA256_FINAL",
+];
+
+fn nodes_v1(circuit: &CompiledCircuit) -> Vec<Node> {
+    let num_blocks = circuit.num_blocks as usize;
+    let son_name = |son: u32| {
+        if is_constant_idx(son) {
+            format!("c[{}]", son & !(1 << 31))
+        } else if (son as usize) < num_blocks {
+            format!("ct[{son}]")
+        } else {
+            format!("g{son}")
+        }
+    };
+    circuit
+        .circuit
+        .iter()
+        .enumerate()
+        .skip(num_blocks)
+        .map(|(i, gate)| Node {
+            name: format!("g{i}"),
+            op: match OPCODES_V1.get(gate.opcode as usize) {
+                Some(op) if circuit.version == 0 => op.to_string(),
+                _ => format!("OP_{}", gate.opcode),
+            },
+            sons: gate.sons.iter().copied().map(son_name).collect(),
+            params: String::new(),
+        })
+        .collect()
+}
+
+// Writes the DOT graph of the nodes. The sons of each node that are not gates (inputs and
+// constants) are declared on first use. The `highlight`ed gate and its incoming edges are colored.
+fn to_dot(nodes: &[Node], highlight: Option<&str>) -> String {
+    let mut declared = HashSet::new();
+    let mut dot =
+        String::from("digraph circuit {\n    node [shape=box, fontname=\"monospace\"];\n");
+    for node in nodes {
+        let id = dot_id(&node.name);
+        let highlighted = highlight == Some(node.name.as_str());
+        for son in &node.sons {
+            let son_id = dot_id(son);
+            if !son.starts_with('g') && declared.insert(son_id.clone()) {
+                let shape = if son.starts_with("c[") {
+                    "note"
+                } else {
+                    "ellipse"
+                };
+                let _ = writeln!(dot, "    {son_id} [label=\"{son}\", shape={shape}];");
+            }
+        }
+        let style = if highlighted {
+            ", style=filled, fillcolor=\"#f4a261\", penwidth=2"
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            dot,
+            "    {id} [label=\"{}\\n{}\", tooltip=\"{}\"{style}];",
+            node.name,
+            node.op,
+            node.line()
+        );
+        for son in &node.sons {
+            let edge_style = if highlighted { " [penwidth=2]" } else { "" };
+            let _ = writeln!(dot, "    {} -> {id}{edge_style};", dot_id(son));
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Disassembles a single V2 gate.
+///
+/// # Arguments
+/// * `gate_num` - Number of the gate in the circuit (1-indexed)
+/// * `gate` - The gate
+///
+/// # Returns
+/// The line of the gate, e.g. `g17 = AES_CTR(ct[3]; ctr=…, bits=512)`
+pub fn disassemble_gate_v2(gate_num: u32, gate: &GateV2) -> String {
+    node_v2(gate_num, gate).line()
+}
+
+/// Disassembles a V2 circuit, one line per gate after a header line.
+///
+/// # Arguments
+/// * `circuit` - The circuit
+///
+/// # Returns
+/// The disassembly
+pub fn disassemble_v2(circuit: &CompiledCircuitV2) -> String {
+    let mut out = format!(
+        "; V2 circuit: {} blocks of {} bytes, {} gates\n",
+        circuit.num_blocks,
+        circuit.block_size,
+        circuit.gates.len()
+    );
+    for node in nodes_v2(circuit) {
+        out.push_str(&node.line());
+        out.push('\n');
+    }
+    out
+}
+
+/// Disassembles a V1 circuit: a header line, the constants, then one line per gate. Input gates
+/// are not listed.
+///
+/// # Arguments
+/// * `circuit` - The circuit
+///
+/// # Returns
+/// The disassembly
+pub fn disassemble_v1(circuit: &CompiledCircuit) -> String {
+    let mut out =
+        format!(
+        "; V1 circuit (instruction set {}): {} blocks of {} bytes (ct[0] is the IV), {} gates\n",
+        circuit.version,
+        circuit.num_blocks,
+        circuit.block_size,
+        circuit.circuit.len().saturating_sub(circuit.num_blocks as usize)
+    );
+    for (i, constant) in circuit.constants.iter().enumerate() {
+        let value = constant.as_deref().map_or("<unbound>".into(), encode);
+        let _ = writeln!(out, "c[{i}] = {value}");
+    }
+    for node in nodes_v1(circuit) {
+        out.push_str(&node.line());
+        out.push('\n');
+    }
+    out
+}
+
+/// Exports the gate DAG of a V2 circuit in the Graphviz DOT format.
+///
+/// # Arguments
+/// * `circuit` - The circuit
+/// * `highlight` - Number of a gate to highlight, e.g. the challenge a dispute converged on
+///
+/// # Returns
+/// The DOT graph
+pub fn circuit_to_dot_v2(circuit: &CompiledCircuitV2, highlight: Option<u32>) -> String {
+    to_dot(
+        &nodes_v2(circuit),
+        highlight.map(|g| format!("g{g}")).as_deref(),
+    )
+}
+
+/// Exports the gate DAG of a V1 circuit in the Graphviz DOT format.
+///
+/// # Arguments
+/// * `circuit` - The circuit
+/// * `highlight` - Absolute index of a gate to highlight, e.g. the challenge a dispute converged on
+///
+/// # Returns
+/// The DOT graph
+pub fn circuit_to_dot_v1(circuit: &CompiledCircuit, highlight: Option<u32>) -> String {
+    to_dot(
+        &nodes_v1(circuit),
+        highlight.map(|g| format!("g{g}")).as_deref(),
+    )
+}
+
+/// Disassembles a circuit of either generation (see `disassemble_v1` and `disassemble_v2`)
+pub fn disassemble(circuit: &AnyCircuit) -> String {
+    match circuit {
+        AnyCircuit::V1(circuit) => disassemble_v1(circuit),
+        AnyCircuit::V2(circuit) => disassemble_v2(circuit),
+    }
+}
+
+/// Exports a circuit of either generation in the DOT format (see `circuit_to_dot_v1` and
+/// `circuit_to_dot_v2`)
+pub fn circuit_to_dot(circuit: &AnyCircuit, highlight: Option<u32>) -> String {
+    match circuit {
+        AnyCircuit::V1(circuit) => circuit_to_dot_v1(circuit, highlight),
+        AnyCircuit::V2(circuit) => circuit_to_dot_v2(circuit, highlight),
+    }
+}
+
+/// JavaScript wrapper of the disassemble function
+///
+/// # Arguments
+/// * `circuit_bytes` - Serialized circuit of either generation
+///
+/// # Returns
+/// The disassembly
+#[wasm_bindgen]
+pub fn disassemble_circuit_js(circuit_bytes: &[u8]) -> SoxResult<String> {
+    Ok(disassemble(&AnyCircuit::from_bytes(circuit_bytes)?))
+}
+
+/// JavaScript wrapper of the circuit_to_dot function
+///
+/// # Arguments
+/// * `circuit_bytes` - Serialized circuit of either generation
+/// * `highlight` - Gate to highlight, numbered like in the dispute contract
+///
+/// # Returns
+/// The DOT graph
+#[wasm_bindgen]
+pub fn circuit_to_dot_js(circuit_bytes: &[u8], highlight: Option<u32>) -> SoxResult<String> {
+    Ok(circuit_to_dot(
+        &AnyCircuit::from_bytes(circuit_bytes)?,
+        highlight,
+    ))
+}
+
+/// Disassembles a 64-byte V2 gate, e.g. the `gate_bytes` submitted in the final step of a dispute.
+///
+/// # Arguments
+/// * `gate_bytes` - 64-byte encoded gate
+/// * `gate_num` - Number of the gate, i.e. the challenge of the dispute
+///
+/// # Returns
+/// The line of the gate, or `SoxError::CircuitShape` if the gate cannot be decoded
+#[wasm_bindgen]
+pub fn disassemble_gate_v2_js(gate_bytes: &[u8], gate_num: u32) -> SoxResult<String> {
+    let gate_bytes: &[u8; 64] = gate_bytes.try_into().map_err(|_| {
+        SoxError::CircuitShape(format!(
+            "gate must be exactly 64 bytes, got {}",
+            gate_bytes.len()
+        ))
+    })?;
+    Ok(disassemble_gate_v2(gate_num, &GateV2::decode(gate_bytes)?))
+}
+
+// =================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuits::compile_basic_circuit;
+    use crate::circuits_v2::compile_circuit_v2;
+
+    #[test]
+    fn test_disassemble_v2() {
+        let iv = [0u8; 16];
+        let circuit = compile_circuit_v2(&[&iv[..], &[5u8; 100]].concat(), &[0xab; 32]).unwrap();
+        let text = disassemble_v2(&circuit);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "; V2 circuit: 2 blocks of 64 bytes, 9 gates");
+        assert_eq!(
+            lines[2],
+            format!("g2 = AES_CTR(ct[2]; ctr={}04, bits=288)", "00".repeat(15))
+        );
+        assert_eq!(lines[5], "g5 = XOR(g2, g4)");
+        assert_eq!(lines[7], "g7 = SHA2(g6, g5)");
+        assert_eq!(lines[8], format!("g8 = CONST(value={})", "ab".repeat(32)));
+        assert_eq!(lines[9], "g9 = COMP(g7, g8)");
+
+        let enc: [u8; 64] = circuit.gates[4].encode().unwrap().try_into().unwrap();
+        assert_eq!(disassemble_gate_v2_js(&enc, 5).unwrap(), "g5 = XOR(g2, g4)");
+        assert!(disassemble_gate_v2_js(&enc[..63], 5).is_err());
+
+        let dot = circuit_to_dot(&AnyCircuit::V2(circuit), Some(5));
+        assert!(dot.starts_with("digraph circuit {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert_eq!(dot.matches("ct1 [").count(), 1);
+        assert!(dot.contains("    ct1 -> g1;\n"));
+        assert!(dot.contains("    g4 -> g5 [penwidth=2];\n"));
+        assert_eq!(dot.matches("fillcolor").count(), 1);
+        assert_eq!(dot.matches(" -> ").count(), 10);
+    }
+
+    #[test]
+    fn test_disassemble_v1() {
+        let circuit = compile_basic_circuit(16 + 100, &[0xcd; 32]).unwrap();
+        let text = disassemble(&AnyCircuit::V1(circuit.clone()));
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines[0],
+            "; V1 circuit (instruction set 0): 3 blocks of 64 bytes (ct[0] is the IV), 6 gates"
+        );
+        assert_eq!(lines[1], "c[0] = 0004");
+        assert_eq!(lines[4], "c[3] = <unbound>");
+        assert_eq!(lines[5], "g3 = ADD(ct[0], c[0])");
+        assert_eq!(lines[7], "g5 = AES_DECRYPT(c[3], ct[2], g3)");
+        assert_eq!(lines[10], "g8 = EQUAL(g7, c[1])");
+
+        let dot = circuit_to_dot_v1(&circuit, None);
+        assert!(dot.contains("    c3 [label=\"c[3]\", shape=note];\n"));
+        assert!(dot.contains("    ct0 [label=\"ct[0]\", shape=ellipse];\n"));
+        assert!(!dot.contains("fillcolor"));
+    }
+}
@@ -0,0 +1,830 @@
+//! Off-chain model of the `DisputeSOX` contract (V2 circuits). `DisputeSox` follows the state
+//! machine of the contract: the bisection on the buyer's responses, the vendor's final step and
+//! the step 9 logic which lets the dispute sponsor of the losing party play a second round.
+//! Buyers and vendors are plugged in through the `BuyerStrategy` and `VendorStrategy` traits, so
+//! full disputes can be simulated without a chain.
+
+use crate::accumulator::{leaf_hash, verify, verify_ext};
+use crate::circuits_v2::{evaluate_gate_v2, CompiledCircuitV2, GateV2};
+use crate::commitment::open_commitment_internal;
+use crate::error::{SoxError, SoxResult};
+use crate::native::{FinalStepV2, Proof};
+use crate::sox_circuit::SoxCircuit;
+use crate::EvaluatedCircuitV2;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// States of the dispute, with the same discriminants as the `State` enum of `DisputeSOX.sol`
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DisputeState {
+    /// The buyer must respond to the current challenge
+    ChallengeBuyer = 0,
+
+    /// The vendor must agree or disagree with the buyer's latest response
+    WaitVendorOpinion = 1,
+
+    /// The vendor must submit the components of step 8a
+    WaitVendorData = 2,
+
+    /// The vendor must submit the components of step 8b (first gate)
+    WaitVendorDataLeft = 3,
+
+    /// The vendor must submit the proof of step 8c (last gate)
+    WaitVendorDataRight = 4,
+
+    /// The vendor won the dispute
+    Complete = 5,
+
+    /// The buyer won the dispute
+    Cancel = 6,
+
+    /// The funds have been withdrawn
+    End = 7,
+}
+
+impl DisputeState {
+    /// Returns whether the dispute is over, i.e. no party can make a move anymore
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            DisputeState::Complete | DisputeState::Cancel | DisputeState::End
+        )
+    }
+
+    /// Returns the name of the contract function the vendor must call in this state, if the
+    /// bisection is over
+    pub fn submit_function(&self) -> Option<&'static str> {
+        match self {
+            DisputeState::WaitVendorData => Some("submitCommitment"),
+            DisputeState::WaitVendorDataLeft => Some("submitCommitmentLeft"),
+            DisputeState::WaitVendorDataRight => Some("submitCommitmentRight"),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<u8> for DisputeState {
+    type Error = SoxError;
+
+    fn try_from(value: u8) -> SoxResult<Self> {
+        Ok(match value {
+            0 => DisputeState::ChallengeBuyer,
+            1 => DisputeState::WaitVendorOpinion,
+            2 => DisputeState::WaitVendorData,
+            3 => DisputeState::WaitVendorDataLeft,
+            4 => DisputeState::WaitVendorDataRight,
+            5 => DisputeState::Complete,
+            6 => DisputeState::Cancel,
+            7 => DisputeState::End,
+            _ => {
+                return Err(SoxError::InvalidInput(format!(
+                    "unknown dispute state {value}"
+                )))
+            }
+        })
+    }
+}
+
+// ####################################
+// ###         STRATEGIES           ###
+// ####################################
+
+/// Behaviour of the buyer during the bisection
+pub trait BuyerStrategy {
+    /// Responds to a challenge with hpre(challenge), or anything else for a dishonest buyer.
+    ///
+    /// # Arguments
+    /// * `challenge` - 1-indexed gate
+    fn respond(&mut self, challenge: u32) -> SoxResult<[u8; 32]>;
+}
+
+/// Behaviour of the vendor during the bisection and the final step
+pub trait VendorStrategy {
+    /// Gives the vendor's opinion on the buyer's response to a challenge.
+    ///
+    /// # Arguments
+    /// * `challenge` - 1-indexed gate
+    /// * `response` - Response of the buyer
+    ///
+    /// # Returns
+    /// `true` if the vendor agrees with the response
+    fn opinion(&mut self, challenge: u32, response: &[u8; 32]) -> SoxResult<bool>;
+
+    /// Returns the opening value of the commitment of the contract
+    fn opening_value(&self) -> Vec<u8>;
+
+    /// Computes the components of step 8a for the 1-indexed gate `gate_num`
+    fn final_step(&mut self, gate_num: u32) -> SoxResult<FinalStepV2>;
+
+    /// Computes the components of step 8b for the 1-indexed gate `gate_num`
+    fn final_step_left(&mut self, gate_num: u32) -> SoxResult<FinalStepV2>;
+
+    /// Computes the proof of step 8c
+    fn proof_right(&mut self) -> SoxResult<Proof>;
+}
+
+/// Buyer answering every challenge with the accumulator of its own evaluation of the circuit
+pub struct HonestBuyer {
+    circuit: CompiledCircuitV2,
+    evaluated: EvaluatedCircuitV2,
+}
+
+impl HonestBuyer {
+    /// Creates an honest buyer.
+    ///
+    /// # Arguments
+    /// * `circuit` - The circuit of the contract
+    /// * `evaluated` - The buyer's evaluation of the circuit
+    pub fn new(circuit: CompiledCircuitV2, evaluated: EvaluatedCircuitV2) -> Self {
+        Self { circuit, evaluated }
+    }
+}
+
+impl BuyerStrategy for HonestBuyer {
+    fn respond(&mut self, challenge: u32) -> SoxResult<[u8; 32]> {
+        to_bytes32(&self.circuit.hpre(&self.evaluated, challenge)?)
+    }
+}
+
+/// Vendor agreeing exactly with the responses matching its own evaluation of the circuit, and
+/// submitting the final step computed by `compute_proofs_v2` and its variants
+pub struct HonestVendor {
+    circuit: CompiledCircuitV2,
+    evaluated: EvaluatedCircuitV2,
+    ct: Vec<u8>,
+    opening_value: Vec<u8>,
+}
+
+impl HonestVendor {
+    /// Creates an honest vendor.
+    ///
+    /// # Arguments
+    /// * `circuit` - The circuit of the contract
+    /// * `evaluated` - The vendor's evaluation of the circuit
+    /// * `ct` - The ciphertext (IV included)
+    /// * `opening_value` - Opening value of the commitment of the contract
+    pub fn new(
+        circuit: CompiledCircuitV2,
+        evaluated: EvaluatedCircuitV2,
+        ct: Vec<u8>,
+        opening_value: Vec<u8>,
+    ) -> Self {
+        Self {
+            circuit,
+            evaluated,
+            ct,
+            opening_value,
+        }
+    }
+}
+
+impl VendorStrategy for HonestVendor {
+    fn opinion(&mut self, challenge: u32, response: &[u8; 32]) -> SoxResult<bool> {
+        Ok(self.circuit.hpre(&self.evaluated, challenge)? == response)
+    }
+
+    fn opening_value(&self) -> Vec<u8> {
+        self.opening_value.clone()
+    }
+
+    fn final_step(&mut self, gate_num: u32) -> SoxResult<FinalStepV2> {
+        self.circuit.prove(&self.evaluated, &self.ct, gate_num)
+    }
+
+    fn final_step_left(&mut self, gate_num: u32) -> SoxResult<FinalStepV2> {
+        self.circuit.prove_left(&self.evaluated, &self.ct, gate_num)
+    }
+
+    fn proof_right(&mut self) -> SoxResult<Proof> {
+        self.circuit.prove_right(&self.evaluated)
+    }
+}
+
+// ####################################
+// ###          DISPUTE             ###
+// ####################################
+
+/// Bounds of the bisection on the buyer's responses. The buyer's response to `chall` is the
+/// accumulator of the outputs of gates 1 to `chall`; the game narrows `a..b` until it contains a
+/// single gate, on which the vendor must prove its evaluation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Bisection {
+    pub(crate) num_gates: u32,
+    pub(crate) a: u32,
+    pub(crate) b: u32,
+    pub(crate) chall: u32,
+}
+
+impl Bisection {
+    // Initial bounds, set by the constructor of the contract and on every restart of step 9
+    pub(crate) fn new(num_gates: u32) -> Self {
+        Self {
+            num_gates,
+            a: 1,
+            b: num_gates + 1,
+            chall: (num_gates + 2) / 2,
+        }
+    }
+
+    // Applies the vendor's opinion on the response to `chall` like `giveOpinion`, and returns the
+    // state the contract moves to. The bounds are left unchanged when the contract reverts.
+    pub(crate) fn narrow(&mut self, vendor_agrees: bool) -> SoxResult<DisputeState> {
+        let (a, b) = if vendor_agrees {
+            (self.chall + 1, self.b)
+        } else {
+            (self.a, self.chall)
+        };
+
+        if a != b {
+            *self = Self {
+                a,
+                b,
+                chall: (a + b) / 2,
+                ..*self
+            };
+            return Ok(DisputeState::ChallengeBuyer);
+        }
+
+        let state = if a == 1 {
+            DisputeState::WaitVendorDataLeft
+        } else if a == self.num_gates + 1 {
+            DisputeState::WaitVendorDataRight
+        } else if a <= self.num_gates {
+            DisputeState::WaitVendorData
+        } else {
+            return Err(SoxError::Index(format!(
+                "bisection ended on gate {a} but the circuit has {} gates",
+                self.num_gates
+            )));
+        };
+        *self = Self {
+            a,
+            b,
+            chall: a,
+            ..*self
+        };
+        Ok(state)
+    }
+}
+
+/// State of a `DisputeSOX` contract. Calls made in the wrong state, and the cases where the
+/// contract reverts, return an error and leave the dispute unchanged.
+#[derive(Debug, Clone)]
+pub struct DisputeSox {
+    state: DisputeState,
+    num_blocks: u32,
+    commitment: Vec<u8>,
+    key: Vec<u8>,
+    bisection: Bisection,
+    buyer_responses: HashMap<u32, [u8; 32]>,
+    step9_count: u32,
+    last_losing_party_was_vendor: bool,
+    buyer_has_sponsor: bool,
+    vendor_has_sponsor: bool,
+}
+
+impl DisputeSox {
+    /// Opens a dispute, like the constructor of the contract.
+    ///
+    /// # Arguments
+    /// * `num_blocks` - Number of blocks of the ciphertext
+    /// * `num_gates` - Number of gates of the circuit
+    /// * `commitment` - Commitment of the circuit and ciphertext accumulators
+    /// * `key` - AES key revealed in the optimistic phase
+    ///
+    /// # Returns
+    /// The dispute in state `ChallengeBuyer`, or `SoxError::InvalidInput` if there is no gate
+    pub fn new(num_blocks: u32, num_gates: u32, commitment: &[u8], key: &[u8]) -> SoxResult<Self> {
+        if num_gates == 0 {
+            return Err(SoxError::InvalidInput(
+                "a dispute needs at least one gate".into(),
+            ));
+        }
+        Ok(Self {
+            state: DisputeState::ChallengeBuyer,
+            num_blocks,
+            commitment: commitment.to_vec(),
+            key: key.to_vec(),
+            bisection: Bisection::new(num_gates),
+            buyer_responses: HashMap::new(),
+            step9_count: 0,
+            last_losing_party_was_vendor: false,
+            buyer_has_sponsor: false,
+            vendor_has_sponsor: false,
+        })
+    }
+
+    /// Sets whether each party was brought by a distinct dispute sponsor, who takes over the
+    /// dispute if the party loses (step 9).
+    pub fn with_sponsors(mut self, buyer_has_sponsor: bool, vendor_has_sponsor: bool) -> Self {
+        self.buyer_has_sponsor = buyer_has_sponsor;
+        self.vendor_has_sponsor = vendor_has_sponsor;
+        self
+    }
+
+    /// Returns the current state
+    pub fn state(&self) -> DisputeState {
+        self.state
+    }
+
+    /// Returns the lower bound of the bisection
+    pub fn a(&self) -> u32 {
+        self.bisection.a
+    }
+
+    /// Returns the upper bound of the bisection
+    pub fn b(&self) -> u32 {
+        self.bisection.b
+    }
+
+    /// Returns the current challenge (1-indexed gate)
+    pub fn chall(&self) -> u32 {
+        self.bisection.chall
+    }
+
+    /// Returns the number of final steps played so far
+    pub fn step9_count(&self) -> u32 {
+        self.step9_count
+    }
+
+    /// Returns the buyer's response to a challenge, zero if the buyer never responded to it
+    pub fn buyer_response(&self, chall: u32) -> [u8; 32] {
+        self.buyer_responses
+            .get(&chall)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Stores the buyer's response to the current challenge.
+    ///
+    /// # Arguments
+    /// * `response` - hpre(chall) according to the buyer
+    pub fn respond_challenge(&mut self, response: [u8; 32]) -> SoxResult<()> {
+        self.expect_state(DisputeState::ChallengeBuyer)?;
+        self.buyer_responses.insert(self.bisection.chall, response);
+        self.state = DisputeState::WaitVendorOpinion;
+        Ok(())
+    }
+
+    /// Narrows the bisection with the vendor's opinion on the latest response.
+    ///
+    /// # Arguments
+    /// * `vendor_agrees` - `true` if the vendor agrees with the latest response
+    pub fn give_opinion(&mut self, vendor_agrees: bool) -> SoxResult<()> {
+        self.expect_state(DisputeState::WaitVendorOpinion)?;
+        self.state = self.bisection.narrow(vendor_agrees)?;
+        Ok(())
+    }
+
+    /// Verifies the components of step 8a for the current challenge and applies step 9.
+    ///
+    /// # Arguments
+    /// * `opening_value` - Opening value of the commitment
+    /// * `step` - Components computed by `compute_proofs_v2`
+    ///
+    /// # Returns
+    /// `true` if the vendor won this round
+    pub fn submit_commitment(
+        &mut self,
+        opening_value: &[u8],
+        step: &FinalStepV2,
+    ) -> SoxResult<bool> {
+        self.expect_state(DisputeState::WaitVendorData)?;
+        let (h_circuit, h_ct) = self.open_commitment(opening_value)?;
+        let (gate, gate_keccak, values_keccak, gate_res_keccak) = self.check_gate(step)?;
+
+        let mut s_in_l = Vec::new();
+        let mut v_in_l = Vec::new();
+        let mut s_not_in_l_minus_m = Vec::new();
+        let mut v_not_in_l = Vec::new();
+        for (&son, value_keccak) in gate.sons.iter().zip(values_keccak) {
+            if son < 0 {
+                s_in_l.push(self.ct_leaf(son)?);
+                v_in_l.push(value_keccak);
+            } else if son > 0 {
+                s_not_in_l_minus_m.push((son - 1) as u32);
+                v_not_in_l.push(value_keccak);
+            } else {
+                return Err(SoxError::CircuitShape("invalid V2 son index 0".into()));
+            }
+        }
+
+        let gate_num = self.bisection.chall;
+        let prev_acc = self.buyer_response(gate_num - 1);
+        let vendor_won = self.buyer_response(gate_num) != step.curr_acc.as_slice()
+            && verify(&h_circuit, &[gate_num - 1], &[gate_keccak], &step.proof1)
+            && verify(&h_ct, &s_in_l, &v_in_l, &step.proof2)
+            && verify(&prev_acc, &s_not_in_l_minus_m, &v_not_in_l, &step.proof3)
+            && verify_ext(
+                gate_num - 1,
+                &prev_acc,
+                &step.curr_acc,
+                &gate_res_keccak,
+                &step.proof_ext,
+            );
+        self.handle_step9(!vendor_won);
+        Ok(vendor_won)
+    }
+
+    /// Verifies the components of step 8b (the challenge is the first gate) and applies step 9.
+    ///
+    /// # Arguments
+    /// * `opening_value` - Opening value of the commitment
+    /// * `step` - Components computed by `compute_proofs_left_v2`
+    ///
+    /// # Returns
+    /// `true` if the vendor won this round
+    pub fn submit_commitment_left(
+        &mut self,
+        opening_value: &[u8],
+        step: &FinalStepV2,
+    ) -> SoxResult<bool> {
+        self.expect_state(DisputeState::WaitVendorDataLeft)?;
+        let (h_circuit, h_ct) = self.open_commitment(opening_value)?;
+        let (gate, gate_keccak, values_keccak, gate_res_keccak) = self.check_gate(step)?;
+
+        let mut non_constant_sons = Vec::new();
+        let mut non_constant_values_keccak = Vec::new();
+        for (&son, value_keccak) in gate.sons.iter().zip(values_keccak) {
+            if son < 0 {
+                non_constant_sons.push(self.ct_leaf(son)?);
+                non_constant_values_keccak.push(value_keccak);
+            }
+        }
+
+        let gate_num = self.bisection.chall;
+        let vendor_won = self.buyer_response(gate_num) != step.curr_acc.as_slice()
+            && verify(&h_circuit, &[gate_num - 1], &[gate_keccak], &step.proof1)
+            && verify(
+                &h_ct,
+                &non_constant_sons,
+                &non_constant_values_keccak,
+                &step.proof2,
+            )
+            && verify_ext(
+                0,
+                &[0u8; 32],
+                &step.curr_acc,
+                &gate_res_keccak,
+                &step.proof_ext,
+            );
+        self.handle_step9(!vendor_won);
+        Ok(vendor_won)
+    }
+
+    /// Verifies the proof of step 8c, i.e. that the last gate of the circuit accepted by the buyer
+    /// outputs true, and applies step 9.
+    ///
+    /// # Arguments
+    /// * `proof` - Proof computed by `compute_proof_right_v2`
+    ///
+    /// # Returns
+    /// `true` if the vendor won this round
+    pub fn submit_commitment_right(&mut self, proof: &Proof) -> SoxResult<bool> {
+        self.expect_state(DisputeState::WaitVendorDataRight)?;
+        let mut true_bytes = [0u8; 64];
+        true_bytes[0] = 1;
+
+        let vendor_won = verify(
+            &self.buyer_response(self.bisection.num_gates),
+            &[self.bisection.num_gates - 1],
+            &[leaf_hash(&true_bytes)],
+            proof,
+        );
+        self.handle_step9(!vendor_won);
+        Ok(vendor_won)
+    }
+
+    /// Plays the dispute with the given strategies until a final step has been submitted.
+    ///
+    /// # Arguments
+    /// * `buyer` - Strategy of the buyer
+    /// * `vendor` - Strategy of the vendor
+    ///
+    /// # Returns
+    /// The state after the final step: `Complete` or `Cancel` if the dispute is over, or
+    /// `ChallengeBuyer` if the dispute sponsor of the losing party must play another round
+    pub fn play_round(
+        &mut self,
+        buyer: &mut dyn BuyerStrategy,
+        vendor: &mut dyn VendorStrategy,
+    ) -> SoxResult<DisputeState> {
+        loop {
+            let chall = self.bisection.chall;
+            match self.state {
+                DisputeState::ChallengeBuyer => {
+                    let response = buyer.respond(chall)?;
+                    self.respond_challenge(response)?;
+                }
+                DisputeState::WaitVendorOpinion => {
+                    let agrees = vendor.opinion(chall, &self.buyer_response(chall))?;
+                    self.give_opinion(agrees)?;
+                }
+                DisputeState::WaitVendorData => {
+                    let step = vendor.final_step(chall)?;
+                    self.submit_commitment(&vendor.opening_value(), &step)?;
+                    return Ok(self.state);
+                }
+                DisputeState::WaitVendorDataLeft => {
+                    let step = vendor.final_step_left(chall)?;
+                    self.submit_commitment_left(&vendor.opening_value(), &step)?;
+                    return Ok(self.state);
+                }
+                DisputeState::WaitVendorDataRight => {
+                    let proof = vendor.proof_right()?;
+                    self.submit_commitment_right(&proof)?;
+                    return Ok(self.state);
+                }
+                DisputeState::Complete | DisputeState::Cancel | DisputeState::End => {
+                    return Ok(self.state)
+                }
+            }
+        }
+    }
+
+    // Fails unless the dispute is in the `expected` state (`onlyExpected` in the contract)
+    fn expect_state(&self, expected: DisputeState) -> SoxResult<()> {
+        if self.state != expected {
+            return Err(SoxError::InvalidInput(format!(
+                "expected state {expected:?}, the dispute is in state {:?}",
+                self.state
+            )));
+        }
+        Ok(())
+    }
+
+    // Opens the commitment and splits it into (hCircuit, hCt)
+    fn open_commitment(&self, opening_value: &[u8]) -> SoxResult<([u8; 32], [u8; 32])> {
+        let opened = open_commitment_internal(&self.commitment, opening_value)?;
+        if opened.len() < 64 {
+            return Err(SoxError::InvalidInput(format!(
+                "the commitment must open to hCircuit || hCt, got {} bytes",
+                opened.len()
+            )));
+        }
+        Ok((to_bytes32(&opened[..32])?, to_bytes32(&opened[32..64])?))
+    }
+
+    // Decodes and evaluates the gate of a final step. Returns the gate, its leaf hash, the leaf
+    // hashes of the sons' values and the leaf hash of the gate's output. The values are hashed as
+    // 64-byte blocks, like the leaves of the accumulators they are proven against.
+    #[allow(clippy::type_complexity)]
+    fn check_gate(
+        &self,
+        step: &FinalStepV2,
+    ) -> SoxResult<(GateV2, [u8; 32], Vec<[u8; 32]>, [u8; 32])> {
+        let gate = GateV2::decode_with_arity(&step.gate_bytes, step.values.len())?;
+        let gate_res = evaluate_gate_v2(&gate, &step.values, &self.key)?;
+        Ok((
+            gate,
+            leaf_hash(&step.gate_bytes),
+            step.values.iter().map(|v| leaf_hash(v)).collect(),
+            leaf_hash(&gate_res),
+        ))
+    }
+
+    // Index in hCt of the block referenced by a negative son. hCt accumulates the IV followed by
+    // the blocks, so block -son (1-indexed) is at index -son, as in
+    // `DisputeSOXAccount._extractInAndNotInL_V2` and the proof2 of `compute_proofs_v2`
+    fn ct_leaf(&self, son: i64) -> SoxResult<u32> {
+        let ct_idx = son.unsigned_abs();
+        if ct_idx > self.num_blocks as u64 {
+            return Err(SoxError::Index(format!(
+                "ciphertext block {ct_idx} out of bounds ({} blocks)",
+                self.num_blocks
+            )));
+        }
+        Ok(ct_idx as u32)
+    }
+
+    // Step 9: the losing party is replaced by its dispute sponsor for one more round, at most
+    // once per party, otherwise the dispute is over
+    fn handle_step9(&mut self, vendor_lost: bool) {
+        self.step9_count += 1;
+        let may_continue = self.step9_count == 1
+            || (self.step9_count == 2 && self.last_losing_party_was_vendor != vendor_lost);
+        self.last_losing_party_was_vendor = vendor_lost;
+
+        let sponsor = if vendor_lost {
+            &mut self.vendor_has_sponsor
+        } else {
+            &mut self.buyer_has_sponsor
+        };
+        if may_continue && *sponsor {
+            *sponsor = false;
+            self.bisection = Bisection::new(self.bisection.num_gates);
+            self.state = DisputeState::ChallengeBuyer;
+        } else if vendor_lost {
+            self.state = DisputeState::Cancel;
+        } else {
+            self.state = DisputeState::Complete;
+        }
+    }
+}
+
+// Converts a 32-byte slice into an array
+fn to_bytes32(bytes: &[u8]) -> SoxResult<[u8; 32]> {
+    bytes
+        .try_into()
+        .map_err(|_| SoxError::InvalidInput(format!("expected 32 bytes, got {}", bytes.len())))
+}
+
+// =================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::native::compute_precontract_values_v2;
+    use crate::Precontract;
+
+    // Buyer answering every challenge with garbage
+    struct LyingBuyer;
+
+    impl BuyerStrategy for LyingBuyer {
+        fn respond(&mut self, challenge: u32) -> SoxResult<[u8; 32]> {
+            Ok([challenge as u8; 32])
+        }
+    }
+
+    // Vendor disagreeing with everything and sending proofs computed with the real circuit
+    struct StubbornVendor(HonestVendor);
+
+    impl VendorStrategy for StubbornVendor {
+        fn opinion(&mut self, _: u32, _: &[u8; 32]) -> SoxResult<bool> {
+            Ok(false)
+        }
+
+        fn opening_value(&self) -> Vec<u8> {
+            self.0.opening_value()
+        }
+
+        fn final_step(&mut self, gate_num: u32) -> SoxResult<FinalStepV2> {
+            self.0.final_step(gate_num)
+        }
+
+        fn final_step_left(&mut self, gate_num: u32) -> SoxResult<FinalStepV2> {
+            self.0.final_step_left(gate_num)
+        }
+
+        fn proof_right(&mut self) -> SoxResult<Proof> {
+            self.0.proof_right()
+        }
+    }
+
+    // Lists `len` bytes and returns the precontract, the key and the evaluated circuit
+    fn setup(len: usize, key: &[u8]) -> (Precontract, CompiledCircuitV2, EvaluatedCircuitV2) {
+        let mut data: Vec<u8> = (0..len).map(|i| (i * 7) as u8).collect();
+        let pc = compute_precontract_values_v2(&mut data, key).unwrap();
+        let circuit = CompiledCircuitV2::from_bytes(&pc.circuit_bytes).unwrap();
+        let evaluated = circuit.evaluate(&pc.ct, key).unwrap();
+        (pc, circuit, evaluated)
+    }
+
+    fn dispute(pc: &Precontract, key: &[u8]) -> DisputeSox {
+        DisputeSox::new(pc.num_blocks, pc.num_gates, &pc.commitment.c, key).unwrap()
+    }
+
+    fn honest_vendor(pc: &Precontract, circuit: &CompiledCircuitV2, key: &[u8]) -> HonestVendor {
+        let evaluated = circuit.evaluate(&pc.ct, key).unwrap();
+        HonestVendor::new(
+            circuit.clone(),
+            evaluated,
+            pc.ct.clone(),
+            pc.commitment.o.clone(),
+        )
+    }
+
+    #[test]
+    fn test_state_discriminants_match_contract() {
+        for code in 0..8u8 {
+            assert_eq!(DisputeState::try_from(code).unwrap() as u8, code);
+        }
+        assert!(DisputeState::try_from(8).is_err());
+    }
+
+    #[test]
+    fn test_lying_buyer_loses_on_every_gate() {
+        let key = [3u8; 16];
+        let (pc, circuit, _) = setup(200, &key);
+
+        // respond with garbage only above `wrong_from`, so that the bisection ends on every gate
+        struct PartlyLyingBuyer(HonestBuyer, u32);
+        impl BuyerStrategy for PartlyLyingBuyer {
+            fn respond(&mut self, challenge: u32) -> SoxResult<[u8; 32]> {
+                if challenge >= self.1 {
+                    Ok([0xEE; 32])
+                } else {
+                    self.0.respond(challenge)
+                }
+            }
+        }
+
+        for wrong_from in 1..=pc.num_gates {
+            let evaluated = circuit.evaluate(&pc.ct, &key).unwrap();
+            let mut buyer =
+                PartlyLyingBuyer(HonestBuyer::new(circuit.clone(), evaluated), wrong_from);
+            let mut vendor = honest_vendor(&pc, &circuit, &key);
+            let mut d = dispute(&pc, &key);
+            assert_eq!(
+                d.play_round(&mut buyer, &mut vendor).unwrap(),
+                DisputeState::Complete,
+                "wrong from gate {wrong_from}"
+            );
+            assert_eq!(d.chall(), wrong_from);
+        }
+    }
+
+    #[test]
+    fn test_ct_leaves_match_proofs() {
+        let key = [4u8; 16];
+        let (pc, circuit, _) = setup(100, &key);
+        let mut vendor = honest_vendor(&pc, &circuit, &key);
+        let d = dispute(&pc, &key);
+
+        // the first gate of step 8a reading ciphertext blocks
+        let gate_num = (2..=pc.num_gates)
+            .find(|&g| circuit.gates[g as usize - 1].sons.iter().any(|&s| s < 0))
+            .unwrap();
+        let step = vendor.final_step(gate_num).unwrap();
+        let (mut leaves, mut values_keccak) = (Vec::new(), Vec::new());
+        for (&son, value) in circuit.gates[gate_num as usize - 1]
+            .sons
+            .iter()
+            .zip(&step.values)
+        {
+            if son < 0 {
+                leaves.push(d.ct_leaf(son).unwrap());
+                values_keccak.push(leaf_hash(value));
+            }
+        }
+        assert!(verify(&pc.h_ct, &leaves, &values_keccak, &step.proof2));
+
+        // the proof does not open the 0-indexed blocks, which would skip the IV
+        let shifted: Vec<u32> = leaves.iter().map(|&leaf| leaf - 1).collect();
+        assert!(!verify(&pc.h_ct, &shifted, &values_keccak, &step.proof2));
+        assert!(d.ct_leaf(-(pc.num_blocks as i64) - 1).is_err());
+    }
+
+    #[test]
+    fn test_honest_buyer_wins_against_wrong_description() {
+        let key = [5u8; 16];
+        let mut data = vec![1u8; 100];
+        let mut pc = compute_precontract_values_v2(&mut data, &key).unwrap();
+
+        // the vendor commits to a circuit checking another description
+        let circuit = crate::circuits_v2::compile_circuit_v2(&pc.ct, &[0u8; 32]).unwrap();
+        pc.commitment = crate::commitment::commit_hashes(&circuit.accumulate().unwrap(), &pc.h_ct);
+        let evaluated = circuit.evaluate(&pc.ct, &key).unwrap();
+        let mut buyer = HonestBuyer::new(circuit.clone(), evaluated);
+        let mut vendor = honest_vendor(&pc, &circuit, &key);
+
+        let mut d = dispute(&pc, &key);
+        assert_eq!(
+            d.play_round(&mut buyer, &mut vendor).unwrap(),
+            DisputeState::Cancel
+        );
+        assert_eq!(d.chall(), pc.num_gates + 1);
+    }
+
+    #[test]
+    fn test_vendor_disagreeing_with_honest_buyer_loses() {
+        let key = [7u8; 16];
+        let (pc, circuit, evaluated) = setup(130, &key);
+        let mut buyer = HonestBuyer::new(circuit.clone(), evaluated);
+        let mut vendor = StubbornVendor(honest_vendor(&pc, &circuit, &key));
+
+        let mut d = dispute(&pc, &key);
+        assert_eq!(
+            d.play_round(&mut buyer, &mut vendor).unwrap(),
+            DisputeState::Cancel
+        );
+        assert_eq!(d.state(), DisputeState::Cancel);
+        assert_eq!(d.chall(), 1);
+    }
+
+    #[test]
+    fn test_sponsors_play_a_second_round() {
+        let key = [9u8; 16];
+        let (pc, circuit, evaluated) = setup(70, &key);
+
+        // the buyer loses the first round, its sponsor plays honestly and loses as well
+        let mut d = dispute(&pc, &key).with_sponsors(true, true);
+        let mut vendor = honest_vendor(&pc, &circuit, &key);
+        assert_eq!(
+            d.play_round(&mut LyingBuyer, &mut vendor).unwrap(),
+            DisputeState::ChallengeBuyer
+        );
+        assert_eq!((d.a(), d.b()), (1, pc.num_gates + 1));
+        let mut sponsor = HonestBuyer::new(circuit.clone(), evaluated);
+        assert_eq!(
+            d.play_round(&mut sponsor, &mut vendor).unwrap(),
+            DisputeState::Complete
+        );
+        assert_eq!(d.step9_count(), 2);
+
+        // calls in the wrong state are rejected
+        assert!(d.respond_challenge([0u8; 32]).is_err());
+        assert!(d.give_opinion(true).is_err());
+    }
+}
@@ -0,0 +1,398 @@
+//! Verified downloads of the ciphertext. The leaves of h_ct are the blocks returned by
+//! `split_ct_blocks(ct, 64)` (the IV, then 64-byte blocks), so the vendor can serve any range of
+//! blocks with a multiproof against h_ct and the buyer can check every chunk as it arrives,
+//! instead of checking the whole ciphertext with `check_precontract` once it is downloaded.
+
+use crate::accumulator::{
+    js_array_to_proof, leaf_hash, proof_to_js_array, prove, verify, MerkleLayers,
+};
+use crate::error::{SoxError, SoxResult};
+use crate::native::{to_proof, Proof};
+use crate::utils::split_ct_blocks;
+use js_sys::Array;
+use std::ops::Range;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+// Size of the IV, which is the first leaf of h_ct
+const IV_SIZE: u64 = 16;
+
+// Size of the other leaves of h_ct
+const BLOCK_SIZE: u64 = 64;
+
+/// Returns the number of leaves of h_ct for a ciphertext of `ct_len` bytes, the IV included.
+///
+/// # Arguments
+/// * `ct_len` - Length of the ciphertext, IV included
+///
+/// # Returns
+/// The number of blocks, or `SoxError::InvalidInput` if the ciphertext is shorter than the IV
+pub fn ct_num_blocks(ct_len: u64) -> SoxResult<u32> {
+    if ct_len < IV_SIZE {
+        return Err(SoxError::InvalidInput(format!(
+            "ciphertext must include a 16-byte IV, got {ct_len} bytes"
+        )));
+    }
+    u32::try_from(1 + (ct_len - IV_SIZE).div_ceil(BLOCK_SIZE))
+        .map_err(|_| SoxError::InvalidInput(format!("ciphertext too large: {ct_len} bytes")))
+}
+
+/// Returns the bytes of the ciphertext covered by a range of blocks.
+///
+/// # Arguments
+/// * `ct_len` - Length of the ciphertext, IV included
+/// * `first_block` - Index of the first block, 0 being the IV
+/// * `num_blocks` - Number of blocks, at least 1
+///
+/// # Returns
+/// The byte range, or `SoxError::Index` if the blocks are out of bounds
+pub fn ct_block_range(ct_len: u64, first_block: u32, num_blocks: u32) -> SoxResult<Range<u64>> {
+    let total = ct_num_blocks(ct_len)?;
+    if num_blocks == 0 || first_block as u64 + num_blocks as u64 > total as u64 {
+        return Err(SoxError::Index(format!(
+            "blocks {first_block}..{} out of bounds ({total} blocks)",
+            first_block as u64 + num_blocks as u64
+        )));
+    }
+    let offset = |block: u32| match block {
+        0 => 0,
+        _ => u64::min(IV_SIZE + (block as u64 - 1) * BLOCK_SIZE, ct_len),
+    };
+    let end = first_block + num_blocks;
+    Ok(offset(first_block)..if end == total { ct_len } else { offset(end) })
+}
+
+// Indices of a range of blocks, as proven by `prove`
+fn block_indices(first_block: u32, num_blocks: u32) -> Vec<u32> {
+    (first_block..first_block + num_blocks).collect()
+}
+
+/// Generates the multiproof of a range of blocks of the ciphertext against h_ct, rebuilding the
+/// tree with `prove` over `split_ct_blocks`.
+///
+/// # Arguments
+/// * `ct` - The ciphertext, IV included
+/// * `first_block` - Index of the first block, 0 being the IV
+/// * `num_blocks` - Number of blocks, at least 1
+///
+/// # Returns
+/// The bytes of the blocks and their proof, or `SoxError::Index` if the blocks are out of bounds
+pub fn prove_ct_chunk(ct: &[u8], first_block: u32, num_blocks: u32) -> SoxResult<(Vec<u8>, Proof)> {
+    let range = ct_block_range(ct.len() as u64, first_block, num_blocks)?;
+    let blocks = split_ct_blocks(ct, BLOCK_SIZE as usize)?;
+    let proof = prove(&blocks, &block_indices(first_block, num_blocks))?;
+    Ok((
+        ct[range.start as usize..range.end as usize].to_vec(),
+        to_proof(proof),
+    ))
+}
+
+/// Generates the multiproof of a range of blocks from a tree of the ciphertext blocks, e.g. a
+/// `MerkleTree` built once from `split_ct_blocks(ct, 64)` or an `MmapLayers`, so that serving a
+/// chunk does not rehash the ciphertext.
+///
+/// # Arguments
+/// * `tree` - Tree of the ciphertext blocks, whose root is h_ct
+/// * `first_block` - Index of the first block, 0 being the IV
+/// * `num_blocks` - Number of blocks, at least 1
+///
+/// # Returns
+/// The proof, or `SoxError::Index` if the blocks are out of bounds
+pub fn prove_ct_blocks<T: MerkleLayers + ?Sized>(
+    tree: &T,
+    first_block: u32,
+    num_blocks: u32,
+) -> SoxResult<Proof> {
+    if num_blocks == 0 {
+        return Err(SoxError::Index(
+            "cannot prove an empty range of blocks".into(),
+        ));
+    }
+    tree.prove(&block_indices(first_block, num_blocks))
+}
+
+/// Verifies a chunk of the ciphertext against h_ct. The length of the chunk must be exactly the
+/// one of its blocks, so that a truncated transfer is rejected even when the missing bytes are
+/// zeros (the leaves are padded with zeros).
+///
+/// # Arguments
+/// * `h_ct` - Accumulator of the ciphertext, from the commitment
+/// * `ct_len` - Length of the whole ciphertext, IV included
+/// * `first_block` - Index of the first block of the chunk, 0 being the IV
+/// * `chunk` - Bytes of the blocks
+/// * `proof` - Proof returned by `prove_ct_chunk` or `prove_ct_blocks`
+///
+/// # Returns
+/// `true` if the chunk is the given range of blocks of the committed ciphertext
+pub fn verify_ct_chunk<P: AsRef<[u8]>>(
+    h_ct: &[u8],
+    ct_len: u64,
+    first_block: u32,
+    chunk: &[u8],
+    proof: &[Vec<P>],
+) -> bool {
+    let Some(num_blocks) = chunk_num_blocks(first_block, chunk.len() as u64) else {
+        return false;
+    };
+    match ct_block_range(ct_len, first_block, num_blocks) {
+        Ok(range) if range.end - range.start == chunk.len() as u64 => {}
+        _ => return false,
+    }
+
+    let (iv, data) = chunk.split_at(if first_block == 0 {
+        IV_SIZE as usize
+    } else {
+        0
+    });
+    let leaves: Vec<[u8; 32]> = (first_block == 0)
+        .then(|| leaf_hash(iv))
+        .into_iter()
+        .chain(data.chunks(BLOCK_SIZE as usize).map(leaf_hash))
+        .collect();
+    verify(
+        h_ct,
+        &block_indices(first_block, num_blocks),
+        &leaves,
+        proof,
+    )
+}
+
+// Number of blocks in a chunk of `len` bytes starting at `first_block`, `None` if it is empty
+fn chunk_num_blocks(first_block: u32, len: u64) -> Option<u32> {
+    let data_len = match first_block {
+        0 => len.checked_sub(IV_SIZE)?,
+        _ => len,
+    };
+    let num_blocks = u64::from(first_block == 0) + data_len.div_ceil(BLOCK_SIZE);
+    u32::try_from(num_blocks).ok().filter(|&n| n > 0)
+}
+
+/// Buyer side of a resumable download: chunks are verified in order against h_ct and the
+/// download can be resumed from `next_block` after a corrupt or interrupted transfer.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct CtDownload {
+    h_ct: Vec<u8>,
+    ct_len: u64,
+    next_block: u32,
+}
+
+impl CtDownload {
+    /// Starts a download.
+    ///
+    /// # Arguments
+    /// * `h_ct` - Accumulator of the ciphertext, from the commitment
+    /// * `ct_len` - Length of the ciphertext, IV included
+    ///
+    /// # Returns
+    /// The download, or `SoxError::InvalidInput` if `h_ct` is not 32 bytes long or the ciphertext
+    /// is shorter than the IV
+    pub fn new(h_ct: &[u8], ct_len: u64) -> SoxResult<Self> {
+        if h_ct.len() != 32 {
+            return Err(SoxError::InvalidInput(format!(
+                "h_ct must be 32 bytes long, got {}",
+                h_ct.len()
+            )));
+        }
+        ct_num_blocks(ct_len)?;
+        Ok(Self {
+            h_ct: h_ct.to_vec(),
+            ct_len,
+            next_block: 0,
+        })
+    }
+
+    /// Verifies the next chunk of the ciphertext, which must start at `next_block`, and moves
+    /// past it if it is valid.
+    ///
+    /// # Arguments
+    /// * `chunk` - Bytes of the blocks
+    /// * `proof` - Proof of the blocks against h_ct
+    ///
+    /// # Returns
+    /// The number of verified blocks, or `SoxError::InvalidInput` if the chunk is rejected; the
+    /// download is then left unchanged
+    pub fn push<P: AsRef<[u8]>>(&mut self, chunk: &[u8], proof: &[Vec<P>]) -> SoxResult<u32> {
+        if !verify_ct_chunk(&self.h_ct, self.ct_len, self.next_block, chunk, proof) {
+            return Err(SoxError::InvalidInput(format!(
+                "chunk of {} bytes at block {} does not match h_ct",
+                chunk.len(),
+                self.next_block
+            )));
+        }
+        let num_blocks = chunk_num_blocks(self.next_block, chunk.len() as u64)
+            .expect("verified chunks are not empty");
+        self.next_block += num_blocks;
+        Ok(num_blocks)
+    }
+}
+
+#[wasm_bindgen]
+impl CtDownload {
+    /// Starts a download (see `new`).
+    pub fn create(h_ct: &[u8], ct_len: u64) -> SoxResult<CtDownload> {
+        Self::new(h_ct, ct_len)
+    }
+
+    /// Returns the index of the first block that has not been verified yet
+    pub fn next_block(&self) -> u32 {
+        self.next_block
+    }
+
+    /// Returns the offset in the ciphertext of the first byte that has not been verified yet
+    pub fn next_offset(&self) -> u64 {
+        match self.next_block {
+            0 => 0,
+            block => u64::min(IV_SIZE + (block as u64 - 1) * BLOCK_SIZE, self.ct_len),
+        }
+    }
+
+    /// Returns whether every block has been verified
+    pub fn is_complete(&self) -> bool {
+        ct_num_blocks(self.ct_len).is_ok_and(|total| self.next_block == total)
+    }
+
+    /// JavaScript wrapper of `push`
+    ///
+    /// # Arguments
+    /// * `chunk` - Bytes of the blocks
+    /// * `proof` - Array of arrays of Uint8Arrays containing the proof layers
+    ///
+    /// # Returns
+    /// The number of verified blocks
+    pub fn push_js(&mut self, chunk: &[u8], proof: Array) -> SoxResult<u32> {
+        self.push(chunk, &js_array_to_proof(&proof))
+    }
+}
+
+/// JavaScript wrapper of the prove_ct_chunk function. The bytes of the blocks are not returned:
+/// they are `ct.subarray(start, end)` with the range of `ct_block_range_js`.
+///
+/// # Arguments
+/// * `ct` - The ciphertext, IV included
+/// * `first_block` - Index of the first block, 0 being the IV
+/// * `num_blocks` - Number of blocks, at least 1
+///
+/// # Returns
+/// Array of arrays of Uint8Arrays containing the proof layers
+#[wasm_bindgen]
+pub fn prove_ct_chunk_js(ct: &[u8], first_block: u32, num_blocks: u32) -> SoxResult<Array> {
+    let (_, proof) = prove_ct_chunk(ct, first_block, num_blocks)?;
+    Ok(proof_to_js_array(&proof))
+}
+
+/// JavaScript wrapper of the ct_block_range function
+///
+/// # Arguments
+/// * `ct_len` - Length of the ciphertext, IV included
+/// * `first_block` - Index of the first block, 0 being the IV
+/// * `num_blocks` - Number of blocks, at least 1
+///
+/// # Returns
+/// The start and end offsets of the blocks in the ciphertext
+#[wasm_bindgen]
+pub fn ct_block_range_js(ct_len: u64, first_block: u32, num_blocks: u32) -> SoxResult<Vec<u64>> {
+    let range = ct_block_range(ct_len, first_block, num_blocks)?;
+    Ok(vec![range.start, range.end])
+}
+
+/// JavaScript wrapper of the verify_ct_chunk function
+///
+/// # Arguments
+/// * `h_ct` - Accumulator of the ciphertext
+/// * `ct_len` - Length of the whole ciphertext, IV included
+/// * `first_block` - Index of the first block of the chunk, 0 being the IV
+/// * `chunk` - Bytes of the blocks
+/// * `proof` - Array of arrays of Uint8Arrays containing the proof layers
+///
+/// # Returns
+/// true if the chunk is valid, false otherwise
+#[wasm_bindgen]
+pub fn verify_ct_chunk_js(
+    h_ct: &[u8],
+    ct_len: u64,
+    first_block: u32,
+    chunk: &[u8],
+    proof: Array,
+) -> bool {
+    verify_ct_chunk(h_ct, ct_len, first_block, chunk, &js_array_to_proof(&proof))
+}
+
+// =================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accumulator::{acc_ct, MerkleTree};
+    use rand::RngCore;
+
+    #[test]
+    fn test_chunks_round_trip() {
+        let mut rng = rand::rng();
+        for ct_len in [16, 17, 80, 81, 16 + 64 * 9, 16 + 64 * 9 + 5] {
+            let mut ct = vec![0u8; ct_len];
+            rng.fill_bytes(&mut ct);
+            let h_ct = acc_ct(&ct, 64).unwrap();
+            let tree = MerkleTree::new(&split_ct_blocks(&ct, 64).unwrap()).unwrap();
+            let total = ct_num_blocks(ct_len as u64).unwrap();
+
+            for first in 0..total {
+                for num in 1..=total - first {
+                    let (chunk, proof) = prove_ct_chunk(&ct, first, num).unwrap();
+                    let range = ct_block_range(ct_len as u64, first, num).unwrap();
+                    assert_eq!(chunk, ct[range.start as usize..range.end as usize]);
+                    assert_eq!(proof, prove_ct_blocks(&tree, first, num).unwrap());
+                    assert!(verify_ct_chunk(&h_ct, ct_len as u64, first, &chunk, &proof));
+
+                    let mut corrupt = chunk.clone();
+                    corrupt[chunk.len() / 2] ^= 1;
+                    assert!(!verify_ct_chunk(
+                        &h_ct,
+                        ct_len as u64,
+                        first,
+                        &corrupt,
+                        &proof
+                    ));
+                    assert!(!verify_ct_chunk(
+                        &h_ct,
+                        ct_len as u64,
+                        first,
+                        &chunk[..chunk.len() - 1],
+                        &proof
+                    ));
+                }
+            }
+            assert!(prove_ct_chunk(&ct, 0, total + 1).is_err());
+            assert!(prove_ct_chunk(&ct, 1, 0).is_err());
+        }
+    }
+
+    #[test]
+    fn test_download_resumes_after_bad_chunk() {
+        // the last block ends with zeros, which the padding of the leaves would hide
+        let mut ct = vec![0u8; 16 + 64 * 20 + 10];
+        rand::rng().fill_bytes(&mut ct[..16 + 64 * 20 + 5]);
+        let h_ct = acc_ct(&ct, 64).unwrap();
+        let mut download = CtDownload::new(&h_ct, ct.len() as u64).unwrap();
+
+        let serve = |first: u32, num: u32| prove_ct_chunk(&ct, first, num).unwrap();
+        assert_eq!(download.push(&serve(0, 8).0, &serve(0, 8).1).unwrap(), 8);
+        assert_eq!(download.next_offset(), 16 + 64 * 7);
+
+        // corrupt, then misplaced chunks are rejected without losing the progress
+        let (mut chunk, proof) = serve(8, 5);
+        chunk[3] ^= 0xff;
+        assert!(download.push(&chunk, &proof).is_err());
+        let (chunk, proof) = serve(9, 5);
+        assert!(download.push(&chunk, &proof).is_err());
+        assert_eq!(download.next_block(), 8);
+
+        let (chunk, proof) = serve(8, 13);
+        download.push(&chunk, &proof).unwrap();
+        assert!(!download.is_complete());
+        let (chunk, proof) = serve(21, 1);
+        assert!(download.push(&chunk[..chunk.len() - 5], &proof).is_err());
+        download.push(&chunk, &proof).unwrap();
+        assert!(download.is_complete());
+        assert_eq!(download.next_offset(), ct.len() as u64);
+    }
+}
@@ -1,8 +1,8 @@
-use crate::utils::die;
+use crate::error::{SoxError, SoxResult};
 use aes::cipher::{KeyIvInit, StreamCipher};
 use rand::RngCore;
 
-type Aes128Ctr128BE = ctr::Ctr128BE<aes::Aes128>;
+pub(crate) type Aes128Ctr128BE = ctr::Ctr128BE<aes::Aes128>;
 
 /// Encrypts data using AES-128 in counter mode and prepends IV in 16 bytes big endian
 /// representation. `ct = IV (16 bytes) || Enc_k(data) (variable size)`
@@ -12,24 +12,22 @@ type Aes128Ctr128BE = ctr::Ctr128BE<aes::Aes128>;
 /// * `key` - Key bytes (must be 16 bytes)
 ///
 /// # Returns
-/// Vector containing IV (16 bytes) followed by encrypted data
-pub fn encrypt_and_prepend_iv(mut data: &mut [u8], key: &[u8]) -> Vec<u8> {
+/// Vector containing IV (16 bytes) followed by encrypted data, or `SoxError::KeyLength` if the key
+/// is not 16 bytes long
+pub fn encrypt_and_prepend_iv(data: &mut [u8], key: &[u8]) -> SoxResult<Vec<u8>> {
     let mut rng = rand::rng();
     let mut iv = vec![0u8; 16];
     rng.fill_bytes(&mut iv);
 
-    let mut cipher = match Aes128Ctr128BE::new_from_slices(key, &iv) {
-        Ok(c) => c,
-        Err(_) => die("Key must be 16 bytes"),
-    };
+    let mut cipher = new_cipher(key, &iv)?;
 
-    cipher.apply_keystream(&mut data);
+    cipher.apply_keystream(data);
 
     // Optimize: pre-allocate with exact capacity
     let mut result = Vec::with_capacity(16 + data.len());
     result.extend_from_slice(&iv);
     result.extend_from_slice(data);
-    result
+    Ok(result)
 }
 
 /// Decrypts AES-128 CTR mode ciphertext. The IV must be in big-endian representation.
@@ -39,19 +37,31 @@ pub fn encrypt_and_prepend_iv(mut data: &mut [u8], key: &[u8]) -> Vec<u8> {
 /// * `key` - Key bytes (must be 16 bytes)
 ///
 /// # Returns
-/// Decrypted plaintext bytes
-pub fn decrypt(ct: &[u8], key: &[u8]) -> Vec<u8> {
+/// Decrypted plaintext bytes, or an error if the key is not 16 bytes long or the ciphertext does
+/// not contain an IV
+pub fn decrypt(ct: &[u8], key: &[u8]) -> SoxResult<Vec<u8>> {
+    if ct.len() < 16 {
+        return Err(SoxError::InvalidInput(format!(
+            "ciphertext must include a 16-byte IV, got {} bytes",
+            ct.len()
+        )));
+    }
     let iv = &ct[..16];
-    let mut cipher = match Aes128Ctr128BE::new_from_slices(key, &iv) {
-        Ok(c) => c,
-        Err(_) => die("Key should be 16 bytes"),
-    };
+    let mut cipher = new_cipher(key, iv)?;
 
     let mut res = ct[16..].to_vec();
 
     cipher.apply_keystream(&mut res);
 
-    res
+    Ok(res)
+}
+
+// Instantiates the AES-128 CTR cipher, checking the key length
+fn new_cipher(key: &[u8], iv: &[u8]) -> SoxResult<Aes128Ctr128BE> {
+    Aes128Ctr128BE::new_from_slices(key, iv).map_err(|_| SoxError::KeyLength {
+        expected: 16,
+        got: key.len(),
+    })
 }
 
 #[cfg(test)]
@@ -70,12 +80,28 @@ mod tests {
             rng.fill_bytes(&mut key);
 
             // encrypt
-            let ct = encrypt_and_prepend_iv(&mut data, &key);
+            let ct = encrypt_and_prepend_iv(&mut data, &key).unwrap();
 
             // decrypt
-            let dec_ct = decrypt(&ct, &key);
+            let dec_ct = decrypt(&ct, &key).unwrap();
 
             assert_eq!(plaintext, dec_ct);
         }
     }
+
+    #[test]
+    fn test_wrong_key_length() {
+        let mut data = vec![0u8; 32];
+        assert_eq!(
+            encrypt_and_prepend_iv(&mut data, &[0u8; 15]),
+            Err(SoxError::KeyLength {
+                expected: 16,
+                got: 15
+            })
+        );
+        assert_eq!(
+            decrypt(&[0u8; 8], &[0u8; 16]).unwrap_err().code(),
+            "SOX_INVALID_INPUT"
+        );
+    }
 }
@@ -0,0 +1,127 @@
+use std::fmt;
+use wasm_bindgen::JsValue;
+
+/// Errors returned by every fallible operation of the crate. Each variant maps to a stable
+/// error code (see [`SoxError::code`]) which is attached to the JavaScript exception thrown by
+/// the wasm bindings, so callers can branch on it without parsing messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SoxError {
+    /// A serialized structure (circuit, evaluated circuit, argument...) could not be decoded
+    Decode(String),
+
+    /// A hex string could not be parsed
+    Hex(String),
+
+    /// The opening value does not hash to the expected commitment
+    CommitmentMismatch,
+
+    /// A circuit or gate is malformed, or does not match the data it is applied on
+    CircuitShape(String),
+
+    /// A key does not have the expected length
+    KeyLength { expected: usize, got: usize },
+
+    /// An index (challenge, son, block...) is out of bounds
+    Index(String),
+
+    /// A value passed to an operation has an invalid length or content
+    InvalidInput(String),
+
+    /// Reading from a source or writing to a sink failed (native streaming APIs only)
+    Io(String),
+}
+
+/// Shorthand for results carrying a [`SoxError`]
+pub type SoxResult<T> = Result<T, SoxError>;
+
+impl SoxError {
+    /// Returns the stable error code of this error. These codes are part of the public API and
+    /// must not be changed.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SoxError::Decode(_) => "SOX_DECODE",
+            SoxError::Hex(_) => "SOX_HEX",
+            SoxError::CommitmentMismatch => "SOX_COMMITMENT_MISMATCH",
+            SoxError::CircuitShape(_) => "SOX_CIRCUIT_SHAPE",
+            SoxError::KeyLength { .. } => "SOX_KEY_LENGTH",
+            SoxError::Index(_) => "SOX_INDEX",
+            SoxError::InvalidInput(_) => "SOX_INVALID_INPUT",
+            SoxError::Io(_) => "SOX_IO",
+        }
+    }
+}
+
+impl fmt::Display for SoxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SoxError::Decode(msg) => write!(f, "decoding error: {msg}"),
+            SoxError::Hex(msg) => write!(f, "invalid hex: {msg}"),
+            SoxError::CommitmentMismatch => write!(f, "the commitments do not match"),
+            SoxError::CircuitShape(msg) => write!(f, "invalid circuit: {msg}"),
+            SoxError::KeyLength { expected, got } => {
+                write!(f, "key must be {expected} bytes, got {got}")
+            }
+            SoxError::Index(msg) => write!(f, "index out of bounds: {msg}"),
+            SoxError::InvalidInput(msg) => write!(f, "invalid input: {msg}"),
+            SoxError::Io(msg) => write!(f, "i/o error: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for SoxError {}
+
+impl From<rmp_serde::decode::Error> for SoxError {
+    fn from(err: rmp_serde::decode::Error) -> Self {
+        SoxError::Decode(err.to_string())
+    }
+}
+
+impl From<std::io::Error> for SoxError {
+    fn from(err: std::io::Error) -> Self {
+        SoxError::Io(err.to_string())
+    }
+}
+
+/// Converts the error into a JavaScript `Error` named `SoxError` with an additional `code`
+/// property holding [`SoxError::code`]. Only meaningful in a wasm context.
+impl From<SoxError> for JsValue {
+    fn from(err: SoxError) -> Self {
+        let js_err = js_sys::Error::new(&err.to_string());
+        js_err.set_name("SoxError");
+        let _ = js_sys::Reflect::set(&js_err, &"code".into(), &err.code().into());
+        js_err.into()
+    }
+}
+
+// =================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_are_distinct() {
+        let errors = [
+            SoxError::Decode(String::new()),
+            SoxError::Hex(String::new()),
+            SoxError::CommitmentMismatch,
+            SoxError::CircuitShape(String::new()),
+            SoxError::KeyLength {
+                expected: 16,
+                got: 0,
+            },
+            SoxError::Index(String::new()),
+            SoxError::InvalidInput(String::new()),
+            SoxError::Io(String::new()),
+        ];
+        let codes: std::collections::HashSet<&str> = errors.iter().map(|e| e.code()).collect();
+        assert_eq!(codes.len(), errors.len());
+    }
+
+    #[test]
+    fn test_decode_error_conversion() {
+        let res: Result<Vec<u32>, _> = rmp_serde::from_read(&[0xc1u8][..]);
+        let err: SoxError = res.unwrap_err().into();
+        assert_eq!(err.code(), "SOX_DECODE");
+    }
+}
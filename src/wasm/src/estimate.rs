@@ -0,0 +1,404 @@
+//! Cost estimation of a dispute from the shape of a precontract only (`num_blocks` and
+//! `num_gates`), so that dispute sponsors can decide whether to fund a dispute. The proof sizes
+//! follow the layers built by `prove`, the calldata is encoded with the functions of the
+//! `calldata` module and the gas is computed under a configurable `GasSchedule`.
+
+use crate::calldata::{
+    give_opinion_calldata, respond_challenge_calldata, submit_commitment_calldata,
+    submit_commitment_left_calldata, submit_commitment_right_calldata,
+};
+use crate::dispute::{Bisection, DisputeState};
+use crate::error::{SoxError, SoxResult};
+use crate::native::{FinalStepV2, Proof};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+// Length of the opening value of a commitment: h_circuit || h_ct || 16 random bytes
+const OPENING_VALUE_LEN: usize = 80;
+
+// Length of a gate output, of a ciphertext block and of an encoded V2 gate
+const VALUE_LEN: usize = 64;
+
+/// Gas costs used by the estimator. The defaults are those of Ethereum mainnet (EIP-2028 calldata
+/// pricing and the Keccak-256 opcode on two words).
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GasSchedule {
+    /// Fixed cost of a transaction
+    pub tx_base: u64,
+
+    /// Cost of a zero byte of calldata
+    pub calldata_zero_byte: u64,
+
+    /// Cost of a non-zero byte of calldata
+    pub calldata_nonzero_byte: u64,
+
+    /// Cost of hashing one proof node with its sibling during the verification
+    pub proof_node: u64,
+}
+
+impl Default for GasSchedule {
+    fn default() -> Self {
+        Self {
+            tx_base: 21_000,
+            calldata_zero_byte: 4,
+            calldata_nonzero_byte: 16,
+            proof_node: 42,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl GasSchedule {
+    /// Creates the default schedule, whose fields can then be changed
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> GasSchedule {
+        GasSchedule::default()
+    }
+}
+
+impl GasSchedule {
+    // Cost of a transaction carrying `data`, excluding the execution of the contract
+    fn transaction(&self, data: &[u8]) -> u64 {
+        let zeros = data.iter().filter(|&&b| b == 0).count() as u64;
+        let nonzeros = data.len() as u64 - zeros;
+        self.tx_base + zeros * self.calldata_zero_byte + nonzeros * self.calldata_nonzero_byte
+    }
+}
+
+/// Shape of a proof: the number of layers and the total number of nodes
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProofSize {
+    /// Number of layers, i.e. of `bytes32[]` in the `bytes32[][]` argument
+    pub layers: u32,
+
+    /// Number of 32-byte nodes over all layers
+    pub nodes: u32,
+}
+
+impl ProofSize {
+    // Proof of the value at `index` among `width` values, following the walk of `prove`: one
+    // layer per level of the tree, holding the sibling of the node when it exists
+    fn single(width: usize, index: usize) -> Self {
+        let mut size = Self::default();
+        let (mut width, mut index) = (width, index);
+        while width > 1 {
+            size.layers += 1;
+            if index ^ 1 < width {
+                size.nodes += 1;
+            }
+            index >>= 1;
+            width = width.div_ceil(2);
+        }
+        size
+    }
+
+    // Upper bound on the proof of two values among `width`: both values need a sibling on each
+    // level until their paths meet, at the latest right below the root
+    fn pair_bound(width: usize) -> Self {
+        if width < 2 {
+            return Self::default();
+        }
+        let layers = Self::single(width, 0).layers;
+        Self {
+            layers,
+            nodes: 2 * (layers - 1),
+        }
+    }
+
+    // Dummy proof of this shape, with non-zero nodes like the Keccak hashes of a real proof
+    fn dummy(self) -> Proof {
+        (0..self.layers)
+            .map(|layer| {
+                let nodes = self.nodes / self.layers + u32::from(layer < self.nodes % self.layers);
+                vec![[0xff; 32]; nodes as usize]
+            })
+            .collect()
+    }
+}
+
+/// Estimated cost of one branch of the vendor's final step (steps 8a, 8b and 8c)
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FinalStepEstimate {
+    /// Number of bisection rounds before the final step
+    pub rounds: u32,
+
+    /// Proof that the gate belongs to the circuit
+    pub proof1: ProofSize,
+
+    /// Proof that the input sons belong to the ciphertext
+    pub proof2: ProofSize,
+
+    /// Proof that the other sons belong to the evaluated values
+    pub proof3: ProofSize,
+
+    /// Extension proof (the only proof of step 8c)
+    pub proof_ext: ProofSize,
+
+    /// Size of the calldata of the final step
+    pub calldata_bytes: u64,
+
+    /// Gas of the final step transaction
+    pub gas: u64,
+
+    /// Gas of the whole branch: the bisection rounds and the final step
+    pub total_gas: u64,
+}
+
+/// Estimated cost of a dispute. The sizes of step 8a depend on the challenged gate, so they are
+/// upper bounds over all gates; those of steps 8b and 8c are exact.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisputeCostEstimate {
+    /// Maximum number of bisection rounds
+    pub max_rounds: u32,
+
+    /// Calldata of one bisection round (`respondChallenge` and `giveOpinion`)
+    pub round_calldata_bytes: u64,
+
+    /// Gas of one bisection round (two transactions)
+    pub round_gas: u64,
+
+    /// Step 8a: `submitCommitment`
+    pub step_8a: FinalStepEstimate,
+
+    /// Step 8b: `submitCommitmentLeft`
+    pub step_8b: FinalStepEstimate,
+
+    /// Step 8c: `submitCommitmentRight`
+    pub step_8c: FinalStepEstimate,
+}
+
+// Contract function of a final step
+#[derive(Clone, Copy)]
+enum Step {
+    Data,
+    Left,
+    Right,
+}
+
+/// Estimates the cost of a dispute over a V2 circuit. The gas covers the transactions (base cost
+/// and calldata) and the hashing of the proof nodes; the rest of the execution of the contract is
+/// not modeled.
+///
+/// # Arguments
+/// * `num_blocks` - Number of blocks of the ciphertext, as in the precontract
+/// * `num_gates` - Number of gates of the circuit, as in the precontract
+/// * `schedule` - Gas costs
+///
+/// # Returns
+/// The estimate, or `SoxError::InvalidInput` if there is no block or no gate
+pub fn estimate_dispute_cost(
+    num_blocks: u32,
+    num_gates: u32,
+    schedule: &GasSchedule,
+) -> SoxResult<DisputeCostEstimate> {
+    if num_blocks == 0 || num_gates == 0 {
+        return Err(SoxError::InvalidInput(
+            "a dispute needs at least one block and one gate".into(),
+        ));
+    }
+    let n = num_gates as usize;
+    // The IV is the first leaf of the ciphertext accumulator
+    let ct_leaves = num_blocks as usize + 1;
+
+    let round = [
+        respond_challenge_calldata(&[0xff; 32])?,
+        give_opinion_calldata(true),
+    ];
+    let round_calldata_bytes = round.iter().map(|d| d.len() as u64).sum();
+    let round_gas = round.iter().map(|d| schedule.transaction(d)).sum();
+    let rounds_to = |agree: bool| -> SoxResult<u32> {
+        let mut bisection = Bisection::new(num_gates);
+        let mut rounds = 1;
+        while bisection.narrow(agree)? == DisputeState::ChallengeBuyer {
+            rounds += 1;
+        }
+        Ok(rounds)
+    };
+    // Disagreeing always keeps the larger half, so the left branch is the longest
+    let max_rounds = rounds_to(false)?;
+
+    let branch = |rounds: u32, sizes: [ProofSize; 4], num_values: usize, step: Step| {
+        let [proof1, proof2, proof3, proof_ext] = sizes;
+        let components = FinalStepV2 {
+            gate_bytes: vec![0xff; VALUE_LEN],
+            values: vec![vec![0xff; VALUE_LEN]; num_values],
+            curr_acc: vec![0xff; 32],
+            proof1: proof1.dummy(),
+            proof2: proof2.dummy(),
+            proof3: proof3.dummy(),
+            proof_ext: proof_ext.dummy(),
+        };
+        let opening = [0xff; OPENING_VALUE_LEN];
+        let data = match step {
+            Step::Data => submit_commitment_calldata(&opening, num_gates, &components)?,
+            Step::Left => submit_commitment_left_calldata(&opening, 1, &components)?,
+            Step::Right => submit_commitment_right_calldata(&components.proof_ext),
+        };
+        let nodes = sizes.iter().map(|s| s.nodes as u64).sum::<u64>();
+        let gas = schedule.transaction(&data) + nodes * schedule.proof_node;
+        Ok::<_, SoxError>(FinalStepEstimate {
+            rounds,
+            proof1,
+            proof2,
+            proof3,
+            proof_ext,
+            calldata_bytes: data.len() as u64,
+            gas,
+            total_gas: rounds as u64 * round_gas + gas,
+        })
+    };
+
+    // Step 8a on the worst gate: either an AES gate reading one ciphertext block, or a gate with
+    // two sons among the outputs of the previous gates
+    let none = ProofSize::default();
+    let gate = ProofSize::single(n, 0);
+    let ext = gate;
+    // (unreachable with a single gate, which ends in step 8b or 8c)
+    let step_8a = if n < 2 {
+        branch(max_rounds, [none; 4], 0, Step::Data)?
+    } else {
+        let ct_son = ProofSize::single(ct_leaves, 0);
+        let two_sons = ProofSize::pair_bound(n - 1);
+        let with_ct = branch(max_rounds, [gate, ct_son, none, ext], 1, Step::Data)?;
+        let with_outputs = branch(max_rounds, [gate, none, two_sons, ext], 2, Step::Data)?;
+        if with_ct.gas >= with_outputs.gas {
+            with_ct
+        } else {
+            with_outputs
+        }
+    };
+
+    // Step 8b on the first gate, which decrypts the first ciphertext block (leaf 1)
+    let step_8b = branch(
+        max_rounds,
+        [gate, ProofSize::single(ct_leaves, 1), none, none],
+        1,
+        Step::Left,
+    )?;
+
+    // Step 8c: the output of the last gate
+    let step_8c = branch(
+        rounds_to(true)?,
+        [none, none, none, ProofSize::single(n, n - 1)],
+        0,
+        Step::Right,
+    )?;
+
+    Ok(DisputeCostEstimate {
+        max_rounds,
+        round_calldata_bytes,
+        round_gas,
+        step_8a,
+        step_8b,
+        step_8c,
+    })
+}
+
+/// JavaScript wrapper of the estimate_dispute_cost function
+///
+/// # Arguments
+/// * `num_blocks` - Number of blocks of the ciphertext
+/// * `num_gates` - Number of gates of the circuit
+/// * `schedule` - Gas costs
+///
+/// # Returns
+/// The estimated cost of the dispute
+#[wasm_bindgen]
+pub fn estimate_dispute_cost_js(
+    num_blocks: u32,
+    num_gates: u32,
+    schedule: &GasSchedule,
+) -> SoxResult<DisputeCostEstimate> {
+    estimate_dispute_cost(num_blocks, num_gates, schedule)
+}
+
+// =================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accumulator::prove;
+
+    #[test]
+    fn test_proof_sizes_match_prove() {
+        for width in 1..40usize {
+            let values: Vec<Vec<u8>> = (0..width).map(|i| vec![i as u8]).collect();
+            for index in 0..width {
+                let proof = prove(&values, &[index as u32]).unwrap();
+                let size = ProofSize::single(width, index);
+                assert_eq!(size.layers as usize, proof.len());
+                assert_eq!(
+                    size.nodes as usize,
+                    proof.iter().map(Vec::len).sum::<usize>()
+                );
+                let dummy = size.dummy();
+                assert_eq!(dummy.len(), proof.len());
+                assert_eq!(
+                    dummy.iter().map(Vec::len).sum::<usize>(),
+                    size.nodes as usize
+                );
+            }
+
+            let bound = ProofSize::pair_bound(width);
+            for i in 0..width {
+                for j in i + 1..width {
+                    let proof = prove(&values, &[i as u32, j as u32]).unwrap();
+                    assert!(proof.iter().map(Vec::len).sum::<usize>() <= bound.nodes as usize);
+                    assert_eq!(proof.len(), bound.layers as usize);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_estimate_dispute_cost() {
+        let schedule = GasSchedule::default();
+        let estimate = estimate_dispute_cost(4, 11, &schedule).unwrap();
+
+        // 11 gates: chall 6, then 3, 2, 1 when the vendor always disagrees
+        assert_eq!(estimate.max_rounds, 4);
+        assert_eq!(estimate.step_8b.rounds, 4);
+        assert!(estimate.step_8c.rounds <= estimate.max_rounds);
+        assert_eq!(estimate.round_calldata_bytes, 72);
+        assert_eq!(estimate.step_8b.proof1, ProofSize::single(11, 0));
+        assert_eq!(estimate.step_8b.proof2, ProofSize::single(5, 1));
+        assert_eq!(estimate.step_8c.proof_ext, ProofSize::single(11, 10));
+        for step in [estimate.step_8a, estimate.step_8b, estimate.step_8c] {
+            assert_eq!(
+                step.total_gas,
+                step.rounds as u64 * estimate.round_gas + step.gas
+            );
+            assert!(step.gas > schedule.tx_base);
+        }
+
+        // the calldata is the one encoded for proofs of the same shape
+        let proof = ProofSize::single(11, 10).dummy();
+        assert_eq!(
+            estimate.step_8c.calldata_bytes,
+            submit_commitment_right_calldata(&proof).len() as u64
+        );
+
+        let free = GasSchedule {
+            tx_base: 0,
+            calldata_zero_byte: 0,
+            calldata_nonzero_byte: 0,
+            proof_node: 1,
+        };
+        let estimate = estimate_dispute_cost(4, 11, &free).unwrap();
+        assert_eq!(estimate.round_gas, 0);
+        assert_eq!(
+            estimate.step_8c.gas,
+            estimate.step_8c.proof_ext.nodes as u64
+        );
+
+        assert!(estimate_dispute_cost(0, 11, &schedule).is_err());
+        assert!(estimate_dispute_cost(4, 0, &schedule).is_err());
+        assert_eq!(
+            estimate_dispute_cost(1, 1, &schedule).unwrap().max_rounds,
+            1
+        );
+    }
+}
@@ -0,0 +1,388 @@
+//! Semantic labels of the gates of compiled circuits. The labels are a side table emitted by
+//! `compile_circuit_v2_with_labels` and `compile_basic_circuit_with_labels`: they are not part of
+//! the circuit nor of its accumulator, they only tell what a gate does (decryption, padding,
+//! compression step...) and which bytes of the plaintext it covers, e.g. once a dispute has
+//! converged on a gate.
+//!
+//! Gates are numbered like in the dispute contracts: from 1 for V2 circuits, by absolute index for
+//! V1 circuits (whose first gates are the input blocks). The table is stored as runs of
+//! consecutive gates with the same role on consecutive blocks, so its size does not depend on the
+//! size of the file.
+
+use crate::error::{SoxError, SoxResult};
+use rmp_serde::{encode::write, from_read};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::Range;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// Role of a gate in a compiled circuit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GateRole {
+    /// Input block of a V1 circuit (the IV has no block)
+    Input,
+
+    /// Increment of the AES-CTR counter of a block (V1)
+    CounterIncrement,
+
+    /// AES-CTR decryption of a block
+    Decrypt,
+
+    /// Constant of the SHA-256 padding: mask of the last block or extra padding block
+    PaddingMask,
+
+    /// Application of the SHA-256 padding mask to the last block
+    Padding,
+
+    /// SHA-256 compression of a block (with the padding for the last one of V1 circuits)
+    HashStep,
+
+    /// Constant holding the description
+    Description,
+
+    /// Final comparison of the hash with the description
+    Compare,
+}
+
+impl fmt::Display for GateRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            GateRole::Input => "input",
+            GateRole::CounterIncrement => "counter increment",
+            GateRole::Decrypt => "AES-CTR decryption",
+            GateRole::PaddingMask => "SHA-256 padding constant",
+            GateRole::Padding => "SHA-256 padding",
+            GateRole::HashStep => "SHA-256 compression",
+            GateRole::Description => "description constant",
+            GateRole::Compare => "comparison with the description",
+        })
+    }
+}
+
+/// Label of a gate: its role and the plaintext block it works on, if any. Blocks are numbered from
+/// 0 and have the block size of the circuit; the block after the last plaintext block is the extra
+/// SHA-256 padding block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GateLabel {
+    pub role: GateRole,
+    pub block: Option<u32>,
+}
+
+// Gates first_gate..first_gate + count, with the same role, on consecutive blocks from
+// first_block (or on no block)
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct LabelRun {
+    first_gate: u32,
+    count: u32,
+    role: GateRole,
+    first_block: Option<u32>,
+}
+
+/// Labels of the gates of a compiled circuit.
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CircuitLabels {
+    pt_len: u64,
+    block_size: u32,
+    runs: Vec<LabelRun>,
+}
+
+impl CircuitLabels {
+    // Empty table of a circuit for a plaintext of pt_len bytes
+    pub(crate) fn new(pt_len: u64, block_size: u32) -> Self {
+        Self {
+            pt_len,
+            block_size,
+            runs: Vec::new(),
+        }
+    }
+
+    // Labels the next gate. Gates must be labeled in increasing order.
+    pub(crate) fn push(&mut self, gate: u32, role: GateRole, block: Option<u32>) {
+        if let Some(run) = self.runs.last_mut() {
+            let follows = run.first_gate + run.count == gate && run.role == role;
+            let next_block = run.first_block.map(|b| b + run.count);
+            if follows && (block.is_none() && run.first_block.is_none() || block == next_block) {
+                run.count += 1;
+                return;
+            }
+        }
+        self.runs.push(LabelRun {
+            first_gate: gate,
+            count: 1,
+            role,
+            first_block: block,
+        });
+    }
+
+    // Labels a range of gates with the same role and block
+    pub(crate) fn push_all(&mut self, gates: Range<u32>, role: GateRole, block: Option<u32>) {
+        for gate in gates {
+            self.push(gate, role, block);
+        }
+    }
+
+    /// Returns the label of a gate.
+    ///
+    /// # Arguments
+    /// * `gate` - Number of the gate, like in the dispute contract
+    ///
+    /// # Returns
+    /// The label, or `None` if the gate is not labeled
+    pub fn label(&self, gate: u32) -> Option<GateLabel> {
+        let idx = self.runs.partition_point(|run| run.first_gate <= gate);
+        let run = &self.runs[idx.checked_sub(1)?];
+        let offset = gate - run.first_gate;
+        (offset < run.count).then(|| GateLabel {
+            role: run.role,
+            block: run.first_block.map(|b| b + offset),
+        })
+    }
+
+    /// Returns the bytes of the plaintext covered by a gate.
+    ///
+    /// # Arguments
+    /// * `gate` - Number of the gate, like in the dispute contract
+    ///
+    /// # Returns
+    /// The range of plaintext bytes, or `None` if the gate is not labeled or does not work on
+    /// plaintext bytes (constants, comparison, extra padding block)
+    pub fn plaintext_range(&self, gate: u32) -> Option<Range<u64>> {
+        let start = self.label(gate)?.block? as u64 * self.block_size as u64;
+        (start < self.pt_len).then(|| start..u64::min(start + self.block_size as u64, self.pt_len))
+    }
+
+    /// Describes a gate, e.g. `AES-CTR decryption of block 3 (plaintext bytes 192..256)`.
+    ///
+    /// # Arguments
+    /// * `gate` - Number of the gate, like in the dispute contract
+    ///
+    /// # Returns
+    /// The description, or `None` if the gate is not labeled
+    pub fn describe(&self, gate: u32) -> Option<String> {
+        let label = self.label(gate)?;
+        Some(match (label.block, self.plaintext_range(gate)) {
+            (Some(block), Some(range)) => format!(
+                "{} of block {block} (plaintext bytes {}..{})",
+                label.role, range.start, range.end
+            ),
+            (Some(block), None) => format!("{} of block {block} (padding only)", label.role),
+            (None, _) => label.role.to_string(),
+        })
+    }
+
+    /// Deserializes labels from bytes.
+    ///
+    /// # Arguments
+    /// * `bytes` - Bytes returned by `to_bytes`
+    ///
+    /// # Returns
+    /// The labels, or `SoxError::Decode` if the bytes are malformed or the runs are empty,
+    /// unsorted, overlapping or past the last gate or block number
+    pub fn from_bytes(bytes: &[u8]) -> SoxResult<CircuitLabels> {
+        let labels: CircuitLabels = from_read(bytes)?;
+        let mut next_gate = 0;
+        for (i, run) in labels.runs.iter().enumerate() {
+            // `label` computes first_gate + offset and first_block + offset for offset < count
+            let end = run.first_gate.checked_add(run.count);
+            let blocks_fit = run
+                .first_block
+                .is_none_or(|b| b.checked_add(run.count).is_some());
+            match end {
+                Some(end) if run.count > 0 && run.first_gate >= next_gate && blocks_fit => {
+                    next_gate = end
+                }
+                _ => return Err(SoxError::Decode(format!("malformed label run {i}"))),
+            }
+        }
+        Ok(labels)
+    }
+}
+
+#[wasm_bindgen]
+impl CircuitLabels {
+    /// Serializes the labels into bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write(&mut buf, self).expect("serializing into a Vec cannot fail");
+        buf
+    }
+
+    /// JavaScript wrapper of `describe`
+    pub fn describe_js(&self, gate: u32) -> Option<String> {
+        self.describe(gate)
+    }
+
+    /// Returns the start and end of the plaintext bytes covered by a gate, empty if there are none
+    /// (see `plaintext_range`)
+    pub fn plaintext_range_js(&self, gate: u32) -> Vec<u64> {
+        self.plaintext_range(gate)
+            .map_or(vec![], |range| vec![range.start, range.end])
+    }
+}
+
+/// JavaScript wrapper of `CircuitLabels::from_bytes`
+///
+/// # Arguments
+/// * `bytes` - Serialized labels
+///
+/// # Returns
+/// The labels
+#[wasm_bindgen]
+pub fn circuit_labels_from_bytes_js(bytes: &[u8]) -> SoxResult<CircuitLabels> {
+    CircuitLabels::from_bytes(bytes)
+}
+
+// =================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuits::compile_basic_circuit_with_labels;
+    use crate::circuits_v2::{
+        compile_circuit_v2_with_labels, OPCODE_AES_CTR, OPCODE_COMP, OPCODE_CONST, OPCODE_SHA2,
+        OPCODE_XOR,
+    };
+
+    #[test]
+    fn test_labels_v2() {
+        for pt_len in [1usize, 55, 56, 64, 65, 200, 1000] {
+            let ct = vec![0x5a; 16 + pt_len];
+            let (circuit, labels) = compile_circuit_v2_with_labels(&ct, &[0xab; 32]).unwrap();
+            let m = pt_len.div_ceil(64) as u32;
+
+            let mut hash_blocks = vec![];
+            for (i, gate) in circuit.gates.iter().enumerate() {
+                let label = labels.label(i as u32 + 1).unwrap();
+                let opcode = match label.role {
+                    GateRole::Decrypt => OPCODE_AES_CTR,
+                    GateRole::PaddingMask | GateRole::Description => OPCODE_CONST,
+                    GateRole::Padding => OPCODE_XOR,
+                    GateRole::HashStep => OPCODE_SHA2,
+                    GateRole::Compare => OPCODE_COMP,
+                    role => panic!("unexpected role {role} in a V2 circuit"),
+                };
+                assert_eq!(gate.opcode, opcode, "pt_len {pt_len}, gate {}", i + 1);
+                if label.role == GateRole::Decrypt {
+                    assert_eq!(gate.sons, vec![-(label.block.unwrap() as i64) - 1]);
+                }
+                if label.role == GateRole::HashStep {
+                    hash_blocks.push(label.block.unwrap());
+                }
+            }
+            assert_eq!(labels.label(0), None);
+            assert_eq!(labels.label(circuit.gates.len() as u32 + 1), None);
+
+            // one compression per block, plus the extra padding block if any
+            let extra = (pt_len % 64 == 0 || pt_len % 64 > 55) as u32;
+            assert_eq!(hash_blocks, (0..m + extra).collect::<Vec<_>>());
+            assert!(labels.runs.len() <= 12, "{} runs", labels.runs.len());
+        }
+    }
+
+    #[test]
+    fn test_labels_v1() {
+        for pt_len in [1u32, 64, 65, 128, 300] {
+            let (circuit, labels) =
+                compile_basic_circuit_with_labels(16 + pt_len, &[1; 32]).unwrap();
+            let m = circuit.num_blocks;
+
+            let mut decrypted = vec![];
+            for (i, gate) in circuit.circuit.iter().enumerate() {
+                let label = labels.label(i as u32).unwrap();
+                let opcodes: &[u32] = match label.role {
+                    GateRole::Input => &[u32::MAX],
+                    GateRole::CounterIncrement => &[3],
+                    GateRole::Decrypt => &[2],
+                    GateRole::HashStep => &[0, 7],
+                    GateRole::Compare => &[5],
+                    role => panic!("unexpected role {role} in a V1 circuit"),
+                };
+                assert!(opcodes.contains(&gate.opcode), "pt_len {pt_len}, gate {i}");
+                if label.role == GateRole::Decrypt {
+                    // the second son is the input gate of the block
+                    assert_eq!(gate.sons[1], label.block.unwrap() + 1);
+                    decrypted.push(label.block.unwrap());
+                }
+            }
+            assert_eq!(decrypted, (0..m - 1).collect::<Vec<_>>());
+            assert_eq!(labels.label(circuit.circuit.len() as u32), None);
+        }
+    }
+
+    #[test]
+    fn test_describe() {
+        let ct = vec![0; 16 + 200];
+        let (circuit, labels) = compile_circuit_v2_with_labels(&ct, &[0; 32]).unwrap();
+
+        assert_eq!(labels.plaintext_range(1), Some(0..64));
+        assert_eq!(labels.plaintext_range(4), Some(192..200));
+        assert_eq!(
+            labels.describe(4).unwrap(),
+            "AES-CTR decryption of block 3 (plaintext bytes 192..200)"
+        );
+        assert_eq!(
+            labels.describe(5).unwrap(),
+            "SHA-256 padding constant of block 3 (plaintext bytes 192..200)"
+        );
+        let last = circuit.gates.len() as u32;
+        assert_eq!(labels.plaintext_range(last), None);
+        assert_eq!(
+            labels.describe(last).unwrap(),
+            "comparison with the description"
+        );
+        assert_eq!(labels.describe(last + 1), None);
+
+        // 64 bytes: the extra padding block covers no plaintext
+        let ct = vec![0; 16 + 64];
+        let (_, labels) = compile_circuit_v2_with_labels(&ct, &[0; 32]).unwrap();
+        assert_eq!(
+            labels.describe(2).unwrap(),
+            "SHA-256 padding constant of block 1 (padding only)"
+        );
+
+        let bytes = labels.to_bytes();
+        assert_eq!(CircuitLabels::from_bytes(&bytes).unwrap(), labels);
+        assert!(CircuitLabels::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_from_bytes_rejects_malformed_runs() {
+        let run = |first_gate, count, first_block| LabelRun {
+            first_gate,
+            count,
+            role: GateRole::Decrypt,
+            first_block,
+        };
+        let labels = |runs| CircuitLabels {
+            pt_len: 100,
+            block_size: 64,
+            runs,
+        };
+
+        let valid = labels(vec![
+            run(1, 2, Some(0)),
+            run(3, 1, None),
+            run(10, 5, Some(7)),
+        ]);
+        assert_eq!(CircuitLabels::from_bytes(&valid.to_bytes()).unwrap(), valid);
+        let last = labels(vec![run(u32::MAX - 1, 1, Some(u32::MAX - 1))]);
+        let last = CircuitLabels::from_bytes(&last.to_bytes()).unwrap();
+        assert_eq!(last.label(u32::MAX - 1).unwrap().block, Some(u32::MAX - 1));
+        assert_eq!(last.label(u32::MAX), None);
+
+        for runs in [
+            vec![run(1, 0, None)],
+            vec![run(3, 1, None), run(1, 2, None)],
+            vec![run(1, 3, None), run(3, 1, None)],
+            vec![run(u32::MAX, 1, None)],
+            vec![run(1, 3, Some(u32::MAX - 1))],
+        ] {
+            let malformed = labels(runs);
+            assert!(matches!(
+                CircuitLabels::from_bytes(&malformed.to_bytes()),
+                Err(SoxError::Decode(_))
+            ));
+        }
+    }
+}
@@ -1,23 +1,36 @@
 mod accumulator;
 mod aes_ctr;
+mod calldata;
+mod circuit_builder;
 mod circuits;
 mod circuits_v2;
 mod commitment;
+mod disasm;
+mod dispute;
+mod download;
 mod encryption;
+mod error;
+mod estimate;
+mod labels;
+#[cfg(not(target_arch = "wasm32"))]
+mod mmap_store;
+mod precontract;
+pub mod native;
+mod session;
 mod sha256;
 mod simple_operations;
+mod sox_circuit;
 mod utils;
+mod validate;
 
-use crate::accumulator::{acc, acc_circuit, acc_ct, proof_to_js_array, prove, prove_ext};
-use crate::circuits::{
-    compile_basic_circuit, evaluate_circuit_internal, get_evaluated_sons, is_constant_idx,
-    CompiledCircuit,
-};
-use crate::circuits_v2::{compile_circuit_v2, evaluate_circuit_v2, CompiledCircuitV2, GateV2};
-use crate::commitment::{commit_hashes, open_commitment_internal, Commitment};
-use crate::encryption::{decrypt, encrypt_and_prepend_iv};
-use crate::sha256::sha256;
-use crate::utils::{error, hex_to_bytes, split_ct_blocks};
+pub use crate::error::{SoxError, SoxResult};
+pub use crate::utils::{bytes_to_hex, hex_to_bytes};
+
+use crate::accumulator::{js_array_to_proof, proof_to_js_array};
+use crate::circuits::{compile_basic_circuit_with_labels, CompiledCircuit};
+use crate::circuits_v2::{compile_circuit_v2, compile_circuit_v2_with_labels, CompiledCircuitV2};
+use crate::commitment::Commitment;
+use crate::labels::CircuitLabels;
 use js_sys::{Array, Number, Uint8Array};
 use rmp_serde::{decode::from_read, encode::write};
 use serde::{Deserialize, Serialize};
@@ -72,27 +85,8 @@ pub struct Precontract {
 /// # Returns
 /// A `Precontract` containing all necessary components for the optimistic phase of the protocol
 #[wasm_bindgen]
-pub fn compute_precontract_values(file: &mut [u8], key: &[u8]) -> Precontract {
-    let description = sha256(file);
-    let ct = encrypt_and_prepend_iv(file, key);
-    let circuit = compile_basic_circuit(ct.len() as u32, &description);
-    let num_blocks = circuit.num_blocks;
-    let num_gates = circuit.circuit.len() as u32;
-    let circuit_bytes = circuit.to_bytes();
-    let h_ct = acc_ct(&ct, circuit.block_size as usize);
-    let h_circuit = acc_circuit(&circuit);
-    let commitment = commit_hashes(&h_circuit, &h_ct);
-
-    Precontract {
-        ct,
-        circuit_bytes,
-        description,
-        h_ct,
-        h_circuit,
-        commitment,
-        num_blocks,
-        num_gates,
-    }
+pub fn compute_precontract_values(file: &mut [u8], key: &[u8]) -> SoxResult<Precontract> {
+    native::compute_precontract_values(file, key)
 }
 
 // ####################################
@@ -124,37 +118,46 @@ pub struct CheckPrecontractResult {
 /// * `ct` - Ciphertext bytes
 ///
 /// # Returns
-/// A `CheckPrecontractResult` containing the verification status and hash values
+/// A `CheckPrecontractResult` containing the verification status and hash values. A commitment
+/// that does not open is reported with `success == false`; malformed inputs are returned as errors.
 #[wasm_bindgen]
 pub fn check_precontract(
     description: String,
     commitment: String,
     opening_value: String,
     ct: &[u8],
-) -> CheckPrecontractResult {
-    let description_bytes = hex_to_bytes(description);
-    let circuit = compile_basic_circuit(ct.len() as u32, &description_bytes);
-    let h_ct = acc_ct(ct, circuit.block_size as usize);
-    let h_circuit = acc_circuit(&circuit);
-    match open_commitment_internal(&hex_to_bytes(commitment), &hex_to_bytes(opening_value)) {
-        Ok(opened) => {
-            let success =
-                opened.len() == 64 && opened[..32].eq(&h_circuit) && opened[32..].eq(&h_ct);
-            CheckPrecontractResult {
-                success,
-                h_circuit,
-                h_ct,
-            }
-        }
-        Err(msg) => {
-            error(msg);
-            CheckPrecontractResult {
-                success: false,
-                h_circuit,
-                h_ct,
-            }
-        }
-    }
+) -> SoxResult<CheckPrecontractResult> {
+    native::check_precontract(
+        &hex_to_bytes(description)?,
+        &hex_to_bytes(commitment)?,
+        &hex_to_bytes(opening_value)?,
+        ct,
+    )
+}
+
+/// Verifies a precontract with a V2 circuit (see `check_precontract`).
+///
+/// # Arguments
+/// * `description` - Description hash in hex format
+/// * `commitment` - Commitment in hex format
+/// * `opening_value` - Opening value in hex format
+/// * `ct` - Ciphertext bytes
+///
+/// # Returns
+/// A `CheckPrecontractResult` containing the verification status and hash values
+#[wasm_bindgen]
+pub fn check_precontract_v2(
+    description: String,
+    commitment: String,
+    opening_value: String,
+    ct: &[u8],
+) -> SoxResult<CheckPrecontractResult> {
+    native::check_precontract_v2(
+        &hex_to_bytes(description)?,
+        &hex_to_bytes(commitment)?,
+        &hex_to_bytes(opening_value)?,
+        ct,
+    )
 }
 
 // ####################################
@@ -182,15 +185,12 @@ pub struct CheckCtResult {
 /// # Returns
 /// A `CheckCtResult` containing the verification status and decrypted data
 #[wasm_bindgen]
-pub fn check_received_ct_key(ct: &mut [u8], key: &[u8], description: String) -> CheckCtResult {
-    let decrypted_file = decrypt(ct, key);
-    let description_computed = sha256(&decrypted_file);
-    let success = hex_to_bytes(description).eq(&description_computed);
-
-    CheckCtResult {
-        success,
-        decrypted_file,
-    }
+pub fn check_received_ct_key(
+    ct: &mut [u8],
+    key: &[u8],
+    description: String,
+) -> SoxResult<CheckCtResult> {
+    native::check_received_ct_key(ct, key, &hex_to_bytes(description)?)
 }
 
 // ####################################
@@ -222,7 +222,7 @@ impl DisputeArgument {
     /// Returns a vector containing the serialized dispute argument data.
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut buf = Vec::new();
-        write(&mut buf, self).unwrap();
+        write(&mut buf, self).expect("serializing into a Vec cannot fail");
         buf
     }
 
@@ -232,9 +232,42 @@ impl DisputeArgument {
     /// * `bytes` - The serialized dispute argument bytes
     ///
     /// # Returns
-    /// A new `DisputeArgument` instance
-    pub fn from_bytes(bytes: &[u8]) -> DisputeArgument {
-        from_read(bytes).unwrap()
+    /// A new `DisputeArgument` instance, or `SoxError::Decode` if the bytes are malformed
+    pub fn from_bytes(bytes: &[u8]) -> SoxResult<DisputeArgument> {
+        Ok(from_read(bytes)?)
+    }
+}
+
+/// Argument in a dispute between buyer and vendor, for a V2 circuit.
+#[derive(Serialize, Deserialize)]
+pub struct DisputeArgumentV2 {
+    /// The compiled circuit
+    pub circuit: CompiledCircuitV2,
+
+    /// The ciphertext
+    pub ct: Vec<u8>,
+
+    /// Opening value for the commitment
+    pub opening_value: Vec<u8>,
+}
+
+impl DisputeArgumentV2 {
+    /// Serializes the dispute argument into a byte vector.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write(&mut buf, self).expect("serializing into a Vec cannot fail");
+        buf
+    }
+
+    /// Deserializes a dispute argument from bytes.
+    ///
+    /// # Arguments
+    /// * `bytes` - The serialized dispute argument bytes
+    ///
+    /// # Returns
+    /// A new `DisputeArgumentV2` instance, or `SoxError::Decode` if the bytes are malformed
+    pub fn from_bytes(bytes: &[u8]) -> SoxResult<DisputeArgumentV2> {
+        Ok(from_read(bytes)?)
     }
 }
 
@@ -248,13 +281,30 @@ impl DisputeArgument {
 /// # Returns
 /// Serialized dispute argument bytes
 #[wasm_bindgen]
-pub fn make_argument(ct: Vec<u8>, description: String, opening_value: String) -> Vec<u8> {
-    DisputeArgument {
-        circuit: compile_basic_circuit(ct.len() as u32, &hex_to_bytes(description)),
-        ct,
-        opening_value: hex_to_bytes(opening_value),
-    }
-    .to_bytes()
+pub fn make_argument(
+    ct: Vec<u8>,
+    description: String,
+    opening_value: String,
+) -> SoxResult<Vec<u8>> {
+    native::make_argument(ct, &hex_to_bytes(description)?, hex_to_bytes(opening_value)?)
+}
+
+/// Creates a dispute argument for a V2 circuit (see `make_argument`).
+///
+/// # Arguments
+/// * `ct` - Ciphertext bytes
+/// * `description` - Description hash in hex format
+/// * `opening_value` - Opening value in hex format
+///
+/// # Returns
+/// Serialized dispute argument bytes
+#[wasm_bindgen]
+pub fn make_argument_v2(
+    ct: Vec<u8>,
+    description: String,
+    opening_value: String,
+) -> SoxResult<Vec<u8>> {
+    native::make_argument_v2(ct, &hex_to_bytes(description)?, hex_to_bytes(opening_value)?)
 }
 
 // ####################################
@@ -291,34 +341,38 @@ pub fn check_argument(
     commitment: String,
     description: String,
     key: String,
-) -> ArgumentCheckResult {
-    let argument = DisputeArgument::from_bytes(argument_bin);
-    let block_size = argument.circuit.block_size;
-    let h_circuit = acc_circuit(&argument.circuit);
-    let h_ct = acc_ct(argument.ct.as_slice(), block_size as usize);
-
-    match open_commitment_internal(&hex_to_bytes(commitment), &argument.opening_value) {
-        Ok(opened) => {
-            let is_valid =
-                opened.len() == 64 && opened[..32].eq(&h_circuit) && opened[32..].eq(&h_ct);
-            let pt = decrypt(&argument.ct, &hex_to_bytes(key));
-            let description_computed = sha256(&pt);
-            let supports_buyer = !hex_to_bytes(description).eq(&description_computed);
-            ArgumentCheckResult {
-                is_valid,
-                supports_buyer,
-                error: None,
-            }
-        }
-        Err(msg) => {
-            error(msg);
-            ArgumentCheckResult {
-                is_valid: false,
-                supports_buyer: false,
-                error: Some(msg.to_string()),
-            }
-        }
-    }
+) -> SoxResult<ArgumentCheckResult> {
+    native::check_argument(
+        argument_bin,
+        &hex_to_bytes(commitment)?,
+        &hex_to_bytes(description)?,
+        &hex_to_bytes(key)?,
+    )
+}
+
+/// Verifies a dispute argument for a V2 circuit (see `check_argument`).
+///
+/// # Arguments
+/// * `argument_bin` - Serialized V2 dispute argument bytes
+/// * `commitment` - Commitment in hex format
+/// * `description` - Description hash in hex format
+/// * `key` - Encryption key in hex format
+///
+/// # Returns
+/// An `ArgumentCheckResult` containing the verification results
+#[wasm_bindgen]
+pub fn check_argument_v2(
+    argument_bin: &[u8],
+    commitment: String,
+    description: String,
+    key: String,
+) -> SoxResult<ArgumentCheckResult> {
+    native::check_argument_v2(
+        argument_bin,
+        &hex_to_bytes(commitment)?,
+        &hex_to_bytes(description)?,
+        &hex_to_bytes(key)?,
+    )
 }
 
 // ####################################
@@ -341,7 +395,7 @@ impl EvaluatedCircuit {
     /// Returns a vector containing the serialized circuit data.
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut buf = Vec::new();
-        write(&mut buf, self).unwrap();
+        write(&mut buf, self).expect("serializing into a Vec cannot fail");
         buf
     }
 
@@ -351,9 +405,16 @@ impl EvaluatedCircuit {
     /// * `bytes` - The serialized circuit bytes
     ///
     /// # Returns
-    /// A new `EvaluatedCircuit` instance
-    pub fn from_bytes(bytes: &[u8]) -> EvaluatedCircuit {
-        from_read(bytes).unwrap()
+    /// A new `EvaluatedCircuit` instance, or `SoxError::Decode` if the bytes are malformed
+    pub fn from_bytes(bytes: &[u8]) -> SoxResult<EvaluatedCircuit> {
+        Ok(from_read(bytes)?)
+    }
+}
+
+impl EvaluatedCircuit {
+    /// Returns the values of the evaluated circuit: the inputs followed by the gate outputs
+    pub fn values(&self) -> &[Vec<u8>] {
+        &self.values
     }
 }
 
@@ -378,24 +439,12 @@ pub fn evaluate_circuit(
     ct: &[u8],
     constants: Vec<String>,
     description: String,
-) -> EvaluatedCircuit {
-    if circuit_bytes.len() == 0 {
-        let circuit = compile_basic_circuit(ct.len() as u32, &hex_to_bytes(description))
-            .bind_missing_constants(constants.into_iter().map(hex_to_bytes).collect());
-        let ct_blocks = split_ct_blocks(&ct, circuit.block_size as usize);
-        EvaluatedCircuit {
-            constants: circuit.constants.clone(),
-            values: evaluate_circuit_internal(&ct_blocks, circuit),
-        }
-    } else {
-        let circuit = CompiledCircuit::from_bytes(circuit_bytes)
-            .bind_missing_constants(constants.into_iter().map(hex_to_bytes).collect());
-        let ct_blocks = split_ct_blocks(&ct, circuit.block_size as usize);
-        EvaluatedCircuit {
-            constants: circuit.constants.clone(),
-            values: evaluate_circuit_internal(&ct_blocks, circuit),
-        }
-    }
+) -> SoxResult<EvaluatedCircuit> {
+    let constants = constants
+        .into_iter()
+        .map(hex_to_bytes)
+        .collect::<SoxResult<Vec<_>>>()?;
+    native::evaluate_circuit(circuit_bytes, ct, constants, &hex_to_bytes(description)?)
 }
 
 // ####################################
@@ -410,11 +459,14 @@ pub fn evaluate_circuit(
 /// * `challenge` - Challenge issued by the smart contract
 ///
 /// # Returns
-/// The response to the challenge
+/// The response to the challenge, or `SoxError::Index` if the challenge is out of bounds
 #[wasm_bindgen]
-pub fn hpre(evaluated_circuit_bytes: &[u8], num_blocks: usize, challenge: usize) -> Vec<u8> {
-    let evaluated_circuit = EvaluatedCircuit::from_bytes(evaluated_circuit_bytes);
-    acc(&evaluated_circuit.values[num_blocks..=challenge])
+pub fn hpre(
+    evaluated_circuit_bytes: &[u8],
+    num_blocks: usize,
+    challenge: usize,
+) -> SoxResult<Vec<u8>> {
+    native::hpre(evaluated_circuit_bytes, num_blocks, challenge)
 }
 
 /// Computes the answer to send to a smart contract based on the issued challenge (V2).
@@ -422,10 +474,11 @@ pub fn hpre(evaluated_circuit_bytes: &[u8], num_blocks: usize, challenge: usize)
 /// # Arguments
 /// * `evaluated_circuit_bytes` - Serialized evaluated V2 circuit bytes
 /// * `num_blocks` - Number of blocks for the ciphertext
-/// * `challenge` - Challenge issued by the smart contract (1-indexed gate index, 1 to numGates+1 inclusive, matching paper notation)
+/// * `challenge` - Challenge issued by the smart contract (1-indexed gate index, 1 to numGates inclusive, matching paper notation)
 ///
 /// # Returns
-/// The response to the challenge (32-byte accumulator hash)
+/// The response to the challenge (32-byte accumulator hash), or `SoxError::Index` if the challenge
+/// is 0 or past the last gate
 ///
 /// # Details
 /// This implements hpre(i) from the paper (Section F.2), where i = challenge.
@@ -443,21 +496,12 @@ pub fn hpre(evaluated_circuit_bytes: &[u8], num_blocks: usize, challenge: usize)
 /// - challenge == 1 → i == 1 (paper) → hpre(1) = Acc(val(1)) = accumulate first gate [num_blocks]
 /// - challenge == k → i == k (paper) → hpre(k) = Acc(val(1), ..., val(k)) = accumulate gates [num_blocks..=num_blocks+k-1]
 #[wasm_bindgen]
-pub fn hpre_v2(evaluated_circuit_bytes: &[u8], num_blocks: usize, challenge: usize) -> Vec<u8> {
-    let evaluated = EvaluatedCircuitV2::from_bytes(evaluated_circuit_bytes);
-    // Start at num_blocks to exclude inputs, consistent with V1 and compute_proofs_v2
-    // Challenge is now 1-indexed from contract (matching paper notation)
-    // So we convert: challenge (1-indexed) → array index = num_blocks + challenge - 1
-    let start_idx = num_blocks;
-    let end_idx = num_blocks + challenge - 1; // Convert 1-indexed challenge to 0-indexed array position
-    if end_idx >= evaluated.values.len() {
-        // This should not happen, but handle gracefully by accumulating from start to end
-        if start_idx >= evaluated.values.len() {
-            return vec![];
-    }
-        return acc(&evaluated.values[start_idx..]);
-    }
-    acc(&evaluated.values[start_idx..=end_idx])
+pub fn hpre_v2(
+    evaluated_circuit_bytes: &[u8],
+    num_blocks: usize,
+    challenge: usize,
+) -> SoxResult<Vec<u8>> {
+    native::hpre_v2(evaluated_circuit_bytes, num_blocks, challenge)
 }
 
 // ####################################
@@ -497,24 +541,22 @@ pub struct FinalStepComponents {
     pub proof_ext: Array,
 }
 
-// Splits the sons according to the paper's set L. Constant indices are not kept.
-fn split_sons_indices(sons: &[u32], num_blocks: u32) -> (Vec<u32>, Vec<u32>) {
-    let mut in_l = Vec::new();
-    let mut not_in_l_minus_m = Vec::new();
-
-    for &s in sons {
-        if is_constant_idx(s) {
-            continue;
-        }
-        if s < num_blocks {
-            // strictly inferior because we start counting from 0
-            in_l.push(s)
-        } else {
-            not_in_l_minus_m.push(s - num_blocks)
+impl From<native::FinalStep> for FinalStepComponents {
+    fn from(step: native::FinalStep) -> Self {
+        FinalStepComponents {
+            gate: step.gate.into_iter().map(Number::from).collect(),
+            values: step
+                .values
+                .iter()
+                .map(|x| Uint8Array::from(x.as_slice()))
+                .collect(),
+            curr_acc: step.curr_acc,
+            proof1: proof_to_js_array(&step.proof1),
+            proof2: proof_to_js_array(&step.proof2),
+            proof3: proof_to_js_array(&step.proof3),
+            proof_ext: proof_to_js_array(&step.proof_ext),
         }
     }
-
-    (in_l, not_in_l_minus_m)
 }
 
 /// Computes proofs for step 8a.
@@ -537,40 +579,9 @@ pub fn compute_proofs(
     evaluated_circuit_bytes: &[u8],
     ct: &[u8],
     challenge: u32,
-) -> FinalStepComponents {
-    let circuit = CompiledCircuit::from_bytes(circuit_bytes);
-    let ct_blocks = split_ct_blocks(ct, circuit.block_size as usize);
-    let num_blocks = ct_blocks.len() as u32;
-    let evaluated_circuit = EvaluatedCircuit::from_bytes(evaluated_circuit_bytes);
-    let gate = circuit.circuit[challenge as usize].clone();
-    let (s_in_l, not_in_l_minus_m) = split_sons_indices(&gate.sons, num_blocks);
-
-    let values = get_evaluated_sons(
-        &gate,
-        &evaluated_circuit.values,
-        &evaluated_circuit.constants,
-    );
-    let curr_acc = acc(&evaluated_circuit.values[(num_blocks as usize)..=(challenge as usize)]);
-    let proof1 = prove(&circuit.to_abi_encoded(), &[challenge]);
-    let proof2 = prove(&ct_blocks, &s_in_l);
-    let proof3 = prove(
-        &evaluated_circuit.values[(num_blocks as usize)..(challenge as usize)],
-        &not_in_l_minus_m,
-    );
-    let proof_ext =
-        prove_ext(&evaluated_circuit.values[(num_blocks as usize)..=(challenge as usize)]);
-    FinalStepComponents {
-        gate: gate.flatten().iter().map(|&x| Number::from(x)).collect(),
-        values: values
-            .iter()
-            .map(|&x| Uint8Array::from(x.as_slice()))
-            .collect(),
-        curr_acc,
-        proof1: proof_to_js_array(proof1),
-        proof2: proof_to_js_array(proof2),
-        proof3: proof_to_js_array(proof3),
-        proof_ext: proof_to_js_array(proof_ext),
-    }
+) -> SoxResult<FinalStepComponents> {
+    native::compute_proofs(circuit_bytes, evaluated_circuit_bytes, ct, challenge)
+        .map(FinalStepComponents::from)
 }
 
 /// Computes proofs for step 8b.
@@ -594,41 +605,9 @@ pub fn compute_proofs_left(
     evaluated_circuit_bytes: &[u8],
     ct: &[u8],
     challenge: u32,
-) -> FinalStepComponents {
-    let circuit = CompiledCircuit::from_bytes(circuit_bytes);
-    let ct_blocks = split_ct_blocks(ct, circuit.block_size as usize);
-    let num_blocks = ct_blocks.len() as u32;
-    let evaluated_circuit = EvaluatedCircuit::from_bytes(evaluated_circuit_bytes);
-    let gate = circuit.circuit[challenge as usize].clone();
-    let non_constant_sons: Vec<u32> = gate
-        .sons
-        .iter()
-        .copied()
-        .filter(|&x| !is_constant_idx(x))
-        .collect();
-
-    let values = get_evaluated_sons(
-        &gate,
-        &evaluated_circuit.values,
-        &evaluated_circuit.constants,
-    );
-    let curr_acc = acc(&evaluated_circuit.values[(num_blocks as usize)..=(challenge as usize)]);
-    let proof1 = prove(&circuit.to_abi_encoded(), &[challenge]);
-    let proof2 = prove(&ct_blocks, &non_constant_sons);
-    let proof_ext = prove_ext(&[evaluated_circuit.values[num_blocks as usize].clone()]);
-
-    FinalStepComponents {
-        gate: gate.flatten().iter().map(|&x| Number::from(x)).collect(),
-        values: values
-            .iter()
-            .map(|&x| Uint8Array::from(x.as_slice()))
-            .collect(),
-        curr_acc,
-        proof1: proof_to_js_array(proof1),
-        proof2: proof_to_js_array(proof2),
-        proof3: Array::new(),
-        proof_ext: proof_to_js_array(proof_ext),
-    }
+) -> SoxResult<FinalStepComponents> {
+    native::compute_proofs_left(circuit_bytes, evaluated_circuit_bytes, ct, challenge)
+        .map(FinalStepComponents::from)
 }
 
 /// Computes the proof for step 8c.
@@ -645,13 +624,9 @@ pub fn compute_proof_right(
     evaluated_circuit_bytes: &[u8],
     num_blocks: u32,
     num_gates: u32,
-) -> Array {
-    let evaluated_circuit = EvaluatedCircuit::from_bytes(evaluated_circuit_bytes);
-
-    proof_to_js_array(prove(
-        &evaluated_circuit.values[(num_blocks as usize)..],
-        &[num_gates - num_blocks - 1],
-    ))
+) -> SoxResult<Array> {
+    let proof = native::compute_proof_right(evaluated_circuit_bytes, num_blocks, num_gates)?;
+    Ok(proof_to_js_array(&proof))
 }
 
 // ####################################
@@ -668,27 +643,8 @@ pub fn compute_proof_right(
 /// # Returns
 /// A `Precontract` containing all necessary components for the optimistic phase of the protocol
 #[wasm_bindgen]
-pub fn compute_precontract_values_v2(file: &mut [u8], key: &[u8]) -> Precontract {
-    let description = sha256(file);
-    let ct = encrypt_and_prepend_iv(file, key);
-    let circuit = compile_circuit_v2(&ct, &description);
-    let num_blocks = circuit.num_blocks;
-    let num_gates = circuit.gates.len() as u32;
-    let circuit_bytes = circuit.to_bytes();
-    let h_ct = acc_ct(&ct, circuit.block_size as usize);
-    let h_circuit = crate::circuits_v2::acc_circuit_v2(&circuit.gates);
-    let commitment = commit_hashes(&h_circuit, &h_ct);
-
-    Precontract {
-        ct,
-        circuit_bytes,
-        description,
-        h_ct,
-        h_circuit,
-        commitment,
-        num_blocks,
-        num_gates,
-    }
+pub fn compute_precontract_values_v2(file: &mut [u8], key: &[u8]) -> SoxResult<Precontract> {
+    native::compute_precontract_values_v2(file, key)
 }
 
 /// Represents an evaluated V2 circuit with its values.
@@ -706,7 +662,7 @@ impl EvaluatedCircuitV2 {
     /// Returns a vector containing the serialized circuit data.
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut buf = Vec::new();
-        write(&mut buf, self).unwrap();
+        write(&mut buf, self).expect("serializing into a Vec cannot fail");
         buf
     }
 
@@ -716,9 +672,16 @@ impl EvaluatedCircuitV2 {
     /// * `bytes` - The serialized circuit bytes
     ///
     /// # Returns
-    /// A new `EvaluatedCircuitV2` instance
-    pub fn from_bytes(bytes: &[u8]) -> EvaluatedCircuitV2 {
-        from_read(bytes).unwrap()
+    /// A new `EvaluatedCircuitV2` instance, or `SoxError::Decode` if the bytes are malformed
+    pub fn from_bytes(bytes: &[u8]) -> SoxResult<EvaluatedCircuitV2> {
+        Ok(from_read(bytes)?)
+    }
+}
+
+impl EvaluatedCircuitV2 {
+    /// Returns the values of the evaluated circuit: the inputs followed by the gate outputs
+    pub fn values(&self) -> &[Vec<u8>] {
+        &self.values
     }
 }
 
@@ -731,10 +694,53 @@ impl EvaluatedCircuitV2 {
 /// # Returns
 /// Serialized CompiledCircuitV2 bytes
 #[wasm_bindgen]
-pub fn compile_circuit_v2_wasm(ct: &[u8], description: String) -> Vec<u8> {
-    let description_bytes = hex_to_bytes(description);
-    let circuit = compile_circuit_v2(ct, &description_bytes);
-    circuit.to_bytes()
+pub fn compile_circuit_v2_wasm(ct: &[u8], description: String) -> SoxResult<Vec<u8>> {
+    let description_bytes = hex_to_bytes(description)?;
+    let circuit = compile_circuit_v2(ct, &description_bytes)?;
+    Ok(circuit.to_bytes())
+}
+
+/// Computes the labels of the gates of the V2 circuit compiled from ciphertext and description
+/// (see `CircuitLabels`).
+///
+/// # Arguments
+/// * `ct` - Ciphertext bytes (must include 16-byte IV)
+/// * `description` - Description hash as hex string
+///
+/// # Returns
+/// The labels of the gates of the circuit
+#[wasm_bindgen]
+pub fn circuit_v2_labels_wasm(ct: &[u8], description: String) -> SoxResult<CircuitLabels> {
+    let description_bytes = hex_to_bytes(description)?;
+    let (_, labels) = compile_circuit_v2_with_labels(ct, &description_bytes)?;
+    Ok(labels)
+}
+
+/// Computes the labels of the gates of the basic (V1) circuit of a ciphertext (see
+/// `CircuitLabels`).
+///
+/// # Arguments
+/// * `ct_size` - Size of the ciphertext (including IV)
+///
+/// # Returns
+/// The labels of the gates of the circuit
+#[wasm_bindgen]
+pub fn basic_circuit_labels_wasm(ct_size: u32) -> SoxResult<CircuitLabels> {
+    let (_, labels) = compile_basic_circuit_with_labels(ct_size, &[0; 32])?;
+    Ok(labels)
+}
+
+/// Checks the structure of a serialized V2 circuit (see `CompiledCircuitV2::validate`).
+///
+/// # Arguments
+/// * `circuit_bytes` - Serialized V2 circuit bytes
+///
+/// # Returns
+/// The descriptions of the problems found in the circuit, empty if it is valid
+#[wasm_bindgen]
+pub fn validate_circuit_v2_wasm(circuit_bytes: &[u8]) -> SoxResult<Vec<String>> {
+    let circuit = CompiledCircuitV2::from_bytes(circuit_bytes)?;
+    Ok(circuit.validate().iter().map(ToString::to_string).collect())
 }
 
 /// Evaluates a V2 circuit with the given ciphertext and key.
@@ -752,50 +758,8 @@ pub fn evaluate_circuit_v2_wasm(
     circuit_bytes: &[u8],
     ct: &[u8],
     key: String,
-) -> EvaluatedCircuitV2 {
-    let circuit = CompiledCircuitV2::from_bytes(circuit_bytes);
-    let key_bytes = hex_to_bytes(key);
-    
-    // Split ciphertext into blocks (skip IV, 64-byte blocks)
-    // This should match how compile_circuit_v2 calculates num_blocks
-    let data = &ct[16..]; // Skip IV
-    let block_size = 64usize;
-    let pt_len = data.len();
-    let expected_num_blocks = (pt_len + block_size - 1) / block_size; // This is num_blocks
-    
-    let mut inputs = Vec::with_capacity(expected_num_blocks);
-    let mut start = 16; // Skip IV
-    while start < ct.len() {
-        let end = usize::min(start + 64, ct.len());
-        let mut block = vec![0u8; 64];
-        block[..(end - start)].copy_from_slice(&ct[start..end]);
-        inputs.push(block);
-        start = end;
-    }
-    
-    // Verify that inputs.len() matches circuit.num_blocks
-    if inputs.len() != circuit.num_blocks as usize {
-        use crate::utils::die;
-        die(&format!(
-            "inputs.len() ({}) does not match circuit.num_blocks ({}) in evaluate_circuit_v2_wasm. Expected {} blocks from pt_len={}, block_size={}",
-            inputs.len(),
-            circuit.num_blocks,
-            expected_num_blocks,
-            pt_len,
-            block_size
-        ));
-    }
-    
-    // Evaluate circuit - this returns only gate outputs, not inputs
-    let gate_values = evaluate_circuit_v2(&circuit.gates, &inputs, &key_bytes);
-    
-    // Combine inputs and gate outputs: [inputs, gate_outputs]
-    // This matches the V1 format where values[0..num_blocks] are inputs
-    // and values[num_blocks..] are gate outputs
-    let mut all_values = inputs;
-    all_values.extend(gate_values);
-    
-    EvaluatedCircuitV2 { values: all_values }
+) -> SoxResult<EvaluatedCircuitV2> {
+    native::evaluate_circuit_v2(circuit_bytes, ct, &hex_to_bytes(key)?)
 }
 
 /// Components returned from the vendor's final step proof generation for V2. Intended for usage in a
@@ -831,61 +795,93 @@ pub struct FinalStepComponentsV2 {
     pub proof_ext: Array,
 }
 
-/// Helper function to get evaluated sons for a V2 gate
-/// Returns direct references (clones) without normalization.
-/// Normalization should be done in individual gate evaluators (XOR, AES-CTR) as needed.
-fn get_evaluated_sons_v2(
-    gate: &GateV2,
-    evaluated_values: &[Vec<u8>],
-    inputs: &[Vec<u8>],
-) -> Vec<Vec<u8>> {
-    use crate::utils::die;
-    let mut sons = Vec::with_capacity(gate.sons.len());
-    
-    for &son_idx in &gate.sons {
-        if son_idx < 0 {
-            // Negative index: dummy gate (input)
-            let input_idx = (-son_idx - 1) as usize;
-            if input_idx >= inputs.len() {
-                die(&format!("Dummy gate index {} out of bounds", son_idx));
-            }
-            sons.push(inputs[input_idx].clone());
-        } else {
-            // Positive index: previous gate (1-indexed)
-            if son_idx == 0 {
-                die("Gate index cannot be 0 (gates are 1-indexed)");
-            }
-            let array_idx = (son_idx - 1) as usize;
-            if array_idx >= evaluated_values.len() {
-                die(&format!("Gate index {} out of bounds", son_idx));
-            }
-            // Return direct clone without normalization
-            sons.push(evaluated_values[array_idx].clone());
+impl From<native::FinalStepV2> for FinalStepComponentsV2 {
+    fn from(step: native::FinalStepV2) -> Self {
+        FinalStepComponentsV2 {
+            gate_bytes: step.gate_bytes,
+            values: step
+                .values
+                .iter()
+                .map(|x| Uint8Array::from(x.as_slice()))
+                .collect(),
+            curr_acc: step.curr_acc,
+            proof1: proof_to_js_array(&step.proof1),
+            proof2: proof_to_js_array(&step.proof2),
+            proof3: proof_to_js_array(&step.proof3),
+            proof_ext: proof_to_js_array(&step.proof_ext),
         }
     }
-    
-    sons
 }
 
-/// Helper function to split sons for V2 gate according to set L
-fn split_sons_indices_v2(sons: &[i64], num_blocks: u32) -> (Vec<u32>, Vec<u32>) {
-    let mut in_l = Vec::new();
-    let mut not_in_l_minus_m = Vec::new();
-
-    for &s in sons {
-        if s < 0 {
-            // Negative: dummy gate (input block)
-            let ct_idx = (-s) as u32;
-            if ct_idx >= 1 && ct_idx <= num_blocks {
-                in_l.push(ct_idx - 1); // Convert to 0-indexed
-            }
-        } else if s > 0 {
-            // Positive: previous gate
-            not_in_l_minus_m.push((s - 1) as u32); // Convert to 0-indexed
-        }
+impl TryFrom<&FinalStepComponentsV2> for native::FinalStepV2 {
+    type Error = SoxError;
+
+    fn try_from(step: &FinalStepComponentsV2) -> SoxResult<Self> {
+        Ok(native::FinalStepV2 {
+            gate_bytes: step.gate_bytes.clone(),
+            values: step.values.iter().map(Uint8Array::to_vec).collect(),
+            curr_acc: step.curr_acc.clone(),
+            proof1: js_array_to_proof_v2(&step.proof1)?,
+            proof2: js_array_to_proof_v2(&step.proof2)?,
+            proof3: js_array_to_proof_v2(&step.proof3)?,
+            proof_ext: js_array_to_proof_v2(&step.proof_ext)?,
+        })
     }
+}
 
-    (in_l, not_in_l_minus_m)
+// Converts a JavaScript proof into a `Proof`, failing if a node is not 32 bytes long
+fn js_array_to_proof_v2(proof: &Array) -> SoxResult<native::Proof> {
+    js_array_to_proof(proof)
+        .into_iter()
+        .map(|layer| {
+            layer
+                .into_iter()
+                .map(|node| {
+                    node.try_into().map_err(|node: Vec<u8>| {
+                        SoxError::InvalidInput(format!(
+                            "proof nodes must be 32 bytes long, got {}",
+                            node.len()
+                        ))
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[wasm_bindgen]
+impl FinalStepComponentsV2 {
+    /// Encodes these components as a call to `submitCommitment` (step 8a).
+    ///
+    /// # Arguments
+    /// * `opening_value` - Opening value of the commitment
+    /// * `gate_num` - Current challenge of the dispute
+    ///
+    /// # Returns
+    /// The calldata (selector and arguments)
+    pub fn submit_commitment_calldata(
+        &self,
+        opening_value: &[u8],
+        gate_num: u32,
+    ) -> SoxResult<Vec<u8>> {
+        calldata::submit_commitment_calldata(opening_value, gate_num, &self.try_into()?)
+    }
+
+    /// Encodes these components as a call to `submitCommitmentLeft` (step 8b).
+    ///
+    /// # Arguments
+    /// * `opening_value` - Opening value of the commitment
+    /// * `gate_num` - Current challenge of the dispute
+    ///
+    /// # Returns
+    /// The calldata (selector and arguments)
+    pub fn submit_commitment_left_calldata(
+        &self,
+        opening_value: &[u8],
+        gate_num: u32,
+    ) -> SoxResult<Vec<u8>> {
+        calldata::submit_commitment_left_calldata(opening_value, gate_num, &self.try_into()?)
+    }
 }
 
 /// Computes proofs for step 8a (V2) - corresponds to Step 8a in paper (Section F.2).
@@ -914,86 +910,9 @@ pub fn compute_proofs_v2(
     evaluated_circuit_bytes: &[u8],
     ct: &[u8],
     challenge: u32,
-) -> FinalStepComponentsV2 {
-    let circuit = CompiledCircuitV2::from_bytes(circuit_bytes);
-    let evaluated = EvaluatedCircuitV2::from_bytes(evaluated_circuit_bytes);
-    
-    // Split ciphertext into blocks
-    let mut ct_blocks = Vec::new();
-    let mut start = 16; // Skip IV
-    while start < ct.len() {
-        let end = usize::min(start + 64, ct.len());
-        let mut block = vec![0u8; 64];
-        block[..(end - start)].copy_from_slice(&ct[start..end]);
-        ct_blocks.push(block);
-        start = end;
-    }
-    
-    let num_blocks = circuit.num_blocks;
-    // Challenge is now 1-indexed from contract (matching paper), so convert to 0-indexed for array access
-    let gate_idx = (challenge as usize) - 1;
-    let gate = &circuit.gates[gate_idx];
-    let (s_in_l, not_in_l_minus_m) = split_sons_indices_v2(&gate.sons, num_blocks);
-    
-    // Get evaluated sons
-    // For V2, evaluated.values contains [inputs (num_blocks), gate_outputs (num_gates)]
-    // So gate outputs start at index num_blocks
-    let gate_outputs = &evaluated.values[(num_blocks as usize)..];
-    let values = get_evaluated_sons_v2(gate, gate_outputs, &ct_blocks);
-    
-    // Compute accumulator
-    // For V2, values start at num_blocks (inputs), then gates are evaluated after
-    // Challenge is 1-indexed (matching paper notation), so we need values from num_blocks to num_blocks + challenge - 1 (inclusive)
-    let curr_acc = acc(&evaluated.values[(num_blocks as usize)..=((num_blocks as usize + challenge as usize - 1) as usize)]);
-    
-    // Generate proofs
-    // For V2, we need to encode all gates for proof1
-    // prove() expects 0-indexed indices, so we pass gate_idx (challenge - 1)
-    let encoded_gates: Vec<Vec<u8>> = circuit.gates
-        .iter()
-        .map(|g| {
-            let mut buf = [0u8; 64];
-            g.encode_into(&mut buf);
-            buf.to_vec()
-        })
-        .collect();
-    let proof1 = prove(&encoded_gates, &[gate_idx as u32]);
-    
-    // ⚠️ FIX: Le root hCt est calculé AVEC IV (via acc_ct qui utilise split_ct_blocks)
-    // Donc proof2 doit être généré AVEC IV pour correspondre au root
-    // Les indices dans s_in_l sont pour ct_blocks (sans IV), donc on doit les décaler de +1
-    let mut ct_blocks_with_iv = Vec::new();
-    ct_blocks_with_iv.push(ct[..16].to_vec()); // IV comme premier bloc
-    ct_blocks_with_iv.extend_from_slice(&ct_blocks); // Ajouter les blocs de données
-    
-    // Décaler les indices de +1 pour correspondre aux nouveaux indices avec IV
-    let s_in_l_with_iv: Vec<u32> = s_in_l.iter().map(|&idx| idx + 1).collect();
-    let proof2 = prove(&ct_blocks_with_iv, &s_in_l_with_iv);
-    
-    let proof3 = prove(
-        &evaluated.values[(num_blocks as usize)..(num_blocks as usize + challenge as usize - 1) as usize],
-        &not_in_l_minus_m,
-    );
-    // proof_ext must use the same range as curr_acc to prove extension correctly
-    // It proves that the last element (gate challenge) is an extension of the previous accumulator
-    let proof_ext = prove_ext(&evaluated.values[(num_blocks as usize)..=((num_blocks as usize + challenge as usize - 1) as usize)]);
-    
-    // Encode gate to 64 bytes
-    let mut gate_bytes = [0u8; 64];
-    gate.encode_into(&mut gate_bytes);
-    
-    FinalStepComponentsV2 {
-        gate_bytes: gate_bytes.to_vec(),
-        values: values
-            .iter()
-            .map(|x| Uint8Array::from(x.as_slice()))
-            .collect(),
-        curr_acc,
-        proof1: proof_to_js_array(proof1),
-        proof2: proof_to_js_array(proof2),
-        proof3: proof_to_js_array(proof3),
-        proof_ext: proof_to_js_array(proof_ext),
-    }
+) -> SoxResult<FinalStepComponentsV2> {
+    native::compute_proofs_v2(circuit_bytes, evaluated_circuit_bytes, ct, challenge)
+        .map(FinalStepComponentsV2::from)
 }
 
 /// Computes proofs for step 8b (V2) - corresponds to Step 8b in paper (Section F.2).
@@ -1023,91 +942,9 @@ pub fn compute_proofs_left_v2(
     evaluated_circuit_bytes: &[u8],
     ct: &[u8],
     challenge: u32,
-) -> FinalStepComponentsV2 {
-    let circuit = CompiledCircuitV2::from_bytes(circuit_bytes);
-    let evaluated = EvaluatedCircuitV2::from_bytes(evaluated_circuit_bytes);
-    
-    // Split ciphertext into blocks (SANS IV, comme compute_proofs_v2)
-    // ⚠️ FIX: Aligner avec compute_proofs_v2 qui utilise ct_blocks SANS IV pour proof2
-    // Le root hCt est calculé AVEC IV (via acc_ct qui utilise split_ct_blocks),
-    // mais les indices dans nonConstantSons (Solidity) sont pour un tableau SANS IV.
-    // Donc proof2 doit être généré SANS IV pour correspondre aux indices Solidity.
-    let mut ct_blocks = Vec::new();
-    let mut start = 16; // Skip IV
-    while start < ct.len() {
-        let end = usize::min(start + 64, ct.len());
-        let mut block = vec![0u8; 64];
-        block[..(end - start)].copy_from_slice(&ct[start..end]);
-        ct_blocks.push(block);
-        start = end;
-    }
-    
-    let num_blocks = circuit.num_blocks;
-    // Challenge is now 1-indexed from contract (matching paper), so convert to 0-indexed for array access
-    // For Step 8b, challenge = 1 (corresponds to i = 1 in paper, first gate)
-    let gate_idx = (challenge as usize) - 1;
-    let gate = &circuit.gates[gate_idx];
-    
-    // Get evaluated sons (utilise ct_blocks SANS IV, comme compute_proofs_v2)
-    // For V2, evaluated.values contains [inputs (num_blocks), gate_outputs (num_gates)]
-    // So gate outputs start at index num_blocks
-    let gate_outputs = &evaluated.values[(num_blocks as usize)..];
-    let values = get_evaluated_sons_v2(gate, gate_outputs, &ct_blocks);
-    
-    // Compute accumulator
-    // For V2, values start at num_blocks (inputs), then gates are evaluated after
-    // Challenge is 1-indexed (matching paper notation), so we need values from num_blocks to num_blocks + challenge - 1 (inclusive)
-    // For challenge = 1 (Step 8b): accumulate only first gate [num_blocks]
-    let curr_acc = acc(&evaluated.values[(num_blocks as usize)..=((num_blocks as usize + challenge as usize - 1) as usize)]);
-    
-    // Generate proofs
-    // prove() expects 0-indexed indices, so we pass gate_idx (challenge - 1)
-    let encoded_gates: Vec<Vec<u8>> = circuit.gates
-        .iter()
-        .map(|g| {
-            let mut buf = [0u8; 64];
-            g.encode_into(&mut buf);
-            buf.to_vec()
-        })
-        .collect();
-    let proof1 = prove(&encoded_gates, &[gate_idx as u32]);
-    
-    // For proof2, we need to convert negative sons to block indices
-    // ⚠️ FIX: Utiliser split_sons_indices_v2 comme dans compute_proofs_v2 pour vérifier la validité des indices
-    // Le problème était que compute_proofs_left_v2 utilisait (-s - 1) directement sans vérifier
-    // si ct_idx >= 1 && ct_idx <= num_blocks, ce qui peut inclure des indices invalides
-    // compute_proofs_v2 utilise split_sons_indices_v2 qui filtre les indices invalides
-    let (s_in_l, _) = split_sons_indices_v2(&gate.sons, num_blocks);
-    
-    // ⚠️ FIX CRITIQUE: Le root hCt est calculé AVEC IV (via acc_ct qui utilise split_ct_blocks)
-    // Donc proof2 doit être généré AVEC IV pour correspondre au root
-    // Les indices dans s_in_l sont pour ct_blocks (sans IV), donc on doit les décaler de +1
-    let mut ct_blocks_with_iv = Vec::new();
-    ct_blocks_with_iv.push(ct[..16].to_vec()); // IV comme premier bloc
-    ct_blocks_with_iv.extend_from_slice(&ct_blocks); // Ajouter les blocs de données
-    
-    // Décaler les indices de +1 pour correspondre aux nouveaux indices avec IV
-    let s_in_l_with_iv: Vec<u32> = s_in_l.iter().map(|&idx| idx + 1).collect();
-    let proof2 = prove(&ct_blocks_with_iv, &s_in_l_with_iv);
-    
-    let proof_ext = prove_ext(&[evaluated.values[num_blocks as usize].clone()]);
-    
-    // Encode gate to 64 bytes
-    let mut gate_bytes = [0u8; 64];
-    gate.encode_into(&mut gate_bytes);
-    
-    FinalStepComponentsV2 {
-        gate_bytes: gate_bytes.to_vec(),
-        values: values
-            .iter()
-            .map(|x| Uint8Array::from(x.as_slice()))
-            .collect(),
-        curr_acc,
-        proof1: proof_to_js_array(proof1),
-        proof2: proof_to_js_array(proof2),
-        proof3: Array::new(),
-        proof_ext: proof_to_js_array(proof_ext),
-    }
+) -> SoxResult<FinalStepComponentsV2> {
+    native::compute_proofs_left_v2(circuit_bytes, evaluated_circuit_bytes, ct, challenge)
+        .map(FinalStepComponentsV2::from)
 }
 
 /// Computes the proof for step 8c (V2) - corresponds to Step 8c in paper (Section F.2).
@@ -1130,61 +967,57 @@ pub fn compute_proof_right_v2(
     evaluated_circuit_bytes: &[u8],
     num_blocks: u32,
     num_gates: u32,
-) -> Array {
-    use crate::utils::die;
-    let evaluated = EvaluatedCircuitV2::from_bytes(evaluated_circuit_bytes);
-
-    // For V2, evaluated.values contains [inputs (num_blocks), gate_outputs (num_gates)]
-    // So gate outputs start at index num_blocks
-    let num_blocks_usize = num_blocks as usize;
-    if num_blocks_usize >= evaluated.values.len() {
-        die(&format!(
-            "num_blocks ({}) is greater than or equal to evaluated.values.len() ({})",
-            num_blocks_usize,
-            evaluated.values.len()
-        ));
-    }
-    
-    let gate_outputs = &evaluated.values[num_blocks_usize..];
-    if gate_outputs.is_empty() {
-        die("gate_outputs is empty");
-    }
-    
-    // Verify that gate_outputs length matches num_gates (should always be true)
-    // This is a sanity check to catch bugs early
-    if gate_outputs.len() != num_gates as usize {
-        die(&format!(
-            "Mismatch: gate_outputs.len() ({}) != num_gates ({})",
-            gate_outputs.len(),
-            num_gates
-        ));
-    }
-    
-    // The last gate is at index num_gates - 1 (0-indexed in gate_outputs array)
-    // This must match the index used in submitCommitmentRight: idxArr[0] = numGates - 1
-    let last_gate_idx = (num_gates - 1) as u32;
-    
-    // Verify the index is valid
-    if last_gate_idx as usize >= gate_outputs.len() {
-        die(&format!(
-            "last_gate_idx ({}) is out of bounds for gate_outputs.len() ({})",
-            last_gate_idx,
-            gate_outputs.len()
-        ));
-    }
-    
-    proof_to_js_array(prove(
-        gate_outputs,
-        &[last_gate_idx],
+) -> SoxResult<Array> {
+    let proof = native::compute_proof_right_v2(evaluated_circuit_bytes, num_blocks, num_gates)?;
+    Ok(proof_to_js_array(&proof))
+}
+
+/// Encodes a call to `submitCommitmentRight` (step 8c).
+///
+/// # Arguments
+/// * `proof` - Proof returned by `compute_proof_right_v2`
+///
+/// # Returns
+/// The calldata (selector and arguments)
+#[wasm_bindgen]
+pub fn submit_commitment_right_calldata_wasm(proof: Array) -> SoxResult<Vec<u8>> {
+    Ok(calldata::submit_commitment_right_calldata(
+        &js_array_to_proof_v2(&proof)?,
     ))
 }
 
+/// Encodes a call to `respondChallenge`.
+///
+/// # Arguments
+/// * `response` - The buyer's 32-byte response
+///
+/// # Returns
+/// The calldata (selector and arguments)
+#[wasm_bindgen]
+pub fn respond_challenge_calldata_wasm(response: &[u8]) -> SoxResult<Vec<u8>> {
+    calldata::respond_challenge_calldata(response)
+}
+
+/// Encodes a call to `giveOpinion`.
+///
+/// # Arguments
+/// * `vendor_agrees` - Whether the vendor agrees with the buyer's latest response
+///
+/// # Returns
+/// The calldata (selector and arguments)
+#[wasm_bindgen]
+pub fn give_opinion_calldata_wasm(vendor_agrees: bool) -> Vec<u8> {
+    calldata::give_opinion_calldata(vendor_agrees)
+}
+
 // =================================================================================================
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::utils::bytes_to_hex;
+    use crate::circuits::compile_basic_circuit;
+    use crate::encryption::encrypt_and_prepend_iv;
+    use crate::sha256::sha256;
     use rand::RngCore;
 
     #[test]
@@ -1199,16 +1032,17 @@ mod tests {
             rng.fill_bytes(&mut key);
 
             // encrypt
-            let ct = encrypt_and_prepend_iv(&mut data, &key);
+            let ct = encrypt_and_prepend_iv(&mut data, &key).unwrap();
 
-            let circuit = compile_basic_circuit(ct.len() as u32, &description);
+            let circuit = compile_basic_circuit(ct.len() as u32, &description).unwrap();
 
             let evaluated = evaluate_circuit(
                 &circuit.to_bytes(),
                 &ct,
                 vec![bytes_to_hex(key)],
                 bytes_to_hex(description),
-            );
+            )
+            .unwrap();
 
             assert_eq!(
                 "0x01",
@@ -1216,4 +1050,39 @@ mod tests {
             )
         }
     }
+
+    #[test]
+    fn test_error_codes() {
+        let err = hpre(&[0xc1], 0, 0).err().unwrap();
+        assert_eq!(err.code(), "SOX_DECODE");
+
+        let err = compile_basic_circuit(16, &[]).err().unwrap();
+        assert_eq!(err.code(), "SOX_INVALID_INPUT");
+
+        let mut ct = vec![0u8; 80];
+        let err = check_received_ct_key(&mut ct, &[0u8; 3], "0x00".into())
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), "SOX_KEY_LENGTH");
+
+        let mut file = vec![1u8; 100];
+        let precontract = compute_precontract_values_v2(&mut file, &[7u8; 16]).unwrap();
+        let evaluated = evaluate_circuit_v2_wasm(
+            &precontract.circuit_bytes,
+            &precontract.ct,
+            bytes_to_hex(vec![7u8; 16]),
+        )
+        .unwrap();
+        let err = hpre_v2(&evaluated.to_bytes(), 2, 0).err().unwrap();
+        assert_eq!(err.code(), "SOX_INDEX");
+        let err = compute_proofs_v2(
+            &precontract.circuit_bytes,
+            &evaluated.to_bytes(),
+            &precontract.ct,
+            precontract.num_gates + 1,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err.code(), "SOX_INDEX");
+    }
 }
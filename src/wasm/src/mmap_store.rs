@@ -0,0 +1,405 @@
+//! Disk-backed storage for the vendor side of multi-gigabyte V2 circuits (native only). The
+//! evaluated values and the Merkle layers used in a dispute live in flat memory-mapped files of
+//! fixed 64-byte and 32-byte records, so that `hpre_v2_mmap` and the `compute_proofs*_v2_mmap`
+//! functions only touch the pages they need instead of deserializing the whole evaluation.
+
+use crate::accumulator::{
+    hash_block64, hash_layer, AccumulatorHash, AccumulatorMode, Keccak256Hasher, MerkleLayers,
+};
+use crate::circuits_v2::CompiledCircuitV2;
+use crate::error::{SoxError, SoxResult};
+use memmap2::{Mmap, MmapMut};
+use rayon::prelude::*;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+// Size of the records of a values file, values being accumulated as 64-byte blocks
+const VALUE_SIZE: usize = 64;
+
+// Size of the records of a layers file
+const NODE_SIZE: usize = 32;
+
+// Names of the files of a `MmapStoreV2` in its directory
+const VALUES_FILE: &str = "values.bin";
+const LENGTHS_FILE: &str = "lengths.bin";
+const OUTPUTS_FILE: &str = "outputs.bin";
+const GATES_FILE: &str = "gates.bin";
+const CT_FILE: &str = "ct.bin";
+
+/// Sequence of values of at most 64 bytes stored in a file of 64-byte records (zero-padded), with
+/// their lengths in a companion file of 1-byte records.
+pub struct MmapValues {
+    records: Mmap,
+    lengths: Mmap,
+}
+
+impl MmapValues {
+    /// Writes a sequence of values to disk and maps it.
+    ///
+    /// # Arguments
+    /// * `path` - File of the records, created or truncated
+    /// * `lengths_path` - File of the lengths, created or truncated
+    /// * `values` - Values to store, 64 bytes long at most
+    ///
+    /// # Returns
+    /// The mapped values, `SoxError::InvalidInput` if a value is longer than 64 bytes or
+    /// `SoxError::Io` if the files cannot be written
+    pub fn create<I, V>(path: &Path, lengths_path: &Path, values: I) -> SoxResult<Self>
+    where
+        I: IntoIterator<Item = V>,
+        V: AsRef<[u8]>,
+    {
+        let mut records = BufWriter::new(File::create(path)?);
+        let mut lengths = BufWriter::new(File::create(lengths_path)?);
+        for (i, value) in values.into_iter().enumerate() {
+            let value = value.as_ref();
+            if value.len() > VALUE_SIZE {
+                return Err(SoxError::InvalidInput(format!(
+                    "value {i} is {} bytes long, records are {VALUE_SIZE} bytes",
+                    value.len()
+                )));
+            }
+            let mut record = [0u8; VALUE_SIZE];
+            record[..value.len()].copy_from_slice(value);
+            records.write_all(&record)?;
+            lengths.write_all(&[value.len() as u8])?;
+        }
+        records.flush()?;
+        lengths.flush()?;
+        drop((records, lengths));
+        Self::open(path, lengths_path)
+    }
+
+    /// Maps values previously written by `create`.
+    ///
+    /// # Arguments
+    /// * `path` - File of the records
+    /// * `lengths_path` - File of the lengths
+    ///
+    /// # Returns
+    /// The mapped values, `SoxError::Io` if the files cannot be mapped or `SoxError::Decode` if
+    /// their sizes do not match
+    pub fn open(path: &Path, lengths_path: &Path) -> SoxResult<Self> {
+        let records = map_file(path)?;
+        let lengths = map_file(lengths_path)?;
+        if records.len() != lengths.len() * VALUE_SIZE {
+            return Err(SoxError::Decode(format!(
+                "{} bytes of records for {} lengths",
+                records.len(),
+                lengths.len()
+            )));
+        }
+        if let Some(i) = lengths.iter().position(|&len| len as usize > VALUE_SIZE) {
+            return Err(SoxError::Decode(format!("invalid length of value {i}")));
+        }
+        Ok(Self { records, lengths })
+    }
+
+    /// Returns the number of values
+    pub fn len(&self) -> usize {
+        self.lengths.len()
+    }
+
+    /// Returns true if there are no values
+    pub fn is_empty(&self) -> bool {
+        self.lengths.is_empty()
+    }
+
+    /// Returns the value at `index`, or `None` if it is out of bounds
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        let len = *self.lengths.get(index)? as usize;
+        Some(&self.record(index)?[..len])
+    }
+
+    /// Returns the zero-padded 64-byte record of the value at `index`, or `None` if it is out of
+    /// bounds
+    pub fn record(&self, index: usize) -> Option<&[u8; VALUE_SIZE]> {
+        self.records.as_chunks::<VALUE_SIZE>().0.get(index)
+    }
+}
+
+/// Layers of a Merkle tree built like `acc`, stored from the leaf hashes to the root in a single
+/// file of 32-byte records. The nodes are hashed with `H`, Keccak-256 by default.
+pub struct MmapLayers<H = Keccak256Hasher> {
+    map: Mmap,
+    // Index of the first record of each layer, plus the total number of records
+    offsets: Vec<usize>,
+    hash: PhantomData<H>,
+}
+
+impl<H: AccumulatorHash> MmapLayers<H> {
+    /// Builds a tree on disk and maps it.
+    ///
+    /// # Arguments
+    /// * `path` - File of the layers, created or truncated
+    /// * `num_leaves` - Number of leaves, at least 1
+    /// * `leaf` - Computes the hash of the leaf at some index, called in parallel
+    ///
+    /// # Returns
+    /// The mapped tree, `SoxError::InvalidInput` if `num_leaves` is 0, `SoxError::Io` if the file
+    /// cannot be written, or the first error returned by `leaf`
+    pub fn create<F>(path: &Path, num_leaves: usize, leaf: F) -> SoxResult<Self>
+    where
+        F: Fn(usize) -> SoxResult<[u8; 32]> + Sync,
+    {
+        if num_leaves == 0 {
+            return Err(SoxError::InvalidInput(
+                "cannot build the Merkle tree of an empty sequence".into(),
+            ));
+        }
+        let offsets = layer_offsets(num_leaves);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len((offsets[offsets.len() - 1] * NODE_SIZE) as u64)?;
+        // SAFETY: the file has just been created by this process and is only accessed through
+        // this mapping until it is dropped
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+
+        let (nodes, _) = map.as_chunks_mut::<NODE_SIZE>();
+        nodes[..num_leaves]
+            .par_iter_mut()
+            .enumerate()
+            .try_for_each(|(i, node)| {
+                *node = leaf(i)?;
+                Ok::<_, SoxError>(())
+            })?;
+        for window in offsets.windows(3) {
+            let (layer, next) = nodes[window[0]..window[2]].split_at_mut(window[1] - window[0]);
+            hash_layer::<H>(layer, next, AccumulatorMode::Legacy);
+        }
+
+        map.flush()?;
+        Ok(Self {
+            map: map.make_read_only()?,
+            offsets,
+            hash: PhantomData,
+        })
+    }
+
+    /// Builds the tree of values stored on disk.
+    ///
+    /// # Arguments
+    /// * `path` - File of the layers, created or truncated
+    /// * `values` - Stored values
+    /// * `start` - Index of the first value of the tree, the tree covering `values[start..]`
+    ///
+    /// # Returns
+    /// The mapped tree, or the errors of `create`
+    pub fn from_values(path: &Path, values: &MmapValues, start: usize) -> SoxResult<Self> {
+        let num_leaves = values.len().saturating_sub(start);
+        Self::create(path, num_leaves, |i| {
+            Ok(H::hash_block64(
+                values.record(start + i).expect("index in bounds"),
+            ))
+        })
+    }
+
+    /// Maps a tree previously written by `create`.
+    ///
+    /// # Arguments
+    /// * `path` - File of the layers
+    /// * `num_leaves` - Expected number of leaves, at least 1
+    ///
+    /// # Returns
+    /// The mapped tree, `SoxError::Io` if the file cannot be mapped or `SoxError::Decode` if its
+    /// size does not match `num_leaves`
+    pub fn open(path: &Path, num_leaves: usize) -> SoxResult<Self> {
+        if num_leaves == 0 {
+            return Err(SoxError::InvalidInput(
+                "cannot open the Merkle tree of an empty sequence".into(),
+            ));
+        }
+        let map = map_file(path)?;
+        let offsets = layer_offsets(num_leaves);
+        if map.len() != offsets[offsets.len() - 1] * NODE_SIZE {
+            return Err(SoxError::Decode(format!(
+                "{} bytes of layers for {num_leaves} leaves",
+                map.len()
+            )));
+        }
+        Ok(Self {
+            map,
+            offsets,
+            hash: PhantomData,
+        })
+    }
+
+    /// Returns the number of leaves
+    pub fn num_leaves(&self) -> usize {
+        self.offsets[1]
+    }
+}
+
+impl<H: AccumulatorHash> MerkleLayers for MmapLayers<H> {
+    type Hash = H;
+
+    fn num_layers(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    fn layer(&self, level: usize) -> &[[u8; 32]] {
+        &self.map.as_chunks::<NODE_SIZE>().0[self.offsets[level]..self.offsets[level + 1]]
+    }
+}
+
+/// Everything a vendor needs on disk to answer the challenges of a dispute on a V2 circuit: the
+/// evaluated values (ciphertext blocks then gate outputs, like `EvaluatedCircuitV2`) and the
+/// Merkle layers of the gate outputs, of the encoded gates and of the ciphertext blocks. The
+/// store is written once in a directory after the evaluation and can be reopened later.
+pub struct MmapStoreV2 {
+    values: MmapValues,
+    outputs: MmapLayers,
+    gates: MmapLayers,
+    ct: MmapLayers,
+    num_blocks: usize,
+}
+
+impl MmapStoreV2 {
+    /// Writes the store of an evaluated circuit.
+    ///
+    /// # Arguments
+    /// * `dir` - Existing directory of the store, whose files are created or truncated
+    /// * `circuit` - The V2 circuit
+    /// * `values` - Evaluated values, e.g. `EvaluatedCircuitV2::values`
+    /// * `ct` - Ciphertext bytes (IV included)
+    ///
+    /// # Returns
+    /// The store, `SoxError::CircuitShape` if the values or the ciphertext do not match the
+    /// circuit, or the errors of `MmapValues::create` and `MmapLayers::create`
+    pub fn create<I, V>(
+        dir: &Path,
+        circuit: &CompiledCircuitV2,
+        values: I,
+        ct: &[u8],
+    ) -> SoxResult<Self>
+    where
+        I: IntoIterator<Item = V>,
+        V: AsRef<[u8]>,
+    {
+        let num_blocks = circuit.num_blocks as usize;
+        if ct.len() < 16 || (ct.len() - 16).div_ceil(VALUE_SIZE) != num_blocks {
+            return Err(SoxError::CircuitShape(format!(
+                "a ciphertext of {} bytes does not have the {num_blocks} blocks of the circuit",
+                ct.len()
+            )));
+        }
+        let values = MmapValues::create(&dir.join(VALUES_FILE), &dir.join(LENGTHS_FILE), values)?;
+        check_num_values(&values, circuit)?;
+
+        let outputs = MmapLayers::from_values(&dir.join(OUTPUTS_FILE), &values, num_blocks)?;
+        let gates = MmapLayers::create(&dir.join(GATES_FILE), circuit.gates.len(), |i| {
+            Ok(hash_block64(&circuit.gates[i].encode()?))
+        })?;
+        // Same leaves as `acc_ct`: the IV, then the 64-byte blocks of the encrypted data
+        let ct = MmapLayers::create(&dir.join(CT_FILE), num_blocks + 1, |i| {
+            let block = match i {
+                0 => &ct[..16],
+                _ => {
+                    let start = 16 + (i - 1) * VALUE_SIZE;
+                    &ct[start..ct.len().min(start + VALUE_SIZE)]
+                }
+            };
+            Ok(hash_block64(block))
+        })?;
+
+        Ok(Self {
+            values,
+            outputs,
+            gates,
+            ct,
+            num_blocks,
+        })
+    }
+
+    /// Opens a store previously written by `create`.
+    ///
+    /// # Arguments
+    /// * `dir` - Directory of the store
+    /// * `circuit` - The V2 circuit the store was written for
+    ///
+    /// # Returns
+    /// The store, `SoxError::Io` if a file cannot be mapped, or `SoxError::Decode` or
+    /// `SoxError::CircuitShape` if the files do not match the circuit
+    pub fn open(dir: &Path, circuit: &CompiledCircuitV2) -> SoxResult<Self> {
+        let num_blocks = circuit.num_blocks as usize;
+        let values = MmapValues::open(&dir.join(VALUES_FILE), &dir.join(LENGTHS_FILE))?;
+        check_num_values(&values, circuit)?;
+        Ok(Self {
+            values,
+            outputs: MmapLayers::open(&dir.join(OUTPUTS_FILE), circuit.gates.len())?,
+            gates: MmapLayers::open(&dir.join(GATES_FILE), circuit.gates.len())?,
+            ct: MmapLayers::open(&dir.join(CT_FILE), num_blocks + 1)?,
+            num_blocks,
+        })
+    }
+
+    /// Returns the number of blocks of the ciphertext
+    pub fn num_blocks(&self) -> usize {
+        self.num_blocks
+    }
+
+    /// Returns the number of gates of the circuit
+    pub fn num_gates(&self) -> usize {
+        self.outputs.num_leaves()
+    }
+
+    /// Returns the evaluated values, ciphertext blocks then gate outputs
+    pub fn values(&self) -> &MmapValues {
+        &self.values
+    }
+
+    /// Returns the tree of the gate outputs, whose prefix roots are the hpre responses
+    pub fn outputs(&self) -> &MmapLayers {
+        &self.outputs
+    }
+
+    /// Returns the tree of the encoded gates, whose root is h_circuit
+    pub fn gates(&self) -> &MmapLayers {
+        &self.gates
+    }
+
+    /// Returns the tree of the IV and the ciphertext blocks, whose root is h_ct
+    pub fn ct(&self) -> &MmapLayers {
+        &self.ct
+    }
+}
+
+// Maps a whole file read-only
+fn map_file(path: &Path) -> SoxResult<Mmap> {
+    let file = File::open(path)?;
+    // SAFETY: the store files are written once by `create` and must not be modified while they
+    // are mapped
+    Ok(unsafe { Mmap::map(&file)? })
+}
+
+// Index of the first record of each layer of a tree with `num_leaves` leaves (at least 1), plus
+// the total number of records
+fn layer_offsets(num_leaves: usize) -> Vec<usize> {
+    let mut offsets = vec![0, num_leaves];
+    let mut width = num_leaves;
+    while width > 1 {
+        width = width.div_ceil(2);
+        offsets.push(offsets[offsets.len() - 1] + width);
+    }
+    offsets
+}
+
+// Fails unless there is one value per ciphertext block and per gate, and at least one gate
+fn check_num_values(values: &MmapValues, circuit: &CompiledCircuitV2) -> SoxResult<()> {
+    let expected = circuit.num_blocks as usize + circuit.gates.len();
+    if circuit.gates.is_empty() || values.len() != expected {
+        return Err(SoxError::CircuitShape(format!(
+            "{} stored values for {} blocks and {} gates",
+            values.len(),
+            circuit.num_blocks,
+            circuit.gates.len()
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,1527 @@
+//! Native counterparts of the wasm entry points. Every function of this module takes and returns
+//! plain Rust types (byte slices instead of hex strings, `Proof` instead of JavaScript arrays) so
+//! that it can be used from the CLIs and from other Rust code. The wasm bindings defined in the
+//! crate root are thin wrappers around these functions.
+
+use crate::accumulator::{acc, acc_circuit, acc_ct, prove, prove_ext};
+use crate::circuits::{
+    compile_basic_circuit, evaluate_circuit_internal, get_evaluated_sons, is_constant_idx,
+    CompiledCircuitWithConstants,
+};
+use crate::circuits_v2::{acc_circuit_v2, compile_circuit_v2};
+use crate::commitment::{commit_hashes, open_commitment_internal};
+use crate::encryption::{decrypt, encrypt_and_prepend_iv};
+use crate::error::{SoxError, SoxResult};
+use crate::sha256::sha256;
+use crate::utils::{error, split_ct_blocks};
+use crate::{
+    ArgumentCheckResult, CheckCtResult, CheckPrecontractResult, DisputeArgument, DisputeArgumentV2,
+    EvaluatedCircuit, EvaluatedCircuitV2, Precontract,
+};
+
+pub use crate::accumulator::{
+    acc_fixed64_with_hash, acc_with_hash, prove_with_hash, verify_with_hash, AccumulableCircuit,
+    AccumulatorBuilder, AccumulatorHash, AccumulatorMode, Blake3Hasher, Keccak256Hasher,
+    MerkleLayers, MerkleTree, Sha256Hasher,
+};
+pub use crate::calldata::{
+    give_opinion_calldata, respond_challenge_calldata, submit_commitment_calldata,
+    submit_commitment_left_calldata, submit_commitment_right_calldata,
+};
+pub use crate::circuit_builder::{CircuitBuilderV2, Wire};
+pub use crate::circuits::{compile_basic_circuit_with_labels, CompiledCircuit};
+pub use crate::circuits_v2::{
+    acc_circuit_v2_with_hash, compile_circuit_v2_with_labels, opcode_info, CompiledCircuitV2,
+    GateV2, OpcodeInfo, OPCODES_V2,
+};
+pub use crate::disasm::{
+    circuit_to_dot, circuit_to_dot_v1, circuit_to_dot_v2, disassemble, disassemble_gate_v2,
+    disassemble_v1, disassemble_v2,
+};
+pub use crate::dispute::{
+    BuyerStrategy, DisputeSox, DisputeState, HonestBuyer, HonestVendor, VendorStrategy,
+};
+pub use crate::download::{
+    ct_block_range, ct_num_blocks, prove_ct_blocks, prove_ct_chunk, verify_ct_chunk, CtDownload,
+};
+pub use crate::estimate::{
+    estimate_dispute_cost, DisputeCostEstimate, FinalStepEstimate, GasSchedule, ProofSize,
+};
+pub use crate::labels::{CircuitLabels, GateLabel, GateRole};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::mmap_store::{MmapLayers, MmapStoreV2, MmapValues};
+pub use crate::precontract::{PrecontractBuilder, StreamedPrecontract};
+pub use crate::session::{BuyerDisputeSession, VendorDisputeSession, VendorFinalStep};
+pub use crate::sox_circuit::{AnyCircuit, AnyEvaluated, AnyFinalStep, CircuitVersion, SoxCircuit};
+pub use crate::validate::{CircuitDiagnostic, CircuitIssue};
+
+/// A multi-value proof of the accumulator. Each layer contains the hashes needed to go one level
+/// up in the Merkle tree.
+pub type Proof = Vec<Vec<[u8; 32]>>;
+
+/// Components of the vendor's final step (V1 circuits).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FinalStep {
+    /// Flattened gate at the challenge point
+    pub gate: Vec<u32>,
+
+    /// Values of the sons of the gate
+    pub values: Vec<Vec<u8>>,
+
+    /// Current accumulator value (w_i)
+    pub curr_acc: Vec<u8>,
+
+    /// Proof that the gate belongs to the circuit
+    pub proof1: Proof,
+
+    /// Proof that the input sons belong to the ciphertext
+    pub proof2: Proof,
+
+    /// Proof that the other sons belong to the evaluated values (empty if not needed)
+    pub proof3: Proof,
+
+    /// Extension proof
+    pub proof_ext: Proof,
+}
+
+/// Components of the vendor's final step (V2 circuits).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FinalStepV2 {
+    /// 64-byte encoded gate at the challenge point
+    pub gate_bytes: Vec<u8>,
+
+    /// Values of the sons of the gate
+    pub values: Vec<Vec<u8>>,
+
+    /// Current accumulator value (w_i)
+    pub curr_acc: Vec<u8>,
+
+    /// Proof that the gate belongs to the circuit
+    pub proof1: Proof,
+
+    /// Proof that the input sons belong to the ciphertext
+    pub proof2: Proof,
+
+    /// Proof that the other sons belong to the evaluated values (empty if not needed)
+    pub proof3: Proof,
+
+    /// Extension proof
+    pub proof_ext: Proof,
+}
+
+// Converts the layers returned by `prove` into a `Proof`. All nodes of the tree are Keccak hashes
+// so the conversion cannot fail.
+pub(crate) fn to_proof(layers: Vec<Vec<Vec<u8>>>) -> Proof {
+    layers
+        .into_iter()
+        .map(|layer| {
+            layer
+                .into_iter()
+                .map(|node| node.try_into().expect("proof nodes are 32-byte hashes"))
+                .collect()
+        })
+        .collect()
+}
+
+// ####################################
+// ###     PRECONTRACT VENDOR       ###
+// ####################################
+
+/// Computes precontract values for a file. This includes encryption, circuit compilation,
+/// and commitment generation.
+///
+/// # Arguments
+/// * `file` - The file data to be encrypted
+/// * `key` - The encryption key
+///
+/// # Returns
+/// A `Precontract` containing all necessary components for the optimistic phase of the protocol
+pub fn compute_precontract_values(file: &mut [u8], key: &[u8]) -> SoxResult<Precontract> {
+    let description = sha256(file);
+    let ct = encrypt_and_prepend_iv(file, key)?;
+    let circuit = compile_basic_circuit(ct.len() as u32, &description)?;
+    let num_blocks = circuit.num_blocks;
+    let num_gates = circuit.circuit.len() as u32;
+    let circuit_bytes = circuit.to_bytes();
+    let h_ct = acc_ct(&ct, circuit.block_size as usize)?;
+    let h_circuit = acc_circuit(&circuit)?;
+    let commitment = commit_hashes(&h_circuit, &h_ct);
+
+    Ok(Precontract {
+        ct,
+        circuit_bytes,
+        description,
+        h_ct,
+        h_circuit,
+        commitment,
+        num_blocks,
+        num_gates,
+    })
+}
+
+/// Computes precontract values for V2 circuit. This includes encryption, V2 circuit compilation,
+/// and commitment generation.
+///
+/// # Arguments
+/// * `file` - The file data to be encrypted
+/// * `key` - The encryption key
+///
+/// # Returns
+/// A `Precontract` containing all necessary components for the optimistic phase of the protocol
+pub fn compute_precontract_values_v2(file: &mut [u8], key: &[u8]) -> SoxResult<Precontract> {
+    let description = sha256(file);
+    let ct = encrypt_and_prepend_iv(file, key)?;
+    let circuit = compile_circuit_v2(&ct, &description)?;
+    let num_blocks = circuit.num_blocks;
+    let num_gates = circuit.gates.len() as u32;
+    let circuit_bytes = circuit.to_bytes();
+    let h_ct = acc_ct(&ct, circuit.block_size as usize)?;
+    let h_circuit = acc_circuit_v2(&circuit.gates)?;
+    let commitment = commit_hashes(&h_circuit, &h_ct);
+
+    Ok(Precontract {
+        ct,
+        circuit_bytes,
+        description,
+        h_ct,
+        h_circuit,
+        commitment,
+        num_blocks,
+        num_gates,
+    })
+}
+
+// ####################################
+// ###    BUYER PRECONTRACT CHECK   ###
+// ####################################
+
+/// Verifies a precontract by checking the commitment and description with respect to the received
+/// ciphertext.
+///
+/// # Arguments
+/// * `description` - Description hash
+/// * `commitment` - Commitment
+/// * `opening_value` - Opening value
+/// * `ct` - Ciphertext bytes
+///
+/// # Returns
+/// A `CheckPrecontractResult` containing the verification status and hash values. A commitment
+/// that does not open is reported with `success == false`; malformed inputs are returned as errors.
+pub fn check_precontract(
+    description: &[u8],
+    commitment: &[u8],
+    opening_value: &[u8],
+    ct: &[u8],
+) -> SoxResult<CheckPrecontractResult> {
+    let circuit = compile_basic_circuit(ct.len() as u32, description)?;
+    let h_ct = acc_ct(ct, circuit.block_size as usize)?;
+    let h_circuit = acc_circuit(&circuit)?;
+    Ok(check_opening(commitment, opening_value, h_circuit, h_ct))
+}
+
+/// Verifies a precontract with a V2 circuit, like `check_precontract`. Precontracts made by
+/// `compute_precontract_values_v2` or `PrecontractBuilder` must be checked with this function.
+///
+/// # Arguments
+/// * `description` - Description hash
+/// * `commitment` - Commitment
+/// * `opening_value` - Opening value
+/// * `ct` - Ciphertext bytes
+///
+/// # Returns
+/// A `CheckPrecontractResult` containing the verification status and hash values
+pub fn check_precontract_v2(
+    description: &[u8],
+    commitment: &[u8],
+    opening_value: &[u8],
+    ct: &[u8],
+) -> SoxResult<CheckPrecontractResult> {
+    let circuit = compile_circuit_v2(ct, description)?;
+    let h_ct = acc_ct(ct, circuit.block_size as usize)?;
+    let h_circuit = acc_circuit_v2(&circuit.gates)?;
+    Ok(check_opening(commitment, opening_value, h_circuit, h_ct))
+}
+
+// Checks that a commitment opens to h_circuit || h_ct
+fn check_opening(
+    commitment: &[u8],
+    opening_value: &[u8],
+    h_circuit: Vec<u8>,
+    h_ct: Vec<u8>,
+) -> CheckPrecontractResult {
+    match open_commitment_internal(commitment, opening_value) {
+        Ok(opened) => {
+            let success =
+                opened.len() == 64 && opened[..32].eq(&h_circuit) && opened[32..].eq(&h_ct);
+            CheckPrecontractResult {
+                success,
+                h_circuit,
+                h_ct,
+            }
+        }
+        Err(err) => {
+            error(&err.to_string());
+            CheckPrecontractResult {
+                success: false,
+                h_circuit,
+                h_ct,
+            }
+        }
+    }
+}
+
+/// Verifies ciphertext decryption by checking against the description.
+///
+/// # Arguments
+/// * `ct` - Ciphertext bytes to decrypt
+/// * `key` - Decryption key
+/// * `description` - Expected description hash
+///
+/// # Returns
+/// A `CheckCtResult` containing the verification status and decrypted data
+pub fn check_received_ct_key(
+    ct: &[u8],
+    key: &[u8],
+    description: &[u8],
+) -> SoxResult<CheckCtResult> {
+    let decrypted_file = decrypt(ct, key)?;
+    let success = description.eq(sha256(&decrypted_file).as_slice());
+
+    Ok(CheckCtResult {
+        success,
+        decrypted_file,
+    })
+}
+
+// ####################################
+// ###    DISPUTE ARGUMENTS         ###
+// ####################################
+
+/// Creates a dispute argument from the given components.
+///
+/// # Arguments
+/// * `ct` - Ciphertext bytes
+/// * `description` - Description hash
+/// * `opening_value` - Opening value of the commitment
+///
+/// # Returns
+/// Serialized dispute argument bytes
+pub fn make_argument(
+    ct: Vec<u8>,
+    description: &[u8],
+    opening_value: Vec<u8>,
+) -> SoxResult<Vec<u8>> {
+    Ok(DisputeArgument {
+        circuit: compile_basic_circuit(ct.len() as u32, description)?,
+        ct,
+        opening_value,
+    }
+    .to_bytes())
+}
+
+/// Creates a dispute argument for a V2 circuit, like `make_argument`.
+///
+/// # Arguments
+/// * `ct` - Ciphertext bytes
+/// * `description` - Description hash
+/// * `opening_value` - Opening value of the commitment
+///
+/// # Returns
+/// Serialized V2 dispute argument bytes
+pub fn make_argument_v2(
+    ct: Vec<u8>,
+    description: &[u8],
+    opening_value: Vec<u8>,
+) -> SoxResult<Vec<u8>> {
+    Ok(DisputeArgumentV2 {
+        circuit: compile_circuit_v2(&ct, description)?,
+        ct,
+        opening_value,
+    }
+    .to_bytes())
+}
+
+/// Verifies a dispute argument.
+///
+/// # Arguments
+/// * `argument_bin` - Serialized dispute argument bytes
+/// * `commitment` - Commitment
+/// * `description` - Description hash
+/// * `key` - Encryption key
+///
+/// # Returns
+/// An `ArgumentCheckResult` containing the verification results
+pub fn check_argument(
+    argument_bin: &[u8],
+    commitment: &[u8],
+    description: &[u8],
+    key: &[u8],
+) -> SoxResult<ArgumentCheckResult> {
+    let argument = DisputeArgument::from_bytes(argument_bin)?;
+    let h_circuit = acc_circuit(&argument.circuit)?;
+    check_argument_opening(
+        &argument.ct,
+        &argument.opening_value,
+        argument.circuit.block_size,
+        h_circuit,
+        commitment,
+        description,
+        key,
+    )
+}
+
+/// Verifies a dispute argument for a V2 circuit, like `check_argument`.
+///
+/// # Arguments
+/// * `argument_bin` - Serialized V2 dispute argument bytes
+/// * `commitment` - Commitment
+/// * `description` - Description hash
+/// * `key` - Encryption key
+///
+/// # Returns
+/// An `ArgumentCheckResult` containing the verification results
+pub fn check_argument_v2(
+    argument_bin: &[u8],
+    commitment: &[u8],
+    description: &[u8],
+    key: &[u8],
+) -> SoxResult<ArgumentCheckResult> {
+    let argument = DisputeArgumentV2::from_bytes(argument_bin)?;
+    let h_circuit = acc_circuit_v2(&argument.circuit.gates)?;
+    check_argument_opening(
+        &argument.ct,
+        &argument.opening_value,
+        argument.circuit.block_size,
+        h_circuit,
+        commitment,
+        description,
+        key,
+    )
+}
+
+// Checks the commitment of a dispute argument and whether its plaintext matches the description
+fn check_argument_opening(
+    ct: &[u8],
+    opening_value: &[u8],
+    block_size: u32,
+    h_circuit: Vec<u8>,
+    commitment: &[u8],
+    description: &[u8],
+    key: &[u8],
+) -> SoxResult<ArgumentCheckResult> {
+    let h_ct = acc_ct(ct, block_size as usize)?;
+
+    match open_commitment_internal(commitment, opening_value) {
+        Ok(opened) => {
+            let is_valid =
+                opened.len() == 64 && opened[..32].eq(&h_circuit) && opened[32..].eq(&h_ct);
+            let pt = decrypt(ct, key)?;
+            let supports_buyer = !description.eq(sha256(&pt).as_slice());
+            Ok(ArgumentCheckResult {
+                is_valid,
+                supports_buyer,
+                error: None,
+            })
+        }
+        Err(err) => {
+            error(&err.to_string());
+            Ok(ArgumentCheckResult {
+                is_valid: false,
+                supports_buyer: false,
+                error: Some(err.to_string()),
+            })
+        }
+    }
+}
+
+// ####################################
+// ###    BUYER/VENDOR EVAL         ###
+// ####################################
+
+/// Evaluates a circuit with the given ciphertext, constants, and description.
+///
+/// # Arguments
+/// * `circuit_bytes` - Serialized circuit bytes. If empty, a new basic circuit will be compiled
+/// * `ct` - Ciphertext bytes to evaluate
+/// * `constants` - Constant values bound to the missing constants of the circuit
+/// * `description` - Description hash
+///
+/// # Returns
+/// An `EvaluatedCircuit` containing the evaluation results and circuit constants
+pub fn evaluate_circuit(
+    circuit_bytes: &[u8],
+    ct: &[u8],
+    constants: Vec<Vec<u8>>,
+    description: &[u8],
+) -> SoxResult<EvaluatedCircuit> {
+    let circuit = if circuit_bytes.is_empty() {
+        compile_basic_circuit(ct.len() as u32, description)?
+    } else {
+        CompiledCircuit::from_bytes(circuit_bytes)?
+    }
+    .bind_missing_constants(constants)?;
+
+    evaluate_compiled(circuit, ct)
+}
+
+// Evaluates a circuit whose constants are already bound
+pub(crate) fn evaluate_compiled(
+    circuit: CompiledCircuitWithConstants,
+    ct: &[u8],
+) -> SoxResult<EvaluatedCircuit> {
+    let ct_blocks = split_ct_blocks(ct, circuit.block_size as usize)?;
+
+    Ok(EvaluatedCircuit {
+        constants: circuit.constants.clone(),
+        values: evaluate_circuit_internal(&ct_blocks, circuit)?,
+    })
+}
+
+// Splits the ciphertext into 64-byte zero-padded blocks, skipping the IV. This is the layout of
+// the inputs of V2 circuits.
+fn ct_blocks_v2(ct: &[u8]) -> SoxResult<Vec<Vec<u8>>> {
+    if ct.len() < 16 {
+        return Err(SoxError::InvalidInput(
+            "ciphertext must include a 16-byte IV".into(),
+        ));
+    }
+    Ok(ct[16..]
+        .chunks(64)
+        .map(|chunk| {
+            let mut block = vec![0u8; 64];
+            block[..chunk.len()].copy_from_slice(chunk);
+            block
+        })
+        .collect())
+}
+
+/// Evaluates a V2 circuit with the given ciphertext and key.
+///
+/// # Arguments
+/// * `circuit_bytes` - Serialized V2 circuit bytes
+/// * `ct` - Ciphertext bytes to evaluate
+/// * `key` - AES key
+///
+/// # Returns
+/// An `EvaluatedCircuitV2` containing the evaluation results
+/// The values array contains: [inputs (num_blocks), gate outputs (num_gates)]
+pub fn evaluate_circuit_v2(
+    circuit_bytes: &[u8],
+    ct: &[u8],
+    key: &[u8],
+) -> SoxResult<EvaluatedCircuitV2> {
+    evaluate_compiled_v2(&CompiledCircuitV2::from_bytes(circuit_bytes)?, ct, key)
+}
+
+// Evaluates a decoded V2 circuit
+pub(crate) fn evaluate_compiled_v2(
+    circuit: &CompiledCircuitV2,
+    ct: &[u8],
+    key: &[u8],
+) -> SoxResult<EvaluatedCircuitV2> {
+    // Split ciphertext into blocks (skip IV, 64-byte blocks)
+    // This should match how compile_circuit_v2 calculates num_blocks
+    let inputs = ct_blocks_v2(ct)?;
+
+    // Verify that inputs.len() matches circuit.num_blocks
+    if inputs.len() != circuit.num_blocks as usize {
+        return Err(SoxError::CircuitShape(format!(
+            "the ciphertext has {} blocks but the circuit expects {}",
+            inputs.len(),
+            circuit.num_blocks
+        )));
+    }
+
+    // Evaluate circuit - this returns only gate outputs, not inputs
+    let gate_values = crate::circuits_v2::evaluate_circuit_v2(&circuit.gates, &inputs, key)?;
+
+    // Combine inputs and gate outputs: [inputs, gate_outputs]
+    // This matches the V1 format where values[0..num_blocks] are inputs
+    // and values[num_blocks..] are gate outputs
+    let mut all_values = inputs;
+    all_values.extend(gate_values);
+
+    Ok(EvaluatedCircuitV2 { values: all_values })
+}
+
+// ####################################
+// ###    BUYER/VENDOR HPRE         ###
+// ####################################
+
+/// Computes the answer to send to a smart contract based on the issued challenge.
+///
+/// # Arguments
+/// * `evaluated_circuit_bytes` - Serialized evaluated circuit bytes
+/// * `num_blocks` - Number of blocks for the ciphertext
+/// * `challenge` - Challenge issued by the smart contract
+///
+/// # Returns
+/// The response to the challenge, or `SoxError::Index` if the challenge is out of bounds
+pub fn hpre(
+    evaluated_circuit_bytes: &[u8],
+    num_blocks: usize,
+    challenge: usize,
+) -> SoxResult<Vec<u8>> {
+    let evaluated_circuit = EvaluatedCircuit::from_bytes(evaluated_circuit_bytes)?;
+    hpre_values(&evaluated_circuit.values, num_blocks, challenge)
+}
+
+// hpre on decoded values, `challenge` being an absolute index in `values`
+pub(crate) fn hpre_values(
+    values: &[Vec<u8>],
+    num_blocks: usize,
+    challenge: usize,
+) -> SoxResult<Vec<u8>> {
+    Ok(acc(values_range(values, num_blocks, challenge + 1)?))
+}
+
+/// Computes the answer to send to a smart contract based on the issued challenge (V2).
+///
+/// # Arguments
+/// * `evaluated_circuit_bytes` - Serialized evaluated V2 circuit bytes
+/// * `num_blocks` - Number of blocks for the ciphertext
+/// * `challenge` - Challenge issued by the smart contract (1-indexed gate index)
+///
+/// # Returns
+/// The response to the challenge (32-byte accumulator hash), i.e. hpre(i) = Acc(val(1), ..., val(i))
+/// with i = challenge, or `SoxError::Index` if the challenge is 0 or past the last gate. See the
+/// wasm binding `hpre_v2` for the details of the notation.
+pub fn hpre_v2(
+    evaluated_circuit_bytes: &[u8],
+    num_blocks: usize,
+    challenge: usize,
+) -> SoxResult<Vec<u8>> {
+    let evaluated = EvaluatedCircuitV2::from_bytes(evaluated_circuit_bytes)?;
+    hpre_values_v2(&evaluated.values, num_blocks, challenge)
+}
+
+// hpre on decoded V2 values, `challenge` being a 1-indexed gate index
+pub(crate) fn hpre_values_v2(
+    values: &[Vec<u8>],
+    num_blocks: usize,
+    challenge: usize,
+) -> SoxResult<Vec<u8>> {
+    if challenge == 0 {
+        return Err(SoxError::Index("challenges are 1-indexed, got 0".into()));
+    }
+    // Start at num_blocks to exclude inputs, consistent with V1 and compute_proofs_v2: gate
+    // `challenge` (1-indexed) is at index num_blocks + challenge - 1
+    let outputs = values_range(values, num_blocks, num_blocks + challenge)?;
+    Ok(acc(outputs))
+}
+
+// ####################################
+// ###    VENDOR FINAL STEP         ###
+// ####################################
+
+// Returns `values[start..end]`, or `SoxError::Index` if the range does not fit in `values`
+fn values_range(values: &[Vec<u8>], start: usize, end: usize) -> SoxResult<&[Vec<u8>]> {
+    values.get(start..end).ok_or_else(|| {
+        SoxError::Index(format!(
+            "range {start}..{end} out of bounds ({} values)",
+            values.len()
+        ))
+    })
+}
+
+// Returns the gate at `idx`, or `SoxError::Index` if the challenge points outside the circuit
+fn gate_at<T>(gates: &[T], idx: usize) -> SoxResult<&T> {
+    gates.get(idx).ok_or_else(|| {
+        SoxError::Index(format!(
+            "challenge points to gate {idx} but the circuit has {} gates",
+            gates.len()
+        ))
+    })
+}
+
+// Splits the sons according to the paper's set L. Constant indices are not kept.
+fn split_sons_indices(sons: &[u32], num_blocks: u32) -> (Vec<u32>, Vec<u32>) {
+    let mut in_l = Vec::new();
+    let mut not_in_l_minus_m = Vec::new();
+
+    for &s in sons {
+        if is_constant_idx(s) {
+            continue;
+        }
+        if s < num_blocks {
+            // strictly inferior because we start counting from 0
+            in_l.push(s)
+        } else {
+            not_in_l_minus_m.push(s - num_blocks)
+        }
+    }
+
+    (in_l, not_in_l_minus_m)
+}
+
+/// Computes proofs for step 8a.
+///
+/// # Arguments
+/// * `circuit_bytes` - Serialized circuit bytes
+/// * `evaluated_circuit_bytes` - Serialized evaluated circuit bytes
+/// * `ct` - Ciphertext bytes
+/// * `challenge` - Challenge point in the circuit
+///
+/// # Returns
+/// A `FinalStep` containing the gate at the challenge point, the values of its sons, the current
+/// accumulator value and the proofs (proof1, proof2, proof3, proof_ext)
+pub fn compute_proofs(
+    circuit_bytes: &[u8],
+    evaluated_circuit_bytes: &[u8],
+    ct: &[u8],
+    challenge: u32,
+) -> SoxResult<FinalStep> {
+    final_step(
+        &CompiledCircuit::from_bytes(circuit_bytes)?,
+        &EvaluatedCircuit::from_bytes(evaluated_circuit_bytes)?,
+        ct,
+        challenge,
+    )
+}
+
+// Step 8a on a decoded circuit, `challenge` being an absolute index in the circuit
+pub(crate) fn final_step(
+    circuit: &CompiledCircuit,
+    evaluated_circuit: &EvaluatedCircuit,
+    ct: &[u8],
+    challenge: u32,
+) -> SoxResult<FinalStep> {
+    let ct_blocks = split_ct_blocks(ct, circuit.block_size as usize)?;
+    let num_blocks = ct_blocks.len() as u32;
+    let gate = gate_at(&circuit.circuit, challenge as usize)?.clone();
+    let (s_in_l, not_in_l_minus_m) = split_sons_indices(&gate.sons, num_blocks);
+
+    let values = get_evaluated_sons(
+        &gate,
+        &evaluated_circuit.values,
+        &evaluated_circuit.constants,
+    )?;
+    let prefix = values_range(
+        &evaluated_circuit.values,
+        num_blocks as usize,
+        challenge as usize + 1,
+    )?;
+    let curr_acc = acc(prefix);
+    let proof1 = prove(&circuit.to_abi_encoded(), &[challenge])?;
+    let proof2 = prove(&ct_blocks, &s_in_l)?;
+    let proof3 = prove(&prefix[..prefix.len() - 1], &not_in_l_minus_m)?;
+    let proof_ext = prove_ext(prefix)?;
+
+    Ok(FinalStep {
+        gate: gate.flatten(),
+        values: values.into_iter().cloned().collect(),
+        curr_acc,
+        proof1: to_proof(proof1),
+        proof2: to_proof(proof2),
+        proof3: to_proof(proof3),
+        proof_ext: to_proof(proof_ext),
+    })
+}
+
+/// Computes proofs for step 8b.
+///
+/// # Arguments
+/// * `circuit_bytes` - Serialized circuit bytes
+/// * `evaluated_circuit_bytes` - Serialized evaluated circuit bytes
+/// * `ct` - Ciphertext bytes
+/// * `challenge` - Challenge point in the circuit
+///
+/// # Returns
+/// A `FinalStep` containing the gate at the challenge point, the values of its sons, the current
+/// accumulator value and the proofs (proof1, proof2, proof_ext). `proof3` is always empty.
+pub fn compute_proofs_left(
+    circuit_bytes: &[u8],
+    evaluated_circuit_bytes: &[u8],
+    ct: &[u8],
+    challenge: u32,
+) -> SoxResult<FinalStep> {
+    final_step_left(
+        &CompiledCircuit::from_bytes(circuit_bytes)?,
+        &EvaluatedCircuit::from_bytes(evaluated_circuit_bytes)?,
+        ct,
+        challenge,
+    )
+}
+
+// Step 8b on a decoded circuit, `challenge` being an absolute index in the circuit
+pub(crate) fn final_step_left(
+    circuit: &CompiledCircuit,
+    evaluated_circuit: &EvaluatedCircuit,
+    ct: &[u8],
+    challenge: u32,
+) -> SoxResult<FinalStep> {
+    let ct_blocks = split_ct_blocks(ct, circuit.block_size as usize)?;
+    let num_blocks = ct_blocks.len() as u32;
+    let gate = gate_at(&circuit.circuit, challenge as usize)?.clone();
+    let non_constant_sons: Vec<u32> = gate
+        .sons
+        .iter()
+        .copied()
+        .filter(|&x| !is_constant_idx(x))
+        .collect();
+
+    let values = get_evaluated_sons(
+        &gate,
+        &evaluated_circuit.values,
+        &evaluated_circuit.constants,
+    )?;
+    let prefix = values_range(
+        &evaluated_circuit.values,
+        num_blocks as usize,
+        challenge as usize + 1,
+    )?;
+    let curr_acc = acc(prefix);
+    let proof1 = prove(&circuit.to_abi_encoded(), &[challenge])?;
+    let proof2 = prove(&ct_blocks, &non_constant_sons)?;
+    let proof_ext = prove_ext(&prefix[..1])?;
+
+    Ok(FinalStep {
+        gate: gate.flatten(),
+        values: values.into_iter().cloned().collect(),
+        curr_acc,
+        proof1: to_proof(proof1),
+        proof2: to_proof(proof2),
+        proof3: vec![],
+        proof_ext: to_proof(proof_ext),
+    })
+}
+
+/// Computes the proof for step 8c.
+///
+/// # Arguments
+/// * `evaluated_circuit_bytes` - Serialized evaluated circuit bytes
+/// * `num_blocks` - Number of blocks for the ciphertext
+/// * `num_gates` - Total number of gates in the circuit
+///
+/// # Returns
+/// The proof that the last value belongs to the evaluated circuit
+pub fn compute_proof_right(
+    evaluated_circuit_bytes: &[u8],
+    num_blocks: u32,
+    num_gates: u32,
+) -> SoxResult<Proof> {
+    proof_right(
+        &EvaluatedCircuit::from_bytes(evaluated_circuit_bytes)?,
+        num_blocks,
+        num_gates,
+    )
+}
+
+// Step 8c on a decoded evaluated circuit
+pub(crate) fn proof_right(
+    evaluated_circuit: &EvaluatedCircuit,
+    num_blocks: u32,
+    num_gates: u32,
+) -> SoxResult<Proof> {
+    if num_gates <= num_blocks {
+        return Err(SoxError::Index(format!(
+            "the circuit has {num_gates} gates for {num_blocks} blocks"
+        )));
+    }
+    let gate_outputs = values_range(
+        &evaluated_circuit.values,
+        num_blocks as usize,
+        evaluated_circuit.values.len(),
+    )?;
+
+    Ok(to_proof(prove(
+        gate_outputs,
+        &[num_gates - num_blocks - 1],
+    )?))
+}
+
+/// Helper function to get evaluated sons for a V2 gate
+/// Returns direct references (clones) without normalization.
+/// Normalization should be done in individual gate evaluators (XOR, AES-CTR) as needed.
+fn get_evaluated_sons_v2(
+    gate: &GateV2,
+    evaluated_values: &[Vec<u8>],
+    inputs: &[Vec<u8>],
+) -> SoxResult<Vec<Vec<u8>>> {
+    sons_values_v2(
+        gate,
+        |i| inputs.get(i).map(Vec::as_slice),
+        |i| evaluated_values.get(i).map(Vec::as_slice),
+    )
+}
+
+// Values of the sons of a V2 gate, looked up with `input` for the dummy gates (0-indexed
+// ciphertext block) and `output` for the previous gates (0-indexed gate output)
+fn sons_values_v2<'a>(
+    gate: &GateV2,
+    input: impl Fn(usize) -> Option<&'a [u8]>,
+    output: impl Fn(usize) -> Option<&'a [u8]>,
+) -> SoxResult<Vec<Vec<u8>>> {
+    let mut sons = Vec::with_capacity(gate.sons.len());
+
+    for &son_idx in &gate.sons {
+        if son_idx < 0 {
+            // Negative index: dummy gate (input)
+            let input_idx = (-son_idx - 1) as usize;
+            let value = input(input_idx).ok_or_else(|| {
+                SoxError::Index(format!("dummy gate index {son_idx} out of bounds"))
+            })?;
+            sons.push(value.to_vec());
+        } else {
+            // Positive index: previous gate (1-indexed)
+            if son_idx == 0 {
+                return Err(SoxError::CircuitShape(
+                    "gate index cannot be 0 (gates are 1-indexed)".into(),
+                ));
+            }
+            let array_idx = (son_idx - 1) as usize;
+            // Return direct copy without normalization
+            let value = output(array_idx)
+                .ok_or_else(|| SoxError::Index(format!("gate index {son_idx} out of bounds")))?;
+            sons.push(value.to_vec());
+        }
+    }
+
+    Ok(sons)
+}
+
+/// Helper function to split sons for V2 gate according to set L
+fn split_sons_indices_v2(sons: &[i64], num_blocks: u32) -> (Vec<u32>, Vec<u32>) {
+    let mut in_l = Vec::new();
+    let mut not_in_l_minus_m = Vec::new();
+
+    for &s in sons {
+        if s < 0 {
+            // Negative: dummy gate (input block)
+            let ct_idx = (-s) as u32;
+            if (1..=num_blocks).contains(&ct_idx) {
+                in_l.push(ct_idx - 1); // Convert to 0-indexed
+            }
+        } else if s > 0 {
+            // Positive: previous gate
+            not_in_l_minus_m.push((s - 1) as u32); // Convert to 0-indexed
+        }
+    }
+
+    (in_l, not_in_l_minus_m)
+}
+
+/// Computes proofs for step 8a (V2) - corresponds to Step 8a in paper (Section F.2).
+///
+/// # Arguments
+/// * `circuit_bytes` - Serialized V2 circuit bytes
+/// * `evaluated_circuit_bytes` - Serialized evaluated V2 circuit bytes
+/// * `ct` - Ciphertext bytes
+/// * `challenge` - Challenge point in the circuit (1-indexed gate index, matching paper notation)
+///
+/// # Returns
+/// A `FinalStepV2` containing the 64-byte encoded gate, the values of its sons, the current
+/// accumulator value and the proofs (proof1, proof2, proof3, proof_ext)
+pub fn compute_proofs_v2(
+    circuit_bytes: &[u8],
+    evaluated_circuit_bytes: &[u8],
+    ct: &[u8],
+    challenge: u32,
+) -> SoxResult<FinalStepV2> {
+    final_step_v2(
+        &CompiledCircuitV2::from_bytes(circuit_bytes)?,
+        &EvaluatedCircuitV2::from_bytes(evaluated_circuit_bytes)?,
+        ct,
+        challenge,
+    )
+}
+
+// Step 8a on a decoded V2 circuit, `challenge` being a 1-indexed gate index
+pub(crate) fn final_step_v2(
+    circuit: &CompiledCircuitV2,
+    evaluated: &EvaluatedCircuitV2,
+    ct: &[u8],
+    challenge: u32,
+) -> SoxResult<FinalStepV2> {
+    let tree = prefix_tree_v2(circuit, evaluated, challenge)?;
+    final_step_v2_with_tree(circuit, evaluated, &tree, ct, challenge)
+}
+
+// Merkle tree of the outputs of gates 1 to `challenge`, the only ones a final step depends on
+fn prefix_tree_v2(
+    circuit: &CompiledCircuitV2,
+    evaluated: &EvaluatedCircuitV2,
+    challenge: u32,
+) -> SoxResult<MerkleTree> {
+    if challenge == 0 {
+        return Err(SoxError::Index("challenges are 1-indexed, got 0".into()));
+    }
+    let gate_outputs = values_range(
+        &evaluated.values,
+        circuit.num_blocks as usize,
+        evaluated.values.len(),
+    )?;
+    MerkleTree::new(values_range(gate_outputs, 0, challenge as usize)?)
+}
+
+// Step 8a with the Merkle tree of (at least the first `challenge`) gate outputs already built,
+// e.g. the one a vendor keeps during the dispute
+pub(crate) fn final_step_v2_with_tree(
+    circuit: &CompiledCircuitV2,
+    evaluated: &EvaluatedCircuitV2,
+    tree: &MerkleTree,
+    ct: &[u8],
+    challenge: u32,
+) -> SoxResult<FinalStepV2> {
+    // Split ciphertext into blocks
+    let ct_blocks = ct_blocks_v2(ct)?;
+
+    let num_blocks = circuit.num_blocks;
+    // Challenge is now 1-indexed from contract (matching paper), so convert to 0-indexed for array access
+    let gate_idx = (challenge as usize)
+        .checked_sub(1)
+        .ok_or_else(|| SoxError::Index("challenges are 1-indexed, got 0".into()))?;
+    let gate = gate_at(&circuit.gates, gate_idx)?;
+    let (s_in_l, not_in_l_minus_m) = split_sons_indices_v2(&gate.sons, num_blocks);
+
+    // Get evaluated sons
+    // For V2, evaluated.values contains [inputs (num_blocks), gate_outputs (num_gates)]
+    // So gate outputs start at index num_blocks
+    let gate_outputs = values_range(
+        &evaluated.values,
+        num_blocks as usize,
+        evaluated.values.len(),
+    )?;
+    let values = get_evaluated_sons_v2(gate, gate_outputs, &ct_blocks)?;
+
+    // Compute accumulator
+    // For V2, values start at num_blocks (inputs), then gates are evaluated after
+    // Challenge is 1-indexed (matching paper notation), so we need values from num_blocks to num_blocks + challenge - 1 (inclusive)
+    let curr_acc = tree.prefix_root(challenge as usize)?.to_vec();
+
+    // Generate proofs
+    // For V2, we need to encode all gates for proof1
+    // prove() expects 0-indexed indices, so we pass gate_idx (challenge - 1)
+    let encoded_gates: Vec<Vec<u8>> = circuit
+        .gates
+        .iter()
+        .map(|g| g.encode())
+        .collect::<SoxResult<_>>()?;
+    let proof1 = prove(&encoded_gates, &[gate_idx as u32])?;
+
+    // ⚠️ FIX: Le root hCt est calculé AVEC IV (via acc_ct qui utilise split_ct_blocks)
+    // Donc proof2 doit être généré AVEC IV pour correspondre au root
+    // Les indices dans s_in_l sont pour ct_blocks (sans IV), donc on doit les décaler de +1
+    let mut ct_blocks_with_iv = Vec::new();
+    ct_blocks_with_iv.push(ct[..16].to_vec()); // IV comme premier bloc
+    ct_blocks_with_iv.extend_from_slice(&ct_blocks); // Ajouter les blocs de données
+
+    // Décaler les indices de +1 pour correspondre aux nouveaux indices avec IV
+    let s_in_l_with_iv: Vec<u32> = s_in_l.iter().map(|&idx| idx + 1).collect();
+    let proof2 = prove(&ct_blocks_with_iv, &s_in_l_with_iv)?;
+
+    let proof3 = tree.prove_prefix(gate_idx, &not_in_l_minus_m)?;
+    // proof_ext must use the same range as curr_acc to prove extension correctly
+    // It proves that the last element (gate challenge) is an extension of the previous accumulator
+    let proof_ext = tree.prove_ext(challenge as usize)?;
+
+    // Encode gate to 64 bytes
+    let gate_bytes = gate.encode()?;
+
+    Ok(FinalStepV2 {
+        gate_bytes,
+        values,
+        curr_acc,
+        proof1: to_proof(proof1),
+        proof2: to_proof(proof2),
+        proof3,
+        proof_ext,
+    })
+}
+
+/// Computes proofs for step 8b (V2) - corresponds to Step 8b in paper (Section F.2).
+///
+/// # Arguments
+/// * `circuit_bytes` - Serialized V2 circuit bytes
+/// * `evaluated_circuit_bytes` - Serialized evaluated V2 circuit bytes
+/// * `ct` - Ciphertext bytes
+/// * `challenge` - Challenge point in the circuit (1-indexed gate index, matching paper notation)
+///
+/// # Returns
+/// A `FinalStepV2` containing the 64-byte encoded gate, the values of its sons, the current
+/// accumulator value and the proofs (proof1, proof2, proof_ext). `proof3` is always empty.
+pub fn compute_proofs_left_v2(
+    circuit_bytes: &[u8],
+    evaluated_circuit_bytes: &[u8],
+    ct: &[u8],
+    challenge: u32,
+) -> SoxResult<FinalStepV2> {
+    final_step_left_v2(
+        &CompiledCircuitV2::from_bytes(circuit_bytes)?,
+        &EvaluatedCircuitV2::from_bytes(evaluated_circuit_bytes)?,
+        ct,
+        challenge,
+    )
+}
+
+// Step 8b on a decoded V2 circuit, `challenge` being a 1-indexed gate index
+pub(crate) fn final_step_left_v2(
+    circuit: &CompiledCircuitV2,
+    evaluated: &EvaluatedCircuitV2,
+    ct: &[u8],
+    challenge: u32,
+) -> SoxResult<FinalStepV2> {
+    let tree = prefix_tree_v2(circuit, evaluated, challenge)?;
+    final_step_left_v2_with_tree(circuit, evaluated, &tree, ct, challenge)
+}
+
+// Step 8b with the Merkle tree of (at least the first `challenge`) gate outputs already built
+pub(crate) fn final_step_left_v2_with_tree(
+    circuit: &CompiledCircuitV2,
+    evaluated: &EvaluatedCircuitV2,
+    tree: &MerkleTree,
+    ct: &[u8],
+    challenge: u32,
+) -> SoxResult<FinalStepV2> {
+    // Split ciphertext into blocks (SANS IV, comme compute_proofs_v2)
+    // ⚠️ FIX: Aligner avec compute_proofs_v2 qui utilise ct_blocks SANS IV pour proof2
+    // Le root hCt est calculé AVEC IV (via acc_ct qui utilise split_ct_blocks),
+    // mais les indices dans nonConstantSons (Solidity) sont pour un tableau SANS IV.
+    // Donc proof2 doit être généré SANS IV pour correspondre aux indices Solidity.
+    let ct_blocks = ct_blocks_v2(ct)?;
+
+    let num_blocks = circuit.num_blocks;
+    // Challenge is now 1-indexed from contract (matching paper), so convert to 0-indexed for array access
+    // For Step 8b, challenge = 1 (corresponds to i = 1 in paper, first gate)
+    let gate_idx = (challenge as usize)
+        .checked_sub(1)
+        .ok_or_else(|| SoxError::Index("challenges are 1-indexed, got 0".into()))?;
+    let gate = gate_at(&circuit.gates, gate_idx)?;
+
+    // Get evaluated sons (utilise ct_blocks SANS IV, comme compute_proofs_v2)
+    // For V2, evaluated.values contains [inputs (num_blocks), gate_outputs (num_gates)]
+    // So gate outputs start at index num_blocks
+    let gate_outputs = values_range(
+        &evaluated.values,
+        num_blocks as usize,
+        evaluated.values.len(),
+    )?;
+    let values = get_evaluated_sons_v2(gate, gate_outputs, &ct_blocks)?;
+
+    // Compute accumulator
+    // For V2, values start at num_blocks (inputs), then gates are evaluated after
+    // Challenge is 1-indexed (matching paper notation), so we need values from num_blocks to num_blocks + challenge - 1 (inclusive)
+    // For challenge = 1 (Step 8b): accumulate only first gate [num_blocks]
+    let curr_acc = tree.prefix_root(challenge as usize)?.to_vec();
+
+    // Generate proofs
+    // prove() expects 0-indexed indices, so we pass gate_idx (challenge - 1)
+    let encoded_gates: Vec<Vec<u8>> = circuit
+        .gates
+        .iter()
+        .map(|g| g.encode())
+        .collect::<SoxResult<_>>()?;
+    let proof1 = prove(&encoded_gates, &[gate_idx as u32])?;
+
+    // For proof2, we need to convert negative sons to block indices
+    // ⚠️ FIX: Utiliser split_sons_indices_v2 comme dans compute_proofs_v2 pour vérifier la validité des indices
+    // Le problème était que compute_proofs_left_v2 utilisait (-s - 1) directement sans vérifier
+    // si ct_idx >= 1 && ct_idx <= num_blocks, ce qui peut inclure des indices invalides
+    // compute_proofs_v2 utilise split_sons_indices_v2 qui filtre les indices invalides
+    let (s_in_l, _) = split_sons_indices_v2(&gate.sons, num_blocks);
+
+    // ⚠️ FIX CRITIQUE: Le root hCt est calculé AVEC IV (via acc_ct qui utilise split_ct_blocks)
+    // Donc proof2 doit être généré AVEC IV pour correspondre au root
+    // Les indices dans s_in_l sont pour ct_blocks (sans IV), donc on doit les décaler de +1
+    let mut ct_blocks_with_iv = Vec::new();
+    ct_blocks_with_iv.push(ct[..16].to_vec()); // IV comme premier bloc
+    ct_blocks_with_iv.extend_from_slice(&ct_blocks); // Ajouter les blocs de données
+
+    // Décaler les indices de +1 pour correspondre aux nouveaux indices avec IV
+    let s_in_l_with_iv: Vec<u32> = s_in_l.iter().map(|&idx| idx + 1).collect();
+    let proof2 = prove(&ct_blocks_with_iv, &s_in_l_with_iv)?;
+
+    let proof_ext = tree.prove_ext(1)?;
+
+    // Encode gate to 64 bytes
+    let gate_bytes = gate.encode()?;
+
+    Ok(FinalStepV2 {
+        gate_bytes,
+        values,
+        curr_acc,
+        proof1: to_proof(proof1),
+        proof2: to_proof(proof2),
+        proof3: vec![],
+        proof_ext,
+    })
+}
+
+/// Computes the proof for step 8c (V2) - corresponds to Step 8c in paper (Section F.2).
+///
+/// # Arguments
+/// * `evaluated_circuit_bytes` - Serialized evaluated V2 circuit bytes
+/// * `num_blocks` - Number of blocks for the ciphertext
+/// * `num_gates` - Total number of gates in the circuit (n in paper notation)
+///
+/// # Returns
+/// The proof that val(n), the output of the last gate, belongs to the evaluated circuit
+pub fn compute_proof_right_v2(
+    evaluated_circuit_bytes: &[u8],
+    num_blocks: u32,
+    num_gates: u32,
+) -> SoxResult<Proof> {
+    proof_right_v2(
+        &EvaluatedCircuitV2::from_bytes(evaluated_circuit_bytes)?,
+        num_blocks,
+        num_gates,
+    )
+}
+
+// Step 8c on a decoded V2 evaluated circuit
+pub(crate) fn proof_right_v2(
+    evaluated: &EvaluatedCircuitV2,
+    num_blocks: u32,
+    num_gates: u32,
+) -> SoxResult<Proof> {
+    // For V2, evaluated.values contains [inputs (num_blocks), gate_outputs (num_gates)]
+    // So gate outputs start at index num_blocks
+    let num_blocks_usize = num_blocks as usize;
+    if num_blocks_usize >= evaluated.values.len() {
+        return Err(SoxError::Index(format!(
+            "num_blocks ({}) is greater than or equal to evaluated.values.len() ({})",
+            num_blocks_usize,
+            evaluated.values.len()
+        )));
+    }
+
+    let gate_outputs = &evaluated.values[num_blocks_usize..];
+
+    // Verify that gate_outputs length matches num_gates (should always be true)
+    // This is a sanity check to catch bugs early
+    if gate_outputs.len() != num_gates as usize {
+        return Err(SoxError::CircuitShape(format!(
+            "mismatch: gate_outputs.len() ({}) != num_gates ({})",
+            gate_outputs.len(),
+            num_gates
+        )));
+    }
+
+    // The last gate is at index num_gates - 1 (0-indexed in gate_outputs array)
+    // This must match the index used in submitCommitmentRight: idxArr[0] = numGates - 1
+    let last_gate_idx = num_gates - 1;
+
+    Ok(to_proof(prove(gate_outputs, &[last_gate_idx])?))
+}
+
+// ####################################
+// ###    DISK-BACKED VENDOR (V2)   ###
+// ####################################
+
+/// Computes the answer to a challenge (V2) against a disk-backed store, like `hpre_v2` but
+/// without loading the evaluated circuit: only O(log n) nodes of the stored tree are read.
+///
+/// # Arguments
+/// * `store` - Disk-backed store of the vendor's evaluation
+/// * `challenge` - Challenge issued by the smart contract (1-indexed gate index)
+///
+/// # Returns
+/// The response to the challenge (32-byte accumulator hash), or `SoxError::Index` if the
+/// challenge is 0 or past the last gate
+#[cfg(not(target_arch = "wasm32"))]
+pub fn hpre_v2_mmap(store: &MmapStoreV2, challenge: usize) -> SoxResult<Vec<u8>> {
+    if challenge == 0 {
+        return Err(SoxError::Index("challenges are 1-indexed, got 0".into()));
+    }
+    Ok(store.outputs().prefix_root(challenge)?.to_vec())
+}
+
+/// Computes proofs for step 8a (V2) against a disk-backed store, like `compute_proofs_v2`.
+///
+/// # Arguments
+/// * `circuit` - The V2 circuit the store was written for
+/// * `store` - Disk-backed store of the vendor's evaluation
+/// * `challenge` - Challenge point in the circuit (1-indexed gate index)
+///
+/// # Returns
+/// The same `FinalStepV2` as `compute_proofs_v2`
+#[cfg(not(target_arch = "wasm32"))]
+pub fn compute_proofs_v2_mmap(
+    circuit: &CompiledCircuitV2,
+    store: &MmapStoreV2,
+    challenge: u32,
+) -> SoxResult<FinalStepV2> {
+    final_step_v2_mmap(circuit, store, challenge, false)
+}
+
+/// Computes proofs for step 8b (V2) against a disk-backed store, like `compute_proofs_left_v2`.
+///
+/// # Arguments
+/// * `circuit` - The V2 circuit the store was written for
+/// * `store` - Disk-backed store of the vendor's evaluation
+/// * `challenge` - Challenge point in the circuit (1-indexed gate index)
+///
+/// # Returns
+/// The same `FinalStepV2` as `compute_proofs_left_v2`
+#[cfg(not(target_arch = "wasm32"))]
+pub fn compute_proofs_left_v2_mmap(
+    circuit: &CompiledCircuitV2,
+    store: &MmapStoreV2,
+    challenge: u32,
+) -> SoxResult<FinalStepV2> {
+    final_step_v2_mmap(circuit, store, challenge, true)
+}
+
+/// Computes the proof for step 8c (V2) against a disk-backed store, like `compute_proof_right_v2`.
+///
+/// # Arguments
+/// * `store` - Disk-backed store of the vendor's evaluation
+///
+/// # Returns
+/// The proof that val(n), the output of the last gate, belongs to the evaluated circuit, or
+/// `SoxError::Index` if the store has no gate
+#[cfg(not(target_arch = "wasm32"))]
+pub fn compute_proof_right_v2_mmap(store: &MmapStoreV2) -> SoxResult<Proof> {
+    let last_gate = store
+        .num_gates()
+        .checked_sub(1)
+        .ok_or_else(|| SoxError::Index("the store has no gate".into()))?;
+    store.outputs().prove(&[last_gate as u32])
+}
+
+// Step 8a, or step 8b if `left`, with every value and proof read from the store
+#[cfg(not(target_arch = "wasm32"))]
+fn final_step_v2_mmap(
+    circuit: &CompiledCircuitV2,
+    store: &MmapStoreV2,
+    challenge: u32,
+    left: bool,
+) -> SoxResult<FinalStepV2> {
+    let num_blocks = store.num_blocks();
+    let gate_idx = (challenge as usize)
+        .checked_sub(1)
+        .ok_or_else(|| SoxError::Index("challenges are 1-indexed, got 0".into()))?;
+    let gate = gate_at(&circuit.gates, gate_idx)?;
+    let (s_in_l, not_in_l_minus_m) = split_sons_indices_v2(&gate.sons, circuit.num_blocks);
+
+    let stored = store.values();
+    let values = sons_values_v2(
+        gate,
+        |i| stored.get(i).filter(|_| i < num_blocks),
+        |i| stored.get(num_blocks + i),
+    )?;
+
+    let outputs = store.outputs();
+    let curr_acc = outputs.prefix_root(challenge as usize)?.to_vec();
+    let proof1 = store.gates().prove(&[gate_idx as u32])?;
+    // The ciphertext tree starts with the IV, hence the +1 (see `final_step_v2_with_tree`)
+    let s_in_l_with_iv: Vec<u32> = s_in_l.iter().map(|&idx| idx + 1).collect();
+    let proof2 = store.ct().prove(&s_in_l_with_iv)?;
+    let (proof3, proof_ext) = if left {
+        (vec![], outputs.prove_ext(1)?)
+    } else {
+        (
+            outputs.prove_prefix(gate_idx, &not_in_l_minus_m)?,
+            outputs.prove_ext(challenge as usize)?,
+        )
+    };
+
+    Ok(FinalStepV2 {
+        gate_bytes: gate.encode()?,
+        values,
+        curr_acc,
+        proof1,
+        proof2,
+        proof3,
+        proof_ext,
+    })
+}
+
+// =================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_native_proofs_v2() {
+        let key = [7u8; 16];
+        let mut file = vec![3u8; 300];
+        let precontract = compute_precontract_values_v2(&mut file, &key).unwrap();
+        let evaluated =
+            evaluate_circuit_v2(&precontract.circuit_bytes, &precontract.ct, &key).unwrap();
+        let evaluated_bytes = evaluated.to_bytes();
+        let num_gates = precontract.num_gates;
+
+        let step = compute_proofs_v2(
+            &precontract.circuit_bytes,
+            &evaluated_bytes,
+            &precontract.ct,
+            num_gates,
+        )
+        .unwrap();
+        assert_eq!(step.gate_bytes.len(), 64);
+        assert_eq!(
+            step.curr_acc,
+            hpre_v2(
+                &evaluated_bytes,
+                precontract.num_blocks as usize,
+                num_gates as usize
+            )
+            .unwrap()
+        );
+        assert!(!step.proof_ext.is_empty());
+        assert!(matches!(
+            hpre_v2(
+                &evaluated_bytes,
+                precontract.num_blocks as usize,
+                num_gates as usize + 1
+            ),
+            Err(SoxError::Index(_))
+        ));
+
+        let left = compute_proofs_left_v2(
+            &precontract.circuit_bytes,
+            &evaluated_bytes,
+            &precontract.ct,
+            1,
+        )
+        .unwrap();
+        assert!(left.proof3.is_empty());
+
+        let right =
+            compute_proof_right_v2(&evaluated_bytes, precontract.num_blocks, num_gates).unwrap();
+        assert!(!right.is_empty());
+    }
+
+    #[test]
+    fn test_mmap_store_matches_in_memory_proofs() {
+        let key = [5u8; 16];
+        let mut file = vec![0xC3u8; 700];
+        let precontract = compute_precontract_values_v2(&mut file, &key).unwrap();
+        let circuit = CompiledCircuitV2::from_bytes(&precontract.circuit_bytes).unwrap();
+        let evaluated = evaluate_compiled_v2(&circuit, &precontract.ct, &key).unwrap();
+        let evaluated_bytes = evaluated.to_bytes();
+        let num_blocks = precontract.num_blocks;
+        let num_gates = precontract.num_gates;
+
+        let dir = std::env::temp_dir().join(format!("sox-mmap-store-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        MmapStoreV2::create(&dir, &circuit, evaluated.values(), &precontract.ct).unwrap();
+        let store = MmapStoreV2::open(&dir, &circuit).unwrap();
+        assert_eq!(store.gates().root().to_vec(), precontract.h_circuit);
+        assert_eq!(store.ct().root().to_vec(), precontract.h_ct);
+
+        for challenge in 1..=num_gates {
+            assert_eq!(
+                hpre_v2_mmap(&store, challenge as usize).unwrap(),
+                hpre_v2(&evaluated_bytes, num_blocks as usize, challenge as usize).unwrap()
+            );
+            assert_eq!(
+                compute_proofs_v2_mmap(&circuit, &store, challenge).unwrap(),
+                compute_proofs_v2(
+                    &precontract.circuit_bytes,
+                    &evaluated_bytes,
+                    &precontract.ct,
+                    challenge
+                )
+                .unwrap(),
+                "challenge {challenge}"
+            );
+        }
+        assert_eq!(
+            compute_proofs_left_v2_mmap(&circuit, &store, 1).unwrap(),
+            compute_proofs_left_v2(
+                &precontract.circuit_bytes,
+                &evaluated_bytes,
+                &precontract.ct,
+                1
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            compute_proof_right_v2_mmap(&store).unwrap(),
+            compute_proof_right_v2(&evaluated_bytes, num_blocks, num_gates).unwrap()
+        );
+        assert!(hpre_v2_mmap(&store, 0).is_err());
+        assert!(matches!(
+            hpre_v2_mmap(&store, num_gates as usize + 1),
+            Err(SoxError::Index(_))
+        ));
+
+        let other = compile_circuit_v2(&[0u8; 16 + 64], &[0u8; 32]).unwrap();
+        assert!(MmapStoreV2::open(&dir, &other).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_native_check_precontract() {
+        let key = [1u8; 16];
+        let mut file = vec![9u8; 200];
+        let precontract = compute_precontract_values(&mut file, &key).unwrap();
+
+        let res = check_precontract(
+            &precontract.description,
+            &precontract.commitment.c,
+            &precontract.commitment.o,
+            &precontract.ct,
+        )
+        .unwrap();
+        assert!(res.success);
+        assert_eq!(res.h_ct, precontract.h_ct);
+        assert_eq!(res.h_circuit, precontract.h_circuit);
+
+        let res = check_precontract(
+            &precontract.description,
+            &[0u8; 32],
+            &precontract.commitment.o,
+            &precontract.ct,
+        )
+        .unwrap();
+        assert!(!res.success);
+    }
+
+    #[test]
+    fn test_native_check_precontract_v2() {
+        let key = [1u8; 16];
+        let file = vec![9u8; 200];
+        let mut ct = Vec::new();
+        let mut builder = PrecontractBuilder::new(&key, &mut ct).unwrap();
+        builder.update(&file).unwrap();
+        let (precontract, _) = builder.finalize().unwrap();
+
+        let res = check_precontract_v2(
+            &precontract.description,
+            &precontract.commitment.c,
+            &precontract.commitment.o,
+            &ct,
+        )
+        .unwrap();
+        assert!(res.success);
+        assert_eq!(res.h_ct, precontract.h_ct);
+        assert_eq!(res.h_circuit, precontract.h_circuit);
+
+        // a V2 precontract does not check as a V1 one
+        let res = check_precontract(
+            &precontract.description,
+            &precontract.commitment.c,
+            &precontract.commitment.o,
+            &ct,
+        )
+        .unwrap();
+        assert!(!res.success);
+
+        let argument = make_argument_v2(
+            ct,
+            &precontract.description,
+            precontract.commitment.o.clone(),
+        )
+        .unwrap();
+        let res = check_argument_v2(
+            &argument,
+            &precontract.commitment.c,
+            &precontract.description,
+            &key,
+        )
+        .unwrap();
+        assert!(res.is_valid);
+        assert!(!res.supports_buyer);
+        assert!(check_argument(
+            &argument,
+            &precontract.commitment.c,
+            &precontract.description,
+            &key
+        )
+        .is_err());
+    }
+}
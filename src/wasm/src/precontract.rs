@@ -0,0 +1,308 @@
+//! Streaming computation of V2 precontracts. `compute_precontract_values_v2` needs the whole file
+//! and the whole ciphertext in memory; `PrecontractBuilder` instead consumes the file chunk by
+//! chunk, writes the ciphertext to a sink and only keeps O(log n) accumulator state, so that
+//! multi-gigabyte files can be listed.
+
+use crate::accumulator::{hash_block64, AccumulatorBuilder};
+use crate::circuits_v2::{acc_circuit_v2, compile_circuit_v2_from_len};
+use crate::commitment::{commit_hashes, Commitment};
+use crate::encryption::Aes128Ctr128BE;
+use crate::error::{SoxError, SoxResult};
+use aes::cipher::{KeyIvInit, StreamCipher};
+use rand::RngCore;
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use std::io::{ErrorKind, Read, Write};
+
+// Size of the ciphertext blocks accumulated in h_ct (see `acc_ct`)
+const BLOCK_SIZE: usize = 64;
+
+// Size of the buffer used by `PrecontractBuilder::read_from`
+const READ_BUFFER_SIZE: usize = 1 << 20;
+
+/// Result of a streamed precontract computation. Same values as a `Precontract`, except for the
+/// ciphertext which has been written to the sink of the builder.
+#[derive(Clone)]
+pub struct StreamedPrecontract {
+    /// Serialized V2 circuit
+    pub circuit_bytes: Vec<u8>,
+
+    /// Description of the original file (SHA-256 of the plaintext)
+    pub description: Vec<u8>,
+
+    /// Result of the accumulator applied on the ciphertext
+    pub h_ct: Vec<u8>,
+
+    /// Result of the accumulator applied on the circuit
+    pub h_circuit: Vec<u8>,
+
+    /// Commitment of the ciphertext and circuit
+    pub commitment: Commitment,
+
+    /// Number of blocks in the ciphertext
+    pub num_blocks: u32,
+
+    /// Number of gates in the circuit
+    pub num_gates: u32,
+
+    /// Number of bytes written to the sink (IV included)
+    pub ct_len: u64,
+}
+
+/// Incremental equivalent of `compute_precontract_values_v2`. The plaintext is fed with
+/// [`update`](Self::update) or [`read_from`](Self::read_from); each chunk is hashed into the
+/// description, encrypted with AES-128-CTR, accumulated into h_ct and written to the sink.
+/// [`finalize`](Self::finalize) then compiles the V2 circuit, which only depends on the IV and the
+/// length of the data, and computes the commitment.
+pub struct PrecontractBuilder<W: Write> {
+    cipher: Aes128Ctr128BE,
+    iv: [u8; 16],
+    description: Sha256,
+    ct_acc: AccumulatorBuilder,
+    pending: Vec<u8>,
+    scratch: Vec<u8>,
+    pt_len: u64,
+    iv_written: bool,
+    sink: W,
+}
+
+impl<W: Write> PrecontractBuilder<W> {
+    /// Creates a builder encrypting with `key` under a random IV. The IV is prepended to the
+    /// ciphertext as in `encrypt_and_prepend_iv`; it is written to the sink with the first bytes of
+    /// the plaintext, so that nothing is written for an empty plaintext.
+    ///
+    /// # Arguments
+    /// * `key` - The encryption key (16 bytes)
+    /// * `sink` - Destination of the ciphertext
+    ///
+    /// # Returns
+    /// The builder, or an error if the key is not 16 bytes long
+    pub fn new(key: &[u8], sink: W) -> SoxResult<Self> {
+        let mut iv = [0u8; 16];
+        rand::rng().fill_bytes(&mut iv);
+        Self::with_iv(key, iv, sink)
+    }
+
+    /// Creates a builder encrypting with `key` under the given IV (see `new`).
+    ///
+    /// # Arguments
+    /// * `key` - The encryption key (16 bytes)
+    /// * `iv` - The IV of the AES-CTR encryption
+    /// * `sink` - Destination of the ciphertext
+    ///
+    /// # Returns
+    /// The builder, or an error if the key is not 16 bytes long
+    pub fn with_iv(key: &[u8], iv: [u8; 16], sink: W) -> SoxResult<Self> {
+        let cipher =
+            Aes128Ctr128BE::new_from_slices(key, &iv).map_err(|_| SoxError::KeyLength {
+                expected: 16,
+                got: key.len(),
+            })?;
+
+        // The IV is the first leaf of the ciphertext accumulator (see `split_ct_blocks`)
+        let mut ct_acc = AccumulatorBuilder::new();
+        ct_acc.push(&iv);
+
+        Ok(Self {
+            cipher,
+            iv,
+            description: Sha256::new(),
+            ct_acc,
+            pending: Vec::with_capacity(BLOCK_SIZE),
+            scratch: Vec::new(),
+            pt_len: 0,
+            iv_written: false,
+            sink,
+        })
+    }
+
+    /// Encrypts the next chunk of the plaintext and writes it to the sink. Chunks can have any
+    /// size.
+    ///
+    /// # Arguments
+    /// * `chunk` - Next bytes of the plaintext
+    ///
+    /// # Returns
+    /// `Ok(())`, or `SoxError::Io` if the sink fails
+    pub fn update(&mut self, chunk: &[u8]) -> SoxResult<()> {
+        if chunk.is_empty() {
+            return Ok(());
+        }
+        if !self.iv_written {
+            self.sink.write_all(&self.iv)?;
+            self.iv_written = true;
+        }
+        self.description.update(chunk);
+        self.pt_len += chunk.len() as u64;
+
+        self.scratch.clear();
+        self.scratch.extend_from_slice(chunk);
+        self.cipher.apply_keystream(&mut self.scratch);
+        self.sink.write_all(&self.scratch)?;
+
+        // Complete the block left over by the previous chunk
+        let mut ct: &[u8] = &self.scratch;
+        if !self.pending.is_empty() {
+            let missing = usize::min(BLOCK_SIZE - self.pending.len(), ct.len());
+            self.pending.extend_from_slice(&ct[..missing]);
+            ct = &ct[missing..];
+            if self.pending.len() == BLOCK_SIZE {
+                self.ct_acc.push(&self.pending);
+                self.pending.clear();
+            }
+        }
+
+        // Hash the full blocks in parallel, then keep the tail for the next chunk
+        let full = ct.len() - ct.len() % BLOCK_SIZE;
+        let leaves: Vec<[u8; 32]> = ct[..full]
+            .par_chunks(BLOCK_SIZE)
+            .map(hash_block64)
+            .collect();
+        for leaf in leaves {
+            self.ct_acc.push_leaf(leaf);
+        }
+        self.pending.extend_from_slice(&ct[full..]);
+
+        Ok(())
+    }
+
+    /// Feeds the whole content of `reader` to the builder.
+    ///
+    /// # Arguments
+    /// * `reader` - Source of the plaintext
+    ///
+    /// # Returns
+    /// The number of bytes read, or `SoxError::Io` if the reader or the sink fails
+    pub fn read_from<R: Read>(&mut self, mut reader: R) -> SoxResult<u64> {
+        let mut buf = vec![0u8; READ_BUFFER_SIZE];
+        let mut total = 0u64;
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => return Ok(total),
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            self.update(&buf[..n])?;
+            total += n as u64;
+        }
+    }
+
+    /// Completes the precontract: compiles the V2 circuit, computes the accumulators and the
+    /// commitment, and flushes the sink.
+    ///
+    /// # Returns
+    /// The precontract values and the sink, or `SoxError::InvalidInput` if no data was fed, in
+    /// which case nothing has been written to the sink
+    pub fn finalize(mut self) -> SoxResult<(StreamedPrecontract, W)> {
+        if self.pt_len == 0 {
+            return Err(SoxError::InvalidInput(
+                "the plaintext must contain at least 1 byte of data".into(),
+            ));
+        }
+        if !self.pending.is_empty() {
+            self.ct_acc.push(&self.pending);
+        }
+        self.sink.flush()?;
+
+        let description = self.description.finalize().to_vec();
+        let pt_len = usize::try_from(self.pt_len).map_err(|_| {
+            SoxError::InvalidInput(format!("data too large: {} bytes", self.pt_len))
+        })?;
+        let circuit = compile_circuit_v2_from_len(&self.iv, pt_len, &description)?;
+        let h_ct = self.ct_acc.root();
+        let h_circuit = acc_circuit_v2(&circuit.gates)?;
+        let commitment = commit_hashes(&h_circuit, &h_ct);
+
+        let precontract = StreamedPrecontract {
+            circuit_bytes: circuit.to_bytes(),
+            description,
+            h_ct,
+            h_circuit,
+            commitment,
+            num_blocks: circuit.num_blocks,
+            num_gates: circuit.gates.len() as u32,
+            ct_len: 16 + self.pt_len,
+        };
+
+        Ok((precontract, self.sink))
+    }
+}
+
+// =================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accumulator::acc_ct;
+    use crate::circuits_v2::compile_circuit_v2;
+    use crate::commitment::open_commitment_internal;
+    use crate::encryption::decrypt;
+    use crate::sha256::sha256;
+
+    #[test]
+    fn test_builder_matches_in_memory_path() {
+        let key = [7u8; 16];
+        let mut rng = rand::rng();
+        for (len, chunk_size) in [
+            (1, 1),
+            (55, 7),
+            (56, 64),
+            (64, 10),
+            (1000, 100),
+            (4099, 333),
+        ] {
+            let mut data = vec![0u8; len];
+            rng.fill_bytes(&mut data);
+
+            let mut builder = PrecontractBuilder::new(&key, Vec::new()).unwrap();
+            for chunk in data.chunks(chunk_size) {
+                builder.update(chunk).unwrap();
+            }
+            let (pre, ct) = builder.finalize().unwrap();
+
+            assert_eq!(decrypt(&ct, &key).unwrap(), data);
+            assert_eq!(pre.ct_len, ct.len() as u64);
+            assert_eq!(pre.description, sha256(&data));
+            assert_eq!(pre.h_ct, acc_ct(&ct, 64).unwrap());
+
+            let circuit = compile_circuit_v2(&ct, &pre.description).unwrap();
+            assert_eq!(pre.circuit_bytes, circuit.to_bytes());
+            assert_eq!(pre.h_circuit, acc_circuit_v2(&circuit.gates).unwrap());
+            assert_eq!(pre.num_blocks, circuit.num_blocks);
+            assert_eq!(pre.num_gates, circuit.gates.len() as u32);
+
+            let opened = open_commitment_internal(&pre.commitment.c, &pre.commitment.o).unwrap();
+            assert_eq!(opened, [pre.h_circuit, pre.h_ct].concat());
+        }
+    }
+
+    #[test]
+    fn test_builder_read_from_and_empty_input() {
+        let key = [1u8; 16];
+        let data = vec![42u8; 3 * READ_BUFFER_SIZE / 2];
+
+        let mut streamed = PrecontractBuilder::with_iv(&key, [3u8; 16], Vec::new()).unwrap();
+        assert_eq!(streamed.read_from(&data[..]).unwrap(), data.len() as u64);
+        let (streamed, ct) = streamed.finalize().unwrap();
+
+        let mut chunked = PrecontractBuilder::with_iv(&key, [3u8; 16], Vec::new()).unwrap();
+        chunked.update(&data).unwrap();
+        let (chunked, ct2) = chunked.finalize().unwrap();
+        assert_eq!(ct, ct2);
+        assert_eq!(streamed.h_ct, chunked.h_ct);
+        assert_eq!(streamed.circuit_bytes, chunked.circuit_bytes);
+
+        // nothing is written for an empty plaintext
+        let mut sink = Vec::new();
+        let mut empty = PrecontractBuilder::new(&key, &mut sink).unwrap();
+        empty.update(&[]).unwrap();
+        assert_eq!(empty.read_from(&[][..]).unwrap(), 0);
+        assert!(matches!(empty.finalize(), Err(SoxError::InvalidInput(_))));
+        assert!(sink.is_empty());
+        assert!(matches!(
+            PrecontractBuilder::new(&[0u8; 3], Vec::new()),
+            Err(SoxError::KeyLength { .. })
+        ));
+    }
+}
@@ -0,0 +1,539 @@
+//! Stateful helpers for the parties of a dispute. `BuyerDisputeSession` and
+//! `VendorDisputeSession` keep the Merkle tree of the party's gate outputs in memory, so that
+//! every challenge is answered in O(log n) instead of deserializing the evaluated circuit and
+//! rebuilding the accumulator like `hpre_v2`, and follow the bisection bounds of the contract.
+
+use crate::accumulator::{proof_to_js_array, MerkleLayers, MerkleTree};
+use crate::circuits_v2::CompiledCircuitV2;
+use crate::dispute::{Bisection, DisputeState};
+use crate::error::{SoxError, SoxResult};
+use crate::native::{
+    evaluate_compiled_v2, final_step_left_v2_with_tree, final_step_v2_with_tree, proof_right_v2,
+    FinalStepV2, Proof,
+};
+use crate::{EvaluatedCircuitV2, FinalStepComponentsV2};
+use js_sys::Array;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+// hpre(challenge) from the tree of the gate outputs, or `SoxError::Index` if the challenge is not
+// a 1-indexed leaf
+fn hpre(tree: &MerkleTree, challenge: u32) -> SoxResult<[u8; 32]> {
+    let num_gates = tree.num_leaves();
+    if challenge == 0 || challenge as usize > num_gates {
+        return Err(SoxError::Index(format!(
+            "challenge {challenge} out of bounds (gates 1 to {num_gates})"
+        )));
+    }
+    tree.prefix_root(challenge as usize)
+}
+
+// Gate outputs of an evaluated V2 circuit, or `SoxError::CircuitShape` if there are none
+fn gate_outputs(evaluated: &EvaluatedCircuitV2, num_blocks: u32) -> SoxResult<&[Vec<u8>]> {
+    match evaluated.values().get(num_blocks as usize..) {
+        Some(outputs) if !outputs.is_empty() => Ok(outputs),
+        _ => Err(SoxError::CircuitShape(format!(
+            "no gate output after the {num_blocks} input blocks"
+        ))),
+    }
+}
+
+/// Buyer side of a dispute on a V2 circuit. The session answers the challenges from the buyer's
+/// evaluation of the circuit and updates the bisection bounds with the vendor's opinions, like
+/// `giveOpinion` in `DisputeSOX.sol`, until the game has narrowed to a single gate.
+#[wasm_bindgen]
+pub struct BuyerDisputeSession {
+    tree: MerkleTree,
+    bisection: Bisection,
+    state: DisputeState,
+}
+
+impl BuyerDisputeSession {
+    /// Creates a session from a circuit, the ciphertext and the key.
+    ///
+    /// # Arguments
+    /// * `circuit` - The V2 circuit of the contract
+    /// * `ct` - Ciphertext bytes (IV included)
+    /// * `key` - AES key
+    ///
+    /// # Returns
+    /// The session, waiting for the buyer's response to the first challenge
+    pub fn from_circuit(circuit: &CompiledCircuitV2, ct: &[u8], key: &[u8]) -> SoxResult<Self> {
+        Self::from_evaluated(&evaluate_compiled_v2(circuit, ct, key)?, circuit.num_blocks)
+    }
+
+    /// Creates a session from an evaluated circuit.
+    ///
+    /// # Arguments
+    /// * `evaluated` - The buyer's evaluation of the circuit
+    /// * `num_blocks` - Number of blocks of the ciphertext
+    ///
+    /// # Returns
+    /// The session, or `SoxError::CircuitShape` if the evaluated circuit has no gate output
+    pub fn from_evaluated(evaluated: &EvaluatedCircuitV2, num_blocks: u32) -> SoxResult<Self> {
+        let gate_outputs = gate_outputs(evaluated, num_blocks)?;
+        Ok(Self {
+            tree: MerkleTree::new(gate_outputs)?,
+            bisection: Bisection::new(gate_outputs.len() as u32),
+            state: DisputeState::ChallengeBuyer,
+        })
+    }
+
+    /// Computes hpre(challenge), the accumulator of the outputs of gates 1 to `challenge`.
+    ///
+    /// # Arguments
+    /// * `challenge` - 1-indexed gate
+    ///
+    /// # Returns
+    /// The response, or `SoxError::Index` if the challenge is not a gate of the circuit
+    pub fn hpre(&self, challenge: u32) -> SoxResult<[u8; 32]> {
+        hpre(&self.tree, challenge)
+    }
+}
+
+#[wasm_bindgen]
+impl BuyerDisputeSession {
+    /// Creates a session from a serialized V2 circuit, the ciphertext and the key.
+    ///
+    /// # Arguments
+    /// * `circuit_bytes` - Serialized V2 circuit bytes
+    /// * `ct` - Ciphertext bytes (IV included)
+    /// * `key` - AES key
+    ///
+    /// # Returns
+    /// The session, waiting for the buyer's response to the first challenge
+    pub fn new(circuit_bytes: &[u8], ct: &[u8], key: &[u8]) -> SoxResult<BuyerDisputeSession> {
+        Self::from_circuit(&CompiledCircuitV2::from_bytes(circuit_bytes)?, ct, key)
+    }
+
+    /// Returns the number of gates of the circuit
+    pub fn num_gates(&self) -> u32 {
+        self.bisection.num_gates
+    }
+
+    /// Returns the lower bound of the bisection
+    pub fn a(&self) -> u32 {
+        self.bisection.a
+    }
+
+    /// Returns the upper bound of the bisection
+    pub fn b(&self) -> u32 {
+        self.bisection.b
+    }
+
+    /// Returns the current challenge (1-indexed gate)
+    pub fn chall(&self) -> u32 {
+        self.bisection.chall
+    }
+
+    /// Returns `ChallengeBuyer` during the bisection, then the state in which the contract waits
+    /// for the vendor's final step
+    pub fn state(&self) -> DisputeState {
+        self.state
+    }
+
+    /// Returns the response to the current challenge, or `SoxError::Index` once the bisection
+    /// has ended on the right edge (challenge `num_gates + 1`), where there is nothing to respond
+    pub fn response(&self) -> SoxResult<Vec<u8>> {
+        self.response_at(self.bisection.chall)
+    }
+
+    /// Returns the response to any challenge, see `hpre`
+    pub fn response_at(&self, challenge: u32) -> SoxResult<Vec<u8>> {
+        Ok(self.hpre(challenge)?.to_vec())
+    }
+
+    /// Updates the bounds with the vendor's opinion on the response to the current challenge.
+    ///
+    /// # Arguments
+    /// * `vendor_agrees` - `true` if the vendor agreed with the response
+    ///
+    /// # Returns
+    /// The new state: `ChallengeBuyer` if the buyer must respond to a new challenge, otherwise the
+    /// state of the final step. Returns `SoxError::InvalidInput` if the bisection is already over.
+    pub fn give_opinion(&mut self, vendor_agrees: bool) -> SoxResult<DisputeState> {
+        if self.state != DisputeState::ChallengeBuyer {
+            return Err(SoxError::InvalidInput(format!(
+                "the bisection is over, the dispute is in state {:?}",
+                self.state
+            )));
+        }
+        self.state = self.bisection.narrow(vendor_agrees)?;
+        Ok(self.state)
+    }
+
+    /// Returns the gate on which the vendor must prove its evaluation, once the bisection is over
+    pub fn final_gate(&self) -> Option<u32> {
+        self.state.submit_function().map(|_| self.bisection.chall)
+    }
+
+    /// Returns the name of the contract function the vendor must call, once the bisection is over
+    pub fn submit_function(&self) -> Option<String> {
+        self.state.submit_function().map(String::from)
+    }
+
+    /// Restarts the bisection from the initial bounds, as the contract does when a dispute
+    /// sponsor takes over (step 9)
+    pub fn restart(&mut self) {
+        self.bisection = Bisection::new(self.bisection.num_gates);
+        self.state = DisputeState::ChallengeBuyer;
+    }
+}
+
+/// Final step of the vendor, to submit with the contract function given by the variant
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VendorFinalStep {
+    /// Components of step 8a, for `submitCommitment`
+    Commitment(FinalStepV2),
+
+    /// Components of step 8b, for `submitCommitmentLeft`
+    CommitmentLeft(FinalStepV2),
+
+    /// Proof of step 8c, for `submitCommitmentRight`
+    CommitmentRight(Proof),
+}
+
+/// Vendor side of a dispute on a V2 circuit. The session compares each response of the buyer
+/// with the vendor's own prefix accumulator to decide the opinion to give in `giveOpinion`,
+/// follows the bisection bounds, and computes the final step as soon as the game has narrowed to
+/// a single gate.
+#[wasm_bindgen]
+pub struct VendorDisputeSession {
+    circuit: CompiledCircuitV2,
+    evaluated: EvaluatedCircuitV2,
+    ct: Vec<u8>,
+    tree: MerkleTree,
+    bisection: Bisection,
+    state: DisputeState,
+    final_step: Option<VendorFinalStep>,
+}
+
+impl VendorDisputeSession {
+    /// Creates a session from a circuit, the ciphertext and the key.
+    ///
+    /// # Arguments
+    /// * `circuit` - The V2 circuit of the contract
+    /// * `ct` - Ciphertext bytes (IV included)
+    /// * `key` - AES key
+    ///
+    /// # Returns
+    /// The session, waiting for the buyer's response to the first challenge
+    pub fn from_circuit(circuit: CompiledCircuitV2, ct: Vec<u8>, key: &[u8]) -> SoxResult<Self> {
+        let evaluated = evaluate_compiled_v2(&circuit, &ct, key)?;
+        let tree = MerkleTree::new(gate_outputs(&evaluated, circuit.num_blocks)?)?;
+        Ok(Self {
+            bisection: Bisection::new(circuit.gates.len() as u32),
+            circuit,
+            evaluated,
+            ct,
+            tree,
+            state: DisputeState::ChallengeBuyer,
+            final_step: None,
+        })
+    }
+
+    /// Computes hpre(challenge) from the vendor's evaluation of the circuit.
+    ///
+    /// # Arguments
+    /// * `challenge` - 1-indexed gate
+    ///
+    /// # Returns
+    /// The expected response, or `SoxError::Index` if the challenge is not a gate of the circuit
+    pub fn hpre(&self, challenge: u32) -> SoxResult<[u8; 32]> {
+        hpre(&self.tree, challenge)
+    }
+
+    /// Returns the final step once the bisection is over
+    pub fn final_step(&self) -> Option<&VendorFinalStep> {
+        self.final_step.as_ref()
+    }
+
+    // Computes the final step matching the state the bisection ended in
+    fn compute_final_step(&self, state: DisputeState, chall: u32) -> SoxResult<VendorFinalStep> {
+        Ok(match state {
+            DisputeState::WaitVendorData => VendorFinalStep::Commitment(final_step_v2_with_tree(
+                &self.circuit,
+                &self.evaluated,
+                &self.tree,
+                &self.ct,
+                chall,
+            )?),
+            DisputeState::WaitVendorDataLeft => {
+                VendorFinalStep::CommitmentLeft(final_step_left_v2_with_tree(
+                    &self.circuit,
+                    &self.evaluated,
+                    &self.tree,
+                    &self.ct,
+                    chall,
+                )?)
+            }
+            DisputeState::WaitVendorDataRight => VendorFinalStep::CommitmentRight(proof_right_v2(
+                &self.evaluated,
+                self.circuit.num_blocks,
+                self.bisection.num_gates,
+            )?),
+            state => {
+                return Err(SoxError::InvalidInput(format!(
+                    "no final step in state {state:?}"
+                )))
+            }
+        })
+    }
+}
+
+#[wasm_bindgen]
+impl VendorDisputeSession {
+    /// Creates a session from a serialized V2 circuit, the ciphertext and the key.
+    ///
+    /// # Arguments
+    /// * `circuit_bytes` - Serialized V2 circuit bytes
+    /// * `ct` - Ciphertext bytes (IV included)
+    /// * `key` - AES key
+    ///
+    /// # Returns
+    /// The session, waiting for the buyer's response to the first challenge
+    pub fn new(circuit_bytes: &[u8], ct: &[u8], key: &[u8]) -> SoxResult<VendorDisputeSession> {
+        Self::from_circuit(
+            CompiledCircuitV2::from_bytes(circuit_bytes)?,
+            ct.to_vec(),
+            key,
+        )
+    }
+
+    /// Returns the number of gates of the circuit
+    pub fn num_gates(&self) -> u32 {
+        self.bisection.num_gates
+    }
+
+    /// Returns the lower bound of the bisection
+    pub fn a(&self) -> u32 {
+        self.bisection.a
+    }
+
+    /// Returns the upper bound of the bisection
+    pub fn b(&self) -> u32 {
+        self.bisection.b
+    }
+
+    /// Returns the current challenge (1-indexed gate)
+    pub fn chall(&self) -> u32 {
+        self.bisection.chall
+    }
+
+    /// Returns `ChallengeBuyer` during the bisection, then the state in which the contract waits
+    /// for the vendor's final step
+    pub fn state(&self) -> DisputeState {
+        self.state
+    }
+
+    /// Decides the opinion on the buyer's latest response and updates the bounds accordingly.
+    /// When the bisection is over, the final step is computed and available with
+    /// `final_step_components` or `proof_right`.
+    ///
+    /// # Arguments
+    /// * `response` - The buyer's response to the current challenge (`getLatestBuyerResponse`)
+    ///
+    /// # Returns
+    /// The opinion to give in `giveOpinion`: `true` if the response matches the vendor's own
+    /// hpre(chall). Returns `SoxError::InvalidInput` if the bisection is already over.
+    pub fn opinion(&mut self, response: &[u8]) -> SoxResult<bool> {
+        if self.state != DisputeState::ChallengeBuyer {
+            return Err(SoxError::InvalidInput(format!(
+                "the bisection is over, the dispute is in state {:?}",
+                self.state
+            )));
+        }
+        let agrees = hpre(&self.tree, self.bisection.chall)? == response;
+
+        let mut bisection = self.bisection;
+        let state = bisection.narrow(agrees)?;
+        if state != DisputeState::ChallengeBuyer {
+            self.final_step = Some(self.compute_final_step(state, bisection.chall)?);
+        }
+        self.bisection = bisection;
+        self.state = state;
+        Ok(agrees)
+    }
+
+    /// Returns the gate on which the vendor must prove its evaluation, once the bisection is over
+    pub fn final_gate(&self) -> Option<u32> {
+        self.state.submit_function().map(|_| self.bisection.chall)
+    }
+
+    /// Returns the name of the contract function the vendor must call, once the bisection is over
+    pub fn submit_function(&self) -> Option<String> {
+        self.state.submit_function().map(String::from)
+    }
+
+    /// Returns the components to submit with `submitCommitment` or `submitCommitmentLeft`, once
+    /// the bisection has ended in the corresponding state
+    pub fn final_step_components(&self) -> Option<FinalStepComponentsV2> {
+        match &self.final_step {
+            Some(VendorFinalStep::Commitment(step) | VendorFinalStep::CommitmentLeft(step)) => {
+                Some(step.clone().into())
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the proof to submit with `submitCommitmentRight`, once the bisection has ended in
+    /// the corresponding state
+    pub fn proof_right(&self) -> Option<Array> {
+        match &self.final_step {
+            Some(VendorFinalStep::CommitmentRight(proof)) => Some(proof_to_js_array(proof)),
+            _ => None,
+        }
+    }
+
+    /// Restarts the bisection from the initial bounds, as the contract does when a dispute
+    /// sponsor takes over (step 9)
+    pub fn restart(&mut self) {
+        self.bisection = Bisection::new(self.bisection.num_gates);
+        self.state = DisputeState::ChallengeBuyer;
+        self.final_step = None;
+    }
+}
+
+// =================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dispute::{DisputeSox, HonestVendor};
+    use crate::native::{compute_precontract_values_v2, hpre_values_v2, VendorStrategy};
+
+    #[test]
+    fn test_session_follows_the_contract() {
+        let key = [4u8; 16];
+        let mut data = vec![0x5Au8; 300];
+        let pc = compute_precontract_values_v2(&mut data, &key).unwrap();
+        let circuit = CompiledCircuitV2::from_bytes(&pc.circuit_bytes).unwrap();
+        let evaluated = evaluate_compiled_v2(&circuit, &pc.ct, &key).unwrap();
+
+        let session = BuyerDisputeSession::new(&pc.circuit_bytes, &pc.ct, &key).unwrap();
+        assert_eq!(session.num_gates(), pc.num_gates);
+        for chall in 1..=pc.num_gates {
+            assert_eq!(
+                session.response_at(chall).unwrap(),
+                hpre_values_v2(evaluated.values(), pc.num_blocks as usize, chall as usize).unwrap()
+            );
+        }
+        assert!(session.response_at(0).is_err());
+        assert!(session.response_at(pc.num_gates + 1).is_err());
+
+        // the vendor's opinions are random, the bounds must match those of the contract
+        for seed in 0..20u32 {
+            let mut session = BuyerDisputeSession::new(&pc.circuit_bytes, &pc.ct, &key).unwrap();
+            let mut dispute =
+                DisputeSox::new(pc.num_blocks, pc.num_gates, &pc.commitment.c, &key).unwrap();
+            let mut vendor = HonestVendor::new(
+                circuit.clone(),
+                evaluate_compiled_v2(&circuit, &pc.ct, &key).unwrap(),
+                pc.ct.clone(),
+                pc.commitment.o.clone(),
+            );
+            let mut round = 0;
+            while session.state() == DisputeState::ChallengeBuyer {
+                assert_eq!(session.chall(), dispute.chall());
+                dispute
+                    .respond_challenge(session.response().unwrap().try_into().unwrap())
+                    .unwrap();
+                let agrees = (seed >> (round % 5)) & 1 == 1;
+                dispute.give_opinion(agrees).unwrap();
+                assert_eq!(session.give_opinion(agrees).unwrap(), dispute.state());
+                assert_eq!((session.a(), session.b()), (dispute.a(), dispute.b()));
+                round += 1;
+            }
+            assert_eq!(session.final_gate(), Some(dispute.chall()));
+            assert!(session.give_opinion(true).is_err());
+
+            // the honest vendor can only win when the bisection ended on the right edge
+            let vendor_won = match session.state() {
+                DisputeState::WaitVendorData => dispute
+                    .submit_commitment(
+                        &vendor.opening_value(),
+                        &vendor.final_step(dispute.chall()).unwrap(),
+                    )
+                    .unwrap(),
+                DisputeState::WaitVendorDataLeft => dispute
+                    .submit_commitment_left(
+                        &vendor.opening_value(),
+                        &vendor.final_step_left(dispute.chall()).unwrap(),
+                    )
+                    .unwrap(),
+                DisputeState::WaitVendorDataRight => dispute
+                    .submit_commitment_right(&vendor.proof_right().unwrap())
+                    .unwrap(),
+                state => panic!("unexpected state {state:?}"),
+            };
+            assert_eq!(
+                vendor_won,
+                session.submit_function() == Some("submitCommitmentRight".into())
+            );
+
+            session.restart();
+            assert_eq!(session.chall(), (pc.num_gates + 2) / 2);
+        }
+    }
+
+    #[test]
+    fn test_response_on_the_right_edge() {
+        let key = [8u8; 16];
+        let mut data = vec![0x3Cu8; 120];
+        let pc = compute_precontract_values_v2(&mut data, &key).unwrap();
+        let mut session = BuyerDisputeSession::new(&pc.circuit_bytes, &pc.ct, &key).unwrap();
+
+        // the vendor agrees with every response, so the bisection narrows to the right edge
+        while session.state() == DisputeState::ChallengeBuyer {
+            assert!(session.response().is_ok());
+            session.give_opinion(true).unwrap();
+        }
+        assert_eq!(session.state(), DisputeState::WaitVendorDataRight);
+        assert_eq!(session.chall(), pc.num_gates + 1);
+        assert!(matches!(session.response(), Err(SoxError::Index(_))));
+        assert!(session.response_at(pc.num_gates).is_ok());
+    }
+
+    #[test]
+    fn test_vendor_session_wins_against_lying_buyer() {
+        let key = [6u8; 16];
+        let mut data = vec![0xA5u8; 250];
+        let pc = compute_precontract_values_v2(&mut data, &key).unwrap();
+        let buyer = BuyerDisputeSession::new(&pc.circuit_bytes, &pc.ct, &key).unwrap();
+
+        for wrong_from in 1..=pc.num_gates + 1 {
+            let mut vendor = VendorDisputeSession::new(&pc.circuit_bytes, &pc.ct, &key).unwrap();
+            let mut dispute =
+                DisputeSox::new(pc.num_blocks, pc.num_gates, &pc.commitment.c, &key).unwrap();
+            while dispute.state() == DisputeState::ChallengeBuyer {
+                let chall = dispute.chall();
+                let response = if chall >= wrong_from {
+                    [0x11; 32]
+                } else {
+                    buyer.hpre(chall).unwrap()
+                };
+                dispute.respond_challenge(response).unwrap();
+                let agrees = vendor.opinion(&dispute.buyer_response(chall)).unwrap();
+                assert_eq!(agrees, chall < wrong_from);
+                dispute.give_opinion(agrees).unwrap();
+            }
+            assert_eq!(vendor.state(), dispute.state());
+            assert_eq!(vendor.final_gate(), Some(wrong_from));
+            assert!(vendor.opinion(&[0u8; 32]).is_err());
+
+            let vendor_won = match vendor.final_step().unwrap() {
+                VendorFinalStep::Commitment(step) => {
+                    dispute.submit_commitment(&pc.commitment.o, step)
+                }
+                VendorFinalStep::CommitmentLeft(step) => {
+                    dispute.submit_commitment_left(&pc.commitment.o, step)
+                }
+                VendorFinalStep::CommitmentRight(proof) => dispute.submit_commitment_right(proof),
+            };
+            assert!(vendor_won.unwrap(), "wrong from gate {wrong_from}");
+            assert_eq!(dispute.state(), DisputeState::Complete);
+
+            vendor.restart();
+            assert!(vendor.final_step().is_none());
+        }
+    }
+}
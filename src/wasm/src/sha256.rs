@@ -1,26 +1,26 @@
 use crate::accumulator::uint8_array_to_vec_u8;
-use crate::utils::die;
+use crate::error::{SoxError, SoxResult};
 use js_sys::Uint8Array;
 use sha2::{Digest, Sha256};
 use sha2_compress::{Sha2, SHA256};
 use wasm_bindgen::prelude::wasm_bindgen;
 
 // Converts a byte array into an array of 32-bit unsigned integers
-fn u8_array_to_u32_array(vec: &[u8]) -> [u32; 8] {
+fn u8_array_to_u32_array(vec: &[u8]) -> SoxResult<[u32; 8]> {
     if vec.len() != 32 {
-        die("Input vector must have exactly 32 elements.");
+        return Err(SoxError::InvalidInput(format!(
+            "SHA-256 state must have exactly 32 bytes, got {}",
+            vec.len()
+        )));
     }
 
     let mut res: [u32; 8] = [0; 8];
 
-    for i in 0..8 {
-        res[i] = ((vec[i * 4] as u32) << 24)
-            | ((vec[i * 4 + 1] as u32) << 16)
-            | ((vec[i * 4 + 2] as u32) << 8)
-            | (vec[i * 4 + 3] as u32);
+    for (i, word) in res.iter_mut().enumerate() {
+        *word = u32::from_be_bytes([vec[i * 4], vec[i * 4 + 1], vec[i * 4 + 2], vec[i * 4 + 3]]);
     }
 
-    res
+    Ok(res)
 }
 
 // Converts an array of 32-bit unsigned integers into a byte vector
@@ -47,31 +47,60 @@ fn u32_array_to_u8_vec(array: &[u32; 8]) -> Vec<u8> {
 ///
 /// # Returns
 /// A 32-byte vector containing the compressed result
-pub fn sha256_compress(data: &Vec<&Vec<u8>>) -> Vec<u8> {
+pub fn sha256_compress(data: &[&Vec<u8>]) -> SoxResult<Vec<u8>> {
     if data.len() != 1 && data.len() != 2 {
-        die("Input data for compression must have exactly 1 or 2 elements.");
+        return Err(SoxError::InvalidInput(format!(
+            "input data for compression must have exactly 1 or 2 elements, got {}",
+            data.len()
+        )));
     }
     let prev_hash = if data.len() == 1 {
         SHA256
     } else {
-        u8_array_to_u32_array(data[0])
+        u8_array_to_u32_array(data[0])?
     };
     let curr_block = if data.len() == 1 { data[0] } else { data[1] };
 
     // Optimize: avoid bounds check by ensuring curr_block is at least 64 bytes
     if curr_block.len() < 64 {
-        die("Current block must be at least 64 bytes for compression");
+        return Err(SoxError::InvalidInput(format!(
+            "current block must be at least 64 bytes for compression, got {}",
+            curr_block.len()
+        )));
     }
 
-    let h1 = u8_array_to_u32_array(&curr_block[..32]);
-    let h2 = u8_array_to_u32_array(&curr_block[32..64]);
+    let h1 = u8_array_to_u32_array(&curr_block[..32])?;
+    let h2 = u8_array_to_u32_array(&curr_block[32..64])?;
     let res = prev_hash.compress(&h1, &h2);
 
-    u32_array_to_u8_vec(&res)
+    Ok(u32_array_to_u8_vec(&res))
+}
+
+// Compresses a 64-byte block into a SHA-256 state (the default initial hash if prev is None)
+// without allocating
+pub(crate) fn sha256_compress64(prev: Option<&[u8; 32]>, block: &[u8; 64]) -> [u8; 32] {
+    let words = |bytes: &[u8]| -> [u32; 8] {
+        std::array::from_fn(|i| u32::from_be_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap()))
+    };
+    let prev_hash = prev.map_or(SHA256, |prev| words(prev));
+    let res = prev_hash.compress(&words(&block[..32]), &words(&block[32..]));
+
+    let mut out = [0u8; 32];
+    for (bytes, word) in out.chunks_exact_mut(4).zip(res) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    out
 }
 
 // Performs SHA-256 standard padding on the input data
-fn sha256_padding(input: &Vec<u8>, data_len: u64) -> Vec<u8> {
+fn sha256_padding(input: &[u8], data_len: u64) -> SoxResult<Vec<u8>> {
+    if input.len() > 119 {
+        return Err(SoxError::InvalidInput(format!(
+            "the final compression accepts at most 119 bytes, got {}",
+            input.len()
+        )));
+    }
+
     let mut padded_len = input.len() + 9;
     if padded_len < 64 {
         padded_len = 64
@@ -80,13 +109,11 @@ fn sha256_padding(input: &Vec<u8>, data_len: u64) -> Vec<u8> {
     }
 
     let mut padded = vec![0u8; padded_len - 8];
-    for i in 0..input.len() {
-        padded[i] = input[i];
-    }
+    padded[..input.len()].copy_from_slice(input);
     padded[input.len()] = 0x80;
     padded.extend(&(data_len * 8).to_be_bytes());
 
-    padded
+    Ok(padded)
 }
 
 /// Performs SHA-256 compression with padding. Only accepts one block.
@@ -99,55 +126,54 @@ fn sha256_padding(input: &Vec<u8>, data_len: u64) -> Vec<u8> {
 /// # Returns
 /// A 32-byte vector containing the final hash
 ///
-/// # Panics
-/// Panics if:
+/// # Errors
+/// Returns `SoxError::InvalidInput` if:
 /// - Input doesn't have exactly 2 or 3 elements
 /// - Previous hash (if present) is not 32 bytes
 /// - Data length is not 8 bytes
-pub fn sha256_compress_final(data: &Vec<&Vec<u8>>) -> Vec<u8> {
+pub fn sha256_compress_final(data: &[&Vec<u8>]) -> SoxResult<Vec<u8>> {
     if data.len() != 2 && data.len() != 3 {
-        let msg = format!(
-            "Input data for the final compression must have exactly 2 or 3 elements. Got {}",
+        return Err(SoxError::InvalidInput(format!(
+            "input data for the final compression must have exactly 2 or 3 elements, got {}",
             data.len()
-        );
-        die(&msg);
+        )));
     }
 
     if data.len() == 3 && data[0].len() != 32 {
-        die(&format!(
-            "Previous hash on the final compression must be 32 bytes long. Got {}",
+        return Err(SoxError::InvalidInput(format!(
+            "previous hash on the final compression must be 32 bytes long, got {}",
             data[0].len()
-        ));
+        )));
     }
 
-    if data[data.len() - 1].len() != 8 {
-        die(&format!(
-            "Data length on the final compression must be 8 bytes long. Got {}",
+    let data_len: [u8; 8] = data[data.len() - 1].as_slice().try_into().map_err(|_| {
+        SoxError::InvalidInput(format!(
+            "data length on the final compression must be 8 bytes long, got {}",
             data[data.len() - 1].len()
-        ));
-    }
+        ))
+    })?;
+    let data_len = u64::from_be_bytes(data_len);
 
     let prev_hash = if data.len() == 2 {
         SHA256
     } else {
-        u8_array_to_u32_array(data[0])
+        u8_array_to_u32_array(data[0])?
     };
     let curr_block = data[data.len() - 2];
-    let data_len = u64::from_be_bytes(data[data.len() - 1].clone().try_into().unwrap());
 
-    let padded = sha256_padding(&curr_block, data_len);
-    let h1 = u8_array_to_u32_array(&padded[..32]);
-    let h2 = u8_array_to_u32_array(&padded[32..64]);
+    let padded = sha256_padding(curr_block, data_len)?;
+    let h1 = u8_array_to_u32_array(&padded[..32])?;
+    let h2 = u8_array_to_u32_array(&padded[32..64])?;
     let mut res = prev_hash.compress(&h1, &h2);
 
     if padded.len() > 64 {
         // an extra block left due to the padding
-        let h1 = u8_array_to_u32_array(&padded[64..96]);
-        let h2 = u8_array_to_u32_array(&padded[96..]);
+        let h1 = u8_array_to_u32_array(&padded[64..96])?;
+        let h2 = u8_array_to_u32_array(&padded[96..])?;
         res = res.compress(&h1, &h2);
     }
 
-    u32_array_to_u8_vec(&res)
+    Ok(u32_array_to_u8_vec(&res))
 }
 
 /// Computes the SHA-256 hash of input data
@@ -171,7 +197,7 @@ pub fn sha256(data: &[u8]) -> Vec<u8> {
 /// # Returns
 /// A byte vector containing the final hash
 #[wasm_bindgen]
-pub fn sha256_compress_final_js(data: Vec<Uint8Array>) -> Vec<u8> {
+pub fn sha256_compress_final_js(data: Vec<Uint8Array>) -> SoxResult<Vec<u8>> {
     let values_vec: Vec<Vec<u8>> = data.iter().map(uint8_array_to_vec_u8).collect();
     let refs: Vec<&Vec<u8>> = values_vec.iter().collect();
     sha256_compress_final(&refs)
@@ -185,7 +211,7 @@ pub fn sha256_compress_final_js(data: Vec<Uint8Array>) -> Vec<u8> {
 /// # Returns
 /// A byte vector containing the compressed result
 #[wasm_bindgen]
-pub fn sha256_compress_js(data: Vec<Uint8Array>) -> Vec<u8> {
+pub fn sha256_compress_js(data: Vec<Uint8Array>) -> SoxResult<Vec<u8>> {
     let values_vec: Vec<Vec<u8>> = data.iter().map(uint8_array_to_vec_u8).collect();
     let refs: Vec<&Vec<u8>> = values_vec.iter().collect();
     sha256_compress(&refs)
@@ -1,4 +1,4 @@
-use crate::utils::die;
+use crate::error::{SoxError, SoxResult};
 use std::cmp::min;
 
 /// Checks if all provided byte arrays are equal
@@ -8,23 +8,18 @@ use std::cmp::min;
 ///
 /// # Returns
 /// Single byte vector containing 1 if all arrays are equal, 0 if not equal
-pub fn equal(data: &Vec<&Vec<u8>>) -> Vec<u8> {
+pub fn equal(data: &[&Vec<u8>]) -> SoxResult<Vec<u8>> {
     if data.len() < 2 {
-        die("Need at least two elements to check for equality")
+        return Err(SoxError::InvalidInput(
+            "need at least two elements to check for equality".into(),
+        ));
     }
 
-    for i in 1..data.len() {
-        if data[0].len() != data[i].len() {
-            return vec![0u8];
-        }
-        for j in 0..data[i].len() {
-            if data[0][j] != data[i][j] {
-                return vec![0u8];
-            }
-        }
+    if data[1..].iter().all(|d| d == &data[0]) {
+        Ok(vec![1u8])
+    } else {
+        Ok(vec![0u8])
     }
-
-    vec![1u8]
 }
 
 /// Internal helper to copy bytes into padded buffer
@@ -34,35 +29,43 @@ pub fn equal(data: &Vec<&Vec<u8>>) -> Vec<u8> {
 /// * `dst` - Destination byte slice to copy into, padding with leading zeros
 fn copy_to_padded(src: &[u8], dst: &mut [u8]) {
     let end = min(src.len(), dst.len());
-    for i in 0..end {
-        dst[dst.len() - end + i] = src[i];
-    }
+    let offset = dst.len() - end;
+    dst[offset..].copy_from_slice(&src[..end]);
 }
 
-/// Adds two numbers represented as byte arrays. They cannot be larger than 16 bytes.
-///
-/// # Arguments
-/// * `data` - Vector containing exactly 2 byte array references to add
-///
-/// # Returns
-/// 16-byte array containing sum as big-endian u128, padded with leading zeros
-pub fn binary_add(data: &Vec<&Vec<u8>>) -> Vec<u8> {
+// Parses the two operands of a binary operation as big-endian u128
+fn binary_operands(data: &[&Vec<u8>], op: &str) -> SoxResult<(u128, u128)> {
     if data.len() != 2 {
-        die("Binary addition only accepts 2 arrays of bytes");
+        return Err(SoxError::InvalidInput(format!(
+            "binary {op} only accepts 2 arrays of bytes"
+        )));
     }
 
     if data[0].len() > 16 || data[1].len() > 16 {
-        die("Binary addition only accepts arrays of at most 16 bytes");
+        return Err(SoxError::InvalidInput(format!(
+            "binary {op} only accepts arrays of at most 16 bytes"
+        )));
     }
 
     let mut left = [0u8; 16];
     let mut right = [0u8; 16];
-    copy_to_padded(&data[0], &mut left);
-    copy_to_padded(&data[1], &mut right);
+    copy_to_padded(data[0], &mut left);
+    copy_to_padded(data[1], &mut right);
+
+    Ok((u128::from_be_bytes(left), u128::from_be_bytes(right)))
+}
+
+/// Adds two numbers represented as byte arrays. They cannot be larger than 16 bytes.
+///
+/// # Arguments
+/// * `data` - Vector containing exactly 2 byte array references to add
+///
+/// # Returns
+/// 16-byte array containing sum as big-endian u128, padded with leading zeros
+pub fn binary_add(data: &[&Vec<u8>]) -> SoxResult<Vec<u8>> {
+    let (left, right) = binary_operands(data, "addition")?;
 
-    (u128::from_be_bytes(left) + u128::from_be_bytes(right))
-        .to_be_bytes()
-        .to_vec()
+    Ok(left.wrapping_add(right).to_be_bytes().to_vec())
 }
 
 /// Multiplies two numbers represented as byte arrays. They cannot be larger than 16 bytes.
@@ -72,23 +75,10 @@ pub fn binary_add(data: &Vec<&Vec<u8>>) -> Vec<u8> {
 ///
 /// # Returns
 /// 16-byte array containing product as big-endian u128, padded with leading zeros
-pub fn binary_mult(data: &Vec<&Vec<u8>>) -> Vec<u8> {
-    if data.len() != 2 {
-        die("Binary multiplication only accepts 2 arrays of bytes");
-    }
-
-    if data[0].len() > 16 || data[1].len() > 16 {
-        die("Binary multiplication only accepts arrays of at most 16 bytes");
-    }
+pub fn binary_mult(data: &[&Vec<u8>]) -> SoxResult<Vec<u8>> {
+    let (left, right) = binary_operands(data, "multiplication")?;
 
-    let mut left = [0u8; 16];
-    let mut right = [0u8; 16];
-    copy_to_padded(&data[0], &mut left);
-    copy_to_padded(&data[1], &mut right);
-
-    (u128::from_be_bytes(left) * u128::from_be_bytes(right))
-        .to_be_bytes()
-        .to_vec()
+    Ok(left.wrapping_mul(right).to_be_bytes().to_vec())
 }
 
 /// Concatenates multiple byte arrays
@@ -98,19 +88,6 @@ pub fn binary_mult(data: &Vec<&Vec<u8>>) -> Vec<u8> {
 ///
 /// # Returns
 /// Single byte array containing all input arrays concatenated
-pub fn concat_bytes(data: &Vec<&Vec<u8>>) -> Vec<u8> {
-    if data.len() == 0 {
-        return vec![];
-    }
-    if data.len() == 1 {
-        return data[0].clone();
-    }
-    let mut res = data[0].iter().map(|x| x.clone()).collect();
-
-    for i in 1..data.len() {
-        let next = data[i].iter().map(|x| x.clone()).collect();
-        res = [res, next].concat();
-    }
-
-    res
+pub fn concat_bytes(data: &[&Vec<u8>]) -> SoxResult<Vec<u8>> {
+    Ok(data.iter().flat_map(|d| d.iter().copied()).collect())
 }
@@ -0,0 +1,738 @@
+//! Common interface of the two circuit generations. `CompiledCircuit` (V1, ABI-encoded gates with
+//! dummy input gates) and `CompiledCircuitV2` (64-byte gates, inputs referenced by negative sons)
+//! expose the same protocol operations through [`SoxCircuit`], and [`AnyCircuit`] decodes a
+//! serialized circuit of either generation.
+//!
+//! Every method of the trait uses the paper's notation for challenges: gates are numbered from 1
+//! to `num_gates()`, input blocks excluded. V1 contracts use absolute indices instead, see
+//! [`SoxCircuit::to_contract_challenge`].
+
+use crate::accumulator::{acc_circuit_with_mode, AccumulableCircuit, AccumulatorMode};
+use crate::circuits::{compile_basic_circuit, CompiledCircuit};
+use crate::circuits_v2::{compile_circuit_v2, CompiledCircuitV2};
+use crate::error::{SoxError, SoxResult};
+use crate::native::{
+    evaluate_compiled, evaluate_compiled_v2, final_step, final_step_left, final_step_left_v2,
+    final_step_v2, hpre_values, hpre_values_v2, proof_right, proof_right_v2, FinalStep,
+    FinalStepV2, Proof,
+};
+use crate::validate::CircuitDiagnostic;
+use crate::{EvaluatedCircuit, EvaluatedCircuitV2};
+
+// msgpack markers of the serialized circuits. Both generations are serialized by rmp_serde as
+// fixed arrays of their fields: 5 fields for V1, 4 fields for V2.
+const V1_MARKER: u8 = 0x95;
+const V2_MARKER: u8 = 0x94;
+
+// msgpack markers of the serialized evaluated circuits: (values, constants) for V1 and (values)
+// for V2
+const EVALUATED_V1_MARKER: u8 = 0x92;
+const EVALUATED_V2_MARKER: u8 = 0x91;
+
+/// Generation of a circuit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitVersion {
+    /// `CompiledCircuit`
+    V1,
+
+    /// `CompiledCircuitV2`
+    V2,
+}
+
+impl CircuitVersion {
+    /// Detects the generation of a serialized circuit without decoding it.
+    ///
+    /// # Arguments
+    /// * `circuit_bytes` - Serialized circuit bytes
+    ///
+    /// # Returns
+    /// The generation of the circuit, or `SoxError::Decode` if the bytes are not a serialized
+    /// circuit
+    pub fn detect(circuit_bytes: &[u8]) -> SoxResult<Self> {
+        match circuit_bytes.first() {
+            Some(&V1_MARKER) => Ok(CircuitVersion::V1),
+            Some(&V2_MARKER) => Ok(CircuitVersion::V2),
+            Some(marker) => Err(SoxError::Decode(format!(
+                "unknown circuit format (marker {marker:#04x})"
+            ))),
+            None => Err(SoxError::Decode("empty circuit".into())),
+        }
+    }
+
+    /// Detects the generation of a serialized evaluated circuit without decoding it.
+    ///
+    /// # Arguments
+    /// * `evaluated_bytes` - Serialized evaluated circuit bytes
+    ///
+    /// # Returns
+    /// The generation of the evaluated circuit, or `SoxError::Decode` if the bytes are not a
+    /// serialized evaluated circuit
+    pub fn detect_evaluated(evaluated_bytes: &[u8]) -> SoxResult<Self> {
+        match evaluated_bytes.first() {
+            Some(&EVALUATED_V1_MARKER) => Ok(CircuitVersion::V1),
+            Some(&EVALUATED_V2_MARKER) => Ok(CircuitVersion::V2),
+            Some(marker) => Err(SoxError::Decode(format!(
+                "unknown evaluated circuit format (marker {marker:#04x})"
+            ))),
+            None => Err(SoxError::Decode("empty evaluated circuit".into())),
+        }
+    }
+
+    /// Returns the hashing scheme of the accumulators of the circuit generation. V1 and V2 keep
+    /// the legacy scheme checked by the deployed contracts; a domain-separated accumulator of a
+    /// circuit is computed explicitly with `acc_circuit_with_mode`.
+    pub fn accumulator_mode(self) -> AccumulatorMode {
+        match self {
+            CircuitVersion::V1 | CircuitVersion::V2 => AccumulatorMode::Legacy,
+        }
+    }
+}
+
+/// Protocol operations shared by every circuit generation.
+pub trait SoxCircuit: AccumulableCircuit + Sized {
+    /// Output of the evaluation of the circuit
+    type Evaluated;
+
+    /// Components sent by the vendor in the final step of a dispute
+    type FinalStep;
+
+    /// Returns the generation of the circuit
+    fn version(&self) -> CircuitVersion;
+
+    /// Compiles the circuit checking that `ct` decrypts to a plaintext hashing to `description`.
+    ///
+    /// # Arguments
+    /// * `ct` - Ciphertext bytes (IV included)
+    /// * `description` - SHA-256 hash of the plaintext
+    ///
+    /// # Returns
+    /// The compiled circuit
+    fn compile(ct: &[u8], description: &[u8]) -> SoxResult<Self>;
+
+    /// Deserializes a circuit from bytes
+    fn from_bytes(bytes: &[u8]) -> SoxResult<Self>;
+
+    /// Serializes the circuit into bytes
+    fn to_bytes(&self) -> Vec<u8>;
+
+    /// Deserializes the output of [`evaluate`](Self::evaluate) from bytes
+    fn evaluated_from_bytes(bytes: &[u8]) -> SoxResult<Self::Evaluated>;
+
+    /// Returns the number of ciphertext blocks taken as input
+    fn num_blocks(&self) -> u32;
+
+    /// Returns the number of gates, input blocks excluded, or `SoxError::CircuitShape` if the
+    /// circuit has fewer gates than input blocks
+    fn num_gates(&self) -> SoxResult<u32>;
+
+    /// Checks the structure of the circuit without evaluating it
+    ///
+    /// # Returns
+    /// The problems found in the circuit, empty if it is valid
+    fn validate(&self) -> Vec<CircuitDiagnostic>;
+
+    /// Converts a challenge of this module (1-indexed gate) into the index used by the dispute
+    /// contract of the circuit generation
+    fn to_contract_challenge(&self, challenge: u32) -> u32;
+
+    /// Evaluates the circuit.
+    ///
+    /// # Arguments
+    /// * `ct` - Ciphertext bytes (IV included)
+    /// * `key` - AES key
+    ///
+    /// # Returns
+    /// The evaluated circuit
+    fn evaluate(&self, ct: &[u8], key: &[u8]) -> SoxResult<Self::Evaluated>;
+
+    /// Computes the accumulator of the circuit (h_circuit), in the hashing mode of its generation
+    fn accumulate(&self) -> SoxResult<Vec<u8>> {
+        acc_circuit_with_mode(self, self.version().accumulator_mode())
+    }
+
+    /// Computes hpre(challenge), the accumulator of the outputs of gates 1 to `challenge`.
+    ///
+    /// # Arguments
+    /// * `evaluated` - The evaluated circuit
+    /// * `challenge` - 1-indexed gate
+    ///
+    /// # Returns
+    /// The response to the challenge
+    fn hpre(&self, evaluated: &Self::Evaluated, challenge: u32) -> SoxResult<Vec<u8>>;
+
+    /// Computes the components of step 8a.
+    ///
+    /// # Arguments
+    /// * `evaluated` - The evaluated circuit
+    /// * `ct` - Ciphertext bytes (IV included)
+    /// * `challenge` - 1-indexed gate
+    ///
+    /// # Returns
+    /// The gate, the values of its sons, the current accumulator value and the proofs
+    fn prove(
+        &self,
+        evaluated: &Self::Evaluated,
+        ct: &[u8],
+        challenge: u32,
+    ) -> SoxResult<Self::FinalStep>;
+
+    /// Computes the components of step 8b. Same arguments as [`prove`](Self::prove).
+    fn prove_left(
+        &self,
+        evaluated: &Self::Evaluated,
+        ct: &[u8],
+        challenge: u32,
+    ) -> SoxResult<Self::FinalStep>;
+
+    /// Computes the proof of step 8c, showing that the output of the last gate belongs to the
+    /// evaluated circuit
+    fn prove_right(&self, evaluated: &Self::Evaluated) -> SoxResult<Proof>;
+}
+
+// Rejects the challenges outside of 1..=num_gates
+fn check_challenge<C: SoxCircuit>(circuit: &C, challenge: u32) -> SoxResult<()> {
+    let num_gates = circuit.num_gates()?;
+    if challenge == 0 || challenge > num_gates {
+        return Err(SoxError::Index(format!(
+            "challenge {challenge} out of bounds (gates 1 to {num_gates})"
+        )));
+    }
+    Ok(())
+}
+
+impl SoxCircuit for CompiledCircuit {
+    type Evaluated = EvaluatedCircuit;
+    type FinalStep = FinalStep;
+
+    fn version(&self) -> CircuitVersion {
+        CircuitVersion::V1
+    }
+
+    fn compile(ct: &[u8], description: &[u8]) -> SoxResult<Self> {
+        compile_basic_circuit(ct.len() as u32, description)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> SoxResult<Self> {
+        CompiledCircuit::from_bytes(bytes)
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        CompiledCircuit::to_bytes(self)
+    }
+
+    fn evaluated_from_bytes(bytes: &[u8]) -> SoxResult<EvaluatedCircuit> {
+        EvaluatedCircuit::from_bytes(bytes)
+    }
+
+    // The IV is an input block of V1 circuits
+    fn num_blocks(&self) -> u32 {
+        self.num_blocks
+    }
+
+    fn num_gates(&self) -> SoxResult<u32> {
+        (self.circuit.len() as u32)
+            .checked_sub(self.num_blocks)
+            .ok_or_else(|| {
+                SoxError::CircuitShape(format!(
+                    "{} gates for {} input blocks",
+                    self.circuit.len(),
+                    self.num_blocks
+                ))
+            })
+    }
+
+    fn validate(&self) -> Vec<CircuitDiagnostic> {
+        self.validate()
+    }
+
+    // V1 contracts index the gates from 0, dummy input gates included
+    fn to_contract_challenge(&self, challenge: u32) -> u32 {
+        self.num_blocks + challenge - 1
+    }
+
+    // The key is the only constant left unbound by `compile_basic_circuit`
+    fn evaluate(&self, ct: &[u8], key: &[u8]) -> SoxResult<EvaluatedCircuit> {
+        evaluate_compiled(self.bind_missing_constants(vec![key.to_vec()])?, ct)
+    }
+
+    fn hpre(&self, evaluated: &EvaluatedCircuit, challenge: u32) -> SoxResult<Vec<u8>> {
+        check_challenge(self, challenge)?;
+        hpre_values(
+            evaluated.values(),
+            self.num_blocks as usize,
+            self.to_contract_challenge(challenge) as usize,
+        )
+    }
+
+    fn prove(
+        &self,
+        evaluated: &EvaluatedCircuit,
+        ct: &[u8],
+        challenge: u32,
+    ) -> SoxResult<FinalStep> {
+        check_challenge(self, challenge)?;
+        final_step(self, evaluated, ct, self.to_contract_challenge(challenge))
+    }
+
+    fn prove_left(
+        &self,
+        evaluated: &EvaluatedCircuit,
+        ct: &[u8],
+        challenge: u32,
+    ) -> SoxResult<FinalStep> {
+        check_challenge(self, challenge)?;
+        final_step_left(self, evaluated, ct, self.to_contract_challenge(challenge))
+    }
+
+    fn prove_right(&self, evaluated: &EvaluatedCircuit) -> SoxResult<Proof> {
+        proof_right(evaluated, self.num_blocks, self.circuit.len() as u32)
+    }
+}
+
+impl SoxCircuit for CompiledCircuitV2 {
+    type Evaluated = EvaluatedCircuitV2;
+    type FinalStep = FinalStepV2;
+
+    fn version(&self) -> CircuitVersion {
+        CircuitVersion::V2
+    }
+
+    fn compile(ct: &[u8], description: &[u8]) -> SoxResult<Self> {
+        compile_circuit_v2(ct, description)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> SoxResult<Self> {
+        CompiledCircuitV2::from_bytes(bytes)
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        CompiledCircuitV2::to_bytes(self)
+    }
+
+    fn evaluated_from_bytes(bytes: &[u8]) -> SoxResult<EvaluatedCircuitV2> {
+        EvaluatedCircuitV2::from_bytes(bytes)
+    }
+
+    fn num_blocks(&self) -> u32 {
+        self.num_blocks
+    }
+
+    fn num_gates(&self) -> SoxResult<u32> {
+        Ok(self.gates.len() as u32)
+    }
+
+    fn validate(&self) -> Vec<CircuitDiagnostic> {
+        self.validate()
+    }
+
+    fn to_contract_challenge(&self, challenge: u32) -> u32 {
+        challenge
+    }
+
+    fn evaluate(&self, ct: &[u8], key: &[u8]) -> SoxResult<EvaluatedCircuitV2> {
+        evaluate_compiled_v2(self, ct, key)
+    }
+
+    fn hpre(&self, evaluated: &EvaluatedCircuitV2, challenge: u32) -> SoxResult<Vec<u8>> {
+        check_challenge(self, challenge)?;
+        hpre_values_v2(
+            evaluated.values(),
+            self.num_blocks as usize,
+            challenge as usize,
+        )
+    }
+
+    fn prove(
+        &self,
+        evaluated: &EvaluatedCircuitV2,
+        ct: &[u8],
+        challenge: u32,
+    ) -> SoxResult<FinalStepV2> {
+        check_challenge(self, challenge)?;
+        final_step_v2(self, evaluated, ct, challenge)
+    }
+
+    fn prove_left(
+        &self,
+        evaluated: &EvaluatedCircuitV2,
+        ct: &[u8],
+        challenge: u32,
+    ) -> SoxResult<FinalStepV2> {
+        check_challenge(self, challenge)?;
+        final_step_left_v2(self, evaluated, ct, challenge)
+    }
+
+    fn prove_right(&self, evaluated: &EvaluatedCircuitV2) -> SoxResult<Proof> {
+        proof_right_v2(evaluated, self.num_blocks, self.gates.len() as u32)
+    }
+}
+
+// ####################################
+// ###     VERSION DISPATCH         ###
+// ####################################
+
+/// A circuit of any generation, decoded with [`AnyCircuit::from_bytes`].
+#[derive(Clone)]
+pub enum AnyCircuit {
+    V1(CompiledCircuit),
+    V2(CompiledCircuitV2),
+}
+
+/// An evaluated circuit of any generation
+pub enum AnyEvaluated {
+    V1(EvaluatedCircuit),
+    V2(EvaluatedCircuitV2),
+}
+
+/// Final step components of any generation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnyFinalStep {
+    V1(FinalStep),
+    V2(FinalStepV2),
+}
+
+impl AnyEvaluated {
+    /// Returns the values of the evaluated circuit: the inputs followed by the gate outputs
+    pub fn values(&self) -> &[Vec<u8>] {
+        match self {
+            AnyEvaluated::V1(evaluated) => evaluated.values(),
+            AnyEvaluated::V2(evaluated) => evaluated.values(),
+        }
+    }
+
+    /// Serializes the evaluated circuit into bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            AnyEvaluated::V1(evaluated) => evaluated.to_bytes(),
+            AnyEvaluated::V2(evaluated) => evaluated.to_bytes(),
+        }
+    }
+}
+
+// Error returned when an evaluated circuit does not come from the same generation as the circuit
+fn version_mismatch() -> SoxError {
+    SoxError::CircuitShape("the evaluated circuit and the circuit have different versions".into())
+}
+
+impl AccumulableCircuit for AnyCircuit {
+    fn encoded_gates(&self) -> SoxResult<Vec<Vec<u8>>> {
+        match self {
+            AnyCircuit::V1(circuit) => circuit.encoded_gates(),
+            AnyCircuit::V2(circuit) => circuit.encoded_gates(),
+        }
+    }
+
+    fn acc_direct(&self) -> Option<SoxResult<Vec<u8>>> {
+        match self {
+            AnyCircuit::V1(circuit) => circuit.acc_direct(),
+            AnyCircuit::V2(circuit) => circuit.acc_direct(),
+        }
+    }
+}
+
+/// Dispatches every operation to the circuit of the detected generation. Evaluated circuits are
+/// decoded according to the generation of the circuit, and mixing generations returns
+/// `SoxError::CircuitShape`. [`compile`](SoxCircuit::compile) produces a V2 circuit.
+impl SoxCircuit for AnyCircuit {
+    type Evaluated = AnyEvaluated;
+    type FinalStep = AnyFinalStep;
+
+    fn version(&self) -> CircuitVersion {
+        match self {
+            AnyCircuit::V1(_) => CircuitVersion::V1,
+            AnyCircuit::V2(_) => CircuitVersion::V2,
+        }
+    }
+
+    fn compile(ct: &[u8], description: &[u8]) -> SoxResult<Self> {
+        Ok(AnyCircuit::V2(compile_circuit_v2(ct, description)?))
+    }
+
+    fn from_bytes(bytes: &[u8]) -> SoxResult<Self> {
+        Ok(match CircuitVersion::detect(bytes)? {
+            CircuitVersion::V1 => AnyCircuit::V1(CompiledCircuit::from_bytes(bytes)?),
+            CircuitVersion::V2 => AnyCircuit::V2(CompiledCircuitV2::from_bytes(bytes)?),
+        })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            AnyCircuit::V1(circuit) => circuit.to_bytes(),
+            AnyCircuit::V2(circuit) => circuit.to_bytes(),
+        }
+    }
+
+    fn evaluated_from_bytes(bytes: &[u8]) -> SoxResult<AnyEvaluated> {
+        Ok(match CircuitVersion::detect_evaluated(bytes)? {
+            CircuitVersion::V1 => AnyEvaluated::V1(EvaluatedCircuit::from_bytes(bytes)?),
+            CircuitVersion::V2 => AnyEvaluated::V2(EvaluatedCircuitV2::from_bytes(bytes)?),
+        })
+    }
+
+    fn num_blocks(&self) -> u32 {
+        match self {
+            AnyCircuit::V1(circuit) => SoxCircuit::num_blocks(circuit),
+            AnyCircuit::V2(circuit) => SoxCircuit::num_blocks(circuit),
+        }
+    }
+
+    fn num_gates(&self) -> SoxResult<u32> {
+        match self {
+            AnyCircuit::V1(circuit) => circuit.num_gates(),
+            AnyCircuit::V2(circuit) => circuit.num_gates(),
+        }
+    }
+
+    fn validate(&self) -> Vec<CircuitDiagnostic> {
+        match self {
+            AnyCircuit::V1(circuit) => circuit.validate(),
+            AnyCircuit::V2(circuit) => circuit.validate(),
+        }
+    }
+
+    fn to_contract_challenge(&self, challenge: u32) -> u32 {
+        match self {
+            AnyCircuit::V1(circuit) => circuit.to_contract_challenge(challenge),
+            AnyCircuit::V2(circuit) => circuit.to_contract_challenge(challenge),
+        }
+    }
+
+    fn evaluate(&self, ct: &[u8], key: &[u8]) -> SoxResult<AnyEvaluated> {
+        Ok(match self {
+            AnyCircuit::V1(circuit) => AnyEvaluated::V1(circuit.evaluate(ct, key)?),
+            AnyCircuit::V2(circuit) => AnyEvaluated::V2(circuit.evaluate(ct, key)?),
+        })
+    }
+
+    fn hpre(&self, evaluated: &AnyEvaluated, challenge: u32) -> SoxResult<Vec<u8>> {
+        match (self, evaluated) {
+            (AnyCircuit::V1(circuit), AnyEvaluated::V1(evaluated)) => {
+                circuit.hpre(evaluated, challenge)
+            }
+            (AnyCircuit::V2(circuit), AnyEvaluated::V2(evaluated)) => {
+                circuit.hpre(evaluated, challenge)
+            }
+            _ => Err(version_mismatch()),
+        }
+    }
+
+    fn prove(
+        &self,
+        evaluated: &AnyEvaluated,
+        ct: &[u8],
+        challenge: u32,
+    ) -> SoxResult<AnyFinalStep> {
+        match (self, evaluated) {
+            (AnyCircuit::V1(circuit), AnyEvaluated::V1(evaluated)) => {
+                Ok(AnyFinalStep::V1(circuit.prove(evaluated, ct, challenge)?))
+            }
+            (AnyCircuit::V2(circuit), AnyEvaluated::V2(evaluated)) => {
+                Ok(AnyFinalStep::V2(circuit.prove(evaluated, ct, challenge)?))
+            }
+            _ => Err(version_mismatch()),
+        }
+    }
+
+    fn prove_left(
+        &self,
+        evaluated: &AnyEvaluated,
+        ct: &[u8],
+        challenge: u32,
+    ) -> SoxResult<AnyFinalStep> {
+        match (self, evaluated) {
+            (AnyCircuit::V1(circuit), AnyEvaluated::V1(evaluated)) => Ok(AnyFinalStep::V1(
+                circuit.prove_left(evaluated, ct, challenge)?,
+            )),
+            (AnyCircuit::V2(circuit), AnyEvaluated::V2(evaluated)) => Ok(AnyFinalStep::V2(
+                circuit.prove_left(evaluated, ct, challenge)?,
+            )),
+            _ => Err(version_mismatch()),
+        }
+    }
+
+    fn prove_right(&self, evaluated: &AnyEvaluated) -> SoxResult<Proof> {
+        match (self, evaluated) {
+            (AnyCircuit::V1(circuit), AnyEvaluated::V1(evaluated)) => {
+                circuit.prove_right(evaluated)
+            }
+            (AnyCircuit::V2(circuit), AnyEvaluated::V2(evaluated)) => {
+                circuit.prove_right(evaluated)
+            }
+            _ => Err(version_mismatch()),
+        }
+    }
+}
+
+// =================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::encrypt_and_prepend_iv;
+    use crate::native;
+    use crate::sha256::sha256;
+
+    // Encrypts `len` bytes and returns the key, the ciphertext and the description
+    fn setup(len: usize) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+        let key = vec![9u8; 16];
+        let mut data: Vec<u8> = (0..len).map(|i| i as u8).collect();
+        let description = sha256(&data);
+        let ct = encrypt_and_prepend_iv(&mut data, &key).unwrap();
+        (key, ct, description)
+    }
+
+    // Runs the whole protocol through the trait and compares it with the byte-level functions
+    fn check_generation<C: SoxCircuit>(len: usize) -> (C, C::Evaluated, Vec<u8>) {
+        let (key, ct, description) = setup(len);
+        let circuit = C::compile(&ct, &description).unwrap();
+        let decoded = C::from_bytes(&circuit.to_bytes()).unwrap();
+        assert_eq!(decoded.to_bytes(), circuit.to_bytes());
+        assert_eq!(decoded.accumulate().unwrap(), circuit.accumulate().unwrap());
+
+        let evaluated = circuit.evaluate(&ct, &key).unwrap();
+        for challenge in 1..=circuit.num_gates().unwrap() {
+            circuit.hpre(&evaluated, challenge).unwrap();
+        }
+        assert!(circuit.hpre(&evaluated, 0).is_err());
+        assert!(circuit
+            .hpre(&evaluated, circuit.num_gates().unwrap() + 1)
+            .is_err());
+        circuit.prove_right(&evaluated).unwrap();
+
+        (circuit, evaluated, ct)
+    }
+
+    #[test]
+    fn test_v1_matches_contract_indices() {
+        let (circuit, evaluated, ct) = check_generation::<CompiledCircuit>(300);
+        let evaluated_bytes = evaluated.to_bytes();
+        let num_blocks = circuit.num_blocks as usize;
+
+        for challenge in 1..=circuit.num_gates().unwrap() {
+            let idx = circuit.to_contract_challenge(challenge);
+            assert_eq!(
+                circuit.hpre(&evaluated, challenge).unwrap(),
+                native::hpre(&evaluated_bytes, num_blocks, idx as usize).unwrap()
+            );
+        }
+        let last = circuit.num_gates().unwrap();
+        assert_eq!(
+            circuit.prove(&evaluated, &ct, last).unwrap(),
+            native::compute_proofs(
+                &circuit.to_bytes(),
+                &evaluated_bytes,
+                &ct,
+                circuit.to_contract_challenge(last)
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_v2_matches_contract_indices() {
+        let (circuit, evaluated, ct) = check_generation::<CompiledCircuitV2>(300);
+        let evaluated_bytes = evaluated.to_bytes();
+
+        for challenge in 1..=circuit.num_gates().unwrap() {
+            assert_eq!(
+                circuit.hpre(&evaluated, challenge).unwrap(),
+                native::hpre_v2(
+                    &evaluated_bytes,
+                    circuit.num_blocks as usize,
+                    challenge as usize
+                )
+                .unwrap()
+            );
+        }
+        assert_eq!(
+            circuit.prove_left(&evaluated, &ct, 1).unwrap(),
+            native::compute_proofs_left_v2(&circuit.to_bytes(), &evaluated_bytes, &ct, 1).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_any_circuit_dispatch() {
+        let (key, ct, description) = setup(200);
+        let v1 = compile_basic_circuit(ct.len() as u32, &description).unwrap();
+        let v2 = compile_circuit_v2(&ct, &description).unwrap();
+
+        let any_v1 = AnyCircuit::from_bytes(&v1.to_bytes()).unwrap();
+        let any_v2 = AnyCircuit::from_bytes(&v2.to_bytes()).unwrap();
+        assert_eq!(any_v1.version(), CircuitVersion::V1);
+        assert_eq!(any_v2.version(), CircuitVersion::V2);
+        assert_eq!(any_v1.accumulate().unwrap(), v1.accumulate().unwrap());
+        assert_eq!(any_v2.accumulate().unwrap(), v2.accumulate().unwrap());
+        assert!(matches!(
+            AnyCircuit::from_bytes(&[0xc0]),
+            Err(SoxError::Decode(_))
+        ));
+
+        let evaluated_v1 = any_v1.evaluate(&ct, &key).unwrap();
+        let evaluated_v2 = any_v2.evaluate(&ct, &key).unwrap();
+        assert!(matches!(
+            AnyCircuit::evaluated_from_bytes(&evaluated_v1.to_bytes()).unwrap(),
+            AnyEvaluated::V1(_)
+        ));
+        assert!(matches!(
+            AnyCircuit::evaluated_from_bytes(&evaluated_v2.to_bytes()).unwrap(),
+            AnyEvaluated::V2(_)
+        ));
+        assert_eq!(
+            CircuitVersion::detect_evaluated(&evaluated_v1.to_bytes()).unwrap(),
+            CircuitVersion::V1
+        );
+        assert_eq!(
+            CircuitVersion::detect_evaluated(&evaluated_v2.to_bytes()).unwrap(),
+            CircuitVersion::V2
+        );
+        assert!(matches!(
+            CircuitVersion::detect_evaluated(&v2.to_bytes()),
+            Err(SoxError::Decode(_))
+        ));
+
+        // The comparison gate is the last gate of both generations and outputs 1
+        for (circuit, evaluated) in [(&any_v1, &evaluated_v1), (&any_v2, &evaluated_v2)] {
+            assert_eq!(evaluated.values().last().unwrap()[0], 1);
+            assert!(circuit
+                .prove(evaluated, &ct, circuit.num_gates().unwrap())
+                .is_ok());
+        }
+        assert!(matches!(
+            any_v1.hpre(&evaluated_v2, 1),
+            Err(SoxError::CircuitShape(_))
+        ));
+    }
+
+    #[test]
+    fn test_accumulator_mode() {
+        let (_, ct, description) = setup(150);
+        let v2 = compile_circuit_v2(&ct, &description).unwrap();
+        assert_eq!(
+            CircuitVersion::V1.accumulator_mode(),
+            AccumulatorMode::Legacy
+        );
+        assert_eq!(v2.version().accumulator_mode(), AccumulatorMode::Legacy);
+
+        // domain separation is an option of the accumulator, not of the circuit generation
+        let legacy = acc_circuit_with_mode(&v2, AccumulatorMode::Legacy).unwrap();
+        let domain_separated =
+            acc_circuit_with_mode(&v2, AccumulatorMode::DomainSeparated).unwrap();
+        assert_eq!(legacy, v2.accumulate().unwrap());
+        assert_ne!(domain_separated, legacy);
+    }
+
+    #[test]
+    fn test_v1_fewer_gates_than_blocks() {
+        let (key, ct, description) = setup(200);
+        let mut circuit = compile_basic_circuit(ct.len() as u32, &description).unwrap();
+        let evaluated = circuit.evaluate(&ct, &key).unwrap();
+        circuit.num_blocks = circuit.circuit.len() as u32 + 1;
+
+        let any = AnyCircuit::from_bytes(&circuit.to_bytes()).unwrap();
+        assert!(matches!(any.num_gates(), Err(SoxError::CircuitShape(_))));
+        assert!(matches!(
+            circuit.hpre(&evaluated, 1),
+            Err(SoxError::CircuitShape(_))
+        ));
+    }
+}
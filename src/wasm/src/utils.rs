@@ -1,50 +1,29 @@
+use crate::error::{SoxError, SoxResult};
 use std::cmp::min;
+use wasm_bindgen::prelude::wasm_bindgen;
 
-// ===== Logging & helpers: wasm32 vs natif =====================================
+// ===== Logging: wasm32 vs natif ===============================================
 
 #[cfg(target_arch = "wasm32")]
 mod platform {
-use wasm_bindgen::prelude::wasm_bindgen;
-
-#[wasm_bindgen]
-extern "C" {
-    /// External JavaScript console.log function binding
-    #[wasm_bindgen(js_namespace = console)]
-    pub fn log(s: &str);
-
-    /// External JavaScript console.error function binding
-    #[wasm_bindgen(js_namespace = console)]
-    pub fn error(s: &str);
-}
-
-/// Terminates execution with error message. It will also be displayed in the browser console.
-pub fn die(s: &str) -> ! {
-    error(s);
-    panic!("{}", s);
-}
-
-    #[wasm_bindgen]
-    pub fn hex_to_bytes(hex_str: String) -> Vec<u8> {
-        // Accept both prefixed ("0x...") and raw hex strings.
-        let trimmed = hex_str.trim();
-        match prefix_hex::decode(trimmed) {
-            Ok(bytes) => bytes,
-            Err(_) => {
-                let no_prefix = trimmed.strip_prefix("0x").unwrap_or(trimmed);
-                hex::decode(no_prefix).expect("invalid hex")
-            }
-        }
-    }
+    use wasm_bindgen::prelude::wasm_bindgen;
 
     #[wasm_bindgen]
-    pub fn bytes_to_hex(vec: Vec<u8>) -> String {
-        prefix_hex::encode(&vec)
+    extern "C" {
+        /// External JavaScript console.log function binding
+        #[wasm_bindgen(js_namespace = console)]
+        pub fn log(s: &str);
+
+        /// External JavaScript console.error function binding
+        #[wasm_bindgen(js_namespace = console)]
+        pub fn error(s: &str);
     }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
 mod platform {
     /// Simple logger for native builds
+    #[allow(dead_code)]
     pub fn log(s: &str) {
         eprintln!("[native-log] {s}");
     }
@@ -53,32 +32,35 @@ mod platform {
     pub fn error(s: &str) {
         eprintln!("[native-error] {s}");
     }
+}
 
-    /// Terminates execution with error message (no wasm-bindgen calls).
-    pub fn die(s: &str) -> ! {
-        error(s);
-        panic!("{s}");
-    }
+#[allow(unused_imports)]
+pub use platform::{error, log};
 
-    pub fn hex_to_bytes(hex_str: String) -> Vec<u8> {
-        // Accept both prefixed ("0x...") and raw hex strings.
-        let trimmed = hex_str.trim();
-        match prefix_hex::decode(trimmed) {
-            Ok(bytes) => bytes,
-            Err(_) => {
-                let no_prefix = trimmed.strip_prefix("0x").unwrap_or(trimmed);
-                hex::decode(no_prefix).expect("invalid hex")
-            }
+/// Decodes a hex string. Accepts both prefixed ("0x...") and raw hex strings.
+///
+/// # Arguments
+/// * `hex_str` - The hex string to decode
+///
+/// # Returns
+/// The decoded bytes, or `SoxError::Hex` if the string is not valid hex
+#[wasm_bindgen]
+pub fn hex_to_bytes(hex_str: String) -> SoxResult<Vec<u8>> {
+    let trimmed = hex_str.trim();
+    match prefix_hex::decode(trimmed) {
+        Ok(bytes) => Ok(bytes),
+        Err(_) => {
+            let no_prefix = trimmed.strip_prefix("0x").unwrap_or(trimmed);
+            hex::decode(no_prefix).map_err(|e| SoxError::Hex(format!("{e} in \"{trimmed}\"")))
         }
     }
-
-    pub fn bytes_to_hex(vec: Vec<u8>) -> String {
-        prefix_hex::encode(&vec)
-    }
 }
 
-pub use platform::{bytes_to_hex, die, error, hex_to_bytes, log};
-// (implémentée dans `platform::die` ci-dessus)
+/// Encodes bytes as a "0x"-prefixed hex string
+#[wasm_bindgen]
+pub fn bytes_to_hex(vec: Vec<u8>) -> String {
+    prefix_hex::encode(&vec)
+}
 
 /// Splits ciphertext into blocks. Assumes the first block is a 16 bytes IV.
 ///
@@ -87,15 +69,27 @@ pub use platform::{bytes_to_hex, die, error, hex_to_bytes, log};
 /// * `block_size` - Size of each block
 ///
 /// # Returns
-/// Vector of blocks where first block is IV and remaining blocks are block_size bytes each
-pub fn split_ct_blocks(ct: &[u8], block_size: usize) -> Vec<Vec<u8>> {
+/// Vector of blocks where first block is IV and remaining blocks are block_size bytes each, or
+/// `SoxError::InvalidInput` if the ciphertext is shorter than the IV or the block size is 0
+pub fn split_ct_blocks(ct: &[u8], block_size: usize) -> SoxResult<Vec<Vec<u8>>> {
+    if block_size == 0 {
+        return Err(SoxError::InvalidInput("block size must not be 0".into()));
+    }
+    if ct.len() < 16 {
+        return Err(SoxError::InvalidInput(format!(
+            "ciphertext must include a 16-byte IV, got {} bytes",
+            ct.len()
+        )));
+    }
+
     // Pre-allocate with estimated capacity
-    let num_blocks = 1 + (ct.len().saturating_sub(16) + block_size - 1) / block_size;
+    let num_blocks = 1 + (ct.len() - 16).div_ceil(block_size);
     let mut res = Vec::with_capacity(num_blocks);
     res.push(ct[..16].to_vec()); // IV
 
     // Optimize: parallelize block extraction for large files
-    if ct.len() > 1024 * 1024 { // Only parallelize for files > 1MB
+    if ct.len() > 1024 * 1024 {
+        // Only parallelize for files > 1MB
         use rayon::prelude::*;
         let block_indices: Vec<usize> = (16..ct.len()).step_by(block_size).collect();
         let data_blocks: Vec<Vec<u8>> = block_indices
@@ -107,14 +101,43 @@ pub fn split_ct_blocks(ct: &[u8], block_size: usize) -> Vec<Vec<u8>> {
             .collect();
         res.extend(data_blocks);
     } else {
-    for i in (16..ct.len()).step_by(block_size) {
-        let end = min(i + block_size, ct.len());
-        res.push(ct[i..end].to_vec());
+        for i in (16..ct.len()).step_by(block_size) {
+            let end = min(i + block_size, ct.len());
+            res.push(ct[i..end].to_vec());
         }
     }
 
-    res
+    Ok(res)
 }
 
-// hex_to_bytes / bytes_to_hex sont ré-exportées depuis `platform` pour
-// être utilisables partout, en wasm comme en natif.
+// =================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_to_bytes() {
+        assert_eq!(
+            hex_to_bytes("0xdead".to_string()).unwrap(),
+            vec![0xde, 0xad]
+        );
+        assert_eq!(hex_to_bytes("beef".to_string()).unwrap(), vec![0xbe, 0xef]);
+        assert_eq!(
+            hex_to_bytes("0xzz".to_string()).unwrap_err().code(),
+            "SOX_HEX"
+        );
+    }
+
+    #[test]
+    fn test_split_ct_blocks_too_short() {
+        let err = split_ct_blocks(&[0u8; 15], 64).unwrap_err();
+        assert_eq!(err.code(), "SOX_INVALID_INPUT");
+    }
+
+    #[test]
+    fn test_split_ct_blocks_zero_block_size() {
+        let err = split_ct_blocks(&[0u8; 32], 0).unwrap_err();
+        assert_eq!(err.code(), "SOX_INVALID_INPUT");
+    }
+}
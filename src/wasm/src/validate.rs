@@ -0,0 +1,497 @@
+//! Static validation of compiled circuits. `validate` reports every problem that would make the
+//! evaluation of a circuit fail or the contract reject one of its gates, without evaluating it,
+//! so that a buyer can reject a vendor's circuit before the precontract is accepted.
+
+use crate::circuits::{is_constant_idx, CompiledCircuit};
+use crate::circuits_v2::{opcode_info, CompiledCircuitV2, OPCODE_COMP};
+use std::fmt;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// Problem found in a circuit by `validate`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CircuitIssue {
+    /// The instruction set version is not supported
+    UnknownVersion(u32),
+
+    /// The opcode does not exist in the instruction set
+    UnknownOpcode(u32),
+
+    /// The number of sons is not accepted by the opcode (`max` is `usize::MAX` if unbounded)
+    Arity { min: usize, max: usize, got: usize },
+
+    /// The params do not have the length expected by the opcode
+    ParamsLength { expected: usize, got: usize },
+
+    /// A son is the gate itself or a later gate
+    ForwardReference { son: i64 },
+
+    /// A son refers to an input that does not exist (or to g_0, which does not exist either)
+    InputOutOfRange { son: i64, num_blocks: u32 },
+
+    /// A son refers to a constant that does not exist
+    ConstantOutOfRange { index: u32, num_constants: usize },
+
+    /// An input gate is missing among the first `num_blocks` gates, or is found after them
+    MisplacedInput,
+
+    /// The gate cannot be encoded in 64 bytes
+    NotEncodable(String),
+
+    /// The last gate of the circuit is not a comparison
+    MissingFinalComp,
+
+    /// A comparison gate is found before the last gate
+    ExtraComp,
+}
+
+/// Problem found in a circuit, with the gate it was found in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CircuitDiagnostic {
+    /// Index of the gate in the circuit (0-indexed), `None` for problems of the whole circuit
+    pub gate: Option<usize>,
+
+    /// The problem
+    pub issue: CircuitIssue,
+}
+
+impl CircuitDiagnostic {
+    // Diagnostic of the gate at index `gate`
+    fn at(gate: usize, issue: CircuitIssue) -> Self {
+        Self {
+            gate: Some(gate),
+            issue,
+        }
+    }
+}
+
+impl fmt::Display for CircuitIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CircuitIssue::UnknownVersion(v) => write!(f, "unknown instruction set version {v}"),
+            CircuitIssue::UnknownOpcode(op) => write!(f, "unknown opcode {op}"),
+            CircuitIssue::Arity { min, max, got } if max == min => {
+                write!(f, "expected {min} sons, got {got}")
+            }
+            CircuitIssue::Arity { min, max, got } if *max == usize::MAX => {
+                write!(f, "expected at least {min} sons, got {got}")
+            }
+            CircuitIssue::Arity { min, max, got } => {
+                write!(f, "expected {min} to {max} sons, got {got}")
+            }
+            CircuitIssue::ParamsLength { expected, got } => {
+                write!(f, "expected {expected} bytes of params, got {got}")
+            }
+            CircuitIssue::ForwardReference { son } => {
+                write!(f, "son {son} is not evaluated before the gate")
+            }
+            CircuitIssue::InputOutOfRange { son, num_blocks } => {
+                write!(
+                    f,
+                    "son {son} is not an input of the circuit ({num_blocks} blocks)"
+                )
+            }
+            CircuitIssue::ConstantOutOfRange {
+                index,
+                num_constants,
+            } => write!(
+                f,
+                "constant {index} out of bounds ({num_constants} constants)"
+            ),
+            CircuitIssue::MisplacedInput => {
+                write!(f, "input gates must be exactly the first num_blocks gates")
+            }
+            CircuitIssue::NotEncodable(reason) => write!(f, "not encodable: {reason}"),
+            CircuitIssue::MissingFinalComp => write!(f, "the last gate must be a comparison"),
+            CircuitIssue::ExtraComp => write!(f, "only the last gate can be a comparison"),
+        }
+    }
+}
+
+impl fmt::Display for CircuitDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.gate {
+            Some(gate) => write!(f, "gate {gate}: {}", self.issue),
+            None => write!(f, "circuit: {}", self.issue),
+        }
+    }
+}
+
+// Checks that the comparison gate is the last gate and the only one
+fn check_final_comp(
+    opcodes: impl ExactSizeIterator<Item = u32>,
+    comp: u32,
+    diagnostics: &mut Vec<CircuitDiagnostic>,
+) {
+    let last = opcodes.len().checked_sub(1);
+    for (i, opcode) in opcodes.enumerate() {
+        if opcode == comp && Some(i) != last {
+            diagnostics.push(CircuitDiagnostic::at(i, CircuitIssue::ExtraComp));
+        } else if opcode != comp && Some(i) == last {
+            diagnostics.push(CircuitDiagnostic::at(i, CircuitIssue::MissingFinalComp));
+        }
+    }
+    if last.is_none() {
+        diagnostics.push(CircuitDiagnostic {
+            gate: None,
+            issue: CircuitIssue::MissingFinalComp,
+        });
+    }
+}
+
+// Accepted numbers of sons of the opcodes of instruction set 0 (see `version_instructions`)
+fn arity_v0(opcode: u32) -> Option<(usize, usize)> {
+    match opcode {
+        0 => Some((1, 2)),          // SHA-256 compression
+        1 | 2 => Some((3, 3)),      // AES-CTR encryption and decryption
+        3 | 4 => Some((2, 2)),      // addition and multiplication
+        5 => Some((2, usize::MAX)), // equality
+        6 => Some((0, usize::MAX)), // concatenation
+        7 => Some((2, 3)),          // SHA-256 compression with padding
+        _ => None,
+    }
+}
+
+// Opcode of the equality gate of instruction set 0
+const EQUAL_V0: u32 = 5;
+
+impl CompiledCircuit {
+    /// Checks the structure of the circuit: the first `num_blocks` gates are the inputs, every
+    /// other gate has a known opcode, an accepted number of sons, sons evaluated before it and
+    /// constants that exist, and the last gate is the only equality gate.
+    ///
+    /// # Returns
+    /// The problems found in the circuit, empty if it is valid
+    pub fn validate(&self) -> Vec<CircuitDiagnostic> {
+        let mut diagnostics = Vec::new();
+        if self.version != 0 {
+            diagnostics.push(CircuitDiagnostic {
+                gate: None,
+                issue: CircuitIssue::UnknownVersion(self.version),
+            });
+            return diagnostics;
+        }
+
+        for (i, gate) in self.circuit.iter().enumerate() {
+            let is_input = i < self.num_blocks as usize;
+            if is_input != gate.is_dummy() || (gate.is_dummy() && !gate.sons.is_empty()) {
+                diagnostics.push(CircuitDiagnostic::at(i, CircuitIssue::MisplacedInput));
+            }
+            if gate.is_dummy() {
+                continue;
+            }
+
+            match arity_v0(gate.opcode) {
+                None => diagnostics.push(CircuitDiagnostic::at(
+                    i,
+                    CircuitIssue::UnknownOpcode(gate.opcode),
+                )),
+                Some((min, max)) if !(min..=max).contains(&gate.sons.len()) => {
+                    diagnostics.push(CircuitDiagnostic::at(
+                        i,
+                        CircuitIssue::Arity {
+                            min,
+                            max,
+                            got: gate.sons.len(),
+                        },
+                    ))
+                }
+                Some(_) => {}
+            }
+
+            for &son in &gate.sons {
+                if is_constant_idx(son) {
+                    let index = son & !(1 << 31);
+                    if index as usize >= self.constants.len() {
+                        diagnostics.push(CircuitDiagnostic::at(
+                            i,
+                            CircuitIssue::ConstantOutOfRange {
+                                index,
+                                num_constants: self.constants.len(),
+                            },
+                        ));
+                    }
+                } else if son as usize >= i {
+                    diagnostics.push(CircuitDiagnostic::at(
+                        i,
+                        CircuitIssue::ForwardReference { son: son.into() },
+                    ));
+                }
+            }
+        }
+        if self.circuit.len() < self.num_blocks as usize {
+            diagnostics.push(CircuitDiagnostic {
+                gate: None,
+                issue: CircuitIssue::MisplacedInput,
+            });
+        }
+
+        // input gates have opcode u32::MAX, they are never comparisons
+        check_final_comp(
+            self.circuit.iter().map(|g| g.opcode),
+            EQUAL_V0,
+            &mut diagnostics,
+        );
+        diagnostics
+    }
+}
+
+#[wasm_bindgen]
+impl CompiledCircuit {
+    /// JavaScript wrapper of `validate`
+    ///
+    /// # Returns
+    /// The descriptions of the problems found in the circuit, empty if it is valid
+    pub fn validate_js(&self) -> Vec<String> {
+        self.validate().iter().map(ToString::to_string).collect()
+    }
+}
+
+// Instruction set version of the V2 circuits (see `CircuitBuilder::build`)
+const VERSION_V2: u32 = 1;
+
+impl CompiledCircuitV2 {
+    /// Checks the instruction set version, then every gate against the opcode table (arity and
+    /// params length), checks that its sons are inputs of the circuit (at most `num_blocks`) or
+    /// earlier gates, that it can be encoded in 64 bytes, and that the last gate is the only COMP
+    /// gate.
+    ///
+    /// # Returns
+    /// The problems found in the circuit, empty if it is valid
+    pub fn validate(&self) -> Vec<CircuitDiagnostic> {
+        let mut diagnostics = Vec::new();
+        if self.version != VERSION_V2 {
+            diagnostics.push(CircuitDiagnostic {
+                gate: None,
+                issue: CircuitIssue::UnknownVersion(self.version),
+            });
+            return diagnostics;
+        }
+
+        for (i, gate) in self.gates.iter().enumerate() {
+            match opcode_info(gate.opcode) {
+                Err(_) => diagnostics.push(CircuitDiagnostic::at(
+                    i,
+                    CircuitIssue::UnknownOpcode(gate.opcode.into()),
+                )),
+                Ok(info) => {
+                    let (min, max) = (info.arities[0], info.arities[info.arities.len() - 1]);
+                    if !info.arities.contains(&gate.sons.len()) {
+                        diagnostics.push(CircuitDiagnostic::at(
+                            i,
+                            CircuitIssue::Arity {
+                                min,
+                                max,
+                                got: gate.sons.len(),
+                            },
+                        ));
+                    }
+                    if gate.params.len() != info.params_len {
+                        diagnostics.push(CircuitDiagnostic::at(
+                            i,
+                            CircuitIssue::ParamsLength {
+                                expected: info.params_len,
+                                got: gate.params.len(),
+                            },
+                        ));
+                    }
+                }
+            }
+
+            // gate i is g_{i+1}: its sons are inputs g_{-1}..g_{-num_blocks} or gates g_1..g_i
+            for &son in &gate.sons {
+                if son > i as i64 {
+                    diagnostics.push(CircuitDiagnostic::at(
+                        i,
+                        CircuitIssue::ForwardReference { son },
+                    ));
+                } else if son == 0 || (son < 0 && son.unsigned_abs() > self.num_blocks as u64) {
+                    diagnostics.push(CircuitDiagnostic::at(
+                        i,
+                        CircuitIssue::InputOutOfRange {
+                            son,
+                            num_blocks: self.num_blocks,
+                        },
+                    ));
+                }
+            }
+
+            if let Err(err) = gate.encode_into(&mut [0u8; 64]) {
+                diagnostics.push(CircuitDiagnostic::at(
+                    i,
+                    CircuitIssue::NotEncodable(err.to_string()),
+                ));
+            }
+        }
+
+        check_final_comp(
+            self.gates.iter().map(|g| g.opcode.into()),
+            OPCODE_COMP.into(),
+            &mut diagnostics,
+        );
+        diagnostics
+    }
+}
+
+// =================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuits::{compile_basic_circuit, Gate};
+    use crate::circuits_v2::{compile_circuit_v2, GateV2, OPCODE_CONST, OPCODE_XOR};
+
+    // Issues of the diagnostics, with their gate
+    fn issues(diagnostics: Vec<CircuitDiagnostic>) -> Vec<(Option<usize>, CircuitIssue)> {
+        diagnostics.into_iter().map(|d| (d.gate, d.issue)).collect()
+    }
+
+    #[test]
+    fn test_validate_v2() {
+        for len in [1, 55, 56, 64, 65, 200] {
+            let circuit = compile_circuit_v2(&vec![3u8; 16 + len], &[1; 32]).unwrap();
+            assert_eq!(circuit.validate(), vec![], "{len} bytes");
+        }
+
+        let mut circuit = compile_circuit_v2(&[3u8; 16 + 100], &[1; 32]).unwrap();
+        let n = circuit.gates.len();
+        circuit.gates[0].sons = vec![-3];
+        circuit.gates[1].params.pop();
+        circuit.gates[2].sons = vec![3, 0];
+        circuit.gates[3] = GateV2 {
+            opcode: OPCODE_XOR,
+            sons: vec![1],
+            params: vec![],
+        };
+        circuit.gates[4].opcode = 9;
+        circuit.gates[5].sons = vec![1 << 50];
+        circuit.gates.push(GateV2 {
+            opcode: OPCODE_CONST,
+            sons: vec![],
+            params: vec![0; 32],
+        });
+
+        let not_encodable = circuit.gates[5].encode().unwrap_err().to_string();
+        let diagnostics = circuit.validate();
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "gate 0: son -3 is not an input of the circuit (2 blocks)"
+        );
+        assert_eq!(
+            issues(diagnostics),
+            vec![
+                (
+                    Some(0),
+                    CircuitIssue::InputOutOfRange {
+                        son: -3,
+                        num_blocks: 2
+                    }
+                ),
+                (
+                    Some(1),
+                    CircuitIssue::ParamsLength {
+                        expected: 18,
+                        got: 17
+                    }
+                ),
+                (
+                    Some(2),
+                    CircuitIssue::Arity {
+                        min: 0,
+                        max: 1,
+                        got: 2
+                    }
+                ),
+                (Some(2), CircuitIssue::ForwardReference { son: 3 }),
+                (
+                    Some(2),
+                    CircuitIssue::InputOutOfRange {
+                        son: 0,
+                        num_blocks: 2
+                    }
+                ),
+                (
+                    Some(3),
+                    CircuitIssue::Arity {
+                        min: 2,
+                        max: 2,
+                        got: 1
+                    }
+                ),
+                (Some(4), CircuitIssue::UnknownOpcode(9)),
+                (Some(5), CircuitIssue::ForwardReference { son: 1 << 50 }),
+                (Some(5), CircuitIssue::NotEncodable(not_encodable)),
+                (Some(n - 1), CircuitIssue::ExtraComp),
+                (Some(n), CircuitIssue::MissingFinalComp),
+            ]
+        );
+        assert_eq!(
+            issues(
+                CompiledCircuitV2 {
+                    gates: vec![],
+                    ..circuit.clone()
+                }
+                .validate()
+            ),
+            vec![(None, CircuitIssue::MissingFinalComp)]
+        );
+
+        circuit.version = 2;
+        assert_eq!(
+            issues(circuit.validate()),
+            vec![(None, CircuitIssue::UnknownVersion(2))]
+        );
+    }
+
+    #[test]
+    fn test_validate_v1() {
+        for ct_size in [17, 80, 81, 300] {
+            let circuit = compile_basic_circuit(ct_size, &[1; 32]).unwrap();
+            assert_eq!(circuit.validate(), vec![], "{ct_size} bytes");
+        }
+
+        let mut circuit = compile_basic_circuit(300, &[1; 32]).unwrap();
+        let m = circuit.num_blocks as usize;
+        circuit.circuit[1] = Gate {
+            opcode: 3,
+            sons: vec![0, 0],
+        };
+        circuit.circuit[m].sons[0] = m as u32;
+        circuit.circuit[m + 1].sons[1] = (1 << 31) | 4;
+        circuit.circuit[m + 2] = Gate::dummy();
+        circuit.circuit[m + 3].opcode = 8;
+        circuit.circuit[m + 4].sons.push(0);
+        circuit.circuit[m + 5].opcode = 5;
+
+        assert_eq!(
+            issues(circuit.validate()),
+            vec![
+                (Some(1), CircuitIssue::MisplacedInput),
+                (Some(m), CircuitIssue::ForwardReference { son: m as i64 }),
+                (
+                    Some(m + 1),
+                    CircuitIssue::ConstantOutOfRange {
+                        index: 4,
+                        num_constants: 4
+                    }
+                ),
+                (Some(m + 2), CircuitIssue::MisplacedInput),
+                (Some(m + 3), CircuitIssue::UnknownOpcode(8)),
+                (
+                    Some(m + 4),
+                    CircuitIssue::Arity {
+                        min: 3,
+                        max: 3,
+                        got: 4
+                    }
+                ),
+                (Some(m + 5), CircuitIssue::ExtraComp),
+            ]
+        );
+
+        circuit.version = 1;
+        assert_eq!(
+            issues(circuit.validate()),
+            vec![(None, CircuitIssue::UnknownVersion(1))]
+        );
+    }
+}
@@ -0,0 +1,55 @@
+use crypto_lib::native;
+use std::fs;
+use std::process::Command;
+
+// Runs the sox binary and returns its JSON output
+fn sox(args: &[&str]) -> serde_json::Value {
+    let output = Command::new(env!("CARGO_BIN_EXE_sox"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "sox {args:?}: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+// Decodes a hex field of a JSON output
+fn field(output: &serde_json::Value, name: &str) -> Vec<u8> {
+    hex::decode(output[name].as_str().unwrap()).unwrap()
+}
+
+#[test]
+fn test_precontract_of_a_ct_file() {
+    let dir = std::env::temp_dir().join(format!("sox-cli-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let key = "000102030405060708090a0b0c0d0e0f";
+    let data: Vec<u8> = (0..300).map(|i| (i * 13) as u8).collect();
+
+    // the ciphertext of data.ct replaces data.ct once the whole input has been read
+    let input = dir.join("data.ct");
+    fs::write(&input, &data).unwrap();
+    let pre = sox(&["precontract", input.to_str().unwrap(), key]);
+    let ct = fs::read(&input).unwrap();
+    assert_eq!(ct.len(), 16 + data.len());
+    assert!(!dir.join("data.ct.tmp").exists());
+
+    let description = field(&pre, "description_hex");
+    let res = native::check_received_ct_key(&ct, &hex::decode(key).unwrap(), &description).unwrap();
+    assert!(res.success);
+    assert_eq!(res.decrypted_file, data);
+
+    let res = native::check_precontract_v2(
+        &description,
+        &field(&pre, "commitment_c_hex"),
+        &field(&pre, "commitment_o_hex"),
+        &ct,
+    )
+    .unwrap();
+    assert!(res.success);
+    assert_eq!(res.h_ct, field(&pre, "h_ct_hex"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}